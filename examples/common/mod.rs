@@ -0,0 +1,181 @@
+//! The command-line handling and the connection setup that the `solicit_cli` and `solicit_load`
+//! examples share.
+
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use solicit::http::{HttpScheme, Request, Method};
+use solicit::http::request::Uri;
+use solicit::http::client::{CleartextConnector, HttpConnect};
+
+/// The CA certificates used for `https` URLs, unless `--cacert` says otherwise.
+pub const DEFAULT_CACERT: &'static str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// The usage of the options that every tool accepts.
+const COMMON_USAGE: &'static str = "    -H, --header 'NAME: VALUE'  Adds a header to the request
+    -d, --data DATA             Sends DATA as the request body (@FILE reads it from FILE)
+        --cacert PATH           The CA certificates used for https (with the `tls` feature)
+    -h, --help                  Prints this message";
+
+/// The options that only one of the tools accepts.
+pub trait ToolOptions: Default {
+    /// Returns the name of the tool and the usage of its own options.
+    fn usage() -> (&'static str, &'static str);
+
+    /// Parses the given option, taking its value from `value` if it needs one. Returns `false` if
+    /// the option is not one of the tool's.
+    fn parse_option(&mut self,
+                    option: &str,
+                    value: &mut FnMut() -> Result<String, String>)
+                    -> Result<bool, String>;
+}
+
+/// The options given on the command line.
+pub struct Options<T> {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub data: Option<Vec<u8>>,
+    pub cacert: String,
+    /// The options of the tool itself.
+    pub tool: T,
+}
+
+/// Returns the usage message of the tool.
+pub fn usage<T: ToolOptions>() -> String {
+    let (name, options) = T::usage();
+    format!("Usage: {} [OPTIONS] [METHOD] URL\n\nOptions:\n{}\n{}",
+            name,
+            options,
+            COMMON_USAGE)
+}
+
+pub fn parse_args<T, I>(mut args: I) -> Result<Options<T>, String>
+    where T: ToolOptions,
+          I: Iterator<Item = String>
+{
+    let mut positional = Vec::new();
+    let mut options = Options {
+        method: Method::Get,
+        url: String::new(),
+        headers: Vec::new(),
+        data: None,
+        cacert: DEFAULT_CACERT.into(),
+        tool: T::default(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} requires a value", arg));
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", usage::<T>());
+                process::exit(0);
+            }
+            "-H" | "--header" => {
+                let header = try!(value());
+                let colon = try!(header.find(':').ok_or(format!("Invalid header: {}", header)));
+                options.headers.push((header[..colon].trim().into(),
+                                      header[colon + 1..].trim().into()));
+            }
+            "-d" | "--data" => {
+                let data = try!(value());
+                options.data = Some(if data.starts_with('@') {
+                    try!(read_file(&data[1..]))
+                } else {
+                    data.into_bytes()
+                });
+            }
+            "--cacert" => options.cacert = try!(value()),
+            _ if try!(options.tool.parse_option(&arg, &mut value)) => {}
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    match positional.len() {
+        1 => {
+            if options.data.is_some() {
+                options.method = Method::Post;
+            }
+            options.url = positional.pop().unwrap();
+        }
+        2 => {
+            options.url = positional.pop().unwrap();
+            options.method = Method::from(&positional[0].to_ascii_uppercase()[..]);
+        }
+        _ => return Err(usage::<T>()),
+    }
+    Ok(options)
+}
+
+/// Parses the value of an option that has to be a positive number.
+pub fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Expected a positive number, got {}", value)),
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    try!(File::open(path)
+             .and_then(|mut file| file.read_to_end(&mut data))
+             .map_err(|err| format!("Unable to read {}: {}", path, err)));
+    Ok(data)
+}
+
+/// What a tool does with the connector for the scheme of its URL.
+pub trait WithConnector {
+    type Output;
+
+    fn with_connector<C>(self, connector: C) -> Result<Self::Output, String>
+        where C: HttpConnect,
+              C::Stream: Send + 'static;
+}
+
+impl<T> Options<T> {
+    /// Builds the request that the options describe.
+    pub fn request(&self) -> Result<Request, String> {
+        let mut builder = Request::builder(self.method.clone(), &self.url);
+        for &(ref name, ref value) in &self.headers {
+            builder = builder.header(name.clone(), value.clone());
+        }
+        if let Some(ref data) = self.data {
+            if !self.headers.iter().any(|h| h.0.eq_ignore_ascii_case("content-length")) {
+                builder = builder.header("content-length", data.len().to_string());
+            }
+            builder = builder.body(data.clone());
+        }
+        builder.build().map_err(|err| format!("Invalid request: {}", err))
+    }
+
+    /// Hands the connector for the host, port and scheme of the URL to the given `WithConnector`.
+    pub fn connect<W: WithConnector>(&self, with: W) -> Result<W::Output, String> {
+        let uri = try!(Uri::parse(&self.url).map_err(|_| format!("Invalid URL: {}", self.url)));
+        let (scheme, host, port) = match (uri.scheme, uri.host(), uri.port()) {
+            (Some(scheme), Some(host), Some(port)) => (scheme, host, port),
+            _ => return Err(format!("The URL must be absolute: {}", self.url)),
+        };
+        match scheme {
+            HttpScheme::Http => with.with_connector(CleartextConnector::with_port(host, port)),
+            HttpScheme::Https => connect_tls(host, port, &self.cacert, with),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn connect_tls<W: WithConnector>(host: &str,
+                                 port: u16,
+                                 cacert: &str,
+                                 with: W)
+                                 -> Result<W::Output, String> {
+    use solicit::http::client::tls::TlsConnector;
+
+    let mut connector = TlsConnector::new(host, &cacert);
+    connector.port = port;
+    with.with_connector(connector)
+}
+
+#[cfg(not(feature = "tls"))]
+fn connect_tls<W: WithConnector>(_: &str, _: u16, _: &str, _: W) -> Result<W::Output, String> {
+    Err("https requires the crate to be built with the `tls` feature".into())
+}
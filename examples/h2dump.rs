@@ -0,0 +1,333 @@
+//! Prints a readable trace of the HTTP/2 frames in a raw byte stream.
+//!
+//! Usage: `cargo run --example h2dump -- [FILE]`
+//!
+//! The input is read from the given file, or from the standard input if no file (or `-`) is
+//! given. It is either the raw bytes that one side of a connection sent (optionally starting with
+//! the client preface), or a capture recorded by a `RecordingStream`, in which case both
+//! directions of the connection are traced, each with its own HPACK decoder.
+
+extern crate solicit_fork as solicit;
+extern crate hpack;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+use std::time::Duration;
+
+use solicit::http::StreamId;
+use solicit::http::capture::{self, read_capture, Direction};
+use solicit::http::connection::HttpFrame;
+use solicit::http::frame::{Frame, RawFrame, HttpSetting, StreamDependency, PushPromiseFrame,
+                           ContinuationFrame};
+use solicit::http::frame::continuation::CONTINUATION_FRAME_TYPE;
+use solicit::http::frame::push_promise::PUSH_PROMISE_FRAME_TYPE;
+
+const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Decodes the frames of one direction of a connection.
+struct Dumper {
+    /// The prefix of every line that describes a frame.
+    prefix: &'static str,
+    /// The bytes that do not make up a full frame yet.
+    buf: Vec<u8>,
+    decoder: hpack::Decoder<'static>,
+    /// The header block whose CONTINUATION frames are still due.
+    block: Option<(StreamId, Vec<u8>)>,
+    /// Whether the start of the stream has been checked for the client preface.
+    preface_checked: bool,
+}
+
+impl Dumper {
+    fn new(prefix: &'static str) -> Dumper {
+        Dumper {
+            prefix: prefix,
+            buf: Vec::new(),
+            decoder: hpack::Decoder::new(),
+            block: None,
+            preface_checked: false,
+        }
+    }
+
+    /// Appends the given bytes to the stream, printing every frame that they complete.
+    fn feed(&mut self, data: &[u8], elapsed: Option<Duration>) {
+        self.buf.extend_from_slice(data);
+        if !self.preface_checked {
+            if self.buf.len() < PREFACE.len() && PREFACE.starts_with(&self.buf) {
+                return;
+            }
+            if self.buf.starts_with(PREFACE) {
+                println!("{}{}client preface", timestamp(elapsed), self.prefix);
+                self.buf.drain(..PREFACE.len());
+            }
+            self.preface_checked = true;
+        }
+        while let Some(len) = RawFrame::parse(&self.buf).map(|raw| raw.len()) {
+            let raw = RawFrame::from(self.buf.drain(..len).collect::<Vec<u8>>());
+            self.dump(&raw, elapsed);
+        }
+    }
+
+    /// Reports the bytes that are left over at the end of the stream.
+    fn finish(&self) {
+        if !self.buf.is_empty() {
+            println!("{}{} trailing bytes (incomplete frame)", self.prefix, self.buf.len());
+        }
+        if let Some((stream_id, _)) = self.block {
+            println!("{}header block on stream {} is missing its CONTINUATION frames",
+                     self.prefix,
+                     stream_id);
+        }
+    }
+
+    fn dump(&mut self, raw: &RawFrame, elapsed: Option<Duration>) {
+        let (len, frame_type, flags, stream_id) = raw.header();
+        println!("{}{}{} stream={} len={} flags={}",
+                 timestamp(elapsed),
+                 self.prefix,
+                 type_name(frame_type),
+                 stream_id,
+                 len,
+                 flag_names(frame_type, flags));
+        let frame = match HttpFrame::from_raw(raw) {
+            Ok(frame) => frame,
+            Err(_) => {
+                println!("    malformed frame");
+                return;
+            }
+        };
+        match frame {
+            HttpFrame::DataFrame(frame) => {
+                println!("    data: {} bytes", frame.data.len());
+                if frame.is_padded() {
+                    println!("    padding: {} bytes", raw.payload()[0]);
+                }
+            }
+            HttpFrame::HeadersFrame(frame) => {
+                if let Some(ref dep) = frame.stream_dep {
+                    print_dependency(dep);
+                }
+                self.header_block(stream_id, frame.header_fragment(), frame.is_headers_end());
+            }
+            HttpFrame::PriorityFrame(frame) => print_dependency(frame.stream_dependency()),
+            HttpFrame::RstStreamFrame(frame) => {
+                println!("    error: {:?} ({:#x})", frame.error_code(), frame.raw_error_code());
+            }
+            HttpFrame::SettingsFrame(frame) => {
+                for setting in &frame.settings {
+                    println!("    {} = {}", setting_name(setting), setting.get_val());
+                }
+            }
+            HttpFrame::PingFrame(frame) => {
+                println!("    opaque data: {:#018x}", frame.opaque_data());
+            }
+            HttpFrame::GoawayFrame(frame) => {
+                println!("    last stream: {}", frame.last_stream_id());
+                println!("    error: {:?} ({:#x})", frame.error_code(), frame.raw_error_code());
+                if let Some(data) = frame.debug_data() {
+                    println!("    debug data: {}", String::from_utf8_lossy(data));
+                }
+            }
+            HttpFrame::WindowUpdateFrame(frame) => {
+                println!("    increment: {}", frame.increment());
+            }
+            HttpFrame::AltSvcFrame(frame) => {
+                println!("    origin: {}", String::from_utf8_lossy(frame.origin()));
+                println!("    alt-svc: {}", String::from_utf8_lossy(frame.field_value()));
+            }
+            HttpFrame::OriginFrame(frame) => {
+                for origin in frame.origins() {
+                    println!("    origin: {}", String::from_utf8_lossy(origin));
+                }
+            }
+            HttpFrame::UnknownFrame(raw) => self.dump_unknown(&raw),
+        }
+    }
+
+    /// Prints the frames that `HttpFrame` passes on as unknown frames.
+    fn dump_unknown(&mut self, raw: &RawFrame) {
+        let (_, frame_type, _, stream_id) = raw.header();
+        match frame_type {
+            PUSH_PROMISE_FRAME_TYPE => {
+                let frame = match PushPromiseFrame::from_raw(raw) {
+                    Some(frame) => frame,
+                    None => {
+                        println!("    malformed frame");
+                        return;
+                    }
+                };
+                println!("    promised stream: {}", frame.promised_stream_id());
+                if let Some(padding_len) = frame.padding_len() {
+                    println!("    padding: {} bytes", padding_len);
+                }
+                self.header_block(stream_id, frame.header_fragment(), frame.is_headers_end());
+            }
+            CONTINUATION_FRAME_TYPE => {
+                let frame = match ContinuationFrame::from_raw(raw) {
+                    Some(frame) => frame,
+                    None => {
+                        println!("    malformed frame");
+                        return;
+                    }
+                };
+                match self.block {
+                    Some((block_stream_id, ref mut block)) if block_stream_id == stream_id => {
+                        block.extend_from_slice(frame.header_fragment());
+                    }
+                    _ => {
+                        println!("    unexpected CONTINUATION frame");
+                        return;
+                    }
+                }
+                if frame.is_headers_end() {
+                    let (_, block) = self.block.take().unwrap();
+                    self.decode(&block);
+                }
+            }
+            _ => println!("    payload: {} bytes", raw.payload().len()),
+        }
+    }
+
+    /// Decodes the given header block fragment, once the block is complete.
+    fn header_block(&mut self, stream_id: StreamId, fragment: &[u8], end_headers: bool) {
+        if end_headers {
+            self.decode(fragment);
+        } else {
+            self.block = Some((stream_id, fragment.to_vec()));
+        }
+    }
+
+    fn decode(&mut self, block: &[u8]) {
+        match self.decoder.decode(block) {
+            Ok(headers) => {
+                for (name, value) in headers {
+                    println!("    {}: {}",
+                             String::from_utf8_lossy(&name),
+                             String::from_utf8_lossy(&value));
+                }
+            }
+            Err(err) => {
+                // The decoder's dynamic table is out of sync from here on out.
+                println!("    HPACK decoding failed: {:?}", err);
+            }
+        }
+    }
+}
+
+fn timestamp(elapsed: Option<Duration>) -> String {
+    match elapsed {
+        Some(elapsed) => format!("[{:4}.{:06}] ", elapsed.as_secs(), elapsed.subsec_micros()),
+        None => String::new(),
+    }
+}
+
+fn print_dependency(dep: &StreamDependency) {
+    println!("    depends on: {}{} weight={}",
+             dep.stream_id,
+             if dep.is_exclusive { " (exclusive)" } else { "" },
+             dep.weight as u16 + 1);
+}
+
+fn type_name(frame_type: u8) -> String {
+    let name = match frame_type {
+        0x0 => "DATA",
+        0x1 => "HEADERS",
+        0x2 => "PRIORITY",
+        0x3 => "RST_STREAM",
+        0x4 => "SETTINGS",
+        0x5 => "PUSH_PROMISE",
+        0x6 => "PING",
+        0x7 => "GOAWAY",
+        0x8 => "WINDOW_UPDATE",
+        0x9 => "CONTINUATION",
+        0xa => "ALTSVC",
+        0xc => "ORIGIN",
+        _ => return format!("UNKNOWN({:#x})", frame_type),
+    };
+    name.to_string()
+}
+
+fn flag_names(frame_type: u8, flags: u8) -> String {
+    let known: &[(u8, &str)] = match frame_type {
+        0x0 => &[(0x1, "END_STREAM"), (0x8, "PADDED")],
+        0x1 => &[(0x1, "END_STREAM"), (0x4, "END_HEADERS"), (0x8, "PADDED"), (0x20, "PRIORITY")],
+        0x4 | 0x6 => &[(0x1, "ACK")],
+        0x5 => &[(0x4, "END_HEADERS"), (0x8, "PADDED")],
+        0x9 => &[(0x4, "END_HEADERS")],
+        _ => &[],
+    };
+    let mut names: Vec<String> = known.iter()
+                                      .filter(|&&(flag, _)| flags & flag != 0)
+                                      .map(|&(_, name)| name.to_string())
+                                      .collect();
+    let unknown = known.iter().fold(flags, |rest, &(flag, _)| rest & !flag);
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join("|")
+    }
+}
+
+fn setting_name(setting: &HttpSetting) -> &'static str {
+    match *setting {
+        HttpSetting::HeaderTableSize(_) => "HEADER_TABLE_SIZE",
+        HttpSetting::EnablePush(_) => "ENABLE_PUSH",
+        HttpSetting::MaxConcurrentStreams(_) => "MAX_CONCURRENT_STREAMS",
+        HttpSetting::InitialWindowSize(_) => "INITIAL_WINDOW_SIZE",
+        HttpSetting::MaxFrameSize(_) => "MAX_FRAME_SIZE",
+        HttpSetting::MaxHeaderListSize(_) => "MAX_HEADER_LIST_SIZE",
+        HttpSetting::EnableConnectProtocol(_) => "ENABLE_CONNECT_PROTOCOL",
+    }
+}
+
+fn read_input() -> io::Result<Vec<u8>> {
+    let mut input = Vec::new();
+    match env::args().nth(1) {
+        None => try!(io::stdin().read_to_end(&mut input)),
+        Some(ref path) if path == "-" => try!(io::stdin().read_to_end(&mut input)),
+        Some(path) => try!(try!(File::open(path)).read_to_end(&mut input)),
+    };
+    Ok(input)
+}
+
+fn main() {
+    if env::args().nth(1).map_or(false, |arg| arg == "-h" || arg == "--help") {
+        println!("Usage: h2dump [FILE]");
+        return;
+    }
+    let input = match read_input() {
+        Ok(input) => input,
+        Err(err) => {
+            println!("Unable to read the input: {}", err);
+            process::exit(1);
+        }
+    };
+
+    if input.starts_with(capture::MAGIC) {
+        let records = match read_capture(&input[..]) {
+            Ok(records) => records,
+            Err(err) => {
+                println!("Invalid capture: {}", err);
+                process::exit(1);
+            }
+        };
+        let mut received = Dumper::new("<- ");
+        let mut sent = Dumper::new("-> ");
+        for record in records {
+            match record.direction {
+                Direction::Read => received.feed(&record.data, Some(record.elapsed)),
+                Direction::Written => sent.feed(&record.data, Some(record.elapsed)),
+            }
+        }
+        sent.finish();
+        received.finish();
+    } else {
+        let mut dumper = Dumper::new("");
+        dumper.feed(&input, None);
+        dumper.finish();
+    }
+}
@@ -0,0 +1,202 @@
+//! A curl-like command-line HTTP/2 client.
+//!
+//! Usage: `cargo run --example solicit_cli -- [OPTIONS] [METHOD] URL`
+//!
+//! The response's status, headers and body are printed to the standard output. With `-n N`, the
+//! same request is sent N times in parallel, over a single connection. With `-v`, every frame
+//! that is sent and received is traced on the standard error, along with the headers. `https`
+//! URLs are only supported when the crate is built with the `tls` feature.
+
+extern crate solicit_fork as solicit;
+
+mod common;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use solicit::http::{Header, StreamId, Request};
+use solicit::http::connection::HttpFrame;
+use solicit::http::observer::FrameObserver;
+use solicit::http::transport::TransportStream;
+use solicit::http::client::HttpConnect;
+use solicit::client::SimpleClient;
+
+use common::{Options, ToolOptions, WithConnector, parse_args, positive};
+
+/// The options of `solicit_cli` itself.
+struct CliOptions {
+    parallel: usize,
+    verbose: bool,
+}
+
+impl Default for CliOptions {
+    fn default() -> CliOptions {
+        CliOptions {
+            parallel: 1,
+            verbose: false,
+        }
+    }
+}
+
+impl ToolOptions for CliOptions {
+    fn usage() -> (&'static str, &'static str) {
+        ("solicit_cli",
+         "    -n, --parallel N            Sends N requests in parallel over one connection
+    -v, --verbose               Traces the frames and headers on the standard error")
+    }
+
+    fn parse_option(&mut self,
+                    option: &str,
+                    value: &mut FnMut() -> Result<String, String>)
+                    -> Result<bool, String> {
+        match option {
+            "-n" | "--parallel" => {
+                let n = try!(value());
+                self.parallel = try!(positive(&n).map_err(|_| {
+                    format!("Invalid number of requests: {}", n)
+                }));
+            }
+            "-v" | "--verbose" => self.verbose = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Traces the frames and headers of the connection on the standard error.
+struct Tracer;
+
+impl Tracer {
+    fn frame(&self, direction: &str, frame: &HttpFrame) {
+        let (len, frame_type, flags, stream_id) = frame.get_header();
+        let name = match *frame {
+            HttpFrame::DataFrame(_) => "DATA",
+            HttpFrame::HeadersFrame(_) => "HEADERS",
+            HttpFrame::PriorityFrame(_) => "PRIORITY",
+            HttpFrame::RstStreamFrame(_) => "RST_STREAM",
+            HttpFrame::SettingsFrame(_) => "SETTINGS",
+            HttpFrame::PingFrame(_) => "PING",
+            HttpFrame::GoawayFrame(_) => "GOAWAY",
+            HttpFrame::WindowUpdateFrame(_) => "WINDOW_UPDATE",
+            HttpFrame::AltSvcFrame(_) => "ALTSVC",
+            HttpFrame::OriginFrame(_) => "ORIGIN",
+            HttpFrame::UnknownFrame(_) if frame_type == 0x5 => "PUSH_PROMISE",
+            HttpFrame::UnknownFrame(_) if frame_type == 0x9 => "CONTINUATION",
+            HttpFrame::UnknownFrame(_) => "UNKNOWN",
+        };
+        eprintln!("* {} {} (type={:#x} stream={} len={} flags={:#x})",
+                  direction,
+                  name,
+                  frame_type,
+                  stream_id,
+                  len,
+                  flags);
+    }
+
+    fn headers(&self, direction: &str, stream_id: StreamId, headers: &[Header]) {
+        for header in headers {
+            eprintln!("{} [{}] {}: {}",
+                      direction,
+                      stream_id,
+                      String::from_utf8_lossy(header.name()),
+                      String::from_utf8_lossy(header.value()));
+        }
+    }
+}
+
+impl FrameObserver for Tracer {
+    fn on_frame_received(&mut self, frame: &HttpFrame) {
+        self.frame("<-", frame);
+    }
+
+    fn on_frame_sent(&mut self, frame: &HttpFrame) {
+        self.frame("->", frame);
+    }
+
+    fn on_headers_received(&mut self, stream_id: StreamId, headers: &[Header]) {
+        self.headers("<", stream_id, headers);
+    }
+
+    fn on_headers_sent(&mut self, stream_id: StreamId, headers: &[Header]) {
+        self.headers(">", stream_id, headers);
+    }
+}
+
+/// Sends the request as many times as the options say and prints the responses.
+fn run<S: TransportStream>(mut client: SimpleClient<S>,
+                           request: Request,
+                           options: &Options<CliOptions>)
+                           -> Result<(), String> {
+    if options.tool.verbose {
+        client.set_frame_observer(Tracer);
+    }
+    let mut stream_ids = Vec::new();
+    for _ in 0..options.tool.parallel {
+        let stream_id = try!(client.send(request.clone())
+                                   .map_err(|err| format!("Unable to send the request: {}", err)));
+        stream_ids.push(stream_id);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for stream_id in stream_ids {
+        let response = try!(client.get_response(stream_id)
+                                  .map_err(|err| format!("Request on stream {} failed: {}",
+                                                         stream_id,
+                                                         err)));
+        let written: io::Result<()> = (|| {
+            if options.tool.parallel > 1 {
+                try!(writeln!(out, "== stream {} ==", stream_id));
+            }
+            for header in &response.headers {
+                try!(out.write_all(header.name()));
+                try!(out.write_all(b": "));
+                try!(out.write_all(header.value()));
+                try!(out.write_all(b"\n"));
+            }
+            try!(out.write_all(b"\n"));
+            try!(out.write_all(&response.body));
+            out.flush()
+        })();
+        try!(written.map_err(|err| format!("Unable to print the response: {}", err)));
+    }
+    Ok(())
+}
+
+/// Connects to the server and runs the requests over the connection.
+struct Run<'a> {
+    request: Request,
+    options: &'a Options<CliOptions>,
+}
+
+impl<'a> WithConnector for Run<'a> {
+    type Output = ();
+
+    fn with_connector<C>(self, connector: C) -> Result<(), String>
+        where C: HttpConnect,
+              C::Stream: Send + 'static
+    {
+        let client = try!(SimpleClient::with_connector(connector)
+                              .map_err(|err| {
+                                  format!("Unable to connect to {}: {}", self.options.url, err)
+                              }));
+        run(client, self.request, self.options)
+    }
+}
+
+fn main_inner() -> Result<(), String> {
+    let options = try!(parse_args::<CliOptions, _>(env::args().skip(1)));
+    let request = try!(options.request());
+    options.connect(Run {
+        request: request,
+        options: &options,
+    })
+}
+
+fn main() {
+    if let Err(err) = main_inner() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
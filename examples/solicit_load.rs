@@ -0,0 +1,227 @@
+//! A load generator for HTTP/2 servers, along the lines of h2load.
+//!
+//! Usage: `cargo run --release --example solicit_load -- [OPTIONS] [METHOD] URL`
+//!
+//! It opens C connections with the async `Client` and keeps S requests in flight on each of them
+//! for the given duration, each of the S streams being sent as soon as the previous one on its
+//! "slot" completes. Once the time is up, it reports the throughput, the status codes and the
+//! latency percentiles of the completed requests. `https` URLs are only supported when the crate
+//! is built with the `tls` feature.
+
+extern crate solicit_fork as solicit;
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use solicit::http::Request;
+use solicit::http::client::HttpConnect;
+use solicit::client::Client;
+
+use common::{ToolOptions, WithConnector, parse_args, positive};
+
+/// The options of `solicit_load` itself.
+struct LoadOptions {
+    connections: usize,
+    streams: usize,
+    duration: Duration,
+    timeout: Duration,
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            connections: 1,
+            streams: 10,
+            duration: Duration::from_secs(10),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ToolOptions for LoadOptions {
+    fn usage() -> (&'static str, &'static str) {
+        ("solicit_load",
+         "    -c, --connections C         The number of connections to open (default: 1)
+    -m, --streams S             The number of concurrent streams per connection (default: 10)
+    -D, --duration SECS         How long to send requests for (default: 10)
+    -t, --timeout SECS          The timeout of each request (default: 10)")
+    }
+
+    fn parse_option(&mut self,
+                    option: &str,
+                    value: &mut FnMut() -> Result<String, String>)
+                    -> Result<bool, String> {
+        match option {
+            "-c" | "--connections" => self.connections = try!(positive(&try!(value()))),
+            "-m" | "--streams" => self.streams = try!(positive(&try!(value()))),
+            "-D" | "--duration" => {
+                self.duration = Duration::from_secs(try!(positive(&try!(value()))) as u64);
+            }
+            "-t" | "--timeout" => {
+                self.timeout = Duration::from_secs(try!(positive(&try!(value()))) as u64);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// The outcome of the requests sent by a single worker.
+#[derive(Default)]
+struct Results {
+    /// The latencies of the requests that received a response.
+    latencies: Vec<Duration>,
+    /// The number of responses with each status code.
+    statuses: BTreeMap<u16, u64>,
+    /// The number of requests that failed, by the reason of the failure.
+    errors: BTreeMap<String, u64>,
+    /// The total size of the received response bodies.
+    body_bytes: u64,
+}
+
+impl Results {
+    fn merge(&mut self, other: Results) {
+        self.latencies.extend(other.latencies);
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_insert(0) += count;
+        }
+        for (error, count) in other.errors {
+            *self.errors.entry(error).or_insert(0) += count;
+        }
+        self.body_bytes += other.body_bytes;
+    }
+
+    fn error(&mut self, error: String) {
+        *self.errors.entry(error).or_insert(0) += 1;
+    }
+}
+
+/// Sends the request over the given client, one at a time, until the deadline.
+fn worker(client: Client, request: Request, deadline: Instant, timeout: Duration) -> Results {
+    let mut results = Results::default();
+    while Instant::now() < deadline {
+        let start = Instant::now();
+        let handle = match client.send_with_timeout(request.clone(), timeout) {
+            Some(handle) => handle,
+            None => {
+                results.error("connection closed".into());
+                break;
+            }
+        };
+        match handle.recv() {
+            Ok(response) => {
+                results.latencies.push(start.elapsed());
+                results.body_bytes += response.body.len() as u64;
+                match response.status_code() {
+                    Ok(status) => *results.statuses.entry(status).or_insert(0) += 1,
+                    Err(_) => results.error("malformed response".into()),
+                }
+            }
+            Err(err) => results.error(err.to_string()),
+        }
+    }
+    results
+}
+
+/// Opens a connection with the async `Client`.
+struct Connect;
+
+impl WithConnector for Connect {
+    type Output = Client;
+
+    fn with_connector<C>(self, connector: C) -> Result<Client, String>
+        where C: HttpConnect,
+              C::Stream: Send + 'static
+    {
+        Client::with_connector(connector).map_err(|err| err.to_string())
+    }
+}
+
+/// Returns the given percentile of the sorted latencies, by the nearest-rank method.
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn report(mut results: Results, elapsed: Duration) {
+    let secs = millis(elapsed) / 1000.0;
+    let completed = results.latencies.len();
+    let failed: u64 = results.errors.values().sum();
+    println!("finished in {:.2}s", secs);
+    println!("requests: {} completed, {} failed", completed, failed);
+    println!("throughput: {:.2} req/s, {:.2} KiB/s of response bodies",
+             completed as f64 / secs,
+             results.body_bytes as f64 / 1024.0 / secs);
+    for (status, count) in &results.statuses {
+        println!("status {}: {}", status, count);
+    }
+    for (error, count) in &results.errors {
+        println!("error \"{}\": {}", error, count);
+    }
+    if completed == 0 {
+        return;
+    }
+
+    results.latencies.sort();
+    let total: f64 = results.latencies.iter().map(|&latency| millis(latency)).sum();
+    println!("latency (ms): min {:.3}, mean {:.3}, max {:.3}",
+             millis(results.latencies[0]),
+             total / completed as f64,
+             millis(results.latencies[completed - 1]));
+    for &percent in &[50.0, 75.0, 90.0, 95.0, 99.0] {
+        println!("  p{:<4} {:.3}", percent, millis(percentile(&results.latencies, percent)));
+    }
+}
+
+fn main_inner() -> Result<(), String> {
+    let options = try!(parse_args::<LoadOptions, _>(env::args().skip(1)));
+    let request = try!(options.request());
+    let load = &options.tool;
+
+    let mut clients = Vec::new();
+    for _ in 0..load.connections {
+        let client = try!(options.connect(Connect)
+                                 .map_err(|err| {
+                                     format!("Unable to connect to {}: {}", options.url, err)
+                                 }));
+        clients.push(client);
+    }
+    println!("{} connection(s), {} stream(s) each, for {}s",
+             load.connections,
+             load.streams,
+             load.duration.as_secs());
+
+    let start = Instant::now();
+    let deadline = start + load.duration;
+    let mut workers = Vec::new();
+    for client in &clients {
+        for _ in 0..load.streams {
+            let client = client.clone();
+            let request = request.clone();
+            let timeout = load.timeout;
+            workers.push(thread::spawn(move || worker(client, request, deadline, timeout)));
+        }
+    }
+    let mut results = Results::default();
+    for worker in workers {
+        results.merge(worker.join().unwrap());
+    }
+    report(results, start.elapsed());
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = main_inner() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
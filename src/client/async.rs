@@ -3,21 +3,31 @@
 //! It allows users to make requests to the same underlying connection from
 //! different threads concurrently, as well as to receive the response
 //! asynchronously.
+use std::cell::Cell;
 use std::collections::HashMap;
 
+use std::error;
 use std::fmt;
 use std::io;
-use std::sync::mpsc::{Sender, Receiver};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError};
 use std::sync::mpsc;
 use std::thread;
+use std::cmp;
+use std::time::{Duration, Instant};
 
-use http::{StreamId, HttpError, Response, StaticResponse, Header, HttpResult, StaticHeader};
-use http::frame::{RawFrame, FrameIR};
+use http::{StreamId, HttpError, Response, StaticResponse, HttpResult, StaticHeader, ErrorCode,
+           Request, Method};
+use http::frame::{RawFrame, StreamDependency};
 use http::transport::TransportStream;
 use http::connection::{SendFrame, ReceiveFrame, HttpFrame, HttpConnection};
 use http::session::{SessionState, DefaultSessionState, DefaultStream, Stream};
 use http::session::Client as ClientMarker;
 use http::client::{ClientConnection, HttpConnect, HttpConnectError, ClientStream, RequestStream};
+use http::validation::ContentLengthError;
+use http::queue::{FrameQueue, FrameQueueHandle, QueueLimits};
+use client::pool::{ConnectorFactory, PoolKey};
 
 /// Like `thread::spawn`, but with a `name` argument
 pub fn spawn_named<F, T, S>(name: S, f: F) -> thread::JoinHandle<T>
@@ -29,29 +39,214 @@ pub fn spawn_named<F, T, S>(name: S, f: F) -> thread::JoinHandle<T>
     thread::Builder::new().name(name.into()).spawn(f).expect("spawn thread")
 }
 
+/// An identifier that the `Client` assigns to each request it issues. Unlike the stream ID, it is
+/// known as soon as the request is queued, so it can be used to refer to requests that the
+/// `ClientService` has not yet sent to the server.
+type RequestId = usize;
+
+/// The outcome of a request, as delivered to its `RequestHandle`.
+type ResponseResult = Result<StaticResponse, RequestError>;
+
+/// An enum representing the reasons for which a request issued by the `Client` can end without
+/// receiving a (valid) response.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The request was cancelled by the client before its response was received.
+    Cancelled,
+    /// The deadline of the request passed before its response was received.
+    Timeout,
+    /// The server reset the stream of the request with the given error code.
+    StreamReset(ErrorCode),
+    /// The server refused the stream of the request before processing it (i.e. reset it with the
+    /// `RefusedStream` error code), so the request can safely be retried.
+    Refused,
+    /// The server terminated the connection by sending a GOAWAY frame with the given error code.
+    GoAway(ErrorCode),
+    /// An I/O error occurred on the underlying connection.
+    Io(io::Error),
+    /// The server sent a response that is not a valid HTTP/2 response, e.g. one without a
+    /// `:status` pseudo-header.
+    MalformedResponse,
+    /// The body of the response does not match the length declared by its `content-length`
+    /// header, so the client reset the stream.
+    ContentLength(ContentLengthError),
+    /// The underlying HTTP/2 connection failed or its service stopped before the response was
+    /// received.
+    ConnectionClosed,
+}
+
+impl<'a> From<&'a HttpError> for RequestError {
+    /// Maps the error that terminated the HTTP/2 connection to the error reported to each request
+    /// that was still waiting for its response.
+    fn from(err: &HttpError) -> RequestError {
+        match *err {
+            HttpError::IoError(ref err) => {
+                RequestError::Io(io::Error::new(err.kind(), err.to_string()))
+            }
+            HttpError::PeerConnectionError(ref err) => RequestError::GoAway(err.error_code()),
+            _ => RequestError::ConnectionClosed,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestError::StreamReset(code) => write!(f, "Stream reset by the server: {:?}", code),
+            RequestError::GoAway(code) => write!(f, "Connection closed by the server: {:?}", code),
+            RequestError::Io(ref err) => write!(f, "I/O error: {}", err),
+            RequestError::ContentLength(ref err) => write!(f, "Malformed response: {}", err),
+            _ => write!(f, "Request failed: {}", (self as &error::Error).description()),
+        }
+    }
+}
+
+impl error::Error for RequestError {
+    fn description(&self) -> &str {
+        match *self {
+            RequestError::Cancelled => "The request was cancelled",
+            RequestError::Timeout => "The request timed out",
+            RequestError::StreamReset(_) => "The stream was reset by the server",
+            RequestError::Refused => "The stream was refused by the server",
+            RequestError::GoAway(_) => "The connection was closed by the server",
+            RequestError::Io(ref err) => err.description(),
+            RequestError::MalformedResponse => "The response was malformed",
+            RequestError::ContentLength(ref err) => err.description(),
+            RequestError::ConnectionClosed => "The connection was closed",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            RequestError::Io(ref err) => Some(err),
+            RequestError::ContentLength(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The status of the connection underlying a `Client`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// The connection is established and requests are sent to the server as they are issued.
+    Connected,
+    /// The connection failed and a new one is being established. Requests issued in the meantime
+    /// are queued until the client reconnects.
+    Reconnecting,
+    /// The connection can no longer be used for new requests.
+    Closed,
+}
+
+/// The parameters that determine how a `Client` reconnects once its connection fails.
+///
+/// Consecutive attempts are delayed by an exponentially increasing backoff, starting from the
+/// `initial_backoff` and doubling after each failed attempt, up to the `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// The delay before the first attempt to reconnect.
+    pub initial_backoff: Duration,
+    /// The upper bound of the delay between two consecutive attempts.
+    pub max_backoff: Duration,
+    /// The number of consecutive failed attempts after which the client gives up and closes, if
+    /// any. If `None`, the client keeps trying indefinitely.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns the delay before the attempt to reconnect that follows the given number of
+    /// consecutive failed attempts.
+    pub fn backoff(&self, failed_attempts: u32) -> Duration {
+        // Beyond 2^16 times the initial backoff, the cap is going to be hit in any practical case.
+        let factor = 1u32 << cmp::min(failed_attempts, 16);
+        match self.initial_backoff.checked_mul(factor) {
+            Some(backoff) => cmp::min(backoff, self.max_backoff),
+            None => self.max_backoff,
+        }
+    }
+}
+
+/// The state of a connection that the `ClientService` shares with all `Client`s that issue
+/// requests on it.
+struct ConnectionState {
+    /// The number of requests that have been issued, but have not yet completed.
+    active_reqs: AtomicUsize,
+    /// The maximum number of concurrent streams that the server allows, or `usize::MAX` if it has
+    /// not advertised one.
+    max_concurrent_streams: AtomicUsize,
+    /// Whether the server's SETTINGS have been received on the connection, so that its limit of
+    /// concurrent streams is known.
+    settings_received: AtomicBool,
+    /// The current status of the connection.
+    status: Mutex<ConnectionStatus>,
+    /// The point in time when the connection last became idle.
+    idle_since: Mutex<Instant>,
+}
+
+impl ConnectionState {
+    /// Creates the state of a newly established connection.
+    fn new() -> ConnectionState {
+        ConnectionState {
+            active_reqs: AtomicUsize::new(0),
+            max_concurrent_streams: AtomicUsize::new(usize::MAX),
+            settings_received: AtomicBool::new(false),
+            status: Mutex::new(ConnectionStatus::Connected),
+            idle_since: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Delivers the outcome of a request to the given channel and marks the request as
+    /// completed.
+    fn complete(&self, tx: &Sender<ResponseResult>, res: ResponseResult) {
+        // The request is accounted for as completed before its outcome is delivered, so that
+        // whoever receives it can rely on that.
+        if self.active_reqs.fetch_sub(1, Ordering::SeqCst) == 1 {
+            *self.idle_since.lock().unwrap() = Instant::now();
+        }
+        let _ = tx.send(res);
+    }
+
+    /// Returns the current status of the connection.
+    fn status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Updates the status of the connection.
+    fn set_status(&self, status: ConnectionStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
 /// A struct representing an asynchronously dispatched request. It is used
 /// internally be the `ClientService` and `Client` structs.
 struct AsyncRequest {
-    /// The method of the request
-    pub method: Vec<u8>,
-    /// The path being requested
-    pub path: Vec<u8>,
-    /// Extra headers that should be included in the request. Does *not*
-    /// include meta-headers.
-    pub headers: Vec<StaticHeader>,
-    /// The body of the request, if any.
-    pub body: Option<Vec<u8>>,
+    /// The identifier of the request, used for cancelling it.
+    id: RequestId,
+    /// The request that should be sent to the server.
+    request: Request,
+    /// The point in time after which the request should be abandoned, if any.
+    deadline: Option<Instant>,
     /// The sender side of a channel where the response to this request should
     /// be delivered.
-    tx: Sender<StaticResponse>,
+    tx: Sender<ResponseResult>,
 }
 
-/// A struct that buffers `RawFrame`s in an internal `mpsc` channel and sends them using the
+/// A struct that buffers `RawFrame`s in an internal `FrameQueue` and sends them using the
 /// wrapped `SendFrame` instance when the `send_next` method is called.
 ///
-/// Additionally, it provides a `ChannelFrameSenderHandle` instance that implements the `SendFrame`
-/// trait and as such can be passed to the `HttpConnection`. This handler simply queues the frame
-/// into the internal channel, without ever blocking.
+/// Additionally, it provides a `FrameQueueHandle` instance that implements the `SendFrame` trait
+/// and as such can be passed to the `HttpConnection`. This handle simply queues the frame into
+/// the internal queue, without ever blocking; once the queue is full, because the peer stopped
+/// reading, queueing fails and the connection is closed.
 ///
 /// As such, this is a convenience struct that makes it possible to provide non-blocking writes
 /// from within `HttpConnection`s, while handling the actual writes using a `SendFrame`
@@ -59,8 +254,8 @@ struct AsyncRequest {
 struct ChannelFrameSender<S>
     where S: SendFrame
 {
-    /// The receiving end of the channel. Buffers the frames that are to be sent.
-    rx: Receiver<Vec<u8>>,
+    /// The writing end of the queue. Buffers the frames that are to be sent.
+    queue: FrameQueue,
     /// The `SendFrame` instance that will perform the actual writes from within the `send_next`
     /// method.
     inner: S,
@@ -71,14 +266,12 @@ impl<S> ChannelFrameSender<S>
 {
     /// Creates a new `ChannelFrameSender` that will use the provided `SendFrame` instance within
     /// the `send_next` method in order to perform the final send to the remote peer.
-    /// The `ChannelFrameSenderHandle` that is returned can be used to queue frames for sending
-    /// from within `HttpConnection`s, as it implements the `SendFrame` trait.
-    fn new(inner: S) -> (ChannelFrameSender<S>, ChannelFrameSenderHandle) {
-        let (send, recv) = mpsc::channel();
-
-        let handle = ChannelFrameSenderHandle { tx: send };
+    /// The `FrameQueueHandle` that is returned can be used to queue frames for sending from
+    /// within `HttpConnection`s, as it implements the `SendFrame` trait.
+    fn new(inner: S) -> (ChannelFrameSender<S>, FrameQueueHandle) {
+        let (queue, handle) = FrameQueue::new(QueueLimits::default());
         let sender = ChannelFrameSender {
-            rx: recv,
+            queue: queue,
             inner: inner,
         };
         (sender, handle)
@@ -86,14 +279,14 @@ impl<S> ChannelFrameSender<S>
 
     /// Performs the send of the next frame that is buffered in the internal channel of the struct.
     ///
-    /// If there is no frame in the channel, it will block until there is one there.
+    /// If there is no frame in the queue, it will block until there is one there. Control frames
+    /// are sent ahead of the frames of streams.
     ///
-    /// If the channel becomes disconnected from all senders, indicating that all handles to the
-    /// sender have been dropped, the mehod will return an error.
+    /// If the handle to the queue has been dropped, the method will return an error.
     fn send_next(&mut self) -> HttpResult<()> {
-        let frame_buffer = try!(self.rx
-                                    .recv()
-                                    .map_err(|_| {
+        let frame_buffer = try!(self.queue
+                                    .pop()
+                                    .ok_or_else(|| {
                                         io::Error::new(io::ErrorKind::Other, "Unable to send frame")
                                     }));
         debug!("Performing the actual send frame IO");
@@ -103,27 +296,6 @@ impl<S> ChannelFrameSender<S>
     }
 }
 
-/// A handle to the `ChannelFrameSender` and an implementation of the `SendFrame` trait. It simply
-/// queues the given frames into the send queue of the `ChannelFrameSender` without ever blocking.
-/// (Except possibly to allocate some memory, as per the `mpsc::channel` specification.)
-struct ChannelFrameSenderHandle {
-    /// The sender side of the channel that buffers the frames to be written. Allows the handle to
-    /// queue the frame for future writing without blocking on the IO.
-    tx: Sender<Vec<u8>>,
-}
-
-impl SendFrame for ChannelFrameSenderHandle {
-    fn send_frame<F: FrameIR>(&mut self, frame: F) -> HttpResult<()> {
-        let mut buf = io::Cursor::new(Vec::with_capacity(1024));
-        try!(frame.serialize_into(&mut buf));
-        try!(self.tx
-                 .send(buf.into_inner())
-                 .map_err(|_| io::Error::new(io::ErrorKind::Other, "Unable to send frame")));
-        debug!("Queued the frame for sending...");
-        Ok(())
-    }
-}
-
 /// A struct that buffers `HttpFrame`s read by the wrapped `ReceiveFrame` instance in an internal
 /// `mpsc` channel. The reads from the wrapped `ReceiveFrame` instance are triggered by calls to
 /// the `read_next` method.
@@ -237,11 +409,11 @@ impl From<HttpError> for ClientServiceErr {
 enum WorkItem {
     /// Queue a new request to the HTTP/2 connection.
     Request(AsyncRequest),
-    /// Trigger a new `handle_next_frame`. The work item should be queued only when there is a
-    /// frame to be handled to avoid blocking the `run_once` call.
-    HandleFrame,
-    /// Trigger a new `send_next_data` operation.
-    SendData,
+    /// Trigger a new `handle_next_frame` on the connection with the given ID. The work item should
+    /// be queued only when there is a frame to be handled to avoid blocking the `run_once` call.
+    HandleFrame(ConnectionId),
+    /// Trigger a new `send_next_data` operation on the connection with the given ID.
+    SendData(ConnectionId),
     /// Signals to the service that a new client is connected. Helps it keep track of whether there
     /// are clients that would expect a response.
     NewClient,
@@ -250,6 +422,147 @@ enum WorkItem {
     ClientLeft,
     /// Send a PING frame to the server
     SendPing,
+    /// Replace the limits of the queue of frames that are yet to be written to the socket.
+    SetQueueLimits(QueueLimits),
+    /// Abandon the request with the given ID. If it has already been sent to the server, its
+    /// stream is reset.
+    Cancel(RequestId),
+    /// Change the priority of the request with the given ID. If it has already been sent to the
+    /// server, a PRIORITY frame is sent for its stream.
+    Reprioritize(RequestId, StreamDependency),
+    /// Signals to the service that the connection with the given ID can no longer be used, due to
+    /// the given error that occurred while performing the IO.
+    ConnectionFailed(ConnectionId, HttpError),
+}
+
+/// An identifier of one of the consecutive connections that a `ClientService` uses. The IO
+/// threads tag the work items they queue with it, so that the items that belong to a connection
+/// that has since been replaced can be ignored.
+type ConnectionId = usize;
+
+/// A function that establishes a new connection for a `ClientService` whose connection failed,
+/// performing its IO on dedicated threads that tag their work items with the given ID.
+type Reconnect = Box<FnMut(ConnectionId) -> HttpResult<Connection> + Send>;
+
+/// The limit to the number of concurrently outstanding requests on a connection whose server has
+/// not (yet) advertised its own limit.
+const DEFAULT_LIMIT: u32 = 3;
+
+/// The components of an HTTP/2 connection used by the `ClientService`, whose blocking IO is
+/// performed elsewhere.
+struct Connection {
+    /// The connection that is used for underlying HTTP/2 communication.
+    conn: ClientConnection,
+    /// The name of the host the connection is established to.
+    host: Vec<u8>,
+    /// The handle to the frames read from the socket.
+    recv_handle: ChannelFrameReceiverHandle,
+    /// The handle through which frames are queued for writing to the socket.
+    send_handle: FrameQueueHandle,
+    /// Shuts down the socket, if the connection owns one. This forces the threads performing its
+    /// blocking IO to stop.
+    closer: Option<Box<FnMut() + Send>>,
+}
+
+impl Connection {
+    /// Creates a new `Connection` that will use the provided `ClientStream` for its network
+    /// communication. Along with it, returns the `ChannelFrameReceiver` and `ChannelFrameSender`
+    /// that wrap the read and write end of the socket, allowing the caller to perform the
+    /// blocking IO in a customized manner (e.g. in separate dedicated threads).
+    fn new<S>(client_stream: ClientStream<S>)
+              -> io::Result<(Connection, ChannelFrameReceiver<S>, ChannelFrameSender<S>)>
+        where S: TransportStream
+    {
+        let ClientStream(stream, scheme, host) = client_stream;
+
+        // Manually split the stream into the write/read ends, so that we can...
+        let sender = try!(stream.try_split());
+        let receiver = stream;
+        // ...wrap them into the adapters...
+        let (recv_frame, recv_handle) = ChannelFrameReceiver::new(receiver);
+        let (send_frame, send_handle) = ChannelFrameSender::new(sender);
+
+        // ...and pass the non-blocking/buffering ends into the `HttpConnect` instead of the
+        // blocking socket itself.
+        let conn = ClientConnection::with_connection(HttpConnection::new(scheme),
+                                                     DefaultSessionState::<ClientMarker, _>::new());
+
+        let connection = Connection {
+            conn: conn,
+            host: host.as_bytes().to_vec(),
+            recv_handle: recv_handle,
+            send_handle: send_handle,
+            closer: None,
+        };
+        Ok((connection, recv_frame, send_frame))
+    }
+
+    /// Creates a new `Connection` for the given `ClientStream` and spawns the threads that
+    /// perform its blocking IO. The threads notify the service of their progress on the given
+    /// work queue, tagging the work items with the given connection ID.
+    fn start<S>(client_stream: ClientStream<S>,
+                work_queue: &Sender<WorkItem>,
+                id: ConnectionId)
+                -> io::Result<Connection>
+        where S: TransportStream + Send + 'static
+    {
+        // Keep a socket handle in order to shut it down once the service stops using the
+        // connection. This is required because if the service decides to stop (due to all clients
+        // disconnecting) while the socket is still open and the read thread waiting, it can
+        // happen that the read thread (and as such the socket itself) ends up waiting
+        // indefinitely (or well, until the server decides to close it), effectively leaking the
+        // socket and thread.
+        let mut sck = try!(client_stream.0.try_split());
+        let (mut connection, mut recv_frame, mut send_frame) = try!(Connection::new(client_stream));
+        connection.closer = Some(Box::new(move || {
+            // If the shutdown fails, there's really nothing we can do to recover at this point...
+            if let Err(err) = sck.close() {
+                debug!("Failed to close the socket: {:?}", err);
+            }
+        }));
+
+        // Keep a handle to the work queue to notify the service of newly read frames, making it so
+        // that it never blocks on waiting for frames to read.
+        let read_notify = work_queue.clone();
+        let sender_work_queue = work_queue.clone();
+
+        spawn_named("Solicit Sender", move || {
+            loop {
+                match send_frame.send_next() {
+                    Ok(_) => {
+                        if sender_work_queue.send(WorkItem::SendData(id)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        // If the service has already stopped, there's no one left to notify.
+                        let _ = sender_work_queue.send(WorkItem::ConnectionFailed(id, err));
+                        break;
+                    }
+                }
+            }
+            debug!("Sender thread halting");
+        });
+
+        spawn_named("Solicit Reader", move || {
+            loop {
+                match recv_frame.read_next() {
+                    Ok(_) => {
+                        if read_notify.send(WorkItem::HandleFrame(id)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = read_notify.send(WorkItem::ConnectionFailed(id, err));
+                        break;
+                    }
+                }
+            }
+            debug!("Reader thread halting");
+        });
+
+        Ok(connection)
+    }
 }
 
 /// An internal struct encapsulating a service that lets multiple clients
@@ -274,7 +587,12 @@ enum WorkItem {
 /// Additionally, the client needs to make sure to perform the actual socket IO (which is fully
 /// blocking, without even timeout support currently in Rust) in threads dedicated for that, by
 /// calling the `send_next` or `read_next` methods of the `ChannelFrameSender` or
-/// `ChannelFrameReceiver`, which are returned from the `ClientService` constructor.
+/// `ChannelFrameReceiver`, which are returned along with the `Connection` that the service is
+/// created with.
+///
+/// If the service is given a way to reconnect, a failure of the connection does not stop the
+/// service. Instead, it establishes a new connection (backing off exponentially, while attempts
+/// fail) and resends the requests that the server has not processed on it.
 ///
 /// TODO: Technically, the `run_once` method could take a `WorkItem`, so a single event loop could
 ///       dispatch work items to a corresponding service, removing the need for the
@@ -290,15 +608,23 @@ struct ClientService {
     limit: u32,
     /// The connection that is used for underlying HTTP/2 communication.
     conn: ClientConnection,
+    /// The ID of the current connection.
+    conn_id: ConnectionId,
+    /// Whether the current connection is still usable.
+    connected: bool,
+    /// Shuts down the socket of the current connection.
+    closer: Option<Box<FnMut() + Send>>,
     /// The handle allows the service to get the HTTP/2 frame that has been extracted from the data
     /// read from the socket on another thread.
     recv_handle: ChannelFrameReceiverHandle,
     /// The handle allows the service to queue HTTP/2 frames for another thread to push out on a
     /// blocking socket.
-    send_handle: ChannelFrameSenderHandle,
-    /// A mapping of stream IDs to the sender side of a channel that is
-    /// expecting a response to the request that is to arrive on that stream.
-    chans: HashMap<StreamId, Sender<StaticResponse>>,
+    send_handle: FrameQueueHandle,
+    /// The limits of the queue of outgoing frames, applied to each new connection.
+    queue_limits: QueueLimits,
+    /// A mapping of stream IDs to the requests that are expecting a response
+    /// that is to arrive on that stream.
+    chans: HashMap<StreamId, AsyncRequest>,
     /// The receiver end of a channel to which work items for the service are
     /// queued. Work items include the variants of the `WorkItem` enum.
     work_queue: Receiver<WorkItem>,
@@ -311,71 +637,51 @@ struct ClientService {
     host: Vec<u8>,
     /// Whether the connection has already been initialized.
     initialized: bool,
+    /// The state of the connection that is observable by the clients.
+    shared: Arc<ConnectionState>,
+    /// Establishes a new connection once the current one fails, if the service should reconnect.
+    reconnect: Option<Reconnect>,
+    /// Determines the backoff between consecutive attempts to reconnect.
+    policy: ReconnectPolicy,
+    /// The number of consecutive failed attempts to reconnect.
+    failed_attempts: u32,
+    /// The point in time at which the next attempt to reconnect is due, if any.
+    reconnect_at: Option<Instant>,
 }
 
-/// A helper wrapper around the components of the `ClientService` that are returned from its
-/// constructor.
-struct Service<S>(ClientService, Sender<WorkItem>, ChannelFrameReceiver<S>, ChannelFrameSender<S>)
-    where S: TransportStream;
-
 impl ClientService {
-    /// Creates a new `ClientService` that will use the provided `ClientStream` for its underlying
-    /// network communication. A handle is returned for both the read, as well as the write end of
-    /// the socket that allows the client that creates the `ClientService` to perform the blocking
-    /// IO without influencing the `ClientService` (i.e. without having its `run_once` method
-    /// block).
-    ///
-    /// # Returns
-    ///
-    /// Returns all the relevant components of the newly created `ClientService`:
-    ///
-    /// - The `ClientService` itself -- processes events (`WorkItem`s) on each `run_once` call.
-    /// - The sender-side of the work queue -- allows `WorkItem`s to be queued into the
-    ///   `ClientService`'s simplified event loop.
-    /// - The `ChannelFrameReceiver` -- the instance that wraps the actual socket that performs
-    ///   the blocking read IO. Allows the caller to block on the IO in a customized manner (e.g.
-    ///   in a separate dedicated thread).
-    /// - The `ChannelFrameSender` -- the instance that wraps the actual socket that performs the
-    ///   blocking write IO. Allows the caller to block on the IO in a customized manner (e.g. in
-    ///   a separate thread).
-    ///
-    /// If no HTTP/2 connection can be established to the given host on the
-    /// given port, returns `None`.
-    pub fn new<S>(client_stream: ClientStream<S>) -> Service<S>
-        where S: TransportStream
-    {
-        let (tx, rx): (Sender<WorkItem>, Receiver<WorkItem>) = mpsc::channel();
-        let ClientStream(stream, scheme, host) = client_stream;
-
-        // Manually split the stream into the write/read ends, so that we can...
-        let sender = stream.try_split().unwrap();
-        let receiver = stream;
-        // ...wrap them into the adapters...
-        let (recv_frame, recv_handle) = ChannelFrameReceiver::new(receiver);
-        let (send_frame, send_handle) = ChannelFrameSender::new(sender);
-
-        // ...and pass the non-blocking/buffering ends into the `HttpConnect` instead of the
-        // blocking socket itself.
-        let conn = ClientConnection::with_connection(HttpConnection::new(scheme),
-                                                     DefaultSessionState::<ClientMarker, _>::new());
-
-        let service = ClientService {
+    /// Creates a new `ClientService` that will use the provided `Connection` for its underlying
+    /// network communication and process the `WorkItem`s received on the given work queue.
+    fn new(connection: Connection, work_queue: Receiver<WorkItem>) -> ClientService {
+        ClientService {
             outstanding_reqs: 0,
-            limit: 3,
-            conn: conn,
+            limit: DEFAULT_LIMIT,
+            conn: connection.conn,
+            conn_id: 0,
+            connected: true,
+            closer: connection.closer,
             chans: HashMap::new(),
-            work_queue: rx,
-            recv_handle: recv_handle,
-            send_handle: send_handle,
+            work_queue: work_queue,
+            recv_handle: connection.recv_handle,
+            send_handle: connection.send_handle,
+            queue_limits: QueueLimits::default(),
             request_queue: Vec::new(),
             client_count: 0,
-            host: host.as_bytes().to_vec(),
+            host: connection.host,
             initialized: false,
-        };
+            shared: Arc::new(ConnectionState::new()),
+            reconnect: None,
+            policy: ReconnectPolicy::default(),
+            failed_attempts: 0,
+            reconnect_at: None,
+        }
+    }
 
-        // Returns the handles to the channel sender/receiver, so that the client can use them to
-        // perform the real IO somewhere.
-        Service(service, tx, recv_frame, send_frame)
+    /// Makes the service use the given function to establish a new connection once the current
+    /// one fails, instead of stopping.
+    fn set_reconnect(&mut self, reconnect: Reconnect, policy: ReconnectPolicy) {
+        self.reconnect = Some(reconnect);
+        self.policy = policy;
     }
 
     /// Performs one iteration of the service.
@@ -399,6 +705,18 @@ impl ClientService {
     /// handling of the frame, it is shipped to the channel that expects it and
     /// a new request from the request queue sent.
     ///
+    /// For `WorkItem::Cancel` work items, the service drops the request from
+    /// its queue or, if it has already been sent, resets its stream.
+    ///
+    /// Requests whose deadline has passed are abandoned in the same way and their
+    /// channel notified of the timeout. While any request has a deadline, the
+    /// wait for new work is bounded so that the deadline is honored.
+    ///
+    /// Once the connection fails, the requests that it was processing are
+    /// failed, except for the ones that the server is known not to have
+    /// processed. If the service is able to reconnect, those are kept queued
+    /// and a new connection is established once the backoff elapses.
+    ///
     /// # Returns
     ///
     /// On a successful pass, the function returns an `Ok(())`.
@@ -408,15 +726,54 @@ impl ClientService {
     /// thus no more requests could ever be issued by the instance). This
     /// corresponds to the `ClientServiceErr::Done` variant.
     ///
-    /// Any HTTP/2 error is propagated (wrapped into a ClientServiceErr::Http
-    /// variant).
+    /// Any HTTP/2 error that the service cannot recover from by reconnecting
+    /// is propagated (wrapped into a ClientServiceErr::Http variant), after
+    /// all requests have been notified of it.
     pub fn run_once(&mut self) -> Result<(), ClientServiceErr> {
-        let work_item = match self.work_queue.recv() {
+        match self.run_next() {
+            Err(ClientServiceErr::Http(err)) if self.connected => self.on_connection_failed(err),
+            res => res,
+        }
+    }
+
+    /// Internal helper method. Performs the next piece of work that is due, as described by the
+    /// `run_once` method, without handling the failures of the connection.
+    fn run_next(&mut self) -> Result<(), ClientServiceErr> {
+        let now = Instant::now();
+        // Reconnecting and abandoning requests whose deadline has passed take precedence over any
+        // queued work.
+        if self.reconnect_at.map_or(false, |at| at <= now) {
+            return self.try_reconnect();
+        }
+        if self.next_deadline().map_or(false, |deadline| deadline <= now) {
+            return self.expire_requests();
+        }
+
+        let wake_at = match (self.next_deadline(), self.reconnect_at) {
+            (Some(deadline), Some(at)) => Some(cmp::min(deadline, at)),
+            (deadline, None) => deadline,
+            (None, at) => at,
+        };
+        let received = match wake_at {
+            Some(at) => {
+                let timeout = if at > now {
+                    at - now
+                } else {
+                    Duration::from_millis(0)
+                };
+                self.work_queue.recv_timeout(timeout)
+            }
+            None => self.work_queue.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let work_item = match received {
             Ok(item) => item,
+            // Nothing arrived before the wake up time, so whatever was due at that point is
+            // handled on the next pass.
+            Err(RecvTimeoutError::Timeout) => return Ok(()),
             // The receive operation can only fail if the sender has
             // disconnected implying no further receives are possible.
             // At that point, we make sure to gracefully stop the service.
-            Err(_) => return Err(ClientServiceErr::Done),
+            Err(RecvTimeoutError::Disconnected) => return Err(ClientServiceErr::Done),
         };
 
         // Dispatch the work to the corresponding method...
@@ -424,27 +781,36 @@ impl ClientService {
             WorkItem::Request(async_req) => {
                 debug!("Queuing request");
                 self.request_queue.push(async_req);
-                self.queue_next_request();
-                Ok(())
+                self.queue_next_request()
             }
-            WorkItem::HandleFrame => {
+            // Work items of a connection that has been replaced (or failed) are ignored.
+            WorkItem::HandleFrame(id) |
+            WorkItem::SendData(id) |
+            WorkItem::ConnectionFailed(id, _) if id != self.conn_id || !self.connected => Ok(()),
+            WorkItem::HandleFrame(_) => {
                 if self.initialized {
                     self.handle_frame()
                 } else {
                     try!(self.conn.expect_settings(&mut self.recv_handle, &mut self.send_handle));
                     self.initialized = true;
+                    self.update_limit();
                     Ok(())
                 }
             }
-            WorkItem::SendData => {
+            WorkItem::SendData(_) => {
                 debug!("Will queue some request data");
                 try!(self.conn.send_next_data(&mut self.send_handle));
                 Ok(())
             },
-            WorkItem::SendPing => {
-                self.send_ping();
+            WorkItem::SendPing => self.send_ping(),
+            WorkItem::SetQueueLimits(limits) => {
+                self.send_handle.set_limits(limits);
+                self.queue_limits = limits;
                 Ok(())
-            },
+            }
+            WorkItem::Cancel(id) => self.abort_request(id, RequestError::Cancelled),
+            WorkItem::Reprioritize(id, priority) => self.reprioritize_request(id, priority),
+            WorkItem::ConnectionFailed(_, err) => Err(ClientServiceErr::Http(err)),
             WorkItem::NewClient => {
                 self.on_new_client();
                 Ok(())
@@ -473,65 +839,288 @@ impl ClientService {
         // Handles the next frame...
         debug!("Handling next frame");
         try!(self.conn.handle_next_frame(&mut self.recv_handle, &mut self.send_handle));
+        // ...adopting the server's limit on concurrent streams, if it has changed...
+        self.update_limit();
         // ...and then any connections that may have been closed in the meantime
         // are converted to responses and notifications sent to appropriate
         // channels.
         self.handle_closed();
         // At this point we try to queue another outstanding request (if the
         // limit has not been reached).
-        self.queue_next_request();
+        self.queue_next_request()
+    }
+
+    /// Internal helper method. Adopts the limit on concurrent streams that the server has
+    /// advertised, if any, as the limit of concurrently outstanding requests.
+    fn update_limit(&mut self) {
+        if let Some(max) = self.conn.peer_max_concurrent_streams() {
+            self.limit = max;
+            self.shared.max_concurrent_streams.store(max as usize, Ordering::SeqCst);
+        }
+        self.shared.settings_received.store(true, Ordering::SeqCst);
+    }
+
+    /// Internal helper method. Handles the failure of the current connection with the given
+    /// error.
+    ///
+    /// Requests on streams that the server is known not to have processed (i.e. the ones above
+    /// the last stream ID reported by its GOAWAY frame) are put back at the front of the queue, if
+    /// the service can reconnect, while all other in-flight requests are failed. If the service
+    /// cannot reconnect, all requests are failed and the error propagated.
+    fn on_connection_failed(&mut self, err: HttpError) -> Result<(), ClientServiceErr> {
+        debug!("Connection failed: {:?}", err);
+        self.close_connection();
+
+        if self.reconnect.is_none() {
+            self.fail_requests(&err);
+            return Err(ClientServiceErr::Http(err));
+        }
+
+        let last_processed = match err {
+            HttpError::PeerConnectionError(ref err) => err.last_stream_id(),
+            _ => None,
+        };
+        let mut stream_ids: Vec<StreamId> = self.chans.keys().cloned().collect();
+        stream_ids.sort();
+        let mut retried = Vec::new();
+        for stream_id in stream_ids {
+            let req = self.chans.remove(&stream_id).unwrap();
+            if last_processed.map_or(false, |last| stream_id > last) {
+                debug!("Retrying request {} on a new connection", req.id);
+                retried.push(req);
+            } else {
+                self.shared.complete(&req.tx, Err((&err).into()));
+            }
+        }
+        self.outstanding_reqs = 0;
+        retried.extend(self.request_queue.drain(..));
+        self.request_queue = retried;
+
+        self.shared.set_status(ConnectionStatus::Reconnecting);
+        self.schedule_reconnect();
+        Ok(())
+    }
+
+    /// Internal helper method. Schedules the next attempt to reconnect, according to the backoff
+    /// of the reconnect policy.
+    fn schedule_reconnect(&mut self) {
+        let backoff = self.policy.backoff(self.failed_attempts);
+        debug!("Reconnecting in {:?}", backoff);
+        self.reconnect_at = Some(Instant::now() + backoff);
+    }
+
+    /// Internal helper method. Attempts to establish a new connection.
+    ///
+    /// On success, the queued requests are sent on the new connection. Otherwise, another attempt
+    /// is scheduled, unless the reconnect policy allows no more attempts, in which case all
+    /// requests are failed and the error propagated.
+    fn try_reconnect(&mut self) -> Result<(), ClientServiceErr> {
+        self.reconnect_at = None;
+        let id = self.conn_id + 1;
+        let res = match self.reconnect {
+            Some(ref mut reconnect) => reconnect(id),
+            None => return Ok(()),
+        };
+        match res {
+            Ok(connection) => {
+                debug!("Reconnected");
+                self.conn = connection.conn;
+                self.conn_id = id;
+                self.connected = true;
+                self.closer = connection.closer;
+                self.recv_handle = connection.recv_handle;
+                self.send_handle = connection.send_handle;
+                self.send_handle.set_limits(self.queue_limits);
+                self.host = connection.host;
+                self.initialized = false;
+                self.limit = DEFAULT_LIMIT;
+                self.shared.max_concurrent_streams.store(usize::MAX, Ordering::SeqCst);
+                self.shared.settings_received.store(false, Ordering::SeqCst);
+                self.failed_attempts = 0;
+                self.shared.set_status(ConnectionStatus::Connected);
+
+                // Send as many of the requests queued in the meantime as the limit allows.
+                while self.outstanding_reqs < self.limit && !self.request_queue.is_empty() {
+                    try!(self.queue_next_request());
+                }
+                Ok(())
+            }
+            Err(err) => {
+                self.failed_attempts += 1;
+                debug!("Reconnect attempt {} failed: {:?}", self.failed_attempts, err);
+                if self.policy.max_attempts.map_or(false, |max| self.failed_attempts >= max) {
+                    self.fail_requests(&err);
+                    return Err(ClientServiceErr::Http(err));
+                }
+                self.schedule_reconnect();
+                Ok(())
+            }
+        }
+    }
+
+    /// Internal helper method. Shuts down the current connection, making the service ignore any
+    /// further work items that belong to it.
+    fn close_connection(&mut self) {
+        self.connected = false;
+        if let Some(mut closer) = self.closer.take() {
+            closer();
+        }
+    }
+
+    /// Notifies all requests that are still queued or awaiting a response that they will not
+    /// receive one, since the connection failed with the given error.
+    fn fail_requests(&mut self, err: &HttpError) {
+        self.shutdown();
+        for async_req in self.request_queue.drain(..) {
+            self.shared.complete(&async_req.tx, Err(err.into()));
+        }
+        for (_, req) in self.chans.drain() {
+            self.shared.complete(&req.tx, Err(err.into()));
+        }
+        self.outstanding_reqs = 0;
+    }
+
+    /// Marks the connection as no longer usable for new requests and shuts it down.
+    pub fn shutdown(&mut self) {
+        self.shared.set_status(ConnectionStatus::Closed);
+        self.close_connection();
+    }
+
+    /// Internal helper method. Returns the earliest deadline of all requests that are either
+    /// queued or awaiting a response.
+    fn next_deadline(&self) -> Option<Instant> {
+        let queued = self.request_queue.iter().filter_map(|req| req.deadline);
+        let pending = self.chans.values().filter_map(|req| req.deadline);
+        queued.chain(pending).min()
+    }
+
+    /// Internal helper method. Abandons all requests whose deadline has passed, notifying their
+    /// channels of the timeout.
+    fn expire_requests(&mut self) -> Result<(), ClientServiceErr> {
+        let now = Instant::now();
+        let expired: Vec<RequestId> = {
+            let queued = self.request_queue.iter().map(|req| (req.id, req.deadline));
+            let pending = self.chans.values().map(|req| (req.id, req.deadline));
+            queued.chain(pending)
+                  .filter(|&(_, deadline)| deadline.map_or(false, |deadline| deadline <= now))
+                  .map(|(id, _)| id)
+                  .collect()
+        };
+        for id in expired {
+            debug!("Request {} timed out", id);
+            try!(self.abort_request(id, RequestError::Timeout));
+        }
+        Ok(())
+    }
+
+    /// Internal helper method. Abandons the request with the given ID and notifies its channel
+    /// with the given error.
+    ///
+    /// A request that has not yet been sent is simply dropped from the queue. Otherwise, its
+    /// stream is reset with a RST_STREAM frame carrying the `Cancel` error code and removed from
+    /// the session state, which frees up the slot for the next queued request.
+    ///
+    /// Requests that are no longer known to the service (e.g. because their response has already
+    /// been delivered) are ignored.
+    fn abort_request(&mut self, id: RequestId, err: RequestError) -> Result<(), ClientServiceErr> {
+        if let Some(pos) = self.request_queue.iter().position(|req| req.id == id) {
+            let async_req = self.request_queue.remove(pos);
+            self.shared.complete(&async_req.tx, Err(err));
+            return Ok(());
+        }
+
+        let stream_id = match self.chans.iter().find(|&(_, req)| req.id == id) {
+            Some((&stream_id, _)) => stream_id,
+            None => return Ok(()),
+        };
+        debug!("Resetting stream {} of request {}", stream_id, id);
+        try!(self.conn.reset_stream(stream_id, ErrorCode::Cancel, &mut self.send_handle));
+        if let Some(req) = self.chans.remove(&stream_id) {
+            self.shared.complete(&req.tx, Err(err));
+        }
+        self.outstanding_reqs -= 1;
+
+        self.queue_next_request()
+    }
+
+    /// Internal helper method. Changes the priority of the request with the given ID.
+    ///
+    /// The new priority of a request that has not yet been sent is simply recorded, so that it is
+    /// sent along with the request's headers. Otherwise, a PRIORITY frame is sent for its stream.
+    ///
+    /// Requests that are no longer known to the service are ignored.
+    fn reprioritize_request(&mut self,
+                            id: RequestId,
+                            priority: StreamDependency)
+                            -> Result<(), ClientServiceErr> {
+        if let Some(req) = self.request_queue.iter_mut().find(|req| req.id == id) {
+            req.request.priority = Some(priority);
+            return Ok(());
+        }
 
+        let stream_id = match self.chans.iter_mut().find(|&(_, ref req)| req.id == id) {
+            Some((&stream_id, req)) => {
+                // Should the request need to be sent again, it keeps its latest priority.
+                req.request.priority = Some(priority.clone());
+                stream_id
+            }
+            None => return Ok(()),
+        };
+        debug!("Reprioritizing stream {} of request {}", stream_id, id);
+        try!(self.conn.reprioritize(stream_id, priority, &mut self.send_handle));
         Ok(())
     }
 
     /// Internal helper method. Sends a request to the server based on the
     /// parameters given in the `AsyncRequest`. It blocks until the request is
     /// fully transmitted to the server.
-    fn send_request(&mut self, async_req: AsyncRequest) {
-        let (req, tx) = self.create_request(async_req);
+    ///
+    /// If the request cannot be sent, its channel is notified and the error propagated.
+    fn send_request(&mut self, async_req: AsyncRequest) -> Result<(), ClientServiceErr> {
+        let req = self.create_request(&async_req);
 
         trace!("Sending new request...");
 
-        let stream_id = self.conn.start_request(req, &mut self.send_handle).ok().unwrap();
+        let stream_id = match self.conn.start_request(req, &mut self.send_handle) {
+            Ok(stream_id) => stream_id,
+            Err(err) => {
+                self.shared.complete(&async_req.tx, Err((&err).into()));
+                return Err(err.into());
+            }
+        };
         // The ID has been assigned to the stream, so attach it to the stream instance too.
         // TODO(mlalic): The `Stream` trait should grow an `on_id_assigned` method which can
         //               then be called by the session (i.e. the `ClientConnection` in this case).
         self.conn.state.get_stream_mut(stream_id).unwrap().stream_id = Some(stream_id);
 
-        self.chans.insert(stream_id, tx);
+        self.chans.insert(stream_id, async_req);
         self.outstanding_reqs += 1;
+
+        Ok(())
     }
 
     /// Internal helper method. Creates a new `RequestStream` instance based on the
     /// given parameters. Such a `RequestStream` instance is ready to be passed to
     /// the connection for transmission to the server (i.e. `start_request`).
-    /// Also returns the sender end of the channel to which the response is to be transmitted,
-    /// once received.
+    ///
+    /// The `AsyncRequest` itself is left intact, so that the request can be sent again, should
+    /// the connection fail before the server processes it.
     fn create_request(&self,
-                      async_req: AsyncRequest)
-                      -> (RequestStream<'static, 'static, DefaultStream>,
-                          Sender<StaticResponse>) {
-        let mut headers: Vec<Header> = Vec::new();
-        headers.extend(vec![
-            Header::new(b":method", async_req.method),
-            Header::new(b":path", async_req.path),
-            Header::new(b":authority", self.host.clone()),
-            Header::new(b":scheme", self.conn.scheme().as_bytes().to_vec()),
-        ]
-                           .into_iter());
-        headers.extend(async_req.headers.into_iter());
+                      async_req: &AsyncRequest)
+                      -> RequestStream<'static, 'static, DefaultStream> {
+        let headers = async_req.request.header_list(self.conn.scheme(), &self.host);
 
         let mut stream = DefaultStream::new();
-        match async_req.body {
-            Some(body) => stream.set_full_data(body),
+        match async_req.request.body {
+            Some(ref body) => stream.set_full_data(body.clone()),
             None => stream.close_local(),
         };
 
-        (RequestStream {
+        RequestStream {
             stream: stream,
             headers: headers,
-        },
-         async_req.tx)
+            priority: async_req.request.priority.clone(),
+        }
     }
 
     /// Internal helper method. Sends a response assembled from the given
@@ -546,21 +1135,46 @@ impl ClientService {
                 // a response that we didn't request.
                 panic!("Received a response for an unknown request!");
             }
-            Some(tx) => {
-                let _ = tx.send(Response {
-                    stream_id: stream_id,
-                    headers: stream.headers.unwrap(),
-                    body: stream.body,
-                });
+            Some(req) => {
+                let response = ClientService::response_from_stream(stream_id, stream);
+                self.shared.complete(&req.tx, response);
             }
         };
     }
 
+    /// Internal helper method. Assembles the outcome of the request from the given closed stream.
+    ///
+    /// A stream that the server reset results in an error, unless it was reset with `NoError`
+    /// after the response was received (as the server is allowed to do in order to stop the
+    /// client from sending the rest of the request body). A response without a valid `:status`
+    /// pseudo-header is considered malformed.
+    fn response_from_stream(stream_id: StreamId, stream: DefaultStream) -> ResponseResult {
+        if let Some(err) = stream.content_length_error {
+            return Err(RequestError::ContentLength(err));
+        }
+        if stream.malformed_headers.is_some() {
+            return Err(RequestError::MalformedResponse);
+        }
+        let headers = match (stream.error_code, stream.headers) {
+            (Some(ErrorCode::RefusedStream), _) => return Err(RequestError::Refused),
+            (Some(ErrorCode::NoError), Some(headers)) |
+            (None, Some(headers)) => headers,
+            (Some(error_code), _) => return Err(RequestError::StreamReset(error_code)),
+            (None, None) => return Err(RequestError::MalformedResponse),
+        };
+        let response = Response {
+            stream_id: stream_id,
+            headers: headers,
+            body: stream.body,
+        };
+        match response.status_code() {
+            Ok(_) => Ok(response),
+            Err(_) => Err(RequestError::MalformedResponse),
+        }
+    }
+
     /// Internal helper method. Handles all closed streams by sending appropriate
     /// notifications to waiting channels.
-    ///
-    /// For now, the channels are all given a `Response`, even though the
-    /// stream might end up being closed by the server with an error.
     fn handle_closed(&mut self) {
         let done = self.conn.state.get_closed();
         for stream in done {
@@ -573,21 +1187,29 @@ impl ClientService {
     /// client to the service and the service has not exceeded the limit of
     /// concurrent requests that it is allowed to issue, it sends a single
     /// new request to the server. Blocks until this request is sent.
-    fn queue_next_request(&mut self) {
-        if self.outstanding_reqs < self.limit {
+    ///
+    /// While the service is reconnecting, the requests are kept queued.
+    fn queue_next_request(&mut self) -> Result<(), ClientServiceErr> {
+        if self.connected && self.outstanding_reqs < self.limit {
             // Try to queue another request since we haven't gone over
             // the (arbitrary) limit.
             debug!("Not over the limit yet. Checking for more requests...");
             if !self.request_queue.is_empty() {
                 let async_req = self.request_queue.remove(0);
-                self.send_request(async_req);
+                try!(self.send_request(async_req));
             }
         }
+        Ok(())
     }
 
-    /// Internal helper method to send a PING frame to the server
-    fn send_ping(&mut self) {
-        self.conn.send_ping(&mut self.send_handle).ok().unwrap();
+    /// Internal helper method to send a PING frame to the server. While the service is
+    /// reconnecting, there is no server to send it to, so it is dropped.
+    fn send_ping(&mut self) -> Result<(), ClientServiceErr> {
+        if !self.connected {
+            return Ok(());
+        }
+        try!(self.conn.send_ping(&mut self.send_handle));
+        Ok(())
     }
 }
 
@@ -631,12 +1253,21 @@ pub struct Client {
     /// The sender side of a channel on which a running `ClientService` expects
     /// to receive new requests, which are to be sent to the server.
     sender: Sender<WorkItem>,
+    /// The source of request IDs, shared by all clones of the client, so that
+    /// the IDs are unique across the whole connection.
+    next_request_id: Arc<AtomicUsize>,
+    /// The state of the underlying connection, as reported by the `ClientService`.
+    state: Arc<ConnectionState>,
 }
 
 impl Clone for Client {
     fn clone(&self) -> Client {
         self.sender.send(WorkItem::NewClient).unwrap();
-        Client { sender: self.sender.clone() }
+        Client {
+            sender: self.sender.clone(),
+            next_request_id: self.next_request_id.clone(),
+            state: self.state.clone(),
+        }
     }
 }
 
@@ -673,48 +1304,75 @@ impl Client {
         // Use the provided connector to establish a network connection...
         let client_stream = try!(connector.connect());
 
-        // Keep a socket handle in order to shut it down once the service stops. This is required
-        // because if the service decides to stop (due to all clients disconnecting) while the
-        // socket is still open and the read thread waiting, it can happen that the read thread
-        // (and as such the socket itself) ends up waiting indefinitely (or well, until the server
-        // decides to close it), effectively leaking the socket and thread.
-        let mut sck = try!(client_stream.0.try_split());
+        Ok(try!(Client::with_stream(client_stream)))
+    }
 
-        let service = ClientService::new(client_stream);
+    /// Creates a new HTTP/2 client that will use the given, already established, `ClientStream`
+    /// for its underlying connection.
+    ///
+    /// It assumes that the stream has already been initialized for HTTP/2 communication (by
+    /// having the required protocol negotiation done and writing the client preface), as done by
+    /// `HttpConnect` implementations. Otherwise, it behaves the same as `with_connector`.
+    pub fn with_stream<S>(client_stream: ClientStream<S>) -> io::Result<Client>
+        where S: TransportStream + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let connection = try!(Connection::start(client_stream, &tx, 0));
+        Ok(Client::spawn_service(ClientService::new(connection, rx), tx))
+    }
 
-        let Service(mut service, rx, mut recv_frame, mut send_frame) = service;
+    /// Creates a new HTTP/2 client that establishes its connection to the origin identified by the
+    /// given `key` by using the given `ConnectorFactory`.
+    ///
+    /// Unlike the clients created by `with_connector`, the returned client survives failures of
+    /// its connection: it uses the factory to reconnect, delaying the attempts according to the
+    /// given `ReconnectPolicy`. Requests issued while the client is reconnecting are queued and
+    /// sent once the new connection is established. Of the requests in flight when the connection
+    /// failed, only the ones that the server is known not to have processed (as reported by its
+    /// GOAWAY frame) are retried, while the others fail.
+    ///
+    /// If the initial connection cannot be established, returns an error. Once the policy's
+    /// `max_attempts` consecutive attempts to reconnect fail, the client is closed.
+    pub fn with_factory<F>(factory: F,
+                           key: PoolKey,
+                           policy: ReconnectPolicy)
+                           -> Result<Client, ClientConnectError<F::Err>>
+        where F: ConnectorFactory + Send + 'static
+    {
+        let (tx, rx) = mpsc::channel();
+        let client_stream = try!(factory.connect(&key));
+        let connection = try!(Connection::start(client_stream, &tx, 0));
+
+        let work_queue = tx.clone();
+        let reconnect: Reconnect = Box::new(move |id| {
+            let client_stream = try!(factory.connect(&key));
+            Ok(try!(Connection::start(client_stream, &work_queue, id)))
+        });
+        let mut service = ClientService::new(connection, rx);
+        service.set_reconnect(reconnect, policy);
 
-        service.on_new_client();
+        Ok(Client::spawn_service(service, tx))
+    }
 
-        // Keep a handle to the work queue to notify the service of newly read frames, making it so
-        // that it never blocks on waiting for frames to read.
-        let read_notify = rx.clone();
-        let sender_work_queue = rx.clone();
+    /// Internal helper method. Spawns the thread that runs the given service until all clients
+    /// disconnect or the connection fails irrecoverably and returns the first `Client` of the
+    /// service.
+    fn spawn_service(mut service: ClientService, sender: Sender<WorkItem>) -> Client {
+        service.on_new_client();
+        let state = service.shared.clone();
 
         spawn_named("Solicit Service", move || {
             while let Ok(_) = service.run_once() {}
             debug!("Service thread halting");
-            // This is the one place where it's okay to unwrap, as if the shutdown fails, there's
-            // really nothing we can do to recover at this point...
-            // This forces the reader thread to stop, as the socket is no longer operational.
-            sck.close().expect("close socket handler");
-        });
-
-        spawn_named("Solicit Sender", move || {
-            while let Ok(_) = send_frame.send_next() {
-                sender_work_queue.send(WorkItem::SendData).unwrap();
-            }
-            debug!("Sender thread halting");
+            // This forces the IO threads to stop, as the socket is no longer operational.
+            service.shutdown();
         });
 
-        spawn_named("Solicit Reader", move || {
-            while let Ok(_) = recv_frame.read_next() {
-                read_notify.send(WorkItem::HandleFrame).unwrap();
-            }
-            debug!("Reader thread halting");
-        });
-
-        Ok(Client { sender: rx })
+        Client {
+            sender: sender,
+            next_request_id: Arc::new(AtomicUsize::new(0)),
+            state: state,
+        }
     }
 
     /// Issues a new request to the server.
@@ -727,10 +1385,10 @@ impl Client {
     /// The method itself returns immediately upon queuing the request. It does
     /// not wait for the request to be transmitted nor for the response to
     /// arrive. Once the caller is interested in the final response, they can
-    /// block on the returned `Receiver` end of a channel which will receive
-    /// the response once generated.
+    /// block on the returned `RequestHandle`, which will receive the response
+    /// once generated. The handle can also be used to cancel the request.
     ///
-    /// The `Response` instance that the channel receives will contain the full
+    /// The `Response` instance that the handle receives will contain the full
     /// response body and is available only once the full response body has
     /// been received.
     ///
@@ -742,49 +1400,222 @@ impl Client {
                    path: &[u8],
                    headers: &[StaticHeader],
                    body: Option<Vec<u8>>)
-                   -> Option<Receiver<StaticResponse>> {
-        let (resp_tx, resp_rx): (Sender<StaticResponse>, Receiver<StaticResponse>) =
-            mpsc::channel();
-        // A send can only fail if the receiver is disconnected. If the send
-        // fails here, it means that the service hit an error on the underlying
-        // HTTP/2 connection and will never come alive again.
-        let res = self.sender.send(WorkItem::Request(AsyncRequest {
-            method: method.to_vec(),
-            path: path.to_vec(),
-            headers: headers.to_vec(),
-            body: body,
-            tx: resp_tx,
-        }));
+                   -> Option<RequestHandle> {
+        self.queue_request(Client::build_request(method, path, headers, body), None)
+    }
 
-        match res {
-            Ok(_) => Some(resp_rx),
-            Err(_) => None,
-        }
+    /// Issues the given `Request` to the server.
+    ///
+    /// Unless the request specifies its own scheme and authority (e.g. by being built from an
+    /// absolute URI), the ones of the client's connection are used. Otherwise, it behaves the
+    /// same as the `request` method.
+    pub fn send(&self, request: Request) -> Option<RequestHandle> {
+        self.queue_request(request, None)
+    }
+
+    /// Issues the given `Request` to the server, abandoning it if its response is not received
+    /// within the given `timeout`, as with the `request_with_timeout` method.
+    pub fn send_with_timeout(&self, request: Request, timeout: Duration) -> Option<RequestHandle> {
+        self.queue_request(request, Some(Instant::now() + timeout))
+    }
+
+    /// Issues a new request to the server that is abandoned if its response is not received
+    /// within the given `timeout`.
+    ///
+    /// Once the timeout elapses, the request's stream is reset (with the `Cancel` error code) and
+    /// the returned `RequestHandle` receives a `RequestError::Timeout`. Otherwise, it behaves the
+    /// same as the `request` method.
+    pub fn request_with_timeout(&self,
+                                method: &[u8],
+                                path: &[u8],
+                                headers: &[StaticHeader],
+                                body: Option<Vec<u8>>,
+                                timeout: Duration)
+                                -> Option<RequestHandle> {
+        self.queue_request(Client::build_request(method, path, headers, body),
+                           Some(Instant::now() + timeout))
     }
 
     /// Issues a GET request to the server.
     ///
     /// A convenience wrapper around the `request` method that sets the correct
     /// method.
-    pub fn get(&self, path: &[u8], headers: &[StaticHeader]) -> Option<Receiver<StaticResponse>> {
+    pub fn get(&self, path: &[u8], headers: &[StaticHeader]) -> Option<RequestHandle> {
         self.request(b"GET", path, headers, None)
     }
 
     /// Issues a POST request to the server.
     ///
-    /// Returns the receiving end of a channel where the `Response` will eventually be pushed.
+    /// Returns the `RequestHandle` on which the `Response` will eventually be received.
     pub fn post(&self,
                 path: &[u8],
                 headers: &[StaticHeader],
                 body: Vec<u8>)
-                -> Option<Receiver<StaticResponse>> {
+                -> Option<RequestHandle> {
         self.request(b"POST", path, headers, Some(body))
     }
 
+    /// Internal helper method. Assembles a `Request` from its raw parts.
+    fn build_request(method: &[u8],
+                     path: &[u8],
+                     headers: &[StaticHeader],
+                     body: Option<Vec<u8>>)
+                     -> Request {
+        let mut request = Request::new(Method::from(method), path);
+        request.headers = headers.to_vec();
+        request.body = body;
+        request
+    }
+
+    /// Internal helper method. Queues a new request with the given deadline to the service.
+    fn queue_request(&self, request: Request, deadline: Option<Instant>) -> Option<RequestHandle> {
+        let (resp_tx, resp_rx): (Sender<ResponseResult>, Receiver<ResponseResult>) =
+            mpsc::channel();
+        let id = self.next_request_id.fetch_add(1, Ordering::SeqCst);
+        // The request is accounted for before it reaches the service, so that it is immediately
+        // reflected by the `active_requests` method.
+        self.state.active_reqs.fetch_add(1, Ordering::SeqCst);
+        // A send can only fail if the receiver is disconnected. If the send
+        // fails here, it means that the service hit an error on the underlying
+        // HTTP/2 connection and will never come alive again.
+        let res = self.sender.send(WorkItem::Request(AsyncRequest {
+            id: id,
+            request: request,
+            deadline: deadline,
+            tx: resp_tx,
+        }));
+
+        match res {
+            Ok(_) => {
+                Some(RequestHandle {
+                    id: id,
+                    rx: resp_rx,
+                    sender: self.sender.clone(),
+                    finished: Cell::new(false),
+                })
+            }
+            Err(_) => {
+                self.state.active_reqs.fetch_sub(1, Ordering::SeqCst);
+                None
+            }
+        }
+    }
+
+    /// Returns the number of requests issued by this client (or any of its clones) that have not
+    /// yet completed.
+    pub fn active_requests(&self) -> usize {
+        self.state.active_reqs.load(Ordering::SeqCst)
+    }
+
+    /// Returns the maximum number of concurrent streams that the server allows on the underlying
+    /// connection, if it has advertised one.
+    ///
+    /// Requests issued beyond this limit are queued until a previous request completes.
+    pub fn max_concurrent_streams(&self) -> Option<usize> {
+        match self.state.max_concurrent_streams.load(Ordering::SeqCst) {
+            usize::MAX => None,
+            max => Some(max),
+        }
+    }
+
+    /// Returns whether the server's SETTINGS have been received on the underlying connection. Until
+    /// they are, it is not known whether the server limits the number of concurrent streams.
+    pub fn settings_received(&self) -> bool {
+        self.state.settings_received.load(Ordering::SeqCst)
+    }
+
+    /// Returns the current status of the underlying connection.
+    pub fn status(&self) -> ConnectionStatus {
+        self.state.status()
+    }
+
+    /// Returns whether the underlying connection has been closed, either because the server
+    /// terminated it (e.g. by sending a GOAWAY frame) or because of an error, without the client
+    /// being able to reconnect. Requests issued on a closed connection fail.
+    pub fn is_closed(&self) -> bool {
+        self.status() == ConnectionStatus::Closed
+    }
+
+    /// Returns for how long the underlying connection has had no active requests. If there are
+    /// active requests, returns `None`.
+    pub fn idle_time(&self) -> Option<Duration> {
+        if self.active_requests() > 0 {
+            return None;
+        }
+        Some(self.state.idle_since.lock().unwrap().elapsed())
+    }
+
     /// Sends a PING to the server
     pub fn ping(&self) -> Result<(), &'static str> {
         self.sender.send(WorkItem::SendPing).map_err(|_| "Client not available")
     }
+
+    /// Replaces the limits of the queue of frames that are yet to be written to the server. The
+    /// limits also apply to the connections that the client reconnects with.
+    pub fn set_queue_limits(&self, limits: QueueLimits) -> Result<(), &'static str> {
+        self.sender.send(WorkItem::SetQueueLimits(limits)).map_err(|_| "Client not available")
+    }
+}
+
+/// A handle to a request issued by the `Client`.
+///
+/// It allows the owner to wait for the response to the request, as well as to cancel it.
+/// Dropping the handle before the response is received also cancels the request, resetting its
+/// stream, if it was already sent.
+pub struct RequestHandle {
+    /// The identifier of the request within the `ClientService`.
+    id: RequestId,
+    /// The receiving end of the channel on which the outcome of the request is delivered.
+    rx: Receiver<ResponseResult>,
+    /// The work queue of the `ClientService` that handles the request.
+    sender: Sender<WorkItem>,
+    /// Whether the outcome of the request has already been received.
+    finished: Cell<bool>,
+}
+
+impl RequestHandle {
+    /// Blocks until the response to the request is received.
+    ///
+    /// # Returns
+    ///
+    /// The full response, or the `RequestError` that describes why the request was abandoned.
+    pub fn recv(&self) -> Result<StaticResponse, RequestError> {
+        self.finished.set(true);
+        match self.rx.recv() {
+            Ok(res) => res,
+            Err(_) => Err(RequestError::ConnectionClosed),
+        }
+    }
+
+    /// Cancels the request.
+    ///
+    /// If the request was already sent to the server, its stream is reset with the `Cancel`
+    /// error code. A subsequent `recv` returns `RequestError::Cancelled`, unless the response has
+    /// already been delivered before the cancellation took effect.
+    pub fn cancel(&self) {
+        let _ = self.sender.send(WorkItem::Cancel(self.id));
+    }
+
+    /// Changes the priority of the request.
+    ///
+    /// If the request was already sent to the server, a PRIORITY frame is sent for its stream;
+    /// otherwise, the new priority is sent along with the request's headers. Once the response has
+    /// been received, this has no effect.
+    pub fn reprioritize(&self, priority: StreamDependency) {
+        let _ = self.sender.send(WorkItem::Reprioritize(self.id, priority));
+    }
+}
+
+impl Drop for RequestHandle {
+    fn drop(&mut self) {
+        // Only notify the service if it might still be working on the request.
+        if self.finished.get() {
+            return;
+        }
+        if let Err(mpsc::TryRecvError::Empty) = self.rx.try_recv() {
+            self.cancel();
+        }
+    }
 }
 
 /// Error that occur when creating/connecting a client
@@ -847,3 +1678,637 @@ impl<E> From<io::Error> for ClientConnectError<E>
         ClientConnectError::Io(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientService, Connection, ChannelFrameSender, ChannelFrameReceiver,
+                ChannelFrameReceiverHandle, AsyncRequest, WorkItem,
+                ResponseResult, RequestError, RequestId, ConnectionStatus, ReconnectPolicy,
+                ClientServiceErr, RequestHandle};
+
+    use std::cell::Cell;
+
+    use std::io;
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc::{self, Sender, Receiver};
+    use std::time::{Duration, Instant};
+
+    use http::{HttpScheme, HttpError, ErrorCode, ConnectionError, Header, StaticHeader, Request};
+    use http::client::{ClientStream, ClientConnection};
+    use http::connection::HttpConnection;
+    use http::connection::HttpFrame;
+    use http::frame::{Frame, RawFrame, HeadersFrame, HeadersFlag, RstStreamFrame, SettingsFrame,
+                      HttpSetting, StreamDependency};
+    use http::session::{SessionState, DefaultSessionState, Client as ClientMarker};
+    use http::queue::{FrameQueue, QueueLimits};
+    use http::tests::common::{StubTransportStream, serialize_frame};
+
+    /// The components of a `ClientService` built on top of a stub transport stream.
+    struct TestService {
+        service: ClientService,
+        work_queue: Sender<WorkItem>,
+        recv_frame: ChannelFrameReceiver<StubTransportStream>,
+        send_frame: ChannelFrameSender<StubTransportStream>,
+    }
+
+    /// Builds a new `ClientService` on top of a stub transport stream.
+    fn build_service() -> TestService {
+        let stub = StubTransportStream::with_stub_content(&[]);
+        let (work_queue, rx) = mpsc::channel();
+        let (connection, recv_frame, send_frame) =
+            Connection::new(ClientStream(stub, HttpScheme::Http, "localhost".into())).unwrap();
+        let mut service = ClientService::new(connection, rx);
+        // Skip waiting for the server preface.
+        service.initialized = true;
+        TestService {
+            service: service,
+            work_queue: work_queue,
+            recv_frame: recv_frame,
+            send_frame: send_frame,
+        }
+    }
+
+    /// Builds a new GET request with the given ID and deadline, returning it along with the
+    /// receiving end of the channel on which its outcome is delivered.
+    fn build_request(id: RequestId,
+                     deadline: Option<Instant>)
+                     -> (AsyncRequest, Receiver<ResponseResult>) {
+        let (tx, rx) = mpsc::channel();
+        let req = AsyncRequest {
+            id: id,
+            request: Request::get("/").build().unwrap(),
+            deadline: deadline,
+            tx: tx,
+        };
+        (req, rx)
+    }
+
+    impl TestService {
+        /// Queues a new request with the given ID and processes it.
+        fn request(&mut self, id: RequestId) -> Receiver<ResponseResult> {
+            let (req, rx) = build_request(id, None);
+            self.work_queue.send(WorkItem::Request(req)).unwrap();
+            self.service.run_once().ok().unwrap();
+            rx
+        }
+
+        /// Makes the service handle the given frame, as if it were received from the server.
+        fn receive(&mut self, frame: Vec<u8>) {
+            self.recv_frame.tx.send(frame.into()).unwrap();
+            self.work_queue.send(WorkItem::HandleFrame(self.service.conn_id)).unwrap();
+            self.service.run_once().ok().unwrap();
+        }
+
+        /// Returns all frames that the service has queued for sending so far.
+        fn sent_frames(&self) -> Vec<RawFrame<'static>> {
+            let mut frames = Vec::new();
+            while let Some(buf) = self.send_frame.queue.try_pop() {
+                frames.push(buf.into());
+            }
+            frames
+        }
+    }
+
+    /// Serializes a HEADERS frame with the given headers that ends the given stream.
+    fn response_headers(stream_id: u32, headers: Vec<StaticHeader>) -> Vec<u8> {
+        let mut encoder = ::hpack::Encoder::new();
+        let fragment = encoder.encode(headers.iter().map(|h| (h.name(), h.value())));
+        let mut frame = HeadersFrame::new(fragment, stream_id);
+        frame.set_flag(HeadersFlag::EndHeaders);
+        frame.set_flag(HeadersFlag::EndStream);
+        serialize_frame(&frame)
+    }
+
+    /// Asserts that the given raw frame is a RST_STREAM frame for the given stream, carrying the
+    /// `Cancel` error code.
+    fn assert_cancel(raw: &RawFrame, stream_id: u32) {
+        match HttpFrame::from_raw(raw).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), stream_id);
+                assert_eq!(frame.error_code(), ErrorCode::Cancel);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that cancelling a request that has already been sent resets its stream and frees up
+    /// the slot for the next queued request.
+    #[test]
+    fn test_cancel_sent_request() {
+        let mut test = build_service();
+        let receivers: Vec<_> = (0..4).map(|id| test.request(id)).collect();
+        // Only as many requests as the limit allows were sent.
+        assert_eq!(test.sent_frames().len(), 3);
+        assert_eq!(test.service.outstanding_reqs, 3);
+        assert_eq!(test.service.request_queue.len(), 1);
+
+        test.work_queue.send(WorkItem::Cancel(0)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        let frames = test.sent_frames();
+        // The stream is reset...
+        assert_cancel(&frames[0], 1);
+        assert!(test.service.conn.state.get_stream_ref(1).is_none());
+        // ...the request notified...
+        assert!(match receivers[0].recv().unwrap() {
+            Err(RequestError::Cancelled) => true,
+            _ => false,
+        });
+        // ...and the queued request sent in its place.
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].header().1, 0x1);
+        assert_eq!(test.service.outstanding_reqs, 3);
+        assert!(test.service.request_queue.is_empty());
+        assert!(test.service.conn.state.get_stream_ref(7).is_some());
+    }
+
+    /// Tests that cancelling a request that is still queued drops it without sending anything.
+    #[test]
+    fn test_cancel_queued_request() {
+        let mut test = build_service();
+        test.service.limit = 0;
+        let rx = test.request(0);
+
+        test.work_queue.send(WorkItem::Cancel(0)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        assert!(test.service.request_queue.is_empty());
+        assert!(test.sent_frames().is_empty());
+        assert!(match rx.recv().unwrap() {
+            Err(RequestError::Cancelled) => true,
+            _ => false,
+        });
+    }
+
+    /// Tests that cancelling an unknown request is ignored.
+    #[test]
+    fn test_cancel_unknown_request() {
+        let mut test = build_service();
+        test.work_queue.send(WorkItem::Cancel(5)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        assert!(test.sent_frames().is_empty());
+    }
+
+    /// Tests that dropping a handle cancels the request only while its outcome is still pending.
+    #[test]
+    fn test_drop_request_handle() {
+        let (work_tx, work_rx) = mpsc::channel();
+        let (_resp_tx, resp_rx) = mpsc::channel();
+        let handle = RequestHandle {
+            id: 0,
+            rx: resp_rx,
+            sender: work_tx.clone(),
+            finished: Cell::new(false),
+        };
+        drop(handle);
+        assert!(match work_rx.try_recv() {
+            Ok(WorkItem::Cancel(0)) => true,
+            _ => false,
+        });
+
+        // The service still holds on to the request's channel, but the outcome was received.
+        let (resp_tx, resp_rx) = mpsc::channel();
+        let handle = RequestHandle {
+            id: 1,
+            rx: resp_rx,
+            sender: work_tx,
+            finished: Cell::new(false),
+        };
+        resp_tx.send(Err(RequestError::ConnectionClosed)).unwrap();
+        assert!(handle.recv().is_err());
+        drop(handle);
+        assert!(work_rx.try_recv().is_err());
+    }
+
+    /// Tests that a request whose deadline passes is abandoned and its stream reset.
+    #[test]
+    fn test_request_deadline() {
+        let mut test = build_service();
+        let (req, rx) = build_request(0, Some(Instant::now()));
+        test.work_queue.send(WorkItem::Request(req)).unwrap();
+        test.service.run_once().ok().unwrap();
+        assert_eq!(test.sent_frames().len(), 1);
+
+        // There is no more work queued, but the deadline has already passed.
+        test.service.run_once().ok().unwrap();
+
+        let frames = test.sent_frames();
+        assert_eq!(frames.len(), 1);
+        assert_cancel(&frames[0], 1);
+        assert!(match rx.recv().unwrap() {
+            Err(RequestError::Timeout) => true,
+            _ => false,
+        });
+        assert_eq!(test.service.outstanding_reqs, 0);
+        assert!(test.service.chans.is_empty());
+    }
+
+    /// Tests that the pseudo-headers of a request built from an absolute URI take precedence over
+    /// the connection's.
+    #[test]
+    fn test_request_from_absolute_uri() {
+        let mut test = build_service();
+        let (mut req, _rx) = build_request(0, None);
+        req.request = Request::get("https://example.com/a").query("b", "c").build().unwrap();
+        test.work_queue.send(WorkItem::Request(req)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        let frames = test.sent_frames();
+        let fragment = match HttpFrame::from_raw(&frames[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => frame.header_fragment().to_vec(),
+            _ => panic!("Expected a HEADERS frame"),
+        };
+        let headers = ::hpack::Decoder::new().decode(&fragment).unwrap();
+        assert_eq!(headers,
+                   vec![(b":method".to_vec(), b"GET".to_vec()),
+                        (b":path".to_vec(), b"/a?b=c".to_vec()),
+                        (b":authority".to_vec(), b"example.com".to_vec()),
+                        (b":scheme".to_vec(), b"https".to_vec())]);
+    }
+
+    /// Tests that the priority of a request is sent along with its headers and that it can be
+    /// changed while the request is in flight.
+    #[test]
+    fn test_request_priority() {
+        let mut test = build_service();
+        let (mut req, _rx) = build_request(0, None);
+        req.request.priority = Some(StreamDependency::new(0, 15, false));
+        test.work_queue.send(WorkItem::Request(req)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        let frames = test.sent_frames();
+        match HttpFrame::from_raw(&frames[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => {
+                assert_eq!(frame.stream_dep, Some(StreamDependency::new(0, 15, false)));
+            }
+            _ => panic!("Expected a HEADERS frame"),
+        }
+
+        let priority = StreamDependency::new(0, 255, true);
+        test.work_queue.send(WorkItem::Reprioritize(0, priority.clone())).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        let frames = test.sent_frames();
+        assert_eq!(frames.len(), 1);
+        match HttpFrame::from_raw(&frames[0]).unwrap() {
+            HttpFrame::PriorityFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.stream_dependency(), &priority);
+            }
+            _ => panic!("Expected a PRIORITY frame"),
+        }
+        assert_eq!(test.service.chans[&1].request.priority, Some(priority));
+    }
+
+    /// Tests that a full response is delivered to the request.
+    #[test]
+    fn test_response_delivered() {
+        let mut test = build_service();
+        let rx = test.request(0);
+
+        test.receive(response_headers(1, vec![Header::new(b":status", b"200")]));
+
+        let response = rx.recv().unwrap().ok().unwrap();
+        assert_eq!(response.status_code().ok().unwrap(), 200);
+        assert_eq!(test.service.outstanding_reqs, 0);
+    }
+
+    /// Tests that a response without a `:status` pseudo-header is reported as malformed.
+    #[test]
+    fn test_malformed_response() {
+        let mut test = build_service();
+        let rx = test.request(0);
+
+        test.receive(response_headers(1, vec![Header::new(b"x-foo", b"bar")]));
+
+        assert!(match rx.recv().unwrap() {
+            Err(RequestError::MalformedResponse) => true,
+            _ => false,
+        });
+    }
+
+    /// Tests that a response whose body is shorter than its `content-length` is reported as such
+    /// and that its stream is reset.
+    #[test]
+    fn test_content_length_mismatch() {
+        let mut test = build_service();
+        let rx = test.request(0);
+        test.sent_frames();
+
+        test.receive(response_headers(1,
+                                      vec![Header::new(b":status", b"200"),
+                                           Header::new(b"content-length", b"5")]));
+
+        match rx.recv().unwrap() {
+            Err(RequestError::ContentLength(err)) => {
+                assert_eq!((err.declared, err.received), (5, 0));
+            }
+            _ => panic!("Expected a content-length error"),
+        }
+        let frames = test.sent_frames();
+        match HttpFrame::from_raw(&frames[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        }
+        assert_eq!(test.service.outstanding_reqs, 0);
+    }
+
+    /// Tests that a stream reset by the server is reported along with its error code, while
+    /// refused streams are reported as such.
+    #[test]
+    fn test_stream_reset() {
+        let mut test = build_service();
+        let reset = test.request(0);
+        let refused = test.request(1);
+
+        test.receive(serialize_frame(&RstStreamFrame::new(1, ErrorCode::InternalError)));
+        test.receive(serialize_frame(&RstStreamFrame::new(3, ErrorCode::RefusedStream)));
+
+        assert!(match reset.recv().unwrap() {
+            Err(RequestError::StreamReset(ErrorCode::InternalError)) => true,
+            _ => false,
+        });
+        assert!(match refused.recv().unwrap() {
+            Err(RequestError::Refused) => true,
+            _ => false,
+        });
+        assert_eq!(test.service.outstanding_reqs, 0);
+    }
+
+    /// Tests that a reset with no error after the full response was received still delivers the
+    /// response.
+    #[test]
+    fn test_reset_no_error_after_response() {
+        let mut test = build_service();
+        let (mut req, rx) = build_request(0, None);
+        // The request has a body, so the stream stays open locally after the response.
+        req.request.body = Some(vec![1, 2, 3]);
+        test.work_queue.send(WorkItem::Request(req)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        test.receive(response_headers(1, vec![Header::new(b":status", b"200")]));
+        test.receive(serialize_frame(&RstStreamFrame::new(1, ErrorCode::NoError)));
+
+        let response = rx.recv().unwrap().ok().unwrap();
+        assert_eq!(response.status_code().ok().unwrap(), 200);
+    }
+
+    /// Tests that the service adopts the server's limit of concurrent streams.
+    #[test]
+    fn test_max_concurrent_streams() {
+        let mut test = build_service();
+        let mut settings = SettingsFrame::new();
+        settings.add_setting(HttpSetting::MaxConcurrentStreams(1));
+
+        test.receive(serialize_frame(&settings));
+        let _first = test.request(0);
+        let _second = test.request(1);
+
+        assert!(test.service.shared.settings_received.load(Ordering::SeqCst));
+        assert_eq!(test.service.limit, 1);
+        assert_eq!(test.service.shared.max_concurrent_streams.load(Ordering::SeqCst), 1);
+        // Only the first request was sent (along with the ACK of the settings).
+        assert_eq!(test.sent_frames().len(), 2);
+        assert_eq!(test.service.request_queue.len(), 1);
+    }
+
+    /// Tests that a connection failure is reported to all requests that are still pending.
+    #[test]
+    fn test_connection_failed() {
+        let mut test = build_service();
+        test.service.limit = 1;
+        let sent = test.request(0);
+        let queued = test.request(1);
+
+        let err = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        test.work_queue.send(WorkItem::ConnectionFailed(0, HttpError::IoError(err))).unwrap();
+        assert!(match test.service.run_once() {
+            Err(ClientServiceErr::Http(HttpError::IoError(_))) => true,
+            _ => false,
+        });
+
+        for rx in vec![sent, queued] {
+            assert!(match rx.recv().unwrap() {
+                Err(RequestError::Io(ref err)) => err.kind() == io::ErrorKind::ConnectionReset,
+                _ => false,
+            });
+        }
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Closed);
+    }
+
+    /// Tests that a GOAWAY is reported to the requests along with its error code.
+    #[test]
+    fn test_goaway_error() {
+        let mut test = build_service();
+        let rx = test.request(0);
+
+        let err = HttpError::PeerConnectionError(ConnectionError::new(ErrorCode::ProtocolError));
+        test.work_queue.send(WorkItem::ConnectionFailed(0, err)).unwrap();
+        assert!(test.service.run_once().is_err());
+
+        assert!(match rx.recv().unwrap() {
+            Err(RequestError::GoAway(ErrorCode::ProtocolError)) => true,
+            _ => false,
+        });
+        assert!(test.service.chans.is_empty());
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Closed);
+    }
+
+    /// The ends of a connection established by the test reconnect function that stand in for the
+    /// socket: the frames that the service sends and the frames that it is to receive.
+    struct TestConnection {
+        sent: FrameQueue,
+        received: Sender<RawFrame<'static>>,
+    }
+
+    /// Makes the service reconnect by using a function that establishes connections which are not
+    /// backed by a socket, without any backoff. If `fail` is set, all attempts fail.
+    ///
+    /// Returns the receiving end of a channel on which the established connections are delivered.
+    fn enable_reconnect(test: &mut TestService, fail: bool) -> Receiver<TestConnection> {
+        let (conn_tx, conn_rx) = mpsc::channel();
+        let reconnect = Box::new(move |_| {
+            if fail {
+                return Err(HttpError::from(io::Error::new(io::ErrorKind::ConnectionRefused,
+                                                          "refused")));
+            }
+            let (send_queue, send_handle) = FrameQueue::new(QueueLimits::default());
+            let (recv_tx, recv_rx) = mpsc::channel();
+            conn_tx.send(TestConnection {
+                       sent: send_queue,
+                       received: recv_tx,
+                   })
+                   .unwrap();
+            let state = DefaultSessionState::<ClientMarker, _>::new();
+            let conn = ClientConnection::with_connection(HttpConnection::new(HttpScheme::Http),
+                                                         state);
+            Ok(Connection {
+                conn: conn,
+                host: b"localhost".to_vec(),
+                recv_handle: ChannelFrameReceiverHandle {
+                    rx: recv_rx,
+                    raw: None,
+                },
+                send_handle: send_handle,
+                closer: None,
+            })
+        });
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(0),
+            max_backoff: Duration::from_millis(0),
+            max_attempts: Some(2),
+        };
+        test.service.set_reconnect(reconnect, policy);
+        conn_rx
+    }
+
+    /// Tests that once the connection fails after a GOAWAY, the requests that the server did not
+    /// process are retried on a new connection, while the others fail.
+    #[test]
+    fn test_reconnect_retries_unprocessed_requests() {
+        let mut test = build_service();
+        let connections = enable_reconnect(&mut test, false);
+        let processed = test.request(0);
+        let unprocessed = test.request(1);
+        assert_eq!(test.sent_frames().len(), 2);
+
+        let mut err = ConnectionError::new(ErrorCode::NoError);
+        err.set_last_stream_id(1);
+        test.work_queue.send(WorkItem::ConnectionFailed(0, HttpError::PeerConnectionError(err)))
+            .unwrap();
+        test.service.run_once().ok().unwrap();
+
+        assert!(match processed.recv().unwrap() {
+            Err(RequestError::GoAway(ErrorCode::NoError)) => true,
+            _ => false,
+        });
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Reconnecting);
+        assert_eq!(test.service.request_queue.len(), 1);
+        // Requests issued in the meantime are queued behind the retried one.
+        let due = test.service.reconnect_at.take();
+        let queued = test.request(2);
+        assert_eq!(test.service.request_queue.len(), 2);
+
+        // The backoff has elapsed, so the service reconnects.
+        test.service.reconnect_at = due;
+        test.service.run_once().ok().unwrap();
+        let connection = connections.try_recv().unwrap();
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Connected);
+        assert_eq!(test.service.conn_id, 1);
+        // Both requests are sent on the new connection, starting from the retried one.
+        assert_eq!(connection.sent.len(), 2);
+        assert_eq!(test.service.chans[&1].id, 1);
+        assert_eq!(test.service.chans[&3].id, 2);
+
+        // Work items of the failed connection are ignored.
+        test.work_queue.send(WorkItem::HandleFrame(0)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        test.service.initialized = true;
+        connection.received
+                  .send(response_headers(1, vec![Header::new(b":status", b"200")]).into())
+                  .unwrap();
+        test.work_queue.send(WorkItem::HandleFrame(1)).unwrap();
+        test.service.run_once().ok().unwrap();
+        let response = unprocessed.recv().unwrap().ok().unwrap();
+        assert_eq!(response.status_code().ok().unwrap(), 200);
+        assert!(queued.try_recv().is_err());
+        // Nothing more was sent on the failed connection.
+        assert!(test.sent_frames().is_empty());
+    }
+
+    /// Tests that the queue limits set on the service apply to the current connection, as well
+    /// as to the connections that it reconnects with.
+    #[test]
+    fn test_set_queue_limits() {
+        let mut test = build_service();
+        let connections = enable_reconnect(&mut test, false);
+        let limits = QueueLimits { max_control_frames: 0, ..QueueLimits::default() };
+        test.work_queue.send(WorkItem::SetQueueLimits(limits)).unwrap();
+        test.service.run_once().ok().unwrap();
+
+        // No PING fits into the queue of the current connection.
+        test.work_queue.send(WorkItem::SendPing).unwrap();
+        assert!(test.service.run_once().is_ok());
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Reconnecting);
+
+        test.service.reconnect_at = Some(Instant::now());
+        test.service.run_once().ok().unwrap();
+        let connection = connections.try_recv().unwrap();
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Connected);
+        // Nor into the queue of the new one.
+        test.work_queue.send(WorkItem::SendPing).unwrap();
+        test.service.run_once().ok().unwrap();
+        assert_eq!(connection.sent.len(), 0);
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Reconnecting);
+    }
+
+    /// Tests that an IO failure fails all in-flight requests, since it is not known whether the
+    /// server processed them, while queued requests wait for the new connection.
+    #[test]
+    fn test_reconnect_after_io_error() {
+        let mut test = build_service();
+        let connections = enable_reconnect(&mut test, false);
+        test.service.limit = 1;
+        let sent = test.request(0);
+        let _queued = test.request(1);
+
+        let err = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        test.work_queue.send(WorkItem::ConnectionFailed(0, HttpError::IoError(err))).unwrap();
+        test.service.run_once().ok().unwrap();
+        assert!(match sent.recv().unwrap() {
+            Err(RequestError::Io(_)) => true,
+            _ => false,
+        });
+
+        test.service.run_once().ok().unwrap();
+        let connection = connections.try_recv().unwrap();
+        // The limit is reset for the new connection.
+        assert_eq!(test.service.limit, 3);
+        assert_eq!(connection.sent.len(), 1);
+        assert!(test.service.request_queue.is_empty());
+    }
+
+    /// Tests that the service gives up once the reconnect policy allows no more attempts.
+    #[test]
+    fn test_reconnect_gives_up() {
+        let mut test = build_service();
+        let _connections = enable_reconnect(&mut test, true);
+        test.service.limit = 0;
+        let queued = test.request(0);
+
+        let err = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        test.work_queue.send(WorkItem::ConnectionFailed(0, HttpError::IoError(err))).unwrap();
+        test.service.run_once().ok().unwrap();
+        // The first attempt fails, the second one exhausts the attempts.
+        test.service.run_once().ok().unwrap();
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Reconnecting);
+        assert!(test.service.run_once().is_err());
+
+        assert_eq!(test.service.shared.status(), ConnectionStatus::Closed);
+        assert!(match queued.recv().unwrap() {
+            Err(RequestError::Io(ref err)) => err.kind() == io::ErrorKind::ConnectionRefused,
+            _ => false,
+        });
+    }
+
+    /// Tests the exponential backoff of the reconnect policy.
+    #[test]
+    fn test_reconnect_backoff() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            max_attempts: None,
+        };
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(100), Duration::from_secs(1));
+    }
+}
@@ -3,8 +3,12 @@
 //! responses.
 
 pub use self::simple::SimpleClient;
-pub use self::async::Client;
+pub use self::async::{Client, RequestHandle, RequestError, ConnectionStatus, ReconnectPolicy};
+pub use self::pool::{Pool, PoolKey, ConnectorFactory, CleartextConnectorFactory};
+pub use self::tunnel::Tunnel;
 
 mod simple;
 mod async;
+mod pool;
+mod tunnel;
 #[cfg(test)]mod tests;
@@ -0,0 +1,512 @@
+//! Contains an implementation of a pool of asynchronous clients.
+//!
+//! It allows users to issue requests to any number of hosts, while the pool takes care of
+//! establishing (and disposing of) the underlying HTTP/2 connections.
+use std::collections::HashMap;
+use std::io;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use http::{HttpScheme, HttpError, HttpResult, StaticHeader, Request};
+use http::transport::TransportStream;
+use http::client::{HttpConnect, HttpConnectError, ClientStream, CleartextConnector,
+                   CleartextConnectError};
+use client::async::{Client, RequestHandle};
+
+/// The time after which a connection that has had no active requests is closed, unless
+/// configured otherwise.
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// A struct identifying the origin that the connections of a `Pool` are established to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    /// The scheme used by the connections
+    pub scheme: HttpScheme,
+    /// The host to which the connections are established
+    pub host: String,
+    /// The port on which the connections are established
+    pub port: u16,
+}
+
+impl PoolKey {
+    /// Creates a new `PoolKey` for the given scheme, host and port.
+    pub fn new(scheme: HttpScheme, host: &str, port: u16) -> PoolKey {
+        PoolKey {
+            scheme: scheme,
+            host: host.into(),
+            port: port,
+        }
+    }
+}
+
+/// A trait that can be implemented by structs that establish the network connections for a
+/// `Pool`, whenever it needs a new connection to the origin identified by a `PoolKey`.
+///
+/// It is implemented for any closure that returns an `HttpConnect` instance for the given key,
+/// as long as the returned connector does not borrow from the key.
+pub trait ConnectorFactory {
+    /// The type of the underlying transport stream of the established connections.
+    type Stream: TransportStream + Send + 'static;
+    /// The type of the error that can be produced by trying to establish a connection.
+    type Err: HttpConnectError + 'static;
+
+    /// Establishes a new network connection to the origin identified by the given key.
+    fn connect(&self, key: &PoolKey) -> Result<ClientStream<Self::Stream>, Self::Err>;
+}
+
+impl<F, C> ConnectorFactory for F
+    where F: Fn(&PoolKey) -> C,
+          C: HttpConnect,
+          C::Stream: Send + 'static
+{
+    type Stream = C::Stream;
+    type Err = C::Err;
+
+    fn connect(&self, key: &PoolKey) -> Result<ClientStream<C::Stream>, C::Err> {
+        self(key).connect()
+    }
+}
+
+/// A `ConnectorFactory` that establishes cleartext TCP connections by using the
+/// `CleartextConnector`. Therefore, it only supports keys with the `http` scheme.
+pub struct CleartextConnectorFactory;
+
+impl ConnectorFactory for CleartextConnectorFactory {
+    type Stream = TcpStream;
+    type Err = CleartextConnectError;
+
+    fn connect(&self, key: &PoolKey) -> Result<ClientStream<TcpStream>, CleartextConnectError> {
+        if key.scheme != HttpScheme::Http {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Cleartext connections require the http scheme")
+                           .into());
+        }
+        CleartextConnector::with_port(&key.host, key.port).connect()
+    }
+}
+
+/// A pool of asynchronous HTTP/2 clients, keyed by the origin (scheme, host and port) that their
+/// connection is established to.
+///
+/// Connections are established lazily, by using the given `ConnectorFactory`, once a request to
+/// a new origin is issued. Requests to the same origin are multiplexed onto the same connection,
+/// until it reaches the server's limit of concurrent streams, at which point an additional
+/// connection is established.
+///
+/// Connections that the server closed (e.g. by sending a GOAWAY frame), as well as the ones that
+/// have been idle for longer than the idle timeout, are evicted from the pool.
+///
+/// # Example
+///
+/// ```no_run
+/// use solicit_fork::client::{Pool, PoolKey, CleartextConnectorFactory};
+/// use solicit_fork::http::HttpScheme;
+///
+/// let pool = Pool::new(CleartextConnectorFactory);
+/// let key = PoolKey::new(HttpScheme::Http, "http2bin.org", 80);
+/// let response = pool.get(&key, b"/get", &[]).unwrap().recv().unwrap();
+/// println!("{}", response.status_code().ok().unwrap());
+/// ```
+pub struct Pool<F>
+    where F: ConnectorFactory
+{
+    /// The factory used to establish new connections.
+    factory: F,
+    /// The clients of all currently pooled connections, with a lock of their own for each origin,
+    /// so that establishing a connection to one origin does not hold up requests to the others.
+    conns: Mutex<HashMap<PoolKey, Arc<Mutex<Vec<Client>>>>>,
+    /// The time after which an idle connection is evicted.
+    idle_timeout: Duration,
+}
+
+impl<F> Pool<F>
+    where F: ConnectorFactory
+{
+    /// Creates a new, empty, `Pool` that will use the given factory to establish connections. Idle
+    /// connections are evicted after `DEFAULT_IDLE_TIMEOUT_SECS`.
+    pub fn new(factory: F) -> Pool<F> {
+        Pool::with_idle_timeout(factory, Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS))
+    }
+
+    /// Creates a new, empty, `Pool` that will use the given factory to establish connections and
+    /// evict the ones that have been idle for longer than the given timeout.
+    pub fn with_idle_timeout(factory: F, idle_timeout: Duration) -> Pool<F> {
+        Pool {
+            factory: factory,
+            conns: Mutex::new(HashMap::new()),
+            idle_timeout: idle_timeout,
+        }
+    }
+
+    /// Issues a new request to the origin identified by the given key.
+    ///
+    /// The request is issued on a pooled connection to the origin that has not reached its limit
+    /// of concurrent streams. If there is no such connection, a new one is established first,
+    /// blocking until it is (along with any other request to the same origin issued in the
+    /// meantime).
+    ///
+    /// # Returns
+    ///
+    /// The `RequestHandle` on which the response will be received, as for `Client::request`.
+    ///
+    /// Any error raised while establishing a new connection is propagated.
+    pub fn request(&self,
+                   key: &PoolKey,
+                   method: &[u8],
+                   path: &[u8],
+                   headers: &[StaticHeader],
+                   body: Option<Vec<u8>>)
+                   -> HttpResult<RequestHandle> {
+        self.issue(key, |client| client.request(method, path, headers, body))
+    }
+
+    /// Issues the given `Request` to the origin identified by the given key, in the same way as
+    /// the `request` method.
+    ///
+    /// Unless the request specifies its own authority, the one of the key is used.
+    pub fn send(&self, key: &PoolKey, request: Request) -> HttpResult<RequestHandle> {
+        self.issue(key, |client| client.send(request))
+    }
+
+    /// Issues a GET request to the origin identified by the given key.
+    ///
+    /// A convenience wrapper around the `request` method that sets the correct method.
+    pub fn get(&self,
+               key: &PoolKey,
+               path: &[u8],
+               headers: &[StaticHeader])
+               -> HttpResult<RequestHandle> {
+        self.request(key, b"GET", path, headers, None)
+    }
+
+    /// Issues a POST request to the origin identified by the given key.
+    ///
+    /// Returns the `RequestHandle` on which the `Response` will eventually be received.
+    pub fn post(&self,
+                key: &PoolKey,
+                path: &[u8],
+                headers: &[StaticHeader],
+                body: Vec<u8>)
+                -> HttpResult<RequestHandle> {
+        self.request(key, b"POST", path, headers, Some(body))
+    }
+
+    /// Internal helper method. Picks a pooled connection to the origin identified by the given key
+    /// that has not reached its limit of concurrent streams, establishing a new one if there is
+    /// none, and issues a request on it by using the given function.
+    fn issue<I>(&self, key: &PoolKey, issue: I) -> HttpResult<RequestHandle>
+        where I: FnOnce(&Client) -> Option<RequestHandle>
+    {
+        let clients = {
+            let mut conns = self.conns.lock().unwrap();
+            self.evict_from(&mut conns);
+            conns.entry(key.clone()).or_insert_with(Default::default).clone()
+        };
+        // Only the origin's own lock is held while a new connection is established.
+        let mut clients = clients.lock().unwrap();
+        let pos = match clients.iter().position(Pool::<F>::has_capacity) {
+            Some(pos) => pos,
+            None => {
+                debug!("Establishing a new connection to {:?}", key);
+                clients.push(try!(self.connect(key)));
+                clients.len() - 1
+            }
+        };
+
+        issue(&clients[pos]).ok_or_else(|| {
+            HttpError::IoError(io::Error::new(io::ErrorKind::NotConnected,
+                                              "The connection is closed"))
+        })
+    }
+
+    /// Evicts all connections that have been closed or that have been idle for longer than the
+    /// idle timeout.
+    ///
+    /// This happens whenever a new request is issued, so it only needs to be called explicitly in
+    /// order to release connections sooner.
+    pub fn evict(&self) {
+        let mut conns = self.conns.lock().unwrap();
+        self.evict_from(&mut conns);
+    }
+
+    /// Returns the number of pooled connections to the origin identified by the given key.
+    pub fn connection_count(&self, key: &PoolKey) -> usize {
+        let clients = self.conns.lock().unwrap().get(key).cloned();
+        clients.map_or(0, |clients| clients.lock().unwrap().len())
+    }
+
+    /// Internal helper method. Establishes a new connection to the origin identified by the given
+    /// key and wraps it into a `Client`.
+    fn connect(&self, key: &PoolKey) -> HttpResult<Client> {
+        let client_stream = try!(self.factory.connect(key));
+        Ok(try!(Client::with_stream(client_stream)))
+    }
+
+    /// Internal helper method. Drops the clients of the given connections that should be evicted.
+    /// Dropping the last client of a connection closes it.
+    ///
+    /// The origins on which a request is being issued (e.g. while a connection is established)
+    /// are skipped, rather than waited for.
+    fn evict_from(&self, conns: &mut HashMap<PoolKey, Arc<Mutex<Vec<Client>>>>) {
+        let idle_timeout = self.idle_timeout;
+        for clients in conns.values() {
+            if let Ok(mut clients) = clients.try_lock() {
+                clients.retain(|client| {
+                    !client.is_closed() &&
+                    client.idle_time().map_or(true, |idle| idle < idle_timeout)
+                });
+            }
+        }
+        // An origin that a request still holds on to cannot be dropped, as the request may be
+        // about to add a connection to it.
+        conns.retain(|_, clients| {
+            Arc::strong_count(clients) > 1 || !clients.lock().unwrap().is_empty()
+        });
+    }
+
+    /// Internal helper method. Checks whether the given client's connection can take on another
+    /// request without exceeding the server's limit of concurrent streams.
+    ///
+    /// Until the server's SETTINGS are received, its limit is unknown, so the connection is only
+    /// considered to allow a single request at a time.
+    fn has_capacity(client: &Client) -> bool {
+        if client.is_closed() {
+            return false;
+        }
+        if !client.settings_received() {
+            return client.active_requests() == 0;
+        }
+        client.max_concurrent_streams().map_or(true, |max| client.active_requests() < max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pool, PoolKey, ConnectorFactory, CleartextConnectorFactory};
+
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::{self, Sender, Receiver};
+    use std::thread;
+    use std::time::Duration;
+
+    use http::HttpScheme;
+    use http::client::{ClientStream, CleartextConnectError};
+    use http::frame::{SettingsFrame, HttpSetting};
+    use http::transport::TransportStream;
+    use http::tests::common::{PipeTransportStream, pipe, serialize_frame};
+
+    /// The type of the SETTINGS frame.
+    const SETTINGS_FRAME_TYPE: u8 = 0x4;
+    /// The type of the PING frame.
+    const PING_FRAME_TYPE: u8 = 0x6;
+
+    /// A `ConnectorFactory` that establishes in-memory connections, handing the server's end of
+    /// each one to the test. The connections to the given port are only established once the test
+    /// allows them to be.
+    struct PipeFactory {
+        conns: Mutex<Sender<PipeTransportStream>>,
+        gated_port: Option<u16>,
+        started: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl ConnectorFactory for PipeFactory {
+        type Stream = PipeTransportStream;
+        type Err = CleartextConnectError;
+
+        fn connect(&self,
+                   key: &PoolKey)
+                   -> Result<ClientStream<PipeTransportStream>, CleartextConnectError> {
+            if Some(key.port) == self.gated_port {
+                self.started.lock().unwrap().send(()).unwrap();
+                self.release.lock().unwrap().recv().unwrap();
+            }
+            let (client, server) = pipe();
+            self.conns.lock().unwrap().send(server).unwrap();
+            Ok(ClientStream(client, key.scheme, key.host.clone()))
+        }
+    }
+
+    /// The components of a `Pool` whose connections are in-memory.
+    struct TestPool {
+        pool: Arc<Pool<PipeFactory>>,
+        /// Receives the server's end of each established connection.
+        conns: Receiver<PipeTransportStream>,
+        /// Receives a notification when a connection to the gated port starts being established.
+        started: Receiver<()>,
+        /// Allows a connection to the gated port to be established.
+        release: Sender<()>,
+    }
+
+    /// Builds a new `Pool` whose connections to the given port are only established once the test
+    /// allows them to be.
+    fn build_pool(gated_port: Option<u16>, idle_timeout: Duration) -> TestPool {
+        let (conns_tx, conns) = mpsc::channel();
+        let (started_tx, started) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let factory = PipeFactory {
+            conns: Mutex::new(conns_tx),
+            gated_port: gated_port,
+            started: Mutex::new(started_tx),
+            release: Mutex::new(release_rx),
+        };
+        TestPool {
+            pool: Arc::new(Pool::with_idle_timeout(factory, idle_timeout)),
+            conns: conns,
+            started: started,
+            release: release,
+        }
+    }
+
+    impl TestPool {
+        /// Accepts the next established connection by sending the server's SETTINGS (advertising
+        /// the given limit of concurrent streams) on it, returning once the client acknowledges
+        /// them. The server never responds to a request.
+        fn accept(&self, max_concurrent_streams: Option<u32>) -> PipeTransportStream {
+            let mut server = self.conns.recv().unwrap();
+            let mut settings = SettingsFrame::new();
+            if let Some(max) = max_concurrent_streams {
+                settings.add_setting(HttpSetting::MaxConcurrentStreams(max));
+            }
+            server.write_all(&serialize_frame(&settings)).unwrap();
+            loop {
+                let frame = server.read_frame().unwrap();
+                if frame.header().1 == SETTINGS_FRAME_TYPE && frame.header().2 & 0x1 != 0 {
+                    return server;
+                }
+            }
+        }
+
+        /// Waits until the client of the connection at the given position has handled everything
+        /// that was sent or issued on it so far, by pinging the server through it.
+        fn sync(&self, key: &PoolKey, pos: usize, server: &mut PipeTransportStream) {
+            let client = {
+                let conns = self.pool.conns.lock().unwrap();
+                let clients = conns[key].lock().unwrap();
+                clients[pos].clone()
+            };
+            client.ping().unwrap();
+            while server.read_frame().unwrap().header().1 != PING_FRAME_TYPE {}
+        }
+    }
+
+    /// Tests that requests to the same origin share a connection, while requests to different
+    /// origins do not.
+    #[test]
+    fn test_pool_reuses_connections() {
+        let test = build_pool(None, Duration::from_secs(60));
+        let key1 = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+        let key2 = PoolKey::new(HttpScheme::Http, "127.0.0.1", 2);
+
+        let _req1 = test.pool.get(&key1, b"/", &[]).unwrap();
+        let mut server1 = test.accept(None);
+        test.sync(&key1, 0, &mut server1);
+        let _req2 = test.pool.get(&key1, b"/", &[]).unwrap();
+        let _req3 = test.pool.get(&key2, b"/", &[]).unwrap();
+
+        assert_eq!(test.pool.connection_count(&key1), 1);
+        assert_eq!(test.pool.connection_count(&key2), 1);
+    }
+
+    /// Tests that an additional connection is established once the pooled one reaches the
+    /// server's limit of concurrent streams.
+    #[test]
+    fn test_pool_opens_connection_when_full() {
+        let test = build_pool(None, Duration::from_secs(60));
+        let key = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+
+        let _req1 = test.pool.get(&key, b"/", &[]).unwrap();
+        let mut server = test.accept(Some(2));
+        test.sync(&key, 0, &mut server);
+        let _req2 = test.pool.get(&key, b"/", &[]).unwrap();
+        assert_eq!(test.pool.connection_count(&key), 1);
+
+        let _req3 = test.pool.get(&key, b"/", &[]).unwrap();
+
+        assert_eq!(test.pool.connection_count(&key), 2);
+    }
+
+    /// Tests that, until the server's SETTINGS are received, a connection is not given more than a
+    /// single request, since the server's limit of concurrent streams is not known yet.
+    #[test]
+    fn test_pool_limits_connection_before_settings() {
+        let test = build_pool(None, Duration::from_secs(60));
+        let key = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+
+        let _req1 = test.pool.get(&key, b"/", &[]).unwrap();
+        let _req2 = test.pool.get(&key, b"/", &[]).unwrap();
+
+        assert_eq!(test.pool.connection_count(&key), 2);
+    }
+
+    /// Tests that a connection that takes long to establish does not hold up the requests to
+    /// other origins.
+    #[test]
+    fn test_pool_connects_without_blocking_other_origins() {
+        let test = build_pool(Some(1), Duration::from_secs(60));
+        let slow = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+        let fast = PoolKey::new(HttpScheme::Http, "127.0.0.1", 2);
+
+        let slow_req = {
+            let pool = test.pool.clone();
+            let slow = slow.clone();
+            thread::spawn(move || pool.get(&slow, b"/", &[]).is_ok())
+        };
+        test.started.recv().unwrap();
+        // The connection to the slow origin is still being established...
+        assert!(test.pool.get(&fast, b"/", &[]).is_ok());
+        assert_eq!(test.pool.connection_count(&fast), 1);
+
+        // ...until it is allowed to be.
+        test.release.send(()).unwrap();
+        assert!(slow_req.join().unwrap());
+        assert_eq!(test.pool.connection_count(&slow), 1);
+    }
+
+    /// Tests that connections closed by the server are evicted.
+    #[test]
+    fn test_pool_evicts_closed_connections() {
+        let test = build_pool(None, Duration::from_secs(60));
+        let key = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+        let req = test.pool.get(&key, b"/", &[]).unwrap();
+
+        // The server goes away...
+        test.accept(None).close().unwrap();
+        // ...which fails the request...
+        assert!(req.recv().is_err());
+        // ...and gets the connection evicted.
+        test.pool.evict();
+        assert_eq!(test.pool.connection_count(&key), 0);
+    }
+
+    /// Tests that idle connections are evicted.
+    #[test]
+    fn test_pool_evicts_idle_connections() {
+        let test = build_pool(None, Duration::from_millis(0));
+        let key = PoolKey::new(HttpScheme::Http, "127.0.0.1", 1);
+
+        let req = test.pool.get(&key, b"/", &[]).unwrap();
+        let mut server = test.accept(None);
+        // The connection has an active request, so it isn't idle.
+        test.pool.evict();
+        assert_eq!(test.pool.connection_count(&key), 1);
+
+        req.cancel();
+        test.sync(&key, 0, &mut server);
+        test.pool.evict();
+
+        assert_eq!(test.pool.connection_count(&key), 0);
+    }
+
+    /// Tests that the `CleartextConnectorFactory` refuses to connect to https origins.
+    #[test]
+    fn test_cleartext_factory_requires_http() {
+        let pool = Pool::new(CleartextConnectorFactory);
+        let key = PoolKey::new(HttpScheme::Https, "127.0.0.1", 443);
+
+        assert!(pool.get(&key, b"/", &[]).is_err());
+        assert_eq!(pool.connection_count(&key), 0);
+    }
+}
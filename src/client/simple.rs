@@ -1,7 +1,9 @@
 //! The module contains an implementation of a simple HTTP/2 client.
 
-use http::{StreamId, HttpResult, HttpError, Response, Header, HttpScheme};
+use http::{StreamId, HttpResult, HttpError, Response, Header, HttpScheme, Request};
 use http::transport::{TransportStream, TransportReceiveFrame};
+use http::frame::StreamDependency;
+use http::observer::FrameObserver;
 use http::connection::{HttpConnection, SendStatus};
 use http::session::{SessionState, DefaultSessionState, DefaultStream, Stream};
 use http::session::Client as ClientMarker;
@@ -176,6 +178,44 @@ impl<S> SimpleClient<S>
                    -> HttpResult<StreamId> {
         // Prepares the request stream
         let stream = self.new_stream(method, path, extras, body);
+        self.start_request(stream)
+    }
+
+    /// Sends the given `Request` to the server. Blocks until the entire request has been sent.
+    ///
+    /// Unless the request specifies its own scheme and authority (e.g. by being built from an
+    /// absolute URI), the ones of the client's connection are used.
+    ///
+    /// # Returns
+    ///
+    /// As with the `request` method, the ID of the stream on which the request was sent.
+    pub fn send(&mut self, request: Request) -> HttpResult<StreamId> {
+        let mut stream = DefaultStream::new();
+        match request.body {
+            Some(ref body) => stream.set_full_data(body.clone()),
+            None => stream.close_local(),
+        };
+        let headers = request.header_list(self.conn.scheme(), &self.host);
+
+        self.start_request(RequestStream {
+            headers: headers,
+            stream: stream,
+            priority: request.priority,
+        })
+    }
+
+    /// Sends the given `Request` to the server and blocks until its response is received. This is
+    /// a shortcut method for calling `send` followed by `get_response` for the returned stream ID.
+    pub fn fetch(&mut self, request: Request) -> HttpResult<Response<'static, 'static>> {
+        let stream_id = try!(self.send(request));
+        self.get_response(stream_id)
+    }
+
+    /// Internal helper method that starts the request described by the given `RequestStream` and
+    /// blocks until its body is sent.
+    fn start_request<'n, 'v>(&mut self,
+                             stream: RequestStream<'n, 'v, DefaultStream>)
+                             -> HttpResult<StreamId> {
         // Starts the request (i.e. sends out the headers)
         let stream_id = try!(self.conn.start_request(stream, &mut self.receiver));
         // TODO(mlalic): Remove when `Stream::on_id_assigned` is invoked by the session.
@@ -211,6 +251,12 @@ impl<S> SimpleClient<S>
         loop {
             if let Some(stream) = self.conn.state.get_stream_ref(stream_id) {
                 if stream.is_closed() {
+                    if let Some(err) = stream.content_length_error {
+                        return Err(HttpError::ContentLength(err));
+                    }
+                    if stream.malformed_headers.is_some() {
+                        return Err(HttpError::MalformedResponse);
+                    }
                     return Ok(Response {
                         stream_id: stream_id,
                         headers: stream.headers.clone().unwrap(),
@@ -222,6 +268,20 @@ impl<S> SimpleClient<S>
         }
     }
 
+    /// Changes the priority of the stream with the given ID, while its request is in flight.
+    pub fn reprioritize(&mut self,
+                        stream_id: StreamId,
+                        priority: StreamDependency)
+                        -> HttpResult<()> {
+        self.conn.reprioritize(stream_id, priority, &mut self.sender)
+    }
+
+    /// Attaches the given observer to the client's connection. It is shown every frame that is
+    /// sent and received from then on, along with the headers.
+    pub fn set_frame_observer<O: FrameObserver + 'static>(&mut self, observer: O) {
+        self.conn.set_frame_observer(observer);
+    }
+
     /// Performs a GET request on the given path. This is a shortcut method for
     /// calling `request` followed by `get_response` for the returned stream ID.
     pub fn get(&mut self,
@@ -273,6 +333,7 @@ impl<S> SimpleClient<S>
         RequestStream {
             headers: headers,
             stream: stream,
+            priority: None,
         }
     }
 
@@ -0,0 +1,478 @@
+//! The module contains an implementation of a client-side CONNECT tunnel, which carries a
+//! bidirectional byte stream to a remote `host:port` over a single HTTP/2 stream (Section 8.3 of
+//! the HTTP/2 spec).
+
+use std::io;
+use std::cmp;
+
+use http::{StreamId, HttpResult, HttpError, HttpScheme, Response, Request, Method, StaticHeader,
+           ErrorCode};
+use http::transport::{TransportStream, TransportReceiveFrame};
+use http::connection::{HttpConnection, SendStatus};
+use http::session::{SessionState, DefaultSessionState, Stream};
+use http::session::Client as ClientMarker;
+use http::duplex::DuplexStream;
+use http::client::{ClientConnection, HttpConnect, RequestStream, ClientStream};
+
+/// A CONNECT tunnel to a remote `host:port`, established over an HTTP/2 connection.
+///
+/// Once the tunnel is open, the handle implements `Read` and `Write`: whatever is written to it
+/// is sent to the tunnel's target in DATA frames and whatever the target sends back can be read
+/// from it. Reads block until the server sends more data, and return `0` once the server closes
+/// its end of the tunnel. If the tunnel fails after it was opened, the server resets the stream
+/// with `CONNECT_ERROR` and the next read or write returns an error of kind `ConnectionReset`.
+///
+/// Writes respect the flow control windows that the server grants: once they are used up, a write
+/// blocks, handling the frames that the server sends, until a WINDOW_UPDATE makes room for more
+/// data. The data received from the server in the meantime is kept for later reads.
+///
+/// The tunnel owns the connection; no other requests are made on it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::{Read, Write};
+/// use solicit_fork::http::client::CleartextConnector;
+/// use solicit_fork::client::Tunnel;
+///
+/// let connector = CleartextConnector::new("proxy.example.com");
+/// let mut tunnel = Tunnel::with_connector(connector, "example.com:80").unwrap();
+/// tunnel.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+/// let mut response = Vec::new();
+/// tunnel.read_to_end(&mut response).unwrap();
+/// ```
+pub struct Tunnel<S>
+    where S: TransportStream
+{
+    /// The underlying `ClientConnection`, tracking the tunnel's stream.
+    conn: ClientConnection<DefaultSessionState<ClientMarker, DuplexStream>>,
+    /// The ID of the stream that the tunnel is established on.
+    stream_id: StreamId,
+    /// The headers of the server's response to the CONNECT request.
+    headers: Vec<StaticHeader>,
+    /// The receiving end of the underlying transport stream.
+    receiver: S,
+    /// The sending end of the underlying transport stream.
+    sender: S,
+    /// The tunnel data that was received, but not read yet.
+    buf: Vec<u8>,
+    /// The position in `buf` from which the next read continues.
+    pos: usize,
+}
+
+impl<S> Tunnel<S>
+    where S: TransportStream
+{
+    /// Opens a tunnel to the given authority (`host:port`) by sending a CONNECT request over the
+    /// given `stream`. Blocks until the server responds.
+    ///
+    /// As with the `SimpleClient`, it assumes that the given `stream` has already been initialized
+    /// for HTTP/2 communication.
+    ///
+    /// # Returns
+    ///
+    /// The open tunnel, once the server accepts the request with a 2xx response. If the server
+    /// responds with any other status, `HttpError::TunnelRefused` is returned; if it resets the
+    /// stream instead (e.g. with `CONNECT_ERROR` when it cannot reach the target),
+    /// `HttpError::StreamReset` is.
+    pub fn with_stream(stream: S, scheme: HttpScheme, authority: &str) -> HttpResult<Tunnel<S>> {
+        let request = try!(Request::builder(Method::Connect, authority).build());
+        let receiver = try!(stream.try_split());
+        let state = DefaultSessionState::<ClientMarker, _>::new();
+        let conn = HttpConnection::new(scheme);
+        let mut tunnel = Tunnel {
+            conn: ClientConnection::with_connection(conn, state),
+            stream_id: 0,
+            headers: Vec::new(),
+            receiver: receiver,
+            sender: stream,
+            buf: Vec::new(),
+            pos: 0,
+        };
+        try!(tunnel.conn.expect_settings(&mut TransportReceiveFrame::new(&mut tunnel.receiver),
+                                         &mut tunnel.sender));
+
+        let headers = request.header_list(scheme, &[]);
+        tunnel.stream_id = try!(tunnel.conn.start_request(RequestStream {
+                                                              headers: headers,
+                                                              stream: DuplexStream::new(),
+                                                              priority: None,
+                                                          },
+                                                          &mut tunnel.sender));
+        try!(tunnel.open());
+
+        Ok(tunnel)
+    }
+
+    /// A convenience constructor that first establishes an HTTP/2 connection by using the given
+    /// connector and then opens a tunnel to the given authority over it.
+    pub fn with_connector<C>(connector: C, authority: &str) -> HttpResult<Tunnel<S>>
+        where C: HttpConnect<Stream = S>
+    {
+        let ClientStream(stream, scheme, _) = try!(connector.connect());
+        Tunnel::with_stream(stream, scheme, authority)
+    }
+
+    /// Returns the ID of the stream that the tunnel is established on.
+    #[inline]
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Returns the headers of the server's response to the CONNECT request.
+    #[inline]
+    pub fn headers(&self) -> &[StaticHeader] {
+        &self.headers
+    }
+
+    /// Closes the local end of the tunnel, once all the data written so far is sent. The server
+    /// can still send data until it closes its own end.
+    pub fn close(&mut self) -> io::Result<()> {
+        if let Some(stream) = self.conn.state.get_stream_mut(self.stream_id) {
+            stream.finish();
+        }
+        self.send_pending()
+    }
+
+    /// Internal helper method. Waits for the server's response to the CONNECT request.
+    fn open(&mut self) -> HttpResult<()> {
+        loop {
+            {
+                let stream = match self.conn.state.get_stream_mut(self.stream_id) {
+                    Some(stream) => stream,
+                    None => return Err(HttpError::MalformedResponse),
+                };
+                if let Some(error_code) = stream.error_code {
+                    return Err(HttpError::StreamReset(error_code));
+                }
+                if let Some(ref headers) = stream.headers {
+                    let response = Response {
+                        stream_id: self.stream_id,
+                        headers: headers.clone(),
+                        body: Vec::new(),
+                    };
+                    let status = try!(response.status_code());
+                    if status < 200 || status >= 300 {
+                        return Err(HttpError::TunnelRefused(status));
+                    }
+                    self.headers = response.headers;
+                    return Ok(());
+                }
+                if stream.is_closed_remote() {
+                    return Err(HttpError::MalformedResponse);
+                }
+            }
+            try!(self.conn.handle_next_frame(&mut TransportReceiveFrame::new(&mut self.receiver),
+                                             &mut self.sender));
+        }
+    }
+
+    /// Internal helper method. Blocks until the flow control windows allow some data to be sent
+    /// on the tunnel's stream, handling the frames that the server sends in the meantime.
+    ///
+    /// Returns the number of octets that can be sent.
+    fn wait_for_window(&mut self) -> io::Result<usize> {
+        loop {
+            {
+                let stream = match self.conn.state.get_stream_mut(self.stream_id) {
+                    Some(stream) => stream,
+                    None => {
+                        return Err(io::Error::new(io::ErrorKind::BrokenPipe,
+                                                  "The tunnel is closed"))
+                    }
+                };
+                if let Some(error_code) = stream.error_code {
+                    return Err(reset_error(error_code));
+                }
+                if stream.is_closed_local() {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The tunnel is closed"));
+                }
+            }
+            let window = self.conn.out_window(self.stream_id);
+            if window > 0 {
+                return Ok(window as usize);
+            }
+            debug!("Waiting for the server to update the flow control window");
+            try!(self.conn
+                     .handle_next_frame(&mut TransportReceiveFrame::new(&mut self.receiver),
+                                        &mut self.sender)
+                     .map_err(into_io_error));
+        }
+    }
+
+    /// Internal helper method. Sends all the data that is queued on the tunnel's stream. Since
+    /// writes never queue more than the flow control windows allow, all of it can be sent.
+    fn send_pending(&mut self) -> io::Result<()> {
+        loop {
+            match try!(self.conn.send_next_data(&mut self.sender).map_err(into_io_error)) {
+                SendStatus::Sent => {}
+                SendStatus::Nothing => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Converts the given `HttpError` into an `io::Error`, unwrapping the errors that were raised by
+/// the underlying IO layer.
+fn into_io_error(err: HttpError) -> io::Error {
+    match err {
+        HttpError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+/// Returns the `io::Error` signaling that the peer reset the tunnel with the given error code.
+fn reset_error(error_code: ErrorCode) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset,
+                   HttpError::StreamReset(error_code))
+}
+
+impl<S> io::Read for Tunnel<S>
+    where S: TransportStream
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.buf.len() {
+                let len = buf.len().min(self.buf.len() - self.pos);
+                buf[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+                self.pos += len;
+                return Ok(len);
+            }
+
+            {
+                let stream = match self.conn.state.get_stream_mut(self.stream_id) {
+                    Some(stream) => stream,
+                    None => return Ok(0),
+                };
+                self.buf = stream.recv();
+                self.pos = 0;
+                if !self.buf.is_empty() {
+                    continue;
+                }
+                if let Some(error_code) = stream.error_code {
+                    return Err(reset_error(error_code));
+                }
+                if stream.is_closed_remote() {
+                    return Ok(0);
+                }
+            }
+
+            try!(self.conn
+                     .handle_next_frame(&mut TransportReceiveFrame::new(&mut self.receiver),
+                                        &mut self.sender)
+                     .map_err(into_io_error));
+        }
+    }
+}
+
+impl<S> io::Write for Tunnel<S>
+    where S: TransportStream
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let window = try!(self.wait_for_window());
+        let len = cmp::min(buf.len(), window);
+        {
+            let stream = match self.conn.state.get_stream_mut(self.stream_id) {
+                Some(stream) => stream,
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The tunnel is closed"))
+                }
+            };
+            if stream.send(&buf[..len]).is_err() {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The tunnel is closed"));
+            }
+        }
+        try!(self.send_pending());
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use super::Tunnel;
+
+    use hpack;
+
+    use http::{HttpScheme, HttpError, ErrorCode, StaticHeader, Header};
+    use http::frame::{SettingsFrame, HeadersFrame, HeadersFlag, DataFrame, DataFlag,
+                      RstStreamFrame, WindowUpdateFrame, RawFrame, Frame};
+    use http::connection::HttpFrame;
+    use http::tests::common::{StubTransportStream, build_stub_from_frames};
+
+    /// Builds a HEADERS frame carrying the given headers on the given stream.
+    fn headers_frame(stream_id: u32,
+                     headers: Vec<StaticHeader>,
+                     end_stream: bool)
+                     -> HttpFrame<'static> {
+        let mut encoder = hpack::Encoder::new();
+        let fragment = encoder.encode(headers.iter().map(|h| (h.name(), h.value())));
+        let mut frame = HeadersFrame::new(fragment, stream_id);
+        frame.set_flag(HeadersFlag::EndHeaders);
+        if end_stream {
+            frame.set_flag(HeadersFlag::EndStream);
+        }
+        HttpFrame::HeadersFrame(frame)
+    }
+
+    /// Opens a tunnel to `example.com:443` over a stub transport stream that yields the given
+    /// frames, following the server's initial SETTINGS.
+    fn open(frames: Vec<HttpFrame<'static>>)
+            -> (StubTransportStream, Result<Tunnel<StubTransportStream>, HttpError>) {
+        let mut all = vec![HttpFrame::SettingsFrame(SettingsFrame::new())];
+        all.extend(frames);
+        let transport = StubTransportStream::with_stub_content(&build_stub_from_frames(&all));
+        let tunnel = Tunnel::with_stream(transport.clone(), HttpScheme::Http, "example.com:443");
+        (transport, tunnel)
+    }
+
+    /// Returns all the frames that were written to the given transport stream.
+    fn written_frames(transport: &StubTransportStream) -> Vec<RawFrame<'static>> {
+        let written = transport.get_written();
+        let mut frames = Vec::new();
+        let mut pos = 0;
+        while let Some(len) = RawFrame::parse(&written[pos..]).map(|raw| raw.len()) {
+            frames.push(RawFrame::from(written[pos..pos + len].to_vec()));
+            pos += len;
+        }
+        frames
+    }
+
+    #[test]
+    fn test_tunnel_read_write() {
+        let mut end = DataFrame::new(1);
+        end.set_flag(DataFlag::EndStream);
+        let (transport, tunnel) = open(vec![
+            headers_frame(1, vec![Header::new(b":status", b"200")], false),
+            HttpFrame::DataFrame(DataFrame::with_data(1, &b"pong"[..])),
+            HttpFrame::DataFrame(end),
+        ]);
+        let mut tunnel = tunnel.ok().unwrap();
+        assert_eq!(tunnel.stream_id(), 1);
+        assert_eq!(tunnel.headers()[0].value(), b"200");
+
+        tunnel.write_all(b"ping").unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(tunnel.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"po");
+        let mut rest = Vec::new();
+        tunnel.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ng".to_vec());
+
+        let raw_frames = written_frames(&transport);
+        let frames: Vec<_> = raw_frames.iter()
+                                       .map(|raw| HttpFrame::from_raw(raw).unwrap())
+                                       .collect();
+        // The CONNECT request carries only the `:method` and `:authority` pseudo-headers...
+        let request = frames.iter().filter_map(|frame| match *frame {
+            HttpFrame::HeadersFrame(ref frame) => Some(frame),
+            _ => None,
+        }).next().unwrap();
+        assert!(!request.is_end_of_stream());
+        let mut decoder = hpack::Decoder::new();
+        let headers = decoder.decode(request.header_fragment()).unwrap();
+        assert_eq!(headers,
+                   vec![(b":method".to_vec(), b"CONNECT".to_vec()),
+                        (b":authority".to_vec(), b"example.com:443".to_vec())]);
+        // ...and the written data follows it on the same stream.
+        let data: Vec<_> = frames.iter().filter_map(|frame| match *frame {
+            HttpFrame::DataFrame(ref frame) => Some(frame),
+            _ => None,
+        }).collect();
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].get_stream_id(), 1);
+        assert_eq!(&data[0].data[..], b"ping");
+    }
+
+    /// Tests that closing the tunnel ends the stream once the pending data is sent.
+    #[test]
+    fn test_tunnel_close() {
+        let (transport, tunnel) = open(vec![
+            headers_frame(1, vec![Header::new(b":status", b"200")], false),
+        ]);
+        let mut tunnel = tunnel.ok().unwrap();
+        tunnel.write_all(b"bye").unwrap();
+        tunnel.close().unwrap();
+        assert_eq!(tunnel.write(b"more").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+
+        let last = written_frames(&transport).pop().unwrap();
+        match HttpFrame::from_raw(&last).unwrap() {
+            HttpFrame::DataFrame(frame) => {
+                assert!(frame.is_end_of_stream());
+                assert!(frame.data.is_empty());
+            }
+            _ => panic!("Expected a DATA frame"),
+        }
+    }
+
+    /// Returns the total length of the DATA frames among the given frames.
+    fn data_len(frames: &[RawFrame]) -> usize {
+        frames.iter()
+              .filter_map(|raw| match HttpFrame::from_raw(raw).unwrap() {
+                  HttpFrame::DataFrame(frame) => Some(frame.data.len()),
+                  _ => None,
+              })
+              .sum()
+    }
+
+    /// Tests that writing more than the initial flow control window waits for the server to
+    /// update the windows, rather than going over them.
+    #[test]
+    fn test_tunnel_write_flow_control() {
+        let (transport, tunnel) = open(vec![
+            headers_frame(1, vec![Header::new(b":status", b"200")], false),
+            HttpFrame::DataFrame(DataFrame::with_data(1, &b"pong"[..])),
+            HttpFrame::WindowUpdateFrame(WindowUpdateFrame::for_connection(40000)),
+            HttpFrame::WindowUpdateFrame(WindowUpdateFrame::for_stream(1, 40000)),
+        ]);
+        let mut tunnel = tunnel.ok().unwrap();
+        let data = vec![0x61; 100000];
+        tunnel.write_all(&data).unwrap();
+        tunnel.flush().unwrap();
+        assert_eq!(data_len(&written_frames(&transport)), 100000);
+        // The data that arrived while the write was waiting can still be read.
+        let mut buf = [0; 4];
+        tunnel.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+
+        // The windows have room for only 5535 more octets, which the server never updates.
+        let err = tunnel.write_all(&data[..10000]).unwrap_err();
+        assert!(err.kind() != io::ErrorKind::BrokenPipe);
+        assert_eq!(data_len(&written_frames(&transport)), 105535);
+    }
+
+    #[test]
+    fn test_tunnel_refused() {
+        let (_, tunnel) = open(vec![
+            headers_frame(1, vec![Header::new(b":status", b"503")], true),
+        ]);
+        assert_eq!(tunnel.err().unwrap(), HttpError::TunnelRefused(503));
+    }
+
+    /// Tests that a tunnel which the server fails to establish is reported as a reset stream.
+    #[test]
+    fn test_tunnel_connect_error() {
+        let (_, tunnel) = open(vec![
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(1, ErrorCode::ConnectError)),
+        ]);
+        assert_eq!(tunnel.err().unwrap(), HttpError::StreamReset(ErrorCode::ConnectError));
+    }
+
+    /// Tests that a tunnel which fails after it was opened makes reads and writes fail.
+    #[test]
+    fn test_tunnel_reset_after_open() {
+        let (_, tunnel) = open(vec![
+            headers_frame(1, vec![Header::new(b":status", b"200")], false),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(1, ErrorCode::ConnectError)),
+        ]);
+        let mut tunnel = tunnel.ok().unwrap();
+        let err = tunnel.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(tunnel.write(b"data").unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+    }
+}
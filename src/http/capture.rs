@@ -0,0 +1,435 @@
+//! The module contains tooling for capturing the raw traffic of a connection and replaying it
+//! later on.
+//!
+//! A `RecordingStream` wraps any `TransportStream` and writes every chunk of bytes that is read
+//! from it or written to it into a capture, along with its direction and the time at which it
+//! went through. A `ReplayStream` is a `TransportStream` that plays the part of the peer of a
+//! captured connection: it gives back the bytes that were read, in the same chunks, and collects
+//! whatever is written to it, so that it can be compared against what was originally written.
+//! This makes it possible to turn a session seen in the wild into a deterministic regression test
+//! for a `ClientConnection` or a `ServerConnection`.
+//!
+//! The capture format is a magic header (`H2CAP001`), followed by one record per chunk: a byte
+//! giving the direction (`0` for read, `1` for written), the number of microseconds since the
+//! recording started (a 64-bit big-endian integer), the length of the chunk (a 32-bit big-endian
+//! integer) and the chunk's bytes.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use http::transport::TransportStream;
+
+/// The magic octets that every capture starts with.
+pub const MAGIC: &'static [u8; 8] = b"H2CAP001";
+
+/// The direction in which a captured chunk of bytes went through the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The bytes were read from the stream, i.e. sent by the peer.
+    Read,
+    /// The bytes were written to the stream, i.e. sent to the peer.
+    Written,
+}
+
+/// A single chunk of bytes in a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub direction: Direction,
+    /// The time between the start of the recording and the moment that the chunk went through.
+    pub elapsed: Duration,
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// Serializes the record into the given writer.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let direction = match self.direction {
+            Direction::Read => 0,
+            Direction::Written => 1,
+        };
+        let micros = self.elapsed.as_secs() * 1_000_000 + self.elapsed.subsec_micros() as u64;
+        try!(out.write_all(&[direction]));
+        try!(out.write_all(&micros.to_be_bytes()));
+        try!(out.write_all(&(self.data.len() as u32).to_be_bytes()));
+        out.write_all(&self.data)
+    }
+
+    /// Parses the next record out of the given reader. Returns `None` if the reader is at its
+    /// end.
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Option<CaptureRecord>> {
+        let mut head = [0; 13];
+        // An end of file is only fine on a record boundary.
+        if try!(input.read(&mut head[..1])) == 0 {
+            return Ok(None);
+        }
+        try!(input.read_exact(&mut head[1..]));
+        let direction = match head[0] {
+            0 => Direction::Read,
+            1 => Direction::Written,
+            _ => return Err(invalid_capture("Invalid record direction")),
+        };
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&head[1..9]);
+        let micros = u64::from_be_bytes(micros);
+        let mut len = [0; 4];
+        len.copy_from_slice(&head[9..13]);
+        let mut data = vec![0; u32::from_be_bytes(len) as usize];
+        try!(input.read_exact(&mut data));
+        Ok(Some(CaptureRecord {
+            direction: direction,
+            elapsed: Duration::new(micros / 1_000_000, (micros % 1_000_000) as u32 * 1000),
+            data: data,
+        }))
+    }
+}
+
+fn invalid_capture(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads all the records of the capture that the given reader provides.
+pub fn read_capture<R: Read>(mut input: R) -> io::Result<Vec<CaptureRecord>> {
+    let mut magic = [0; 8];
+    try!(input.read_exact(&mut magic));
+    if &magic != MAGIC {
+        return Err(invalid_capture("Not a capture"));
+    }
+    let mut records = Vec::new();
+    while let Some(record) = try!(CaptureRecord::read_from(&mut input)) {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Reads all the records of the capture in the file at the given path.
+pub fn read_capture_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<CaptureRecord>> {
+    read_capture(io::BufReader::new(try!(File::open(path))))
+}
+
+/// The writer of a capture, shared by all the handles of a `RecordingStream`.
+struct CaptureSink<W: Write> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> CaptureSink<W> {
+    fn record(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let record = CaptureRecord {
+            direction: direction,
+            elapsed: self.start.elapsed(),
+            data: data.to_vec(),
+        };
+        try!(record.write_to(&mut self.out));
+        self.out.flush()
+    }
+}
+
+/// A `TransportStream` that wraps another one, writing every chunk of bytes that goes through it
+/// into a capture.
+///
+/// The handles that `try_split` returns write into the same capture.
+pub struct RecordingStream<TS, W>
+    where TS: TransportStream,
+          W: Write
+{
+    inner: TS,
+    sink: Arc<Mutex<CaptureSink<W>>>,
+}
+
+impl<TS, W> RecordingStream<TS, W>
+    where TS: TransportStream,
+          W: Write
+{
+    /// Creates a new `RecordingStream` that wraps the given stream and writes the capture into
+    /// the given writer.
+    pub fn new(inner: TS, mut out: W) -> io::Result<RecordingStream<TS, W>> {
+        try!(out.write_all(MAGIC));
+        Ok(RecordingStream {
+            inner: inner,
+            sink: Arc::new(Mutex::new(CaptureSink {
+                out: out,
+                start: Instant::now(),
+            })),
+        })
+    }
+
+    fn record(&self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        self.sink.lock().unwrap().record(direction, data)
+    }
+}
+
+impl<TS> RecordingStream<TS, BufWriter<File>>
+    where TS: TransportStream
+{
+    /// Creates a new `RecordingStream` that wraps the given stream and writes the capture into
+    /// the file at the given path, replacing the file if it already exists.
+    pub fn to_file<P: AsRef<Path>>(inner: TS,
+                                   path: P)
+                                   -> io::Result<RecordingStream<TS, BufWriter<File>>> {
+        let file = try!(File::create(path));
+        RecordingStream::new(inner, BufWriter::new(file))
+    }
+}
+
+impl<TS, W> Read for RecordingStream<TS, W>
+    where TS: TransportStream,
+          W: Write
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.inner.read(buf));
+        if read > 0 {
+            try!(self.record(Direction::Read, &buf[..read]));
+        }
+        Ok(read)
+    }
+}
+
+impl<TS, W> Write for RecordingStream<TS, W>
+    where TS: TransportStream,
+          W: Write
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.inner.write(buf));
+        if written > 0 {
+            try!(self.record(Direction::Written, &buf[..written]));
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<TS, W> TransportStream for RecordingStream<TS, W>
+    where TS: TransportStream,
+          W: Write
+{
+    fn try_split(&self) -> Result<RecordingStream<TS, W>, io::Error> {
+        Ok(RecordingStream {
+            inner: try!(self.inner.try_split()),
+            sink: self.sink.clone(),
+        })
+    }
+
+    fn close(&mut self) -> Result<(), io::Error> {
+        self.inner.close()
+    }
+}
+
+/// The state of a replay, shared by all the handles of a `ReplayStream`.
+struct Replay {
+    /// The chunks that are still to be read, the first one of which may be partially read.
+    reads: VecDeque<Vec<u8>>,
+    /// The bytes that were written in the capture.
+    expected: Vec<u8>,
+    /// The bytes that have been written to the stream.
+    written: Vec<u8>,
+    closed: bool,
+}
+
+/// A `TransportStream` that replays the part of the peer in a captured session.
+///
+/// Reads return the bytes that were read in the capture, never more than a single captured chunk
+/// at a time, and then report the end of the stream. Writes are collected, so that they can be
+/// checked against the bytes that were written in the capture. The timing of the capture is not
+/// reproduced.
+#[derive(Clone)]
+pub struct ReplayStream {
+    replay: Arc<Mutex<Replay>>,
+}
+
+impl ReplayStream {
+    /// Creates a new `ReplayStream` that replays the given records.
+    pub fn new(records: &[CaptureRecord]) -> ReplayStream {
+        let mut reads = VecDeque::new();
+        let mut expected = Vec::new();
+        for record in records {
+            match record.direction {
+                Direction::Read => reads.push_back(record.data.clone()),
+                Direction::Written => expected.extend_from_slice(&record.data),
+            }
+        }
+        ReplayStream {
+            replay: Arc::new(Mutex::new(Replay {
+                reads: reads,
+                expected: expected,
+                written: Vec::new(),
+                closed: false,
+            })),
+        }
+    }
+
+    /// Creates a new `ReplayStream` that replays the capture in the file at the given path.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<ReplayStream> {
+        Ok(ReplayStream::new(&try!(read_capture_file(path))))
+    }
+
+    /// Returns all the bytes that have been written to the stream so far.
+    pub fn get_written(&self) -> Vec<u8> {
+        self.replay.lock().unwrap().written.clone()
+    }
+
+    /// Returns all the bytes that were written in the capture.
+    pub fn expected_written(&self) -> Vec<u8> {
+        self.replay.lock().unwrap().expected.clone()
+    }
+
+    /// Returns whether all the captured bytes have been read.
+    pub fn is_exhausted(&self) -> bool {
+        self.replay.lock().unwrap().reads.is_empty()
+    }
+}
+
+impl Read for ReplayStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut replay = self.replay.lock().unwrap();
+        if replay.closed {
+            return Err(io::Error::new(io::ErrorKind::Other, "Closed"));
+        }
+        let (read, done) = match replay.reads.front_mut() {
+            None => return Ok(0),
+            Some(chunk) => {
+                let read = ::std::cmp::min(buf.len(), chunk.len());
+                buf[..read].copy_from_slice(&chunk[..read]);
+                chunk.drain(..read);
+                (read, chunk.is_empty())
+            }
+        };
+        if done {
+            replay.reads.pop_front();
+        }
+        Ok(read)
+    }
+}
+
+impl Write for ReplayStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut replay = self.replay.lock().unwrap();
+        if replay.closed {
+            return Err(io::Error::new(io::ErrorKind::Other, "Closed"));
+        }
+        replay.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TransportStream for ReplayStream {
+    fn try_split(&self) -> Result<ReplayStream, io::Error> {
+        Ok(self.clone())
+    }
+
+    fn close(&mut self) -> Result<(), io::Error> {
+        self.replay.lock().unwrap().closed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{RecordingStream, ReplayStream, CaptureRecord, Direction, read_capture};
+    use http::{Header, HttpScheme, Response};
+    use http::frame::{SettingsFrame, DataFrame, DataFlag};
+    use http::connection::HttpFrame;
+    use http::transport::TransportStream;
+    use http::tests::common::{StubTransportStream, build_stub_from_frames, build_headers_frame};
+    use client::SimpleClient;
+    use hpack;
+
+    /// A writer into a buffer that remains reachable once the writer is moved away.
+    #[derive(Clone)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The frames of a server that answers the first request with `200` and a body.
+    fn server_frames() -> Vec<u8> {
+        let block = hpack::Encoder::new().encode(vec![(&b":status"[..], &b"200"[..])]);
+        let mut data = DataFrame::with_data(1, &b"hello"[..]);
+        data.set_flag(DataFlag::EndStream);
+        build_stub_from_frames(&vec![
+            HttpFrame::SettingsFrame(SettingsFrame::new()),
+            HttpFrame::HeadersFrame(build_headers_frame(block, 1)),
+            HttpFrame::DataFrame(data),
+        ])
+    }
+
+    /// Performs a GET request over the given stream.
+    fn fetch<S: TransportStream>(stream: S) -> Response<'static, 'static> {
+        let mut client = SimpleClient::with_stream(stream, "example.com".into(), HttpScheme::Http)
+                             .unwrap();
+        let stream_id = client.request(b"GET", b"/", &[], None).unwrap();
+        client.get_response(stream_id).unwrap()
+    }
+
+    /// Tests that the records survive being written into a capture and read back.
+    #[test]
+    fn test_capture_record_round_trip() {
+        let records = vec![
+            CaptureRecord {
+                direction: Direction::Written,
+                elapsed: Duration::from_millis(1),
+                data: vec![1, 2, 3],
+            },
+            CaptureRecord {
+                direction: Direction::Read,
+                elapsed: Duration::new(2, 5000),
+                data: vec![],
+            },
+        ];
+        let mut buf = b"H2CAP001".to_vec();
+        for record in &records {
+            record.write_to(&mut buf).unwrap();
+        }
+
+        assert_eq!(read_capture(&buf[..]).unwrap(), records);
+        assert!(read_capture(&buf[1..]).is_err());
+        assert!(read_capture(&buf[..buf.len() - 1]).is_err());
+    }
+
+    /// Tests that a session recorded through a `RecordingStream` can be replayed with the same
+    /// outcome and the same bytes written.
+    #[test]
+    fn test_record_and_replay() {
+        let stub = StubTransportStream::with_stub_content(&server_frames());
+        let capture = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+        let recorded = fetch(RecordingStream::new(stub.clone(), capture.clone()).unwrap());
+
+        let records = read_capture(&capture.0.lock().unwrap()[..]).unwrap();
+        let read: Vec<u8> = records.iter()
+                                   .filter(|r| r.direction == Direction::Read)
+                                   .flat_map(|r| r.data.clone())
+                                   .collect();
+        assert_eq!(&read[..], &server_frames()[..]);
+
+        let replay = ReplayStream::new(&records);
+        let replayed = fetch(replay.clone());
+        assert_eq!(replayed.headers, recorded.headers);
+        assert_eq!(replayed.body, b"hello".to_vec());
+        assert_eq!(replay.get_written(), stub.get_written());
+        assert_eq!(replay.get_written(), replay.expected_written());
+        assert!(replay.is_exhausted());
+        assert_eq!(replayed.headers[0], Header::new(b":status", b"200"));
+    }
+}
@@ -8,11 +8,18 @@ use std::error;
 
 use http::{HttpScheme, HttpResult, StreamId, Header, HttpError, ErrorCode};
 use http::transport::TransportStream;
-use http::frame::{SettingsFrame, HttpSetting, FrameIR, PingFrame};
+use http::frame::{SettingsFrame, HttpSetting, FrameIR, PingFrame, StreamDependency, RawFrame,
+                  ExtensionFrame, AltSvcFrame, OriginFrame};
 use http::connection::{SendFrame, ReceiveFrame, SendStatus, HttpConnection, EndStream};
 use http::session::{Session, Stream, DefaultStream, DefaultSessionState, SessionState};
 use http::session::Client as ClientMarker;
 use http::priority::SimplePrioritizer;
+use http::padding::PaddingPolicy;
+use http::compression::IndexingStrategy;
+use http::flood::FloodLimits;
+use http::stats::ConnectionStats;
+use http::observer::FrameObserver;
+use http::validation::{validate_headers, HeaderBlockKind, ContentLengthError};
 
 #[cfg(feature="tls")]
 pub mod tls;
@@ -185,6 +192,19 @@ pub struct RequestStream<'n, 'v, S>
     /// The underlying `Stream` instance, which will handle the response, as well as optionally
     /// provide the body of the request.
     pub stream: S,
+    /// The priority to assign to the stream when sending the request, if any.
+    pub priority: Option<StreamDependency>,
+}
+
+/// The alternative services and the origins that the server has advertised on a connection,
+/// by sending `ALTSVC` (RFC 7838) and `ORIGIN` (RFC 8336) frames.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Advertisements {
+    /// All `ALTSVC` frames received on the connection, in the order of their arrival.
+    pub alt_svcs: Vec<AltSvcFrame>,
+    /// The origin set of the connection: the origins from all `ORIGIN` frames received on it.
+    /// It is `None` until the server sends the first `ORIGIN` frame.
+    pub origins: Option<Vec<Vec<u8>>>,
 }
 
 /// The struct extends the `HttpConnection` API with client-specific methods (such as
@@ -198,6 +218,8 @@ pub struct ClientConnection<State = DefaultSessionState<ClientMarker, DefaultStr
     /// The state of the session associated to this client connection. Maintains the status of the
     /// connection streams.
     pub state: State,
+    /// The alternative services and origins that the server advertised.
+    advertisements: Advertisements,
 }
 
 impl<State> ClientConnection<State>
@@ -207,10 +229,14 @@ impl<State> ClientConnection<State>
     /// for all its underlying HTTP/2 communication.
     ///
     /// The given `state` instance will handle the maintenance of the session's state.
-    pub fn with_connection(conn: HttpConnection, state: State) -> ClientConnection<State> {
+    ///
+    /// The client preface is expected to have been sent already (see `write_preface`).
+    pub fn with_connection(mut conn: HttpConnection, state: State) -> ClientConnection<State> {
+        conn.preface_settings_sent();
         ClientConnection {
             conn: conn,
             state: state,
+            advertisements: Advertisements::default(),
         }
     }
 
@@ -220,6 +246,99 @@ impl<State> ClientConnection<State>
         self.conn.scheme
     }
 
+    /// Sets the policy that decides how the frames sent on the connection are padded.
+    #[inline]
+    pub fn set_padding_policy(&mut self, policy: PaddingPolicy) {
+        self.conn.padding_policy = policy;
+    }
+
+    /// Sets the strategy with which the HPACK encoder indexes the headers with the given name
+    /// (e.g. `IndexingStrategy::NeverIndexed` for headers that carry secrets).
+    #[inline]
+    pub fn set_indexing_strategy<N: Into<Vec<u8>>>(&mut self, name: N, strategy: IndexingStrategy) {
+        self.conn.encoder.set_strategy(name, strategy);
+    }
+
+    /// Advertises the given maximum size of the HPACK dynamic table that the connection's decoder
+    /// uses, by sending a SETTINGS frame. The decoder adopts the new size once the server
+    /// acknowledges the settings.
+    pub fn set_header_table_size<S: SendFrame>(&mut self,
+                                               size: u32,
+                                               sender: &mut S)
+                                               -> HttpResult<()> {
+        self.conn.sender(sender).send_settings(vec![HttpSetting::HeaderTableSize(size)])
+    }
+
+    /// Advertises the given maximum size of the header lists that the connection accepts, by
+    /// sending a SETTINGS frame. Larger header lists that the server sends are discarded from then
+    /// on.
+    pub fn set_max_header_list_size<S: SendFrame>(&mut self,
+                                                  size: u32,
+                                                  sender: &mut S)
+                                                  -> HttpResult<()> {
+        self.conn.sender(sender).send_settings(vec![HttpSetting::MaxHeaderListSize(size)])
+    }
+
+    /// Sets the limits on the rate at which the server may send RST_STREAM, PING, SETTINGS and
+    /// empty DATA frames. A server that goes over any of them is sent a GOAWAY frame with the
+    /// `ENHANCE_YOUR_CALM` error code.
+    #[inline]
+    pub fn set_flood_limits(&mut self, limits: FloodLimits) {
+        self.conn.flood_guard.set_limits(limits);
+    }
+
+    /// Attaches the given observer to the connection, replacing the previous one, if any. It is
+    /// shown every frame that is sent to and received from the server, along with the headers.
+    pub fn set_frame_observer<O: FrameObserver + 'static>(&mut self, observer: O) {
+        self.conn.observer = Some(Box::new(observer));
+    }
+
+    /// Returns the number of octets of data that can currently be sent on the given stream, without
+    /// going over either the stream's or the connection's outbound flow control window.
+    pub fn out_window(&self, stream_id: StreamId) -> i32 {
+        self.conn.stream_out_window(stream_id)
+    }
+
+    /// Returns a snapshot of the connection's statistics, including the flow control windows of
+    /// all the streams that the session state tracks.
+    pub fn stats(&mut self) -> ConnectionStats {
+        let mut stats = self.conn.stats();
+        let mut stream_ids: Vec<StreamId> = self.state.iter().map(|(&id, _)| id).collect();
+        stream_ids.sort();
+        stats.streams = stream_ids.iter().map(|&id| self.conn.stream_stats(id)).collect();
+        stats.active_streams = stream_ids.len();
+        stats
+    }
+
+    /// Registers the given parser for the frames of the given extension frame type. Parsed frames
+    /// are passed to the session's `on_extension_frame` callback.
+    pub fn register_extension<P>(&mut self, frame_type: u8, parser: P)
+        where P: Fn(&RawFrame) -> Option<Box<ExtensionFrame>> + Send + 'static
+    {
+        self.conn.extensions.register(frame_type, parser);
+    }
+
+    /// Sends the given frame of an extension frame type.
+    pub fn send_extension_frame<F: FrameIR, S: SendFrame>(&mut self,
+                                                          frame: F,
+                                                          sender: &mut S)
+                                                          -> HttpResult<()> {
+        self.conn.sender(sender).send_extension_frame(frame)
+    }
+
+    /// Returns the alternative services and origins that the server has advertised so far.
+    #[inline]
+    pub fn advertisements(&self) -> &Advertisements {
+        &self.advertisements
+    }
+
+    /// Returns the maximum number of concurrent streams that the server allows, if it has
+    /// advertised one.
+    #[inline]
+    pub fn peer_max_concurrent_streams(&self) -> Option<u32> {
+        self.conn.peer_max_concurrent_streams
+    }
+
     /// Handles the next frame provided by the given frame receiver and expects it to be a
     /// `SETTINGS` frame. If it is not, it returns an error.
     ///
@@ -247,11 +366,83 @@ impl<State> ClientConnection<State>
             EndStream::No
         };
         let stream_id = self.state.insert_outgoing(req.stream);
-        try!(self.conn.sender(sender).send_headers(req.headers, stream_id, end_stream));
+        try!(self.conn
+                 .sender(sender)
+                 .send_headers_with_priority(req.headers, stream_id, req.priority, end_stream));
 
         Ok(stream_id)
     }
 
+    /// Starts an extended CONNECT request (RFC 8441), which opens a stream for the given
+    /// protocol (e.g. `websocket`) at the given authority and path, sending the given extra
+    /// headers along with the pseudo-headers.
+    ///
+    /// Once the server accepts the request with a 2xx response, data flows in both directions on
+    /// the stream until either side closes it, so the given stream is usually a `DuplexStream`.
+    ///
+    /// # Returns
+    ///
+    /// `HttpError::ConnectProtocolDisabled` if the server has not enabled extended CONNECT; it
+    /// can only have done so in the settings that it sent at the start of the connection.
+    pub fn start_extended_connect<'n, 'v, S: SendFrame>(&mut self,
+                                                        protocol: &[u8],
+                                                        authority: &[u8],
+                                                        path: &[u8],
+                                                        extra_headers: Vec<Header<'n, 'v>>,
+                                                        stream: State::Stream,
+                                                        sender: &mut S)
+                                                        -> HttpResult<StreamId> {
+        if !self.conn.peer_connect_protocol_enabled {
+            return Err(HttpError::ConnectProtocolDisabled);
+        }
+        let mut headers = vec![
+            Header::new(b":method", b"CONNECT"),
+            Header::new(b":protocol", protocol.to_vec()),
+            Header::new(b":scheme", self.conn.scheme.as_bytes()),
+            Header::new(b":path", path.to_vec()),
+            Header::new(b":authority", authority.to_vec()),
+        ];
+        headers.extend(extra_headers);
+        self.start_request(RequestStream {
+                               headers: headers,
+                               stream: stream,
+                               priority: None,
+                           },
+                           sender)
+    }
+
+    /// Returns whether the server has enabled extended CONNECT (RFC 8441), allowing the client to
+    /// use `start_extended_connect`.
+    #[inline]
+    pub fn peer_connect_protocol_enabled(&self) -> bool {
+        self.conn.peer_connect_protocol_enabled
+    }
+
+    /// Changes the priority of the stream with the given ID by sending a PRIORITY frame, which
+    /// can be done at any point while the request is in flight.
+    pub fn reprioritize<S: SendFrame>(&mut self,
+                                      stream_id: StreamId,
+                                      priority: StreamDependency,
+                                      sender: &mut S)
+                                      -> HttpResult<()> {
+        self.conn.sender(sender).send_priority(stream_id, priority)
+    }
+
+    /// Resets the stream with the given ID by sending a RST_STREAM frame with the given error code
+    /// and stops tracking the stream in the session state.
+    ///
+    /// # Returns
+    ///
+    /// The `Stream` instance that was removed from the session state, if the stream was known.
+    pub fn reset_stream<S: SendFrame>(&mut self,
+                                      stream_id: StreamId,
+                                      error_code: ErrorCode,
+                                      sender: &mut S)
+                                      -> HttpResult<Option<State::Stream>> {
+        try!(self.conn.sender(sender).rst_stream(stream_id, error_code));
+        Ok(self.state.remove_stream(stream_id))
+    }
+
     /// Send a PING
     pub fn send_ping<S: SendFrame>(&mut self, sender: &mut S) -> HttpResult<()> {
         try!(self.conn.sender(sender).send_ping(0));
@@ -264,8 +455,23 @@ impl<State> ClientConnection<State>
                                                                     rx: &mut Recv,
                                                                     tx: &mut Sender)
                                                                     -> HttpResult<()> {
-        let mut session = ClientSession::new(&mut self.state, tx);
-        self.conn.handle_next_frame(rx, &mut session)
+        let res = {
+            let mut session = ClientSession::with_advertisements(&mut self.state,
+                                                                 &mut self.advertisements,
+                                                                 tx);
+            self.conn.handle_next_frame(rx, &mut session)
+        };
+        try!(self.conn.sender(tx).send_pending_resets());
+        let error_code = match res {
+            Err(HttpError::Flood(_)) => Some(ErrorCode::EnhanceYourCalm),
+            Err(HttpError::WindowSizeOverflow) => Some(ErrorCode::FlowControlError),
+            _ => None,
+        };
+        if let Some(error_code) = error_code {
+            // The server cannot open streams of its own, so none of them were processed.
+            try!(self.conn.sender(tx).send_goaway(0, error_code));
+        }
+        res
     }
 
     /// Queues a new DATA frame onto the underlying `SendFrame`.
@@ -278,6 +484,16 @@ impl<State> ClientConnection<State>
         const MAX_CHUNK_SIZE: usize = 8 * 1024;
         let mut buf = [0; MAX_CHUNK_SIZE];
 
+        // Give back the window of the data that the streams have consumed in the meantime, and
+        // let the streams know how much data they may send.
+        for (&stream_id, stream) in self.state.iter() {
+            stream.set_out_window(self.conn.stream_out_window(stream_id));
+            if stream.defers_window_updates() {
+                self.conn.release_window(stream_id, stream.take_consumed());
+            }
+        }
+        try!(self.conn.sender(sender).send_window_updates());
+
         let mut prioritizer = SimplePrioritizer::new(&mut self.state, &mut buf);
         self.conn.sender(sender).send_next_data(&mut prioritizer)
     }
@@ -302,6 +518,7 @@ pub struct ClientSession<'a, State, S>
 {
     state: &'a mut State,
     sender: &'a mut S,
+    advertisements: Option<&'a mut Advertisements>,
 }
 
 impl<'a, State, S> ClientSession<'a, State, S>
@@ -309,11 +526,28 @@ impl<'a, State, S> ClientSession<'a, State, S>
           S: SendFrame + 'a
 {
     /// Returns a new `ClientSession` associated to the given state.
+    ///
+    /// The session ignores any alternative services and origins that the server advertises.
     #[inline]
     pub fn new(state: &'a mut State, sender: &'a mut S) -> ClientSession<'a, State, S> {
         ClientSession {
             state: state,
             sender: sender,
+            advertisements: None,
+        }
+    }
+
+    /// Returns a new `ClientSession` associated to the given state, which records the alternative
+    /// services and origins that the server advertises into the given `Advertisements`.
+    #[inline]
+    pub fn with_advertisements(state: &'a mut State,
+                               advertisements: &'a mut Advertisements,
+                               sender: &'a mut S)
+                               -> ClientSession<'a, State, S> {
+        ClientSession {
+            state: state,
+            sender: sender,
+            advertisements: Some(advertisements),
         }
     }
 }
@@ -325,28 +559,31 @@ impl<'a, State, S> Session for ClientSession<'a, State, S>
     fn new_data_chunk(&mut self,
                       stream_id: StreamId,
                       data: &[u8],
-                      _: &mut HttpConnection)
+                      conn: &mut HttpConnection)
                       -> HttpResult<()> {
         debug!("Data chunk for stream {}", stream_id);
-        let mut stream = match self.state.get_stream_mut(stream_id) {
+        match self.state.get_stream_mut(stream_id) {
             None => {
                 debug!("Received a frame for an unknown stream!");
                 // TODO(mlalic): This can currently indicate two things:
                 //                 1) the stream was idle => PROTOCOL_ERROR
                 //                 2) the stream was closed => STREAM_CLOSED (stream error)
-                return Ok(());
             }
-            Some(stream) => stream,
+            // Now let the stream handle the data chunk
+            Some(stream) => {
+                stream.new_data_chunk(data);
+                if stream.defers_window_updates() {
+                    conn.hold_window(stream_id, data.len() as u32);
+                }
+            }
         };
-        // Now let the stream handle the data chunk
-        stream.new_data_chunk(data);
-        Ok(())
+        conn.sender(self.sender).send_window_updates()
     }
 
     fn new_headers<'n, 'v>(&mut self,
                            stream_id: StreamId,
                            headers: Vec<Header<'n, 'v>>,
-                           _conn: &mut HttpConnection)
+                           conn: &mut HttpConnection)
                            -> HttpResult<()> {
         debug!("Headers for stream {}", stream_id);
         let mut stream = match self.state.get_stream_mut(stream_id) {
@@ -358,6 +595,20 @@ impl<'a, State, S> Session for ClientSession<'a, State, S>
             }
             Some(stream) => stream,
         };
+        // Headers on a stream that already has its response correspond to having received
+        // trailers...
+        let kind = if conn.message_started(stream_id) {
+            HeaderBlockKind::Trailers
+        } else {
+            HeaderBlockKind::Response
+        };
+        if let Err(err) = validate_headers(&headers, kind) {
+            // A malformed response is a stream error of type PROTOCOL_ERROR.
+            debug!("Stream {}: {}", stream_id, err);
+            try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::ProtocolError));
+            stream.on_malformed_headers(err);
+            return Ok(());
+        }
         // Now let the stream handle the headers
         stream.set_headers(headers);
         Ok(())
@@ -379,6 +630,42 @@ impl<'a, State, S> Session for ClientSession<'a, State, S>
         Ok(())
     }
 
+    fn content_length_mismatch(&mut self,
+                               stream_id: StreamId,
+                               err: ContentLengthError,
+                               conn: &mut HttpConnection)
+                               -> HttpResult<()> {
+        debug!("Stream {}: {}", stream_id, err);
+        let mut stream = match self.state.get_stream_mut(stream_id) {
+            None => {
+                debug!("Received a frame for an unknown stream!");
+                return Ok(());
+            }
+            Some(stream) => stream,
+        };
+        // A malformed response is a stream error of type PROTOCOL_ERROR.
+        try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::ProtocolError));
+        stream.on_content_length_mismatch(err);
+        Ok(())
+    }
+
+    fn header_list_too_large(&mut self,
+                             stream_id: StreamId,
+                             conn: &mut HttpConnection)
+                             -> HttpResult<()> {
+        let mut stream = match self.state.get_stream_mut(stream_id) {
+            None => {
+                debug!("Received a frame for an unknown stream!");
+                return Ok(());
+            }
+            Some(stream) => stream,
+        };
+        // The response is abandoned, since its headers cannot be handed to the stream.
+        try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::Cancel));
+        stream.on_rst_stream(ErrorCode::Cancel);
+        Ok(())
+    }
+
     fn rst_stream(&mut self,
                   stream_id: StreamId,
                   error_code: ErrorCode,
@@ -406,19 +693,45 @@ impl<'a, State, S> Session for ClientSession<'a, State, S>
         debug!("Received a PING ack");
         Ok(())
     }
+
+    fn on_altsvc(&mut self, frame: AltSvcFrame, _conn: &mut HttpConnection) -> HttpResult<()> {
+        if let Some(ref mut advertisements) = self.advertisements {
+            advertisements.alt_svcs.push(frame);
+        }
+        Ok(())
+    }
+
+    fn on_origin(&mut self, frame: OriginFrame, _conn: &mut HttpConnection) -> HttpResult<()> {
+        if let Some(ref mut advertisements) = self.advertisements {
+            let origins = advertisements.origins.get_or_insert_with(Vec::new);
+            for origin in frame.origins() {
+                if !origins.contains(origin) {
+                    origins.push(origin.clone());
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ClientSession, write_preface, RequestStream};
+    use std::io::{Read, Write};
+
+    use super::{ClientSession, ClientConnection, write_preface, RequestStream};
 
     use http::{Header, ErrorCode, HttpError};
     use http::tests::common::{TestStream, build_mock_client_conn, build_mock_http_conn,
-                              MockReceiveFrame, MockSendFrame};
-    use http::frame::{SettingsFrame, DataFrame, Frame, RawFrame};
+                              build_headers_frame, MockReceiveFrame, MockSendFrame,
+                              serialize_frame};
+    use http::frame::{SettingsFrame, DataFrame, Frame, RawFrame, AltSvcFrame, OriginFrame,
+                      HttpSetting, WindowUpdateFrame};
+    use http::handle::{StreamHandle, SharedStream};
+    use hpack;
     use http::connection::{HttpFrame, SendStatus};
     use http::session::{Session, SessionState, Stream, DefaultSessionState};
     use http::session::Client as ClientMarker;
+    use http::validation::ContentLengthError;
 
     /// Tests that a client connection is correctly initialized, by reading the
     /// server preface (i.e. a settings frame) as the first frame of the connection.
@@ -528,6 +841,7 @@ mod tests {
                     Header::new(b":method", b"GET"),
                 ],
                 stream: prepare_stream(None),
+                priority: None,
             };
             conn.start_request(stream, &mut sender).unwrap();
 
@@ -554,6 +868,7 @@ mod tests {
                     Header::new(b":method", b"POST"),
                 ],
                 stream: prepare_stream(Some(vec![1, 2, 3])),
+                priority: None,
             };
             conn.start_request(stream, &mut sender).unwrap();
 
@@ -572,6 +887,35 @@ mod tests {
         }
     }
 
+    /// Tests that the `ClientConnection::reset_stream` method sends a RST_STREAM frame and stops
+    /// tracking the stream.
+    #[test]
+    fn test_client_conn_reset_stream() {
+        let mut conn = build_mock_client_conn();
+        let mut sender = MockSendFrame::new();
+        conn.state.insert_outgoing(TestStream::new());
+        conn.state.insert_outgoing(TestStream::new());
+
+        let removed = conn.reset_stream(3, ErrorCode::Cancel, &mut sender).unwrap();
+
+        assert!(removed.is_some());
+        assert!(conn.state.get_stream_ref(3).is_none());
+        assert!(conn.state.get_stream_ref(1).is_some());
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 3);
+                assert_eq!(frame.error_code(), ErrorCode::Cancel);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+
+        // Resetting an unknown stream still notifies the peer.
+        let removed = conn.reset_stream(5, ErrorCode::Cancel, &mut sender).unwrap();
+        assert!(removed.is_none());
+        assert_eq!(sender.sent.len(), 2);
+    }
+
     /// Tests that a `ClientSession` notifies the correct stream when the
     /// appropriate callback is invoked.
     ///
@@ -601,7 +945,7 @@ mod tests {
         assert_eq!(state.get_stream_ref(1).unwrap().body, vec![1, 2, 3, 4]);
         // Now headers?
         let headers = vec![
-            Header::new(b":method", b"GET"),
+            Header::new(b":status", b"200"),
         ];
         {
             let mut session = ClientSession::new(&mut state, &mut sender);
@@ -655,6 +999,225 @@ mod tests {
         assert!(state.get_stream_ref(1).map(|stream| stream.errors.len() == 0).unwrap());
     }
 
+    /// Tests that the `ClientSession` resets a stream on which a malformed response is received,
+    /// without passing the headers on to the stream.
+    #[test]
+    fn test_client_session_malformed_headers() {
+        let mut state = DefaultSessionState::<ClientMarker, TestStream>::new();
+        state.insert_outgoing(TestStream::new());
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        {
+            let mut session = ClientSession::new(&mut state, &mut sender);
+            let headers = vec![Header::new(b":status", b"200"),
+                               Header::new(b"connection", b"close")];
+            session.new_headers(1, headers, &mut conn).unwrap();
+        }
+
+        let stream = state.get_stream_ref(1).unwrap();
+        assert!(stream.headers.is_none());
+        assert_eq!(stream.errors, vec![ErrorCode::ProtocolError]);
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that a HEADERS frame without a `:status` is treated as a malformed response, rather
+    /// than as trailers, when the stream has not received its response yet.
+    #[test]
+    fn test_client_conn_response_without_status() {
+        let headers: Vec<(&[u8], &[u8])> = vec![(b"content-type", b"text/plain")];
+        let fragment = hpack::Encoder::new().encode(headers);
+        let mut conn = build_mock_client_conn();
+        conn.state.insert_outgoing(TestStream::new());
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(vec![
+            HttpFrame::HeadersFrame(build_headers_frame(fragment, 1)),
+        ]);
+
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+
+        let stream = conn.state.get_stream_ref(1).unwrap();
+        assert!(stream.headers.is_none());
+        assert_eq!(stream.errors, vec![ErrorCode::ProtocolError]);
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that the header blocks that follow the response on a stream are handled as trailers,
+    /// while those that follow an informational response are not.
+    #[test]
+    fn test_client_conn_response_trailers() {
+        let blocks: Vec<Vec<(&[u8], &[u8])>> = vec![
+            vec![(b":status", b"100")],
+            vec![(b":status", b"200")],
+            vec![(b"grpc-status", b"0")],
+        ];
+        let frames = blocks.into_iter()
+                           .map(|block| {
+                               let fragment = hpack::Encoder::new().encode(block);
+                               HttpFrame::HeadersFrame(build_headers_frame(fragment, 1))
+                           })
+                           .collect();
+        let mut conn = build_mock_client_conn();
+        conn.state.insert_outgoing(TestStream::new());
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        }
+
+        let stream = conn.state.get_stream_ref(1).unwrap();
+        assert!(stream.errors.is_empty());
+        assert_eq!(stream.headers,
+                   Some(vec![(b"grpc-status".to_vec(), b"0".to_vec())]));
+        assert_eq!(sender.sent.len(), 0);
+    }
+
+    /// Tests that the `ClientSession` resets a stream whose response body does not match its
+    /// declared `content-length`, and notifies the stream of it.
+    #[test]
+    fn test_client_session_content_length_mismatch() {
+        let mut state = DefaultSessionState::<ClientMarker, TestStream>::new();
+        state.insert_outgoing(TestStream::new());
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        {
+            let mut session = ClientSession::new(&mut state, &mut sender);
+            let err = ContentLengthError {
+                declared: 5,
+                received: 3,
+            };
+            session.content_length_mismatch(1, err, &mut conn).unwrap();
+        }
+
+        let stream = state.get_stream_ref(1).unwrap();
+        assert_eq!(stream.errors, vec![ErrorCode::ProtocolError]);
+        assert!(stream.is_closed());
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that the `ClientSession` resets a stream whose response headers are too large.
+    #[test]
+    fn test_client_session_header_list_too_large() {
+        let mut state = DefaultSessionState::<ClientMarker, TestStream>::new();
+        state.insert_outgoing(TestStream::new());
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        {
+            let mut session = ClientSession::new(&mut state, &mut sender);
+            session.header_list_too_large(1, &mut conn).unwrap();
+        }
+
+        let stream = state.get_stream_ref(1).unwrap();
+        assert_eq!(stream.errors, vec![ErrorCode::Cancel]);
+        assert!(stream.is_closed());
+        assert!(stream.headers.is_none());
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::Cancel);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that the `ClientConnection` only starts extended CONNECT requests once the server has
+    /// enabled them.
+    #[test]
+    fn test_client_conn_extended_connect() {
+        let mut settings = SettingsFrame::new();
+        settings.add_setting(HttpSetting::EnableConnectProtocol(1));
+        let mut conn = build_mock_client_conn();
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(vec![HttpFrame::SettingsFrame(settings)]);
+
+        let res = conn.start_extended_connect(b"websocket",
+                                              b"example.com",
+                                              b"/chat",
+                                              vec![],
+                                              TestStream::new(),
+                                              &mut sender);
+        assert_eq!(res.err().unwrap(), HttpError::ConnectProtocolDisabled);
+        assert!(sender.sent.is_empty());
+
+        conn.expect_settings(&mut receiver, &mut sender).unwrap();
+        assert!(conn.peer_connect_protocol_enabled());
+        let stream_id = conn.start_extended_connect(b"websocket",
+                                                    b"example.com",
+                                                    b"/chat",
+                                                    vec![Header::new(b"sec-websocket-version",
+                                                                     b"13")],
+                                                    TestStream::new(),
+                                                    &mut sender)
+                            .unwrap();
+
+        assert_eq!(stream_id, 1);
+        // The SETTINGS ack, followed by the request
+        assert_eq!(sender.sent.len(), 2);
+        let headers = match HttpFrame::from_raw(&sender.sent[1]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => {
+                assert!(!frame.is_end_of_stream());
+                hpack::Decoder::new().decode(frame.header_fragment()).unwrap()
+            }
+            _ => panic!("Expected a HEADERS frame"),
+        };
+        assert_eq!(headers,
+                   vec![(b":method".to_vec(), b"CONNECT".to_vec()),
+                        (b":protocol".to_vec(), b"websocket".to_vec()),
+                        (b":scheme".to_vec(), b"http".to_vec()),
+                        (b":path".to_vec(), b"/chat".to_vec()),
+                        (b":authority".to_vec(), b"example.com".to_vec()),
+                        (b"sec-websocket-version".to_vec(), b"13".to_vec())]);
+    }
+
+    /// Tests that the `ClientConnection` records the alternative services and the origin set that
+    /// the server advertises.
+    #[test]
+    fn test_client_conn_advertisements() {
+        let frames = vec![
+            HttpFrame::AltSvcFrame(AltSvcFrame::new(0, "a.com", "h2=\":8443\"")),
+            HttpFrame::OriginFrame(OriginFrame::new(vec!["https://a.com", "https://b.com"])),
+            HttpFrame::OriginFrame(OriginFrame::new(vec!["https://b.com", "https://c.com"])),
+        ];
+        let mut conn = build_mock_client_conn();
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(frames);
+        assert_eq!(conn.advertisements().origins, None);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        }
+
+        let advertisements = conn.advertisements();
+        assert_eq!(advertisements.alt_svcs,
+                   vec![AltSvcFrame::new(0, "a.com", "h2=\":8443\"")]);
+        assert_eq!(advertisements.origins,
+                   Some(vec![b"https://a.com".to_vec(),
+                             b"https://b.com".to_vec(),
+                             b"https://c.com".to_vec()]));
+        assert!(sender.sent.is_empty());
+    }
+
     /// Tests that the `ClientSession` signals the correct error to client code when told to go
     /// away by the peer.
     #[test]
@@ -669,6 +1232,7 @@ mod tests {
         if let Err(HttpError::PeerConnectionError(err)) = res {
             assert_eq!(err.error_code(), ErrorCode::ProtocolError);
             assert_eq!(err.debug_data(), None);
+            assert_eq!(err.last_stream_id(), Some(0));
         } else {
             panic!("Expected a PeerConnectionError");
         }
@@ -695,4 +1259,128 @@ mod tests {
         // ...which was not an ack, but our own settings.
         assert!(!frame.is_ack());
     }
+
+    /// Tests that the server's acknowledgement of the SETTINGS frame of the client preface is not
+    /// taken for the one of a header table size that the client advertises afterwards.
+    #[test]
+    fn test_client_conn_header_table_size_after_preface() {
+        // A block that indexes a header that it has just added.
+        let block = vec![0x88, 0x40, 3, b'x', b'-', b'a', 1, b'b', 0x80 | 62];
+        let mut conn = build_mock_client_conn();
+        conn.state.insert_outgoing(TestStream::new());
+        conn.state.insert_outgoing(TestStream::new());
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(vec![
+            HttpFrame::SettingsFrame(SettingsFrame::new_ack()),
+            HttpFrame::HeadersFrame(build_headers_frame(block.clone(), 1)),
+            HttpFrame::SettingsFrame(SettingsFrame::new_ack()),
+            HttpFrame::HeadersFrame(build_headers_frame(block, 3)),
+        ]);
+        conn.set_header_table_size(0, &mut sender).unwrap();
+
+        // The acknowledgement of the preface leaves the decoder's table as it was...
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        assert!(conn.state.get_stream_ref(1).unwrap().headers.is_some());
+        // ...while the next one shrinks it.
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        match conn.handle_next_frame(&mut receiver, &mut sender) {
+            Err(HttpError::CompressionError(_)) => {}
+            _ => panic!("Expected a compression error"),
+        }
+    }
+
+    /// Tests that an initial window size over the maximum is a FLOW_CONTROL_ERROR.
+    #[test]
+    fn test_client_conn_initial_window_size_too_large() {
+        let mut settings = SettingsFrame::new();
+        settings.add_setting(HttpSetting::InitialWindowSize(0x80000000));
+        let mut conn = build_mock_client_conn();
+        let mut sender = MockSendFrame::new();
+        let mut receiver = MockReceiveFrame::new(vec![HttpFrame::SettingsFrame(settings)]);
+
+        match conn.handle_next_frame(&mut receiver, &mut sender) {
+            Err(HttpError::WindowSizeOverflow) => {}
+            _ => panic!("Expected a window size overflow"),
+        }
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::GoawayFrame(frame) => {
+                assert_eq!(frame.error_code(), ErrorCode::FlowControlError);
+            }
+            _ => panic!("Expected a GOAWAY frame"),
+        };
+    }
+
+    /// Tests that the statistics of a `ClientConnection` include the windows of its open streams.
+    #[test]
+    fn test_client_conn_stats() {
+        let state = DefaultSessionState::<ClientMarker, SharedStream>::new();
+        let mut conn = ClientConnection::with_connection(build_mock_http_conn(), state);
+        let mut sender = MockSendFrame::new();
+        for _ in 0..2 {
+            let (_, stream) = StreamHandle::new();
+            conn.start_request(RequestStream {
+                                   headers: vec![Header::new(b":method", b"POST")],
+                                   stream: stream,
+                                   priority: None,
+                               },
+                               &mut sender)
+                .unwrap();
+        }
+        let mut receiver = MockReceiveFrame::new(vec![
+            HttpFrame::WindowUpdateFrame(WindowUpdateFrame::for_stream(3, 10)),
+        ]);
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+
+        let stats = conn.stats();
+        assert_eq!(stats.active_streams, 2);
+        assert_eq!(stats.streams.iter().map(|s| s.stream_id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(stats.streams[0].out_window, 65_535);
+        assert_eq!(stats.streams[1].out_window, 65_535 + 10);
+        assert_eq!(stats.frames_sent.headers, 2);
+    }
+
+    /// Tests that the window of the data received on a `SharedStream` is only given back to the
+    /// server once the data is read from the stream's handle.
+    #[test]
+    fn test_client_conn_stream_handle() {
+        static CHUNK: [u8; 20_000] = [0; 20_000];
+        let state = DefaultSessionState::<ClientMarker, SharedStream>::new();
+        let mut conn = ClientConnection::with_connection(build_mock_http_conn(), state);
+        let mut sender = MockSendFrame::new();
+        let (mut handle, stream) = StreamHandle::new();
+        let stream_id = conn.start_request(RequestStream {
+                                               headers: vec![Header::new(b":method", b"POST")],
+                                               stream: stream,
+                                               priority: None,
+                                           },
+                                           &mut sender)
+                            .unwrap();
+        handle.write_all(b"ping").unwrap();
+        handle.close();
+        let mut receiver = MockReceiveFrame::new(vec![
+            HttpFrame::DataFrame(DataFrame::with_data(stream_id, &CHUNK[..])),
+            HttpFrame::DataFrame(DataFrame::with_data(stream_id, &CHUNK[..])),
+        ]);
+
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        // Only the connection's window is given back while the data is not read...
+        assert_eq!(sender.sent.len(), 2);
+        assert_eq!(sender.sent[1],
+                   RawFrame::from(serialize_frame(&WindowUpdateFrame::for_connection(40_000))));
+
+        // ...and the stream's once it is.
+        let mut body = vec![0; 40_000];
+        handle.read_exact(&mut body).unwrap();
+        assert_eq!(conn.send_next_data(&mut sender).unwrap(), SendStatus::Sent);
+        assert_eq!(sender.sent[2],
+                   RawFrame::from(serialize_frame(&WindowUpdateFrame::for_stream(stream_id,
+                                                                                 40_000))));
+        // The written data ends the request.
+        let frame: DataFrame = Frame::from_raw(&sender.sent[3]).unwrap();
+        assert_eq!(&frame.data[..], b"ping");
+        assert!(frame.is_end_of_stream());
+    }
 }
@@ -85,6 +85,8 @@ use openssl::ssl::SslMethod;
 /// ```
 pub struct TlsConnector<'a, 'ctx> {
     pub host: &'a str,
+    /// The port on which the connection should be established. Port 443 unless changed.
+    pub port: u16,
     context: Http2TlsContext<'ctx>,
 }
 
@@ -185,6 +187,7 @@ impl<'a, 'ctx> TlsConnector<'a, 'ctx> {
     pub fn new<P: AsRef<Path>>(host: &'a str, ca_file_path: &'ctx P) -> TlsConnector<'a, 'ctx> {
         TlsConnector {
             host: host,
+            port: 443,
             context: Http2TlsContext::CertPath(ca_file_path.as_ref()),
         }
     }
@@ -194,6 +197,7 @@ impl<'a, 'ctx> TlsConnector<'a, 'ctx> {
     pub fn with_context(host: &'a str, context: &'ctx SslContext) -> TlsConnector<'a, 'ctx> {
         TlsConnector {
             host: host,
+            port: 443,
             context: Http2TlsContext::Wrapped(context),
         }
     }
@@ -221,8 +225,8 @@ impl<'a, 'ctx> HttpConnect for TlsConnector<'a, 'ctx> {
     type Err = TlsConnectError;
 
     fn connect(self) -> Result<ClientStream<SslStream<TcpStream>>, TlsConnectError> {
-        // First, create a TCP connection to port 443
-        let raw_tcp = try!(TcpStream::connect(&(self.host, 443)));
+        // First, create a TCP connection to the server's port
+        let raw_tcp = try!(TcpStream::connect(&(self.host, self.port)));
         // Now build the SSL instance, depending on which SSL context should be
         // used...
         let ssl = match self.context {
@@ -264,3 +268,34 @@ impl<'a, 'ctx> HttpConnect for TlsConnector<'a, 'ctx> {
         Ok(ClientStream(ssl_stream, HttpScheme::Https, self.host.into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use openssl::ssl::{SslContext, SslMethod};
+
+    use super::TlsConnector;
+    use http::client::HttpConnect;
+
+    /// Tests that the connector connects to the port that it is given, instead of port 443.
+    #[test]
+    fn test_connect_to_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // The connection is dropped right away, so the TLS handshake fails.
+            tx.send(listener.accept().is_ok()).unwrap();
+        });
+        let context = SslContext::new(SslMethod::Tlsv1_2).unwrap();
+        let mut connector = TlsConnector::with_context("127.0.0.1", &context);
+        connector.port = port;
+
+        assert!(connector.connect().is_err());
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(true));
+    }
+}
@@ -0,0 +1,445 @@
+//! The module contains the `HeaderEncoder`, the HPACK (RFC 7541) encoder that an `HttpConnection`
+//! uses to compress the headers that it sends.
+//!
+//! Unlike the encoder of the `hpack` crate, it keeps its dynamic table within the size that the
+//! peer allows in its `SETTINGS_HEADER_TABLE_SIZE` and lets each header be encoded with its own
+//! indexing strategy. Headers that carry secrets, such as `authorization` and `cookie`, are never
+//! indexed by default: otherwise, an attacker who can inject headers into requests could guess a
+//! secret from the size of the compressed header blocks (a CRIME-style attack).
+
+use std::collections::{HashMap, VecDeque};
+
+/// The default maximum size of the dynamic table, as defined by the spec (the initial value of
+/// `SETTINGS_HEADER_TABLE_SIZE`).
+pub const DEFAULT_TABLE_SIZE: usize = 4_096;
+
+/// The number of octets that each entry takes up in the dynamic table, in addition to the lengths
+/// of its name and value.
+pub const ENTRY_OVERHEAD: usize = 32;
+
+/// The static table, as defined by the spec (Appendix A).
+static STATIC_TABLE: &'static [(&'static [u8], &'static [u8])] = &[
+    (b":authority", b""),
+    (b":method", b"GET"),
+    (b":method", b"POST"),
+    (b":path", b"/"),
+    (b":path", b"/index.html"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"200"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"304"),
+    (b":status", b"400"),
+    (b":status", b"404"),
+    (b":status", b"500"),
+    (b"accept-charset", b""),
+    (b"accept-encoding", b"gzip, deflate"),
+    (b"accept-language", b""),
+    (b"accept-ranges", b""),
+    (b"accept", b""),
+    (b"access-control-allow-origin", b""),
+    (b"age", b""),
+    (b"allow", b""),
+    (b"authorization", b""),
+    (b"cache-control", b""),
+    (b"content-disposition", b""),
+    (b"content-encoding", b""),
+    (b"content-language", b""),
+    (b"content-length", b""),
+    (b"content-location", b""),
+    (b"content-range", b""),
+    (b"content-type", b""),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"expect", b""),
+    (b"expires", b""),
+    (b"from", b""),
+    (b"host", b""),
+    (b"if-match", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"if-range", b""),
+    (b"if-unmodified-since", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"max-forwards", b""),
+    (b"proxy-authenticate", b""),
+    (b"proxy-authorization", b""),
+    (b"range", b""),
+    (b"referer", b""),
+    (b"refresh", b""),
+    (b"retry-after", b""),
+    (b"server", b""),
+    (b"set-cookie", b""),
+    (b"strict-transport-security", b""),
+    (b"transfer-encoding", b""),
+    (b"user-agent", b""),
+    (b"vary", b""),
+    (b"via", b""),
+    (b"www-authenticate", b""),
+];
+
+/// The headers that are never indexed by default, since their values are usually secrets.
+static SENSITIVE_HEADERS: &'static [&'static [u8]] = &[b"authorization",
+                                                        b"proxy-authorization",
+                                                        b"cookie",
+                                                        b"set-cookie"];
+
+/// An enum representing the ways in which the `HeaderEncoder` can encode a header that it does not
+/// find in its tables (Section 6.2 of the spec).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexingStrategy {
+    /// The header is added to the dynamic table, so that its later occurrences can be encoded
+    /// as an index.
+    Incremental,
+    /// The header is not added to the dynamic table, but it is still encoded as an index if it is
+    /// already found in one of the tables.
+    WithoutIndexing,
+    /// The header is always encoded as a literal that is never indexed, which also tells any
+    /// intermediary not to index it when forwarding it. Meant for headers with secret values.
+    NeverIndexed,
+}
+
+/// An HPACK encoder, whose dynamic table follows the size that the peer allows.
+///
+/// When the peer changes its `SETTINGS_HEADER_TABLE_SIZE`, the encoder adjusts its table and
+/// signals the change at the start of the next header block, by the dynamic table size updates
+/// that the spec requires. Strings are always encoded as literals (Huffman encoding is not used).
+#[derive(Debug, Clone)]
+pub struct HeaderEncoder {
+    /// The entries of the dynamic table, the most recently added one first.
+    table: VecDeque<(Vec<u8>, Vec<u8>)>,
+    /// The current size of the dynamic table.
+    size: usize,
+    /// The maximum size of the dynamic table.
+    max_size: usize,
+    /// The largest table that the encoder uses, regardless of how large a one the peer allows.
+    size_limit: usize,
+    /// The smallest maximum size that the table had since the last header block, if the maximum
+    /// size changed since then.
+    pending_update: Option<usize>,
+    /// The indexing strategies of particular headers, by name.
+    strategies: HashMap<Vec<u8>, IndexingStrategy>,
+    /// The indexing strategy of all other headers.
+    default_strategy: IndexingStrategy,
+}
+
+impl HeaderEncoder {
+    /// Creates a new `HeaderEncoder` with a dynamic table of the default size. The sensitive
+    /// headers (`authorization`, `proxy-authorization`, `cookie` and `set-cookie`) are never
+    /// indexed, while all others are indexed incrementally.
+    pub fn new() -> HeaderEncoder {
+        HeaderEncoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+            size_limit: DEFAULT_TABLE_SIZE,
+            pending_update: None,
+            strategies: SENSITIVE_HEADERS.iter()
+                                         .map(|name| {
+                                             (name.to_vec(), IndexingStrategy::NeverIndexed)
+                                         })
+                                         .collect(),
+            default_strategy: IndexingStrategy::Incremental,
+        }
+    }
+
+    /// Sets the indexing strategy of the headers with the given name.
+    pub fn set_strategy<N: Into<Vec<u8>>>(&mut self, name: N, strategy: IndexingStrategy) {
+        self.strategies.insert(name.into(), strategy);
+    }
+
+    /// Sets the indexing strategy of the headers for which no particular strategy is set.
+    pub fn set_default_strategy(&mut self, strategy: IndexingStrategy) {
+        self.default_strategy = strategy;
+    }
+
+    /// Returns the indexing strategy of the headers with the given name.
+    pub fn strategy(&self, name: &[u8]) -> IndexingStrategy {
+        self.strategies.get(name).cloned().unwrap_or(self.default_strategy)
+    }
+
+    /// Returns the current maximum size of the dynamic table.
+    pub fn max_table_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Returns the current size of the entries in the dynamic table.
+    pub fn table_size(&self) -> usize {
+        self.size
+    }
+
+    /// Limits the size of the dynamic table to the given number of octets, even if the peer
+    /// allows a larger one. This bounds the memory that the encoder uses.
+    pub fn set_table_size_limit(&mut self, limit: usize) {
+        self.size_limit = limit;
+        if self.max_size > limit {
+            self.set_max_table_size(limit);
+        }
+    }
+
+    /// Handles a change of the peer's `SETTINGS_HEADER_TABLE_SIZE`. The encoder uses a table as
+    /// large as the peer allows, up to its own size limit.
+    pub fn set_peer_max_table_size(&mut self, size: usize) {
+        let size = size.min(self.size_limit);
+        if size != self.max_size {
+            self.set_max_table_size(size);
+        }
+    }
+
+    /// Encodes the given headers into a header block.
+    pub fn encode<'b, I>(&mut self, headers: I) -> Vec<u8>
+        where I: IntoIterator<Item = (&'b [u8], &'b [u8])>
+    {
+        let mut buf = Vec::new();
+        if let Some(min_size) = self.pending_update.take() {
+            // If the table shrunk and then grew again, the decoder needs to see the smallest
+            // size first, so that it evicts the same entries as the encoder did.
+            if min_size < self.max_size {
+                encode_integer(min_size, 5, 0x20, &mut buf);
+            }
+            encode_integer(self.max_size, 5, 0x20, &mut buf);
+        }
+        for (name, value) in headers {
+            self.encode_header(name, value, &mut buf);
+        }
+        buf
+    }
+
+    /// Internal helper method. Encodes a single header into the given buffer.
+    fn encode_header(&mut self, name: &[u8], value: &[u8], buf: &mut Vec<u8>) {
+        let strategy = self.strategy(name);
+        let (name_index, full_match) = match self.find(name, value) {
+            Some((index, full_match)) => (index, full_match),
+            None => (0, false),
+        };
+        match strategy {
+            IndexingStrategy::NeverIndexed => {
+                encode_integer(name_index, 4, 0x10, buf);
+            }
+            _ if full_match => {
+                encode_integer(name_index, 7, 0x80, buf);
+                return;
+            }
+            IndexingStrategy::WithoutIndexing => {
+                encode_integer(name_index, 4, 0x00, buf);
+            }
+            IndexingStrategy::Incremental => {
+                encode_integer(name_index, 6, 0x40, buf);
+                self.add(name, value);
+            }
+        }
+        if name_index == 0 {
+            encode_string(name, buf);
+        }
+        encode_string(value, buf);
+    }
+
+    /// Internal helper method. Finds the given header in the static or dynamic table.
+    ///
+    /// # Returns
+    ///
+    /// The index of the header in the tables and whether its value matches as well, preferring a
+    /// full match; `None` if not even the name is found.
+    fn find(&self, name: &[u8], value: &[u8]) -> Option<(usize, bool)> {
+        let statics = STATIC_TABLE.iter().map(|&(n, v)| (n, v));
+        let dynamics = self.table.iter().map(|&(ref n, ref v)| (&n[..], &v[..]));
+        let mut name_index = None;
+        for (index, (n, v)) in statics.chain(dynamics).enumerate() {
+            if n == name {
+                if v == value {
+                    return Some((index + 1, true));
+                }
+                name_index = name_index.or(Some(index + 1));
+            }
+        }
+        name_index.map(|index| (index, false))
+    }
+
+    /// Internal helper method. Adds the given header to the dynamic table, evicting the oldest
+    /// entries to make room for it.
+    fn add(&mut self, name: &[u8], value: &[u8]) {
+        let entry_size = name.len() + value.len() + ENTRY_OVERHEAD;
+        if entry_size > self.max_size {
+            // An entry larger than the whole table empties the table (Section 4.4).
+            self.table.clear();
+            self.size = 0;
+            return;
+        }
+        self.evict(self.max_size - entry_size);
+        self.table.push_front((name.to_vec(), value.to_vec()));
+        self.size += entry_size;
+    }
+
+    /// Internal helper method. Changes the maximum size of the dynamic table, remembering to
+    /// signal the change in the next header block.
+    fn set_max_table_size(&mut self, size: usize) {
+        self.max_size = size;
+        self.pending_update = Some(self.pending_update.map_or(size, |min| min.min(size)));
+        self.evict(size);
+    }
+
+    /// Internal helper method. Evicts the oldest entries until the table is at most the given
+    /// size.
+    fn evict(&mut self, size: usize) {
+        while self.size > size {
+            match self.table.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for HeaderEncoder {
+    fn default() -> HeaderEncoder {
+        HeaderEncoder::new()
+    }
+}
+
+/// Encodes the given integer with the given prefix size (Section 5.1), setting the given bits in
+/// the first octet.
+fn encode_integer(mut value: usize, prefix_size: u8, leading_bits: u8, buf: &mut Vec<u8>) {
+    let max_prefix = (1usize << prefix_size) - 1;
+    if value < max_prefix {
+        buf.push(leading_bits | value as u8);
+        return;
+    }
+    buf.push(leading_bits | max_prefix as u8);
+    value -= max_prefix;
+    while value >= 128 {
+        buf.push((value % 128) as u8 | 0x80);
+        value /= 128;
+    }
+    buf.push(value as u8);
+}
+
+/// Encodes the given string as a literal (Section 5.2), without Huffman encoding.
+fn encode_string(octets: &[u8], buf: &mut Vec<u8>) {
+    encode_integer(octets.len(), 7, 0, buf);
+    buf.extend(octets);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderEncoder, IndexingStrategy, encode_integer};
+
+    use hpack;
+
+    /// Encodes the given headers with the given encoder and decodes them with the given decoder.
+    fn round_trip(encoder: &mut HeaderEncoder,
+                  decoder: &mut hpack::Decoder,
+                  headers: &[(&[u8], &[u8])])
+                  -> Vec<u8> {
+        let block = encoder.encode(headers.iter().cloned());
+        let decoded = decoder.decode(&block).unwrap();
+        let expected: Vec<_> = headers.iter().map(|&(n, v)| (n.to_vec(), v.to_vec())).collect();
+        assert_eq!(decoded, expected);
+        block
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        let mut buf = Vec::new();
+        encode_integer(10, 5, 0, &mut buf);
+        assert_eq!(buf, vec![10]);
+        buf.clear();
+        encode_integer(1337, 5, 0, &mut buf);
+        assert_eq!(buf, vec![31, 154, 10]);
+        buf.clear();
+        encode_integer(31, 5, 0x20, &mut buf);
+        assert_eq!(buf, vec![0x3f, 0]);
+    }
+
+    #[test]
+    fn test_incremental_indexing() {
+        let mut encoder = HeaderEncoder::new();
+        let mut decoder = hpack::Decoder::new();
+        let headers: &[(&[u8], &[u8])] = &[(b":method", b"GET"), (b"x-custom", b"value")];
+
+        let first = round_trip(&mut encoder, &mut decoder, headers);
+        assert_eq!(first[0], 0x80 | 2);
+        assert_eq!(first[1], 0x40);
+        // The second time, both headers are found in the tables.
+        let second = round_trip(&mut encoder, &mut decoder, headers);
+        assert_eq!(second, vec![0x80 | 2, 0x80 | 62]);
+    }
+
+    /// Tests that sensitive headers are never indexed, even when they are repeated.
+    #[test]
+    fn test_never_indexed() {
+        let mut encoder = HeaderEncoder::new();
+        let mut decoder = hpack::Decoder::new();
+        let headers: &[(&[u8], &[u8])] = &[(b"authorization", b"secret"), (b"x-token", b"abc")];
+        encoder.set_strategy("x-token", IndexingStrategy::NeverIndexed);
+
+        let first = round_trip(&mut encoder, &mut decoder, headers);
+        let second = round_trip(&mut encoder, &mut decoder, headers);
+        assert_eq!(first, second);
+        // The name of the `authorization` header is indexed in the static table...
+        assert_eq!(&first[..8], &[0x1f, 23 - 15, 6, b's', b'e', b'c', b'r', b'e']);
+        // ...while `x-token` is encoded with a literal name.
+        assert_eq!(first[9], 0x10);
+    }
+
+    #[test]
+    fn test_without_indexing() {
+        let mut encoder = HeaderEncoder::new();
+        encoder.set_default_strategy(IndexingStrategy::WithoutIndexing);
+        let mut decoder = hpack::Decoder::new();
+        let headers: &[(&[u8], &[u8])] = &[(b":status", b"200"), (b"x-custom", b"value")];
+
+        let first = round_trip(&mut encoder, &mut decoder, headers);
+        assert_eq!(first[0], 0x80 | 8);
+        assert_eq!(first[1], 0x00);
+        assert_eq!(round_trip(&mut encoder, &mut decoder, headers), first);
+    }
+
+    /// Tests that the table size that the peer allows is signaled and respected.
+    #[test]
+    fn test_peer_table_size() {
+        let mut encoder = HeaderEncoder::new();
+        let mut decoder = hpack::Decoder::new();
+        let headers: &[(&[u8], &[u8])] = &[(b"x-custom", b"value")];
+        round_trip(&mut encoder, &mut decoder, headers);
+
+        // Disabling the dynamic table evicts all entries.
+        encoder.set_peer_max_table_size(0);
+        let block = round_trip(&mut encoder, &mut decoder, headers);
+        assert_eq!(block[0], 0x20);
+        assert_eq!(block[1], 0x40);
+        assert_eq!(encoder.max_table_size(), 0);
+        // The update is only signaled once.
+        assert_eq!(round_trip(&mut encoder, &mut decoder, headers)[0], 0x40);
+
+        // Shrinking and growing the table again signals both sizes.
+        encoder.set_peer_max_table_size(100);
+        encoder.set_peer_max_table_size(50);
+        encoder.set_peer_max_table_size(8_192);
+        let block = round_trip(&mut encoder, &mut decoder, headers);
+        // The size stays within the encoder's limit.
+        assert_eq!(encoder.max_table_size(), 4_096);
+        assert_eq!(&block[..5], &[0x3f, 0x13, 0x3f, 0xe1, 0x1f]);
+        assert_eq!(&round_trip(&mut encoder, &mut decoder, headers)[..], &[0x80 | 62]);
+    }
+
+    /// Tests that the oldest entries are evicted to keep the table within its maximum size.
+    #[test]
+    fn test_eviction() {
+        let mut encoder = HeaderEncoder::new();
+        encoder.set_table_size_limit(100);
+        let mut decoder = hpack::Decoder::new();
+        decoder.set_max_table_size(100);
+        // Each entry takes up 32 + 2 + 8 = 42 octets, so only two fit.
+        round_trip(&mut encoder, &mut decoder, &[(b"a1", b"aaaaaaaa")]);
+        round_trip(&mut encoder, &mut decoder, &[(b"a2", b"aaaaaaaa")]);
+        round_trip(&mut encoder, &mut decoder, &[(b"a3", b"aaaaaaaa")]);
+        assert_eq!(encoder.table.len(), 2);
+        assert_eq!(encoder.size, 84);
+        let block = round_trip(&mut encoder, &mut decoder, &[(b"a2", b"aaaaaaaa")]);
+        assert_eq!(block, vec![0x80 | 63]);
+    }
+}
@@ -15,17 +15,40 @@
 //! `HttpConnection`) and the higher layers that handle these events and pass them on to the
 //! application.
 
+use std::cmp;
+use std::io;
 use std::borrow::Cow;
 use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Instant;
 
 use http::{Header, StreamId, HttpError, HttpResult, HttpScheme, WindowSize,
-           ErrorCode, INITIAL_CONNECTION_WINDOW_SIZE};
+           ErrorCode, INITIAL_CONNECTION_WINDOW_SIZE, DEFAULT_MAX_HEADER_LIST_SIZE,
+           DEFAULT_MAX_HEADER_BLOCK_SIZE};
 use http::priority::DataPrioritizer;
+use http::padding::PaddingPolicy;
 use http::session::Session;
-use http::frame::{Frame, FrameIR, RawFrame, DataFrame, DataFlag, HeadersFrame, HeadersFlag,
-                  SettingsFrame, RstStreamFrame, PingFrame, GoawayFrame, WindowUpdateFrame};
+use http::compression::{HeaderEncoder, ENTRY_OVERHEAD};
+use http::flood::{Flood, FloodGuard};
+use http::stats::{ConnectionStats, StreamStats};
+use http::observer::FrameObserver;
+use http::validation::{declared_body_length, BodyLength};
+use http::frame::{Frame, FrameIR, FrameHeader, RawFrame, DataFrame, DataFlag, HeadersFrame,
+                  HeadersFlag, SettingsFrame, RstStreamFrame, PingFrame, GoawayFrame,
+                  WindowUpdateFrame, PriorityFrame, StreamDependency, HttpSetting,
+                  ExtensionRegistry, AltSvcFrame, OriginFrame};
+use http::frame::continuation::CONTINUATION_FRAME_TYPE;
 use hpack;
 
+/// The number of received bytes after which the flow control window of a stream (or of the
+/// connection) is given back to the peer: half of the initial window size.
+const WINDOW_UPDATE_THRESHOLD: u32 = INITIAL_CONNECTION_WINDOW_SIZE as u32 / 2;
+
+/// The flag that marks the frame that ends a header block.
+const END_HEADERS_FLAG: u8 = 0x4;
+
 /// An enum representing all frame variants that can be returned by an `HttpConnection` can handle.
 ///
 /// The variants wrap the appropriate `Frame` implementation, except for the `UnknownFrame`
@@ -36,11 +59,14 @@ use hpack;
 pub enum HttpFrame<'a> {
     DataFrame(DataFrame<'a>),
     HeadersFrame(HeadersFrame<'a>),
+    PriorityFrame(PriorityFrame),
     RstStreamFrame(RstStreamFrame),
     SettingsFrame(SettingsFrame),
     PingFrame(PingFrame),
     GoawayFrame(GoawayFrame<'a>),
     WindowUpdateFrame(WindowUpdateFrame),
+    AltSvcFrame(AltSvcFrame),
+    OriginFrame(OriginFrame),
     UnknownFrame(RawFrame<'a>),
 }
 
@@ -49,11 +75,22 @@ impl<'a> HttpFrame<'a> {
         let frame = match raw_frame.header().1 {
             0x0 => HttpFrame::DataFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x1 => HttpFrame::HeadersFrame(try!(HttpFrame::parse_frame(&raw_frame))),
+            0x2 => HttpFrame::PriorityFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x3 => HttpFrame::RstStreamFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x4 => HttpFrame::SettingsFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x6 => HttpFrame::PingFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x7 => HttpFrame::GoawayFrame(try!(HttpFrame::parse_frame(&raw_frame))),
             0x8 => HttpFrame::WindowUpdateFrame(try!(HttpFrame::parse_frame(&raw_frame))),
+            // Malformed ALTSVC and ORIGIN frames are not errors; the spec requires that they be
+            // ignored, so they are passed on like any other unknown frame.
+            0xa => match Frame::from_raw(&raw_frame) {
+                Some(frame) => HttpFrame::AltSvcFrame(frame),
+                None => HttpFrame::UnknownFrame(raw_frame.as_ref().into()),
+            },
+            0xc => match Frame::from_raw(&raw_frame) {
+                Some(frame) => HttpFrame::OriginFrame(frame),
+                None => HttpFrame::UnknownFrame(raw_frame.as_ref().into()),
+            },
             _ => HttpFrame::UnknownFrame(raw_frame.as_ref().into()),
         };
 
@@ -75,16 +112,36 @@ impl<'a> HttpFrame<'a> {
         Frame::from_raw(&raw_frame).ok_or(HttpError::InvalidFrame)
     }
 
+    /// Get the header of the frame
+    pub fn get_header(&self) -> FrameHeader {
+        match self {
+            &HttpFrame::DataFrame(ref f) => f.get_header(),
+            &HttpFrame::HeadersFrame(ref f) => f.get_header(),
+            &HttpFrame::PriorityFrame(ref f) => f.get_header(),
+            &HttpFrame::RstStreamFrame(ref f) => f.get_header(),
+            &HttpFrame::SettingsFrame(ref f) => f.get_header(),
+            &HttpFrame::PingFrame(ref f) => f.get_header(),
+            &HttpFrame::GoawayFrame(ref f) => f.get_header(),
+            &HttpFrame::WindowUpdateFrame(ref f) => f.get_header(),
+            &HttpFrame::AltSvcFrame(ref f) => f.get_header(),
+            &HttpFrame::OriginFrame(ref f) => f.get_header(),
+            &HttpFrame::UnknownFrame(ref f) => f.header(),
+        }
+    }
+
     /// Get stream id, zero for special frames
     pub fn get_stream_id(&self) -> StreamId {
         match self {
             &HttpFrame::DataFrame(ref f) => f.get_stream_id(),
             &HttpFrame::HeadersFrame(ref f) => f.get_stream_id(),
+            &HttpFrame::PriorityFrame(ref f) => f.get_stream_id(),
             &HttpFrame::RstStreamFrame(ref f) => f.get_stream_id(),
             &HttpFrame::SettingsFrame(ref f) => f.get_stream_id(),
             &HttpFrame::PingFrame(ref f) => f.get_stream_id(),
             &HttpFrame::GoawayFrame(ref f) => f.get_stream_id(),
             &HttpFrame::WindowUpdateFrame(ref f) => f.get_stream_id(),
+            &HttpFrame::AltSvcFrame(ref f) => f.get_stream_id(),
+            &HttpFrame::OriginFrame(ref f) => f.get_stream_id(),
             &HttpFrame::UnknownFrame(ref f) => f.get_stream_id(),
         }
     }
@@ -116,14 +173,114 @@ pub enum SendStatus {
 pub struct HttpConnection {
     /// HPACK decoder used to decode incoming headers before passing them on to the session.
     pub decoder: hpack::Decoder<'static>,
-    /// The HPACK encoder used to encode headers before sending them on this connection.
-    pub encoder: hpack::Encoder<'static>,
+    /// The HPACK encoder used to encode headers before sending them on this connection. Its
+    /// dynamic table follows the peer's `SETTINGS_HEADER_TABLE_SIZE`.
+    pub encoder: HeaderEncoder,
     /// Tracks the size of the outbound flow control window
     pub out_window_size: WindowSize,
     /// Tracks the size of the inbound flow control window
     pub in_window_size: WindowSize,
     /// The scheme of the connection
     pub scheme: HttpScheme,
+    /// The maximum number of concurrent streams that the peer allows us to open, if it has
+    /// advertised one in its settings.
+    pub peer_max_concurrent_streams: Option<u32>,
+    /// Whether the peer has enabled extended CONNECT (RFC 8441) by sending the
+    /// `SETTINGS_ENABLE_CONNECT_PROTOCOL` setting.
+    pub peer_connect_protocol_enabled: bool,
+    /// Whether the connection has enabled extended CONNECT by sending the
+    /// `SETTINGS_ENABLE_CONNECT_PROTOCOL` setting, allowing the peer to use the `:protocol`
+    /// pseudo-header.
+    pub connect_protocol_enabled: bool,
+    /// The policy that decides how the DATA and HEADERS frames sent on the connection are padded.
+    pub padding_policy: PaddingPolicy,
+    /// The maximum size of a received header list, counted as in `SETTINGS_MAX_HEADER_LIST_SIZE`.
+    /// It follows the value of that setting, once it is sent to the peer. Header lists over the
+    /// limit are never handed to the session (see `Session::header_list_too_large`).
+    pub max_header_list_size: u32,
+    /// The maximum size of a received header block, across the HEADERS frame and its CONTINUATION
+    /// frames. A larger block is a connection error, as it is never buffered in full.
+    pub max_header_block_size: usize,
+    /// The parsers of the extension frame types that the connection understands.
+    pub extensions: ExtensionRegistry,
+    /// Limits the rate at which the peer may send RST_STREAM, PING, SETTINGS and empty DATA
+    /// frames, as well as reset the streams that it opens.
+    pub flood_guard: FloodGuard,
+    /// The observer that is shown every frame that is sent and received on the connection, if
+    /// any.
+    pub observer: Option<Box<FrameObserver>>,
+    /// The state of the pseudo-random generator that provides the lengths of random padding.
+    padding_rng: u64,
+    /// The state that the connection keeps for each stream, for as long as there is any; stream
+    /// `0` stands for the connection (see `TrackedStream::unacked_data`).
+    streams: HashMap<StreamId, TrackedStream>,
+    /// The stream whose message is started by the header block that is being handed to the
+    /// session.
+    starting_message: Option<StreamId>,
+    /// The `SETTINGS_HEADER_TABLE_SIZE` carried by each SETTINGS frame that was sent, but not yet
+    /// acknowledged by the peer, oldest first. The decoder only adopts a new table size once the
+    /// peer acknowledges it, since the peer's encoder keeps using the old one until then.
+    unacked_table_sizes: VecDeque<Option<u32>>,
+    /// The header block that is being received, while its CONTINUATION frames are still due.
+    partial_headers: Option<PartialHeaders>,
+    /// The highest ID of the streams on which the peer has sent a header block.
+    last_stream_id: StreamId,
+    /// The statistics of the connection's traffic, without the ones that are only computed when a
+    /// snapshot is taken (see `stats`).
+    stats: ConnectionStats,
+    /// The peer's `SETTINGS_INITIAL_WINDOW_SIZE`.
+    peer_initial_window_size: i32,
+    /// The payload of the last PING that was sent and the time at which it was sent, until the
+    /// peer acknowledges it.
+    unacked_ping: Option<(u64, Instant)>,
+    /// The streams that are to be reset, with the error code of each, the next time that the
+    /// connection has a `SendFrame` at hand (see `reset_stream`).
+    pending_resets: Vec<(StreamId, ErrorCode)>,
+}
+
+/// The state that an `HttpConnection` keeps for a single stream.
+#[derive(Default)]
+struct TrackedStream {
+    /// The length of the body being received, if the peer's message declared a `content-length`.
+    body_length: Option<BodyLength>,
+    /// Whether a HEAD request was sent on the stream, so that its response carries no body,
+    /// regardless of the `content-length` that it declares.
+    head_request: bool,
+    /// Whether the header block that starts the peer's message (i.e. anything but an
+    /// informational response) has been received.
+    message_started: bool,
+    /// The number of bytes of received DATA frames for which the flow control window has not yet
+    /// been given back to the peer.
+    unacked_data: u32,
+    /// The number of bytes of received DATA frames whose flow control window is held back until
+    /// the stream's data is consumed (see `hold_window`).
+    held_data: u32,
+    /// The outbound flow control window that the peer has granted the stream, while we are still
+    /// sending on it. It is only tracked for the statistics.
+    out_window: Option<i32>,
+}
+
+impl TrackedStream {
+    /// Discards the state of the data that the peer sends on the stream.
+    fn close_remote(&mut self) {
+        let out_window = self.out_window;
+        *self = TrackedStream::default();
+        self.out_window = out_window;
+    }
+
+    /// Returns whether nothing is left of the stream's state.
+    fn is_empty(&self) -> bool {
+        self.body_length.is_none() && !self.head_request && !self.message_started &&
+        self.unacked_data == 0 && self.held_data == 0 && self.out_window.is_none()
+    }
+}
+
+/// A header block whose HEADERS frame has been received, but whose CONTINUATION frames are still
+/// due.
+struct PartialHeaders {
+    stream_id: StreamId,
+    end_stream: bool,
+    block: Vec<u8>,
 }
 
 /// A trait that should be implemented by types that can provide the functionality
@@ -217,16 +374,114 @@ impl<'a, S> HttpConnectionSender<'a, S>
     /// `HttpError::IoError` variant and propagated upwards.
     ///
     /// If the frame is successfully written, returns a unit Ok (`Ok(())`).
-    #[inline]
     fn send_frame<F: FrameIR>(&mut self, frame: F) -> HttpResult<()> {
-        self.sender.send_frame(frame)
+        let (len, frame_type, _, _) = match frame.frame_header() {
+            Some(header) if self.conn.observer.is_none() => {
+                try!(self.sender.send_frame(frame));
+                header
+            }
+            _ => {
+                // The frame is serialized up front, so that the observer is shown exactly what is
+                // written on the wire, or so that a frame without a known header can be counted.
+                let mut buf = io::Cursor::new(Vec::with_capacity(1024));
+                try!(frame.serialize_into(&mut buf));
+                let raw: RawFrame = buf.into_inner().into();
+                if let Some(ref mut observer) = self.conn.observer {
+                    match HttpFrame::from_raw(&raw) {
+                        Ok(frame) => observer.on_frame_sent(&frame),
+                        Err(_) => {
+                            observer.on_frame_sent(&HttpFrame::UnknownFrame(raw.as_ref().into()))
+                        }
+                    }
+                }
+                let header = raw.header();
+                try!(self.sender.send_frame(raw));
+                header
+            }
+        };
+        self.conn.stats.frames_sent.record(frame_type);
+        self.conn.stats.bytes_sent += 9 + len as u64;
+        Ok(())
+    }
+
+    /// Sends the RST_STREAM frames that were queued by `HttpConnection::reset_stream`.
+    pub fn send_pending_resets(&mut self) -> HttpResult<()> {
+        let resets: Vec<_> = self.conn.pending_resets.drain(..).collect();
+        for (stream_id, code) in resets {
+            try!(self.rst_stream(stream_id, code));
+        }
+        Ok(())
     }
 
     /// Send a RST_STREAM frame for the given frame id
     pub fn rst_stream(&mut self, id: StreamId, code: ErrorCode) -> HttpResult<()> {
+        self.conn.discard_stream_state(id, |stream| *stream = TrackedStream::default());
+        *self.conn.stats.resets_sent.entry(code).or_insert(0) += 1;
         self.send_frame(RstStreamFrame::new(id, code))
     }
 
+    /// Sends a PRIORITY frame that assigns the given dependency to the given stream.
+    pub fn send_priority(&mut self, id: StreamId, dep: StreamDependency) -> HttpResult<()> {
+        self.send_frame(PriorityFrame::new(id, dep))
+    }
+
+    /// Sends the given frame of an extension frame type (e.g. a `RawFrame` built with
+    /// `RawFrame::from_parts`).
+    ///
+    /// The connection does not interpret the frame in any way, so it is up to the caller to make
+    /// sure that the peer supports the extension (or that the frame can safely be ignored).
+    pub fn send_extension_frame<F: FrameIR>(&mut self, frame: F) -> HttpResult<()> {
+        self.send_frame(frame)
+    }
+
+    /// Sends a SETTINGS frame carrying the given settings to the peer.
+    pub fn send_settings(&mut self, settings: Vec<HttpSetting>) -> HttpResult<()> {
+        let mut frame = SettingsFrame::new();
+        let mut table_size = None;
+        for setting in settings {
+            match setting {
+                HttpSetting::EnableConnectProtocol(val) => {
+                    self.conn.connect_protocol_enabled = val == 1;
+                }
+                HttpSetting::HeaderTableSize(size) => table_size = Some(size),
+                HttpSetting::MaxHeaderListSize(size) => self.conn.max_header_list_size = size,
+                _ => {}
+            }
+            frame.add_setting(setting);
+        }
+        self.conn.unacked_table_sizes.push_back(table_size);
+        self.send_frame(frame)
+    }
+
+    /// Gives the flow control window taken up by the received DATA frames back to the peer, by
+    /// sending WINDOW_UPDATE frames for the connection and for each stream on which at least
+    /// half of the initial window has been used up.
+    ///
+    /// Without this, the peer could only ever send an initial window's worth of data on a stream
+    /// (or on the whole connection).
+    pub fn send_window_updates(&mut self) -> HttpResult<()> {
+        let mut due: Vec<(StreamId, u32)> = self.conn
+            .streams
+            .iter()
+            .filter(|&(_, stream)| stream.unacked_data >= WINDOW_UPDATE_THRESHOLD)
+            .map(|(&stream_id, stream)| (stream_id, stream.unacked_data))
+            .collect();
+        due.sort();
+        for (stream_id, len) in due {
+            self.conn.discard_stream_state(stream_id, |stream| stream.unacked_data = 0);
+            if stream_id == 0 {
+                try!(self.conn
+                         .in_window_size
+                         .try_increase(len)
+                         .map_err(|_| HttpError::WindowSizeOverflow));
+                try!(self.send_frame(WindowUpdateFrame::for_connection(len)));
+            } else {
+                try!(self.send_frame(WindowUpdateFrame::for_stream(stream_id, len)));
+            }
+        }
+        Ok(())
+    }
+
     /// Sends a SETTINGS acknowledge frame to the peer.
     pub fn send_settings_ack(&mut self) -> HttpResult<()> {
         self.send_frame(SettingsFrame::new_ack())
@@ -239,9 +494,16 @@ impl<'a, S> HttpConnectionSender<'a, S>
 
     /// Sends a PING request
     pub fn send_ping(&mut self, bytes: u64) -> HttpResult<()> {
+        self.conn.unacked_ping = Some((bytes, Instant::now()));
         self.send_frame(PingFrame::with_data(bytes))
     }
 
+    /// Sends a GOAWAY frame with the given error code, telling the peer that no streams with IDs
+    /// higher than the given one were (or will be) processed.
+    pub fn send_goaway(&mut self, last_stream_id: StreamId, code: ErrorCode) -> HttpResult<()> {
+        self.send_frame(GoawayFrame::new(last_stream_id, code))
+    }
+
     /// A helper function that inserts the frames required to send the given headers onto the
     /// `SendFrame` stream.
     ///
@@ -260,14 +522,51 @@ impl<'a, S> HttpConnectionSender<'a, S>
                                                               stream_id: StreamId,
                                                               end_stream: EndStream)
                                                               -> HttpResult<()> {
+        self.send_headers_with_priority(headers, stream_id, None, end_stream)
+    }
+
+    /// Sends the given headers, like `send_headers`, additionally assigning the given priority
+    /// to the stream, if any. The priority is carried by the HEADERS frame itself.
+    pub fn send_headers_with_priority<'n, 'v, H>(&mut self,
+                                                 headers: H,
+                                                 stream_id: StreamId,
+                                                 priority: Option<StreamDependency>,
+                                                 end_stream: EndStream)
+                                                 -> HttpResult<()>
+        where H: Into<Vec<Header<'n, 'v>>>
+    {
+        let headers = headers.into();
+        if headers.iter().any(|h| h.name() == b":method" && h.value() == b"HEAD") {
+            self.conn.stream_mut(stream_id).head_request = true;
+        }
+        if let Some(ref mut observer) = self.conn.observer {
+            observer.on_headers_sent(stream_id, &headers);
+        }
         let headers_fragment = self.conn
                                    .encoder
-                                   .encode(headers.into().iter().map(|h| (h.name(), h.value())));
+                                   .encode(headers.iter().map(|h| (h.name(), h.value())));
+        self.conn.stats.hpack.header_bytes_sent +=
+            headers.iter().map(|h| (h.name().len() + h.value().len()) as u64).sum::<u64>();
+        self.conn.stats.hpack.block_bytes_sent += headers_fragment.len() as u64;
+        if end_stream == EndStream::No {
+            let window = self.conn.peer_initial_window_size;
+            let stream = self.conn.stream_mut(stream_id);
+            stream.out_window = stream.out_window.or(Some(window));
+        } else {
+            self.conn.discard_stream_state(stream_id, |stream| stream.out_window = None);
+        }
         // For now, sending header fragments larger than 16kB is not supported
         // (i.e. the encoded representation cannot be split into CONTINUATION
         // frames).
-        let mut frame = HeadersFrame::new(headers_fragment, stream_id);
+        let payload_len = headers_fragment.len() + if priority.is_some() { 5 } else { 0 };
+        let mut frame = match priority {
+            Some(dep) => HeadersFrame::with_dependency(headers_fragment, stream_id, dep),
+            None => HeadersFrame::new(headers_fragment, stream_id),
+        };
         frame.set_flag(HeadersFlag::EndHeaders);
+        if let Some(padding_len) = self.conn.next_padding(payload_len) {
+            frame.set_padding(padding_len);
+        }
 
         if end_stream == EndStream::Yes {
             frame.set_flag(HeadersFlag::EndStream);
@@ -286,9 +585,23 @@ impl<'a, S> HttpConnectionSender<'a, S>
         if end_stream == EndStream::Yes {
             frame.set_flag(DataFlag::EndStream);
         }
-        // Adjust the flow control window...
+        // The data was sized to fit into the flow control window, so the padding (along with its
+        // length field) only gets the room that the data leaves in the window.
+        let window = self.conn.stream_out_window(stream_id) as i64;
+        let room = window - frame.data.len() as i64 - 1;
+        if let Some(padding_len) = self.conn.next_padding(frame.data.len()) {
+            if room >= 0 {
+                frame.set_padding(cmp::min(padding_len as i64, room) as u8);
+            }
+        }
+        // Adjust the flow control window, which the padding also counts against...
         try!(self.conn.decrease_out_window(frame.payload_len()));
         trace!("New OUT WINDOW size = {}", self.conn.out_window_size());
+        if end_stream == EndStream::Yes {
+            self.conn.discard_stream_state(stream_id, |stream| stream.out_window = None);
+        } else if let Some(window) = self.conn.out_window_mut(stream_id) {
+            *window = window.saturating_sub(frame.payload_len() as i32);
+        }
         // ...and now send it out.
         self.send_frame(frame)
     }
@@ -321,12 +634,93 @@ impl HttpConnection {
         HttpConnection {
             scheme: scheme,
             decoder: hpack::Decoder::new(),
-            encoder: hpack::Encoder::new(),
+            encoder: HeaderEncoder::new(),
             in_window_size: WindowSize::new(INITIAL_CONNECTION_WINDOW_SIZE),
             out_window_size: WindowSize::new(INITIAL_CONNECTION_WINDOW_SIZE),
+            peer_max_concurrent_streams: None,
+            peer_connect_protocol_enabled: false,
+            connect_protocol_enabled: false,
+            padding_policy: PaddingPolicy::None,
+            max_header_list_size: DEFAULT_MAX_HEADER_LIST_SIZE,
+            max_header_block_size: DEFAULT_MAX_HEADER_BLOCK_SIZE,
+            extensions: ExtensionRegistry::new(),
+            flood_guard: FloodGuard::default(),
+            observer: None,
+            padding_rng: padding_seed(),
+            streams: HashMap::new(),
+            starting_message: None,
+            unacked_table_sizes: VecDeque::new(),
+            partial_headers: None,
+            last_stream_id: 0,
+            stats: ConnectionStats::default(),
+            peer_initial_window_size: INITIAL_CONNECTION_WINDOW_SIZE,
+            unacked_ping: None,
+            pending_resets: Vec::new(),
         }
     }
 
+    /// Records that a SETTINGS frame, which does not change the header table size, was sent to the
+    /// peer outside of the connection (i.e. the one of the client preface, see `write_preface`),
+    /// so that the peer's acknowledgement of it is not taken for the one of a later SETTINGS frame.
+    pub fn preface_settings_sent(&mut self) {
+        self.unacked_table_sizes.push_back(None);
+    }
+
+    /// Queues a RST_STREAM frame for the given stream, for the cases where no `SendFrame` is at
+    /// hand (e.g. in the default implementations of the `Session` methods). The frame is sent by
+    /// the next call to `HttpConnectionSender::send_pending_resets`, which the `ClientConnection`
+    /// and the `ServerConnection` make after handling each frame.
+    pub fn reset_stream(&mut self, stream_id: StreamId, code: ErrorCode) {
+        self.pending_resets.push((stream_id, code));
+    }
+
+    /// Returns whether the header block that starts the peer's message on the given stream has
+    /// already been received, so that any further header block on it carries trailers.
+    /// Informational responses do not start the message.
+    pub fn message_started(&self, stream_id: StreamId) -> bool {
+        self.streams.get(&stream_id).map_or(false, |stream| stream.message_started) &&
+        self.starting_message != Some(stream_id)
+    }
+
+    /// Internal helper method. Returns the state of the given stream, starting to track it if it
+    /// is not yet.
+    fn stream_mut(&mut self, stream_id: StreamId) -> &mut TrackedStream {
+        self.streams.entry(stream_id).or_insert_with(Default::default)
+    }
+
+    /// Internal helper method. Returns the outbound flow control window of the given stream, if
+    /// it is still sending.
+    fn out_window_mut(&mut self, stream_id: StreamId) -> Option<&mut i32> {
+        self.streams.get_mut(&stream_id).and_then(|stream| stream.out_window.as_mut())
+    }
+
+    /// Internal helper method. Discards a part of the state of the given stream by using the given
+    /// function, and stops tracking the stream once nothing is left of its state.
+    fn discard_stream_state<F: FnOnce(&mut TrackedStream)>(&mut self,
+                                                           stream_id: StreamId,
+                                                           discard: F) {
+        let empty = match self.streams.get_mut(&stream_id) {
+            Some(stream) => {
+                discard(stream);
+                stream.is_empty()
+            }
+            None => return,
+        };
+        if empty {
+            self.streams.remove(&stream_id);
+        }
+    }
+
+    /// Internal helper method. Returns the length of the padding that the connection's policy
+    /// prescribes for a frame with a payload of the given (unpadded) length, if any.
+    fn next_padding(&mut self, payload_len: usize) -> Option<u8> {
+        // A xorshift generator is plenty for obscuring the frame sizes.
+        self.padding_rng ^= self.padding_rng << 13;
+        self.padding_rng ^= self.padding_rng >> 7;
+        self.padding_rng ^= self.padding_rng << 17;
+        self.padding_policy.padding_len(payload_len, self.padding_rng)
+    }
+
     /// Creates a new `HttpConnectionSender` instance that will use the given `SendFrame` instance
     /// to send the frames that it prepares. This is a convenience struct so that clients do not
     /// have to pass the same `sender` reference to multiple send methods.
@@ -369,12 +763,76 @@ impl HttpConnection {
     pub fn in_window_size(&self) -> i32 {
         self.in_window_size.size()
     }
+
+    /// Returns a snapshot of the connection's statistics. The statistics of the streams are left
+    /// empty, since the connection does not track which streams are active (see `stream_stats`).
+    pub fn stats(&self) -> ConnectionStats {
+        let mut stats = self.stats.clone();
+        stats.in_window = self.in_window_size();
+        stats.out_window = self.out_window_size();
+        stats.hpack.table_size = self.encoder.table_size();
+        stats.hpack.max_table_size = self.encoder.max_table_size();
+        stats
+    }
+
+    /// Returns the flow control windows of the given stream.
+    pub fn stream_stats(&self, stream_id: StreamId) -> StreamStats {
+        let stream = self.streams.get(&stream_id);
+        let pending = stream.map_or(0, |stream| stream.unacked_data + stream.held_data);
+        StreamStats {
+            stream_id: stream_id,
+            in_window: INITIAL_CONNECTION_WINDOW_SIZE - pending as i32,
+            out_window: stream.and_then(|stream| stream.out_window)
+                              .unwrap_or(self.peer_initial_window_size),
+        }
+    }
+
+    /// Returns the number of octets of data that can currently be sent on the given stream: the
+    /// smaller of the stream's and the connection's outbound flow control windows.
+    pub fn stream_out_window(&self, stream_id: StreamId) -> i32 {
+        cmp::min(self.out_window_size(), self.stream_stats(stream_id).out_window)
+    }
+
+    /// Returns the highest ID of the streams on which the peer has sent a header block, which is
+    /// the last stream that a GOAWAY frame sent to the peer should declare as processed.
+    pub fn last_received_stream_id(&self) -> StreamId {
+        self.last_stream_id
+    }
+
     /// Returns the current size of the outbound flow control window (i.e. the number of octets
     /// that can be sent on the connection to the peer without violating flow control).
     pub fn out_window_size(&self) -> i32 {
         self.out_window_size.size()
     }
 
+    /// Holds back the flow control window taken up by the given number of bytes received on the
+    /// given stream, so that `send_window_updates` does not give it back to the peer until it is
+    /// released by `release_window`. The connection's own window is not held back.
+    ///
+    /// Holding back the window makes the peer stop sending once the stream's unconsumed data
+    /// reaches the size of its window.
+    pub fn hold_window(&mut self, stream_id: StreamId, len: u32) {
+        match self.streams.get_mut(&stream_id) {
+            Some(stream) => {
+                let held = cmp::min(stream.unacked_data, len);
+                stream.unacked_data -= held;
+                stream.held_data += held;
+            }
+            // The peer has already closed the stream, so there is no window to hold back.
+            None => {}
+        }
+    }
+
+    /// Releases up to the given number of bytes of the window held back by `hold_window` on the
+    /// given stream, so that it is given back to the peer by the next `send_window_updates`.
+    pub fn release_window(&mut self, stream_id: StreamId, len: u32) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            let released = cmp::min(stream.held_data, len);
+            stream.held_data -= released;
+            stream.unacked_data += released;
+        }
+    }
+
     /// The method processes the next frame provided by the given `ReceiveFrame` instance, expecting
     /// it to be a SETTINGS frame.
     /// Additionally, the frame cannot be an ACK settings frame, but rather it should contain the
@@ -438,25 +896,59 @@ impl HttpConnection {
                                    frame: HttpFrame,
                                    session: &mut Sess)
                                    -> HttpResult<()> {
+        let (len, frame_type, _, _) = frame.get_header();
+        self.stats.frames_received.record(frame_type);
+        self.stats.bytes_received += 9 + len as u64;
+        if let Some(ref mut observer) = self.observer {
+            observer.on_frame_received(&frame);
+        }
+        if self.partial_headers.is_some() {
+            // Nothing but the CONTINUATION frames of the header block may follow its HEADERS.
+            return match frame {
+                HttpFrame::UnknownFrame(ref frame) if is_continuation(frame) => {
+                    self.handle_continuation_frame(frame, session)
+                }
+                _ => {
+                    debug!("Expected a CONTINUATION frame");
+                    Err(HttpError::InvalidFrame)
+                }
+            };
+        }
         match frame {
             HttpFrame::DataFrame(frame) => {
                 debug!("Data frame received");
+                if frame.data.is_empty() && !frame.is_set(DataFlag::EndStream) {
+                    try!(self.flood_guard.check(Flood::EmptyData));
+                }
                 self.handle_data_frame(frame, session)
             }
             HttpFrame::HeadersFrame(frame) => {
                 debug!("Headers frame received");
                 self.handle_headers_frame(frame, session)
             }
+            HttpFrame::PriorityFrame(frame) => {
+                // The priorities that the peer assigns to its streams are only advisory, so they
+                // are not taken into account when sending data.
+                debug!("PRIORITY frame received for stream {}", frame.get_stream_id());
+                Ok(())
+            }
             HttpFrame::RstStreamFrame(frame) => {
                 debug!("RST_STREAM frame received");
+                try!(self.flood_guard.check(Flood::RstStream));
                 self.handle_rst_stream_frame(frame, session)
             }
             HttpFrame::SettingsFrame(frame) => {
                 debug!("Settings frame received");
+                if !frame.is_ack() {
+                    try!(self.flood_guard.check(Flood::Settings));
+                }
                 self.handle_settings_frame::<Sess>(frame, session)
             },
             HttpFrame::PingFrame(frame) => {
                 debug!("PING frame received");
+                if !frame.is_ack() {
+                    try!(self.flood_guard.check(Flood::Ping));
+                }
                 self.handle_ping_frame(frame, session)
             },
             HttpFrame::GoawayFrame(frame) => {
@@ -466,16 +958,33 @@ impl HttpConnection {
                                   frame.debug_data(),
                                   self)
             }
-            HttpFrame::WindowUpdateFrame(_) => {
+            HttpFrame::WindowUpdateFrame(frame) => {
                 debug!("WINDOW_UPDATE frame received");
-                Ok(())
+                self.handle_window_update_frame(frame)
+            }
+            HttpFrame::AltSvcFrame(frame) => {
+                debug!("ALTSVC frame received; frame = {:?}", frame);
+                session.on_altsvc(frame, self)
+            }
+            HttpFrame::OriginFrame(frame) => {
+                debug!("ORIGIN frame received; frame = {:?}", frame);
+                session.on_origin(frame, self)
+            }
+            HttpFrame::UnknownFrame(ref frame) if is_continuation(frame) => {
+                debug!("CONTINUATION frame received without a preceding HEADERS frame");
+                Err(HttpError::InvalidFrame)
             }
             HttpFrame::UnknownFrame(frame) => {
-                debug!("Unknown frame received; raw = {:?}", frame);
-                // We simply drop any unknown frames...
-                // TODO Signal this to the session so that a hook is available
-                //      for implementing frame-level protocol extensions.
-                Ok(())
+                match self.extensions.parse(&frame) {
+                    Some(frame) => {
+                        debug!("Extension frame received; frame = {:?}", frame);
+                        session.on_extension_frame(frame, self)
+                    }
+                    None => {
+                        debug!("Unknown frame received; raw = {:?}", frame);
+                        session.on_unknown_frame(&frame, self)
+                    }
+                }
             }
         }
     }
@@ -487,15 +996,30 @@ impl HttpConnection {
                                         -> HttpResult<()> {
         try!(self.decrease_in_window(frame.payload_len()));
         trace!("New IN WINDOW size = {}", self.in_window_size());
-        try!(session.new_data_chunk(frame.get_stream_id(), &frame.data, self));
+        let stream_id = frame.get_stream_id();
+        self.stream_mut(0).unacked_data += frame.payload_len();
+        if !frame.is_set(DataFlag::EndStream) {
+            // No more data can follow on a stream that the peer has closed, so only the
+            // connection's window needs to be given back.
+            self.stream_mut(stream_id).unacked_data += frame.payload_len();
+        }
+        let length = match self.streams.get_mut(&stream_id).and_then(|s| s.body_length.as_mut()) {
+            Some(length) => length.add(frame.data.len()),
+            None => Ok(()),
+        };
+        if let Err(err) = length {
+            debug!("Content-length mismatch on stream {}: {}", stream_id, err);
+            self.forget_stream(stream_id);
+            return session.content_length_mismatch(stream_id, err, self);
+        }
+        try!(session.new_data_chunk(stream_id, &frame.data, self));
         // TODO(mlalic): Should the connection separately signal the decrease in the flow control
         //               window? For now, it is expected that the data callback is enough, as the
         //               session would be able to inspect the new window size there (and know that
         //               it was affected by the data already).
 
         if frame.is_set(DataFlag::EndStream) {
-            debug!("End of stream {}", frame.get_stream_id());
-            try!(session.end_of_stream(frame.get_stream_id(), self));
+            try!(self.end_of_stream(stream_id, session));
         }
 
         Ok(())
@@ -506,26 +1030,163 @@ impl HttpConnection {
                                            frame: HeadersFrame,
                                            session: &mut Sess)
                                            -> HttpResult<()> {
-        let headers = try!(self.decoder
-                               .decode(&frame.header_fragment())
-                               .map_err(HttpError::CompressionError));
-        let headers = headers.into_iter().map(|h| h.into()).collect();
-        try!(session.new_headers(frame.get_stream_id(), headers, self));
+        let stream_id = frame.get_stream_id();
+        self.last_stream_id = cmp::max(self.last_stream_id, stream_id);
+        let fragment = frame.header_fragment();
+        if fragment.len() > self.max_header_block_size {
+            debug!("Header block on stream {} is too large", stream_id);
+            return Err(HttpError::HeaderBlockTooLarge);
+        }
+        if !frame.is_headers_end() {
+            self.partial_headers = Some(PartialHeaders {
+                stream_id: stream_id,
+                end_stream: frame.is_end_of_stream(),
+                block: fragment.to_vec(),
+            });
+            return Ok(());
+        }
+        self.handle_header_block(stream_id, fragment, frame.is_end_of_stream(), session)
+    }
+
+    /// Private helper method that handles a received CONTINUATION frame, which carries the next
+    /// part of the header block that is being received.
+    fn handle_continuation_frame<Sess: Session>(&mut self,
+                                                frame: &RawFrame,
+                                                session: &mut Sess)
+                                                -> HttpResult<()> {
+        let (_, _, flags, stream_id) = frame.header();
+        let mut partial = self.partial_headers.take().expect("a header block is being received");
+        if partial.stream_id != stream_id {
+            debug!("CONTINUATION frame for stream {}, expected stream {}",
+                   stream_id,
+                   partial.stream_id);
+            return Err(HttpError::InvalidFrame);
+        }
+        if partial.block.len() + frame.payload().len() > self.max_header_block_size {
+            debug!("Header block on stream {} is too large", stream_id);
+            return Err(HttpError::HeaderBlockTooLarge);
+        }
+        partial.block.extend_from_slice(frame.payload());
+        if flags & END_HEADERS_FLAG == 0 {
+            self.partial_headers = Some(partial);
+            return Ok(());
+        }
+        self.handle_header_block(stream_id, &partial.block, partial.end_stream, session)
+    }
+
+    /// Private helper method that decodes a complete header block received on the given stream
+    /// and passes the headers on to the session, unless they are over the header list size limit.
+    fn handle_header_block<Sess: Session>(&mut self,
+                                          stream_id: StreamId,
+                                          block: &[u8],
+                                          end_stream: bool,
+                                          session: &mut Sess)
+                                          -> HttpResult<()> {
+        self.stats.hpack.block_bytes_received += block.len() as u64;
+        let headers = match try!(self.decode_header_block(block)) {
+            Some(headers) => headers,
+            None => {
+                debug!("Header list on stream {} is too large", stream_id);
+                self.forget_stream(stream_id);
+                return session.header_list_too_large(stream_id, self);
+            }
+        };
+        if let Some(ref mut observer) = self.observer {
+            observer.on_headers_received(stream_id, &headers);
+        }
+        let informational = headers.iter().any(|h| {
+            h.name() == b":status" && h.value().first() == Some(&b'1')
+        });
+        let starting = {
+            let stream = self.stream_mut(stream_id);
+            if !stream.head_request && stream.body_length.is_none() {
+                stream.body_length = declared_body_length(&headers).map(BodyLength::new);
+            }
+            // The stream is marked up front, so that it is forgotten if the session resets it.
+            let starting = !informational && !stream.message_started;
+            stream.message_started |= starting;
+            starting
+        };
+        if starting {
+            self.starting_message = Some(stream_id);
+        }
+        let res = session.new_headers(stream_id, headers, self);
+        self.starting_message = None;
+        try!(res);
 
-        if frame.is_end_of_stream() {
-            debug!("End of stream {}", frame.get_stream_id());
-            try!(session.end_of_stream(frame.get_stream_id(), self));
+        if end_stream {
+            try!(self.end_of_stream(stream_id, session));
         }
 
         Ok(())
     }
 
+    /// Private helper method that decodes the given header block. Returns `None` if the decoded
+    /// header list is over the connection's `max_header_list_size`, in which case none of the
+    /// headers past the limit are kept.
+    fn decode_header_block(&mut self,
+                           block: &[u8])
+                           -> HttpResult<Option<Vec<Header<'static, 'static>>>> {
+        let limit = self.max_header_list_size as usize;
+        let mut headers = Vec::new();
+        let mut size = 0;
+        let mut raw_size = 0;
+        // The whole block is decoded even once the list goes over the limit, since the decoder's
+        // dynamic table has to stay in sync with the peer's encoder.
+        try!(self.decoder
+                 .decode_with_cb(block, |name, value| {
+                     raw_size += name.len() + value.len();
+                     size += name.len() + value.len() + ENTRY_OVERHEAD;
+                     if size <= limit {
+                         headers.push(Header::new(name.into_owned(), value.into_owned()));
+                     }
+                 })
+                 .map_err(HttpError::CompressionError));
+        self.stats.hpack.header_bytes_received += raw_size as u64;
+        if size > limit {
+            Ok(None)
+        } else {
+            Ok(Some(headers))
+        }
+    }
+
+    /// Private helper method that signals the end of the given stream to the session, once the
+    /// peer has closed it. If the stream's message declared a `content-length` that its body does
+    /// not match, the mismatch is signaled instead.
+    fn end_of_stream<Sess: Session>(&mut self,
+                                    stream_id: StreamId,
+                                    session: &mut Sess)
+                                    -> HttpResult<()> {
+        let length = self.streams
+                         .get(&stream_id)
+                         .and_then(|stream| stream.body_length.as_ref())
+                         .map_or(Ok(()), |length| length.finish());
+        self.forget_stream(stream_id);
+        if let Err(err) = length {
+            debug!("Content-length mismatch on stream {}: {}", stream_id, err);
+            return session.content_length_mismatch(stream_id, err, self);
+        }
+        debug!("End of stream {}", stream_id);
+        session.end_of_stream(stream_id, self)
+    }
+
+    /// Private helper method that discards all the per-stream state that the connection keeps for
+    /// the given stream.
+    fn forget_stream(&mut self, stream_id: StreamId) {
+        if stream_id != 0 {
+            self.discard_stream_state(stream_id, TrackedStream::close_remote);
+        }
+    }
+
     /// Private helper method that handles a received `RstStreamFrame`
     #[inline]
     fn handle_rst_stream_frame<Sess: Session>(&mut self,
                                               frame: RstStreamFrame,
                                               session: &mut Sess)
                                               -> HttpResult<()> {
+        self.discard_stream_state(frame.get_stream_id(),
+                                  |stream| *stream = TrackedStream::default());
+        *self.stats.resets_received.entry(frame.error_code()).or_insert(0) += 1;
         session.rst_stream(frame.get_stream_id(), frame.error_code(), self)
     }
 
@@ -533,12 +1194,34 @@ impl HttpConnection {
     fn handle_ping_frame<Sess: Session>(&mut self, frame: PingFrame, session: &mut Sess)
             -> HttpResult<()> {
         if frame.is_ack() {
+            if let Some((bytes, sent)) = self.unacked_ping {
+                if bytes == frame.opaque_data() {
+                    self.stats.ping_rtt = Some(sent.elapsed());
+                    self.unacked_ping = None;
+                }
+            }
             session.on_pong(&frame, self)
         } else {
             session.on_ping(&frame, self)
         }
     }
 
+    /// Private helper method that handles a received `WindowUpdateFrame`. The connection's outbound
+    /// window grows by the increment, while the windows of the streams are only tracked for the
+    /// statistics.
+    fn handle_window_update_frame(&mut self, frame: WindowUpdateFrame) -> HttpResult<()> {
+        let stream_id = frame.get_stream_id();
+        if stream_id == 0 {
+            return self.out_window_size
+                       .try_increase(frame.increment())
+                       .map_err(|_| HttpError::WindowSizeOverflow);
+        }
+        if let Some(window) = self.out_window_mut(stream_id) {
+            *window = window.saturating_add(frame.increment() as i32);
+        }
+        Ok(())
+    }
+
     /// Private helper method that handles a received `SettingsFrame`.
     fn handle_settings_frame<Sess: Session>(&mut self,
                                             frame: SettingsFrame,
@@ -548,7 +1231,39 @@ impl HttpConnection {
             // TODO: Actually handle the settings change before sending out the ACK
             //       sending out the ACK.
             trace!("New settings frame {:#?}", frame);
+            for setting in frame.settings.iter() {
+                match *setting {
+                    HttpSetting::MaxConcurrentStreams(max) => {
+                        self.peer_max_concurrent_streams = Some(max);
+                    }
+                    HttpSetting::EnableConnectProtocol(val) => {
+                        self.peer_connect_protocol_enabled = val == 1;
+                    }
+                    HttpSetting::HeaderTableSize(size) => {
+                        self.encoder.set_peer_max_table_size(size as usize);
+                    }
+                    HttpSetting::InitialWindowSize(size) => {
+                        // A window larger than 2^31 - 1 is a connection error of the type
+                        // FLOW_CONTROL_ERROR.
+                        if size > 0x7fffffff {
+                            return Err(HttpError::WindowSizeOverflow);
+                        }
+                        // The change applies to the windows of all the open streams.
+                        let delta = (size as i32).wrapping_sub(self.peer_initial_window_size);
+                        for stream in self.streams.values_mut() {
+                            if let Some(ref mut window) = stream.out_window {
+                                *window = window.saturating_add(delta);
+                            }
+                        }
+                        self.peer_initial_window_size = size as i32;
+                    }
+                    _ => {}
+                }
+            }
             try!(session.new_settings(frame.settings, self));
+        } else if let Some(Some(size)) = self.unacked_table_sizes.pop_front() {
+            debug!("Decoder table size changed to {}", size);
+            self.decoder.set_max_table_size(size as usize);
         }
 
         Ok(())
@@ -578,18 +1293,40 @@ impl HttpConnection {
     }
 }
 
+/// Returns whether the given raw frame is a CONTINUATION frame.
+fn is_continuation(frame: &RawFrame) -> bool {
+    frame.header().1 == CONTINUATION_FRAME_TYPE
+}
+
+/// Returns a seed for the pseudo-random generator of padding lengths. It is derived from the
+/// random keys of a `RandomState`, which the standard library draws from the operating system's
+/// entropy source. The seed is never zero, as that is a fixed point of the generator.
+fn padding_seed() -> u64 {
+    RandomState::new().build_hasher().finish() | 1
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
     use std::io;
+    use std::sync::{Arc, Mutex};
 
     use super::{HttpConnection, HttpFrame, SendFrame, EndStream, DataChunk, SendStatus};
 
-    use http::tests::common::{build_mock_http_conn, StubDataPrioritizer, TestSession,
-                              MockReceiveFrame, MockSendFrame};
-    use http::frame::{Frame, DataFrame, HeadersFrame, RstStreamFrame, GoawayFrame, SettingsFrame,
-                      PingFrame, pack_header, RawFrame, FrameIR};
-    use http::{HttpResult, HttpScheme, Header, OwnedHeader, ErrorCode};
+    use http::tests::common::{build_mock_http_conn, build_headers_frame, StubDataPrioritizer,
+                              TestSession, MockReceiveFrame, MockSendFrame, serialize_frame,
+                              raw_frame_from_parts};
+    use http::frame::{Frame, DataFrame, DataFlag, HeadersFrame, HeadersFlag, RstStreamFrame,
+                      GoawayFrame, SettingsFrame, PingFrame, PriorityFrame, StreamDependency,
+                      pack_header, RawFrame, FrameIR, HttpSetting, AltSvcFrame, OriginFrame,
+                      WindowUpdateFrame};
+    use http::validation::ContentLengthError;
+    use http::padding::PaddingPolicy;
+    use http::flood::{Flood, FloodLimits};
+    use std::time::Duration;
+    use http::frame::extension::tests::{TestExtensionFrame, parse_test_frame};
+    use http::{HttpError, HttpResult, HttpScheme, Header, OwnedHeader, ErrorCode, StreamId};
+    use http::observer::FrameObserver;
     use hpack;
 
     /// A helper function that performs a `send_frame` operation on the given
@@ -607,9 +1344,12 @@ mod tests {
             HttpFrame::SettingsFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::RstStreamFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::HeadersFrame(frame) => conn.sender(sender).send_frame(frame),
+            HttpFrame::PriorityFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::PingFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::GoawayFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::WindowUpdateFrame(frame) => conn.sender(sender).send_frame(frame),
+            HttpFrame::AltSvcFrame(frame) => conn.sender(sender).send_frame(frame),
+            HttpFrame::OriginFrame(frame) => conn.sender(sender).send_frame(frame),
             HttpFrame::UnknownFrame(_) => Ok(()),
         }
     }
@@ -639,10 +1379,36 @@ mod tests {
             _ => false,
         });
 
+        let dep = StreamDependency::new(0, 15, false);
+        assert!(match HttpFrame::from_raw(&to_raw(PriorityFrame::new(1, dep))) {
+            Ok(HttpFrame::PriorityFrame(_)) => true,
+            _ => false,
+        });
+
+        assert!(match HttpFrame::from_raw(&to_raw(AltSvcFrame::new(0, "a.com", "clear"))) {
+            Ok(HttpFrame::AltSvcFrame(_)) => true,
+            _ => false,
+        });
+
+        assert!(match HttpFrame::from_raw(&to_raw(OriginFrame::new(vec!["a.com"]))) {
+            Ok(HttpFrame::OriginFrame(_)) => true,
+            _ => false,
+        });
+
+        // ALTSVC and ORIGIN frames that must be ignored are handled as unknown frames.
+        assert!(match HttpFrame::from_raw(&to_raw(AltSvcFrame::new(0, "", "clear"))) {
+            Ok(HttpFrame::UnknownFrame(_)) => true,
+            _ => false,
+        });
+        assert!(match HttpFrame::from_raw(&RawFrame::from_parts(0xc, 0, 1, b"\x00\x01a")) {
+            Ok(HttpFrame::UnknownFrame(_)) => true,
+            _ => false,
+        });
+
         let unknown_frame = RawFrame::from({
             let mut buf: Vec<u8> = Vec::new();
-            // Frame type 10 with a payload of length 1 on stream 1
-            let header = (1u32, 10u8, 0u8, 1u32);
+            // Frame type 11 with a payload of length 1 on stream 1
+            let header = (1u32, 11u8, 0u8, 1u32);
             buf.extend(pack_header(&header).to_vec().into_iter());
             buf.push(1);
             buf
@@ -813,6 +1579,114 @@ mod tests {
         }
     }
 
+    /// Tests that the priority given along with the headers is carried by the HEADERS frame.
+    #[test]
+    fn test_send_headers_with_priority() {
+        let headers = vec![Header::new(b":method", b"GET")];
+        let dep = StreamDependency::new(1, 31, true);
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+
+        conn.sender(&mut sender)
+            .send_headers_with_priority(headers, 3, Some(dep.clone()), EndStream::Yes)
+            .unwrap();
+
+        let frame = match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => frame,
+            _ => panic!("Headers frame not sent"),
+        };
+        assert!(frame.is_set(HeadersFlag::Priority));
+        assert!(frame.is_headers_end());
+        assert!(frame.is_end_of_stream());
+        assert_eq!(frame.stream_dep, Some(dep));
+    }
+
+    /// Tests that the padding policy of the connection is applied to the HEADERS frames it sends.
+    #[test]
+    fn test_send_headers_padded() {
+        let headers = vec![Header::new(b":method", b"GET")];
+        let mut conn = build_mock_http_conn();
+        conn.padding_policy = PaddingPolicy::Fixed(10);
+        let mut sender = MockSendFrame::new();
+
+        conn.sender(&mut sender).send_headers(headers.clone(), 1, EndStream::Yes).unwrap();
+
+        let frame = match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => frame,
+            _ => panic!("Headers frame not sent"),
+        };
+        assert!(frame.is_set(HeadersFlag::Padded));
+        assert_eq!(frame.get_header().0 as usize, frame.header_fragment().len() + 11);
+        let decoded = hpack::Decoder::new().decode(frame.header_fragment()).unwrap();
+        assert_eq!(decoded, vec![(b":method".to_vec(), b"GET".to_vec())]);
+    }
+
+    /// Tests that padded DATA frames carry the original data and that their padding counts
+    /// against the outbound flow control window.
+    #[test]
+    fn test_send_data_padded() {
+        let mut conn = build_mock_http_conn();
+        conn.padding_policy = PaddingPolicy::Bucket(16);
+        let mut sender = MockSendFrame::new();
+
+        let data = b"123";
+        let chunk = DataChunk::new_borrowed(&data[..], 1, EndStream::No);
+        conn.sender(&mut sender).send_data(chunk).unwrap();
+
+        let frame = match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::DataFrame(frame) => frame,
+            _ => panic!("Data frame not sent"),
+        };
+        assert!(frame.is_padded());
+        assert_eq!(&frame.data[..], &data[..]);
+        assert_eq!(frame.payload_len(), 16);
+        assert_eq!(conn.out_window_size(), 65_535 - 16);
+    }
+
+    /// Tests that padding does not overshoot the flow control window that the data leaves.
+    #[test]
+    fn test_send_data_padded_window_boundary() {
+        let mut conn = build_mock_http_conn();
+        conn.padding_policy = PaddingPolicy::Fixed(100);
+        let mut sender = MockSendFrame::new();
+        let data = [0; 1000];
+        // The window leaves room for the data and 50 more bytes...
+        conn.decrease_out_window(65_535 - 1050).unwrap();
+
+        let chunk = DataChunk::new_borrowed(&data[..], 1, EndStream::No);
+        conn.sender(&mut sender).send_data(chunk).unwrap();
+        // ...which take the padding length field and 49 bytes of padding...
+        let frame = match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::DataFrame(frame) => frame,
+            _ => panic!("Data frame not sent"),
+        };
+        assert_eq!(frame.payload_len(), 1050);
+        assert_eq!(conn.out_window_size(), 0);
+
+        // ...while data that fills the window up is not padded at all.
+        conn.out_window_size.try_increase(1000).unwrap();
+        let chunk = DataChunk::new_borrowed(&data[..], 1, EndStream::No);
+        conn.sender(&mut sender).send_data(chunk).unwrap();
+        let frame = match HttpFrame::from_raw(&sender.sent[1]).unwrap() {
+            HttpFrame::DataFrame(frame) => frame,
+            _ => panic!("Data frame not sent"),
+        };
+        assert!(!frame.is_padded());
+        assert_eq!(conn.out_window_size(), 0);
+    }
+
+    #[test]
+    fn test_send_priority() {
+        let dep = StreamDependency::new(0, 200, false);
+        let expected = vec![HttpFrame::PriorityFrame(PriorityFrame::new(1, dep.clone()))];
+
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender).send_priority(1, dep).unwrap();
+
+        expect_frame_list(expected, sender.sent);
+    }
+
     /// Tests that `HttpConnection::send_data` correctly sends the given data when it can fit into
     /// a single frame's payload.
     #[test]
@@ -904,12 +1778,33 @@ mod tests {
         expect_frame_list(expected, sender.sent);
     }
 
+    /// Tests that the resets queued by `reset_stream` are only sent by `send_pending_resets`, and
+    /// only once.
+    #[test]
+    fn test_send_pending_resets() {
+        let expected = vec![
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(1, ErrorCode::ProtocolError)),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(3, ErrorCode::Cancel)),
+        ];
+
+        let mut conn = build_mock_http_conn();
+        conn.reset_stream(1, ErrorCode::ProtocolError);
+        conn.reset_stream(3, ErrorCode::Cancel);
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender).send_window_updates().unwrap();
+        assert_eq!(sender.sent.len(), 0);
+        conn.sender(&mut sender).send_pending_resets().unwrap();
+        conn.sender(&mut sender).send_pending_resets().unwrap();
+
+        expect_frame_list(expected, sender.sent);
+    }
+
     /// Tests that the `HttpConnection` correctly notifies the session on a
     /// new headers frame, with no continuation.
     #[test]
     fn test_http_conn_notifies_session_header() {
         let frames: Vec<HttpFrame> = vec![
-            HttpFrame::HeadersFrame(HeadersFrame::new(vec![], 1)),
+            HttpFrame::HeadersFrame(build_headers_frame(vec![], 1)),
         ];
         let mut conn = HttpConnection::new(HttpScheme::Http);
         let mut session = TestSession::new();
@@ -953,7 +1848,7 @@ mod tests {
     fn test_http_conn_session_gets_headers_data_values() {
         let expected_headers = vec![(b":method".to_vec(), b"GET".to_vec())];
         let frames: Vec<HttpFrame> = vec![
-            HttpFrame::HeadersFrame(HeadersFrame::new(
+            HttpFrame::HeadersFrame(build_headers_frame(
                     hpack::Encoder::new().encode(
                         expected_headers.iter().map(|h| (&h.0[..], &h.1[..]))),
                     1)),
@@ -1049,6 +1944,464 @@ mod tests {
         }
     }
 
+    /// Tests that the connection keeps track of the peer's SETTINGS_MAX_CONCURRENT_STREAMS.
+    #[test]
+    fn test_peer_max_concurrent_streams() {
+        let mut settings = SettingsFrame::new();
+        settings.add_setting(HttpSetting::MaxConcurrentStreams(10));
+        let frames = vec![HttpFrame::SettingsFrame(SettingsFrame::new()),
+                          HttpFrame::SettingsFrame(settings)];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        assert_eq!(conn.peer_max_concurrent_streams, None);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        // Not advertised by the first frame...
+        assert_eq!(conn.peer_max_concurrent_streams, None);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        // ...but by the second one.
+        assert_eq!(conn.peer_max_concurrent_streams, Some(10));
+    }
+
+    /// Tests that the connection keeps track of whether the peer has enabled extended CONNECT.
+    #[test]
+    fn test_peer_connect_protocol_enabled() {
+        let mut enable = SettingsFrame::new();
+        enable.add_setting(HttpSetting::EnableConnectProtocol(1));
+        let mut disable = SettingsFrame::new();
+        disable.add_setting(HttpSetting::EnableConnectProtocol(0));
+        let frames = vec![HttpFrame::SettingsFrame(enable), HttpFrame::SettingsFrame(disable)];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        assert!(!conn.peer_connect_protocol_enabled);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert!(conn.peer_connect_protocol_enabled);
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert!(!conn.peer_connect_protocol_enabled);
+    }
+
+    /// Tests that sending the `SETTINGS_ENABLE_CONNECT_PROTOCOL` setting enables extended CONNECT
+    /// on the local side of the connection.
+    #[test]
+    fn test_send_settings() {
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+
+        conn.sender(&mut sender)
+            .send_settings(vec![HttpSetting::EnableConnectProtocol(1)])
+            .unwrap();
+
+        assert!(conn.connect_protocol_enabled);
+        let mut expected = SettingsFrame::new();
+        expected.add_setting(HttpSetting::EnableConnectProtocol(1));
+        assert_eq!(sender.sent, vec![RawFrame::from(serialize_frame(&expected))]);
+    }
+
+    /// Tests that the peer's `SETTINGS_HEADER_TABLE_SIZE` limits the encoder's dynamic table,
+    /// while the one that the connection sends applies to its decoder once it is acknowledged.
+    #[test]
+    fn test_header_table_size() {
+        let mut settings = SettingsFrame::new();
+        settings.add_setting(HttpSetting::HeaderTableSize(0));
+        let frames = vec![
+            HttpFrame::SettingsFrame(settings),
+            HttpFrame::SettingsFrame(SettingsFrame::new_ack()),
+        ];
+        let mut conn = build_mock_http_conn();
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert_eq!(conn.encoder.max_table_size(), 0);
+        // The next header block starts with the dynamic table size update.
+        conn.sender(&mut sender)
+            .send_headers(vec![Header::new(b"x-a", b"b")], 1, EndStream::Yes)
+            .unwrap();
+        let frame: HeadersFrame = Frame::from_raw(&sender.sent[0]).unwrap();
+        assert_eq!(frame.header_fragment(), &[0x20, 0x40, 3, b'x', b'-', b'a', 1, b'b'][..]);
+
+        // A block that indexes a header that it has just added...
+        let block = [0x40, 3, b'x', b'-', b'a', 1, b'b', 0x80 | 62];
+        conn.sender(&mut sender).send_settings(vec![HttpSetting::HeaderTableSize(0)]).unwrap();
+        // ...can still be decoded until the peer acknowledges the settings...
+        assert!(conn.decoder.decode(&block).is_ok());
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        // ...but not after that.
+        assert!(conn.decoder.decode(&block).is_err());
+    }
+
+    /// Tests that a header list over the connection's limit is not passed on to the session, while
+    /// the decoder is kept in sync for the header blocks that follow.
+    #[test]
+    fn test_header_list_too_large() {
+        let large = vec![b'a'; 100];
+        let mut encoder = hpack::Encoder::new();
+        let mut oversized = HeadersFrame::new(encoder.encode(vec![(&b"x-large"[..], &large[..])]),
+                                              1);
+        oversized.set_flag(HeadersFlag::EndHeaders);
+        oversized.set_flag(HeadersFlag::EndStream);
+        // The header is now in the dynamic table, so it only takes an index to send it again.
+        let indexed = encoder.encode(vec![(&b"x-large"[..], &large[..])]);
+        let frames = vec![
+            HttpFrame::HeadersFrame(oversized),
+            HttpFrame::HeadersFrame(build_headers_frame(indexed.clone(), 3)),
+            HttpFrame::HeadersFrame(build_headers_frame(encoder.encode(vec![(&b"x-small"[..],
+                                                                             &b"b"[..])]),
+                                                        5)),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender).send_settings(vec![HttpSetting::MaxHeaderListSize(100)]).unwrap();
+        assert_eq!(conn.max_header_list_size, 100);
+        let mut session = TestSession::new_verify(vec![vec![(b"x-small".to_vec(), b"b".to_vec())]],
+                                                  vec![]);
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(indexed.len(), 1);
+        assert_eq!(session.oversized_headers, vec![1, 3]);
+        assert_eq!(session.curr_header, 1);
+        // The end of the stream is not signaled for a discarded header block.
+        assert!(session.ends.is_empty());
+    }
+
+    /// Tests that a header block is reassembled from its HEADERS frame and the CONTINUATION frames
+    /// that follow it.
+    #[test]
+    fn test_continuation_frames() {
+        let block = hpack::Encoder::new().encode(vec![(&b":method"[..], &b"GET"[..]),
+                                                      (&b":path"[..], &b"/"[..])]);
+        let mut headers = HeadersFrame::new(block[..1].to_vec(), 1);
+        headers.set_flag(HeadersFlag::EndStream);
+        let frames = vec![
+            HttpFrame::HeadersFrame(headers),
+            HttpFrame::UnknownFrame(raw_frame_from_parts((1, 0x9, 0, 1), block[1..2].to_vec())),
+            HttpFrame::UnknownFrame(raw_frame_from_parts(((block.len() - 2) as u32, 0x9, 0x4, 1),
+                                                         block[2..].to_vec())),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let expected = vec![(b":method".to_vec(), b"GET".to_vec()),
+                            (b":path".to_vec(), b"/".to_vec())];
+        let mut session = TestSession::new_verify(vec![expected], vec![]);
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert_eq!(session.curr_header, 0);
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+
+        assert_eq!(session.curr_header, 1);
+        assert_eq!(session.ends, vec![1]);
+    }
+
+    /// Tests that any frame other than a CONTINUATION frame on the same stream is a connection
+    /// error while a header block is incomplete, as is a CONTINUATION frame outside of a block.
+    #[test]
+    fn test_continuation_frames_invalid() {
+        let frames = vec![
+            HttpFrame::HeadersFrame(HeadersFrame::new(vec![], 1)),
+            HttpFrame::DataFrame(DataFrame::new(1)),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                   HttpError::InvalidFrame);
+
+        let frames = vec![
+            HttpFrame::HeadersFrame(HeadersFrame::new(vec![], 1)),
+            HttpFrame::UnknownFrame(raw_frame_from_parts((0, 0x9, 0x4, 3), vec![])),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                   HttpError::InvalidFrame);
+
+        let frames = vec![HttpFrame::UnknownFrame(raw_frame_from_parts((0, 0x9, 0x4, 1), vec![]))];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                   HttpError::InvalidFrame);
+        assert_eq!(session.curr_header, 0);
+    }
+
+    /// Tests that the connection errors out once the peer sends more PING, SETTINGS, RST_STREAM or
+    /// empty DATA frames than its limits allow, while acknowledgements are not counted.
+    #[test]
+    fn test_flood_limits() {
+        let limits = FloodLimits {
+            interval: Duration::from_secs(60),
+            max_rst_streams: 1,
+            max_pings: 1,
+            max_settings: 1,
+            max_empty_data: 1,
+            max_rapid_resets: 1,
+        };
+        let floods = vec![
+            (Flood::Ping, HttpFrame::PingFrame(PingFrame::new())),
+            (Flood::Settings, HttpFrame::SettingsFrame(SettingsFrame::new())),
+            (Flood::RstStream,
+             HttpFrame::RstStreamFrame(RstStreamFrame::new(1, ErrorCode::Cancel))),
+            (Flood::EmptyData, HttpFrame::DataFrame(DataFrame::new(1))),
+        ];
+        for (flood, frame) in floods {
+            let frames = vec![
+                frame.clone(),
+                HttpFrame::PingFrame(PingFrame::new_ack(0)),
+                HttpFrame::SettingsFrame(SettingsFrame::new_ack()),
+                frame,
+            ];
+            let mut conn = HttpConnection::new(HttpScheme::Http);
+            conn.flood_guard.set_limits(limits);
+            let mut session = TestSession::new();
+            let mut frame_provider = MockReceiveFrame::new(frames);
+
+            for _ in 0..3 {
+                conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+            }
+            assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                       HttpError::Flood(flood));
+        }
+    }
+
+    /// Tests that a header block over the connection's hard limit is a connection error, without
+    /// the block ever being buffered past the limit.
+    #[test]
+    fn test_header_block_too_large() {
+        let frames = vec![
+            HttpFrame::HeadersFrame(HeadersFrame::new(vec![0x82; 60], 1)),
+            HttpFrame::UnknownFrame(raw_frame_from_parts((60, 0x9, 0x4, 1), vec![0x82; 60])),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        conn.max_header_block_size = 100;
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                   HttpError::HeaderBlockTooLarge);
+        assert_eq!(session.curr_header, 0);
+
+        let frames = vec![HttpFrame::HeadersFrame(build_headers_frame(vec![0x82; 101], 1))];
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        assert_eq!(conn.handle_next_frame(&mut frame_provider, &mut session).err().unwrap(),
+                   HttpError::HeaderBlockTooLarge);
+    }
+
+    /// Tests that the connection's statistics account for the frames that it sends and receives.
+    #[test]
+    fn test_connection_stats() {
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender)
+            .send_headers(vec![Header::new(b":method", b"POST")], 1, EndStream::No)
+            .unwrap();
+        conn.sender(&mut sender)
+            .send_data(DataChunk::new_borrowed(&b"abcd"[..], 1, EndStream::No))
+            .unwrap();
+        conn.sender(&mut sender).send_ping(7).unwrap();
+        conn.sender(&mut sender).rst_stream(3, ErrorCode::Cancel).unwrap();
+        let frames = vec![
+            HttpFrame::PingFrame(PingFrame::new_ack(7)),
+            HttpFrame::WindowUpdateFrame(WindowUpdateFrame::for_stream(1, 100)),
+            HttpFrame::WindowUpdateFrame(WindowUpdateFrame::for_connection(4)),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(5, ErrorCode::RefusedStream)),
+            headers_frame(1, &[(b":status", b"200")]),
+        ];
+        let received: usize = frames.iter().map(|frame| 9 + frame.get_header().0 as usize).sum();
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        for _ in 0..5 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        let stats = conn.stats();
+        assert_eq!(stats.frames_sent.headers, 1);
+        assert_eq!(stats.frames_sent.data, 1);
+        assert_eq!(stats.frames_sent.total(), 4);
+        assert_eq!(stats.bytes_sent,
+                   sender.sent.iter().map(|raw| raw.len() as u64).sum::<u64>());
+        assert_eq!(stats.frames_received.window_update, 2);
+        assert_eq!(stats.frames_received.total(), 5);
+        assert_eq!(stats.bytes_received, received as u64);
+        assert_eq!(stats.out_window, 65_535);
+        assert_eq!(stats.resets_sent.get(&ErrorCode::Cancel), Some(&1));
+        assert_eq!(stats.resets_received.get(&ErrorCode::RefusedStream), Some(&1));
+        assert!(stats.ping_rtt.is_some());
+        assert_eq!(stats.hpack.header_bytes_sent, 11);
+        assert!(stats.hpack.block_bytes_sent > 0);
+        assert_eq!(stats.hpack.header_bytes_received, 10);
+        assert!(stats.hpack.received_compression_ratio().is_some());
+        assert_eq!(conn.stream_stats(1).out_window, 65_535 - 4 + 100);
+    }
+
+    /// An observer that records the events that it is shown in a shared log.
+    struct RecordingObserver {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FrameObserver for RecordingObserver {
+        fn on_frame_received(&mut self, frame: &HttpFrame) {
+            let (_, frame_type, _, stream_id) = frame.get_header();
+            self.log.lock().unwrap().push(format!("recv {} {}", frame_type, stream_id));
+        }
+
+        fn on_frame_sent(&mut self, frame: &HttpFrame) {
+            let (_, frame_type, _, stream_id) = frame.get_header();
+            self.log.lock().unwrap().push(format!("sent {} {}", frame_type, stream_id));
+        }
+
+        fn on_headers_received(&mut self, stream_id: StreamId, headers: &[Header]) {
+            self.log.lock().unwrap().push(format!("headers recv {} {}", stream_id, names(headers)));
+        }
+
+        fn on_headers_sent(&mut self, stream_id: StreamId, headers: &[Header]) {
+            self.log.lock().unwrap().push(format!("headers sent {} {}", stream_id, names(headers)));
+        }
+    }
+
+    /// Joins the names of the given headers.
+    fn names(headers: &[Header]) -> String {
+        let names: Vec<_> = headers.iter().map(|h| String::from_utf8_lossy(h.name())).collect();
+        names.join(",")
+    }
+
+    /// Tests that an attached `FrameObserver` is shown every frame that is sent and received, as
+    /// well as the headers of the header blocks.
+    #[test]
+    fn test_frame_observer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut conn = build_mock_http_conn();
+        conn.observer = Some(Box::new(RecordingObserver { log: log.clone() }));
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender)
+            .send_headers(vec![Header::new(b":method", b"GET")], 1, EndStream::Yes)
+            .unwrap();
+        let frames = vec![
+            headers_frame(1, &[(b":status", b"200")]),
+            data_frame(1, b"abc", true),
+            HttpFrame::UnknownFrame(raw_frame_from_parts((2, 0xbb, 0, 0), vec![1, 2])),
+        ];
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["headers sent 1 :method",
+                        "sent 1 1",
+                        "recv 1 1",
+                        "headers recv 1 :status",
+                        "recv 0 1",
+                        "recv 187 0"]);
+    }
+
+    /// Tests that the flow control window taken up by received data is given back to the peer
+    /// once half of the initial window has been used up.
+    #[test]
+    fn test_send_window_updates() {
+        static CHUNK: [u8; 20_000] = [0; 20_000];
+        let frames = vec![
+            data_frame(1, &CHUNK, false),
+            data_frame(1, &CHUNK, false),
+            // The window of a stream that the peer has closed is not given back.
+            data_frame(3, &CHUNK, true),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.sender(&mut sender).send_window_updates().unwrap();
+        assert!(sender.sent.is_empty());
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.sender(&mut sender).send_window_updates().unwrap();
+        assert_eq!(sender.sent,
+                   vec![RawFrame::from(serialize_frame(&WindowUpdateFrame::for_connection(40_000))),
+                        RawFrame::from(serialize_frame(&WindowUpdateFrame::for_stream(1,
+                                                                                      40_000)))]);
+        assert_eq!(conn.in_window_size(), 65_535);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.sender(&mut sender).send_window_updates().unwrap();
+        assert_eq!(sender.sent.len(), 2);
+        assert_eq!(conn.in_window_size(), 65_535 - 20_000);
+    }
+
+    /// Tests that frames of unknown types are passed on to the session, unless a parser is
+    /// registered for their type, in which case the parsed frame is passed on instead.
+    #[test]
+    fn test_unknown_and_extension_frames() {
+        let frames = vec![
+            HttpFrame::UnknownFrame(RawFrame::from_parts(0xf1, 0, 1, &[1])),
+            HttpFrame::UnknownFrame(RawFrame::from_parts(0xf0, 0, 1, &[2, 3])),
+            // The parser rejects frames without a payload.
+            HttpFrame::UnknownFrame(RawFrame::from_parts(0xf0, 0, 1, &[])),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        conn.extensions.register(0xf0, parse_test_frame);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(session.unknown_frames,
+                   vec![RawFrame::from_parts(0xf1, 0, 1, &[1]),
+                        RawFrame::from_parts(0xf0, 0, 1, &[])]);
+        assert_eq!(session.extension_frames.len(), 1);
+        let frame = session.extension_frames[0].as_any().downcast_ref::<TestExtensionFrame>();
+        assert_eq!(frame,
+                   Some(&TestExtensionFrame {
+                       stream_id: 1,
+                       payload: vec![2, 3],
+                   }));
+    }
+
+    /// Tests that the session is notified of received ALTSVC and ORIGIN frames.
+    #[test]
+    fn test_altsvc_and_origin_frames() {
+        let frames = vec![
+            HttpFrame::AltSvcFrame(AltSvcFrame::new(3, "", "clear")),
+            HttpFrame::OriginFrame(OriginFrame::new(vec!["https://a.com"])),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+
+        assert_eq!(session.altsvcs, vec![AltSvcFrame::new(3, "", "clear")]);
+        assert_eq!(session.origins, vec![OriginFrame::new(vec!["https://a.com"])]);
+        assert!(session.unknown_frames.is_empty());
+    }
+
+    #[test]
+    fn test_send_extension_frame() {
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+
+        let frame = RawFrame::from_parts(0xf0, 0x1, 0, &[1, 2, 3]);
+        conn.sender(&mut sender).send_extension_frame(frame.clone()).unwrap();
+
+        assert_eq!(sender.sent, vec![frame]);
+    }
+
     /// Tests that the session is appropriately notified when a PING frame is received.
     #[test]
     fn test_on_ping() {
@@ -1076,4 +2429,172 @@ mod tests {
         assert_eq!(session.pongs, vec![123]);
         assert_eq!(session.pings, vec![]);
     }
+
+    /// A helper function that builds a HEADERS frame for the given stream carrying the given
+    /// headers.
+    fn headers_frame(stream_id: u32, headers: &[(&[u8], &[u8])]) -> HttpFrame<'static> {
+        let fragment = hpack::Encoder::new().encode(headers.iter().cloned());
+        HttpFrame::HeadersFrame(build_headers_frame(fragment, stream_id))
+    }
+
+    /// A helper function that builds a DATA frame for the given stream, optionally ending it.
+    fn data_frame(stream_id: u32, data: &'static [u8], end_stream: bool) -> HttpFrame<'static> {
+        let mut frame = DataFrame::with_data(stream_id, data);
+        if end_stream {
+            frame.set_flag(DataFlag::EndStream);
+        }
+        HttpFrame::DataFrame(frame)
+    }
+
+    /// Tests that the connection stops tracking a stream once both ends have closed it, or once it
+    /// is reset.
+    #[test]
+    fn test_stream_state_forgotten() {
+        let frames = vec![
+            headers_frame(1, &[(b":status", b"200"), (b"content-length", b"3")]),
+            data_frame(1, b"123", true),
+            headers_frame(3, &[(b":status", b"200")]),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(3, ErrorCode::Cancel)),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+        for &stream_id in [1, 3].iter() {
+            conn.sender(&mut sender)
+                .send_headers(vec![Header::new(b":method", b"POST")], stream_id, EndStream::No)
+                .unwrap();
+        }
+
+        // The peer closes the stream, but it is still being sent on...
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert!(conn.streams.contains_key(&1));
+        // ...until it is closed on this end as well.
+        conn.sender(&mut sender)
+            .send_data(DataChunk::new_borrowed(&b""[..], 1, EndStream::Yes))
+            .unwrap();
+        assert!(!conn.streams.contains_key(&1));
+
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert!(conn.streams.contains_key(&3));
+        conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        assert!(!conn.streams.contains_key(&3));
+    }
+
+    /// Tests that a body matching its declared `content-length` ends the stream normally.
+    #[test]
+    fn test_content_length_match() {
+        let frames = vec![
+            headers_frame(1, &[(b":status", b"200"), (b"content-length", b"6")]),
+            data_frame(1, b"123", false),
+            data_frame(1, b"456", true),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(session.curr_chunk, 2);
+        assert_eq!(session.ends, vec![1]);
+        assert!(session.content_length_errors.is_empty());
+    }
+
+    /// Tests that receiving more data than declared is signaled as a mismatch as soon as the
+    /// excess data arrives, without passing that data on to the session.
+    #[test]
+    fn test_content_length_too_long() {
+        let frames = vec![
+            headers_frame(1, &[(b":status", b"200"), (b"content-length", b"4")]),
+            data_frame(1, b"123", false),
+            data_frame(1, b"456", false),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(session.curr_chunk, 1);
+        assert_eq!(session.content_length_errors,
+                   vec![(1,
+                         ContentLengthError {
+                             declared: 4,
+                             received: 6,
+                         })]);
+        assert!(session.ends.is_empty());
+    }
+
+    /// Tests that ending the stream before the declared length is received is signaled as a
+    /// mismatch instead of the end of the stream, whether the stream ends with DATA or with
+    /// trailers.
+    #[test]
+    fn test_content_length_too_short() {
+        let mut trailers = match headers_frame(3, &[(b"grpc-status", b"0")]) {
+            HttpFrame::HeadersFrame(frame) => frame,
+            _ => unreachable!(),
+        };
+        trailers.set_flag(HeadersFlag::EndStream);
+        let frames = vec![
+            headers_frame(1, &[(b":status", b"200"), (b"content-length", b"4")]),
+            data_frame(1, b"123", true),
+            headers_frame(3, &[(b":status", b"200"), (b"content-length", b"1")]),
+            HttpFrame::HeadersFrame(trailers),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..4 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(session.content_length_errors,
+                   vec![(1,
+                         ContentLengthError {
+                             declared: 4,
+                             received: 3,
+                         }),
+                        (3,
+                         ContentLengthError {
+                             declared: 1,
+                             received: 0,
+                         })]);
+        assert!(session.ends.is_empty());
+    }
+
+    /// Tests that the `content-length` of responses that never carry a body (those to HEAD
+    /// requests and `204` responses) is not enforced.
+    #[test]
+    fn test_content_length_bodyless_responses() {
+        let mut head = match headers_frame(1, &[(b":status", b"200"), (b"content-length", b"10")]) {
+            HttpFrame::HeadersFrame(frame) => frame,
+            _ => unreachable!(),
+        };
+        head.set_flag(HeadersFlag::EndStream);
+        let frames = vec![
+            HttpFrame::HeadersFrame(head),
+            headers_frame(3, &[(b":status", b"204"), (b"content-length", b"10")]),
+            data_frame(3, b"", true),
+        ];
+        let mut conn = HttpConnection::new(HttpScheme::Http);
+        let mut sender = MockSendFrame::new();
+        conn.sender(&mut sender)
+            .send_headers(vec![Header::new(b":method", b"HEAD")], 1, EndStream::Yes)
+            .unwrap();
+        let mut session = TestSession::new();
+        let mut frame_provider = MockReceiveFrame::new(frames);
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut frame_provider, &mut session).unwrap();
+        }
+
+        assert_eq!(session.ends, vec![1, 3]);
+        assert!(session.content_length_errors.is_empty());
+    }
 }
@@ -0,0 +1,204 @@
+//! The module contains the `DuplexStream`, a `Stream` implementation for streams on which data
+//! flows in both directions for as long as the stream lives, rather than following the
+//! request/response pattern.
+//!
+//! Such streams are typically established by an extended CONNECT request (RFC 8441), which
+//! bootstraps another protocol, such as WebSockets, on a single HTTP/2 stream.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use http::{Header, OwnedHeader, StreamId, ErrorCode};
+use http::session::{Stream, StreamState, StreamDataChunk, StreamDataError};
+
+/// A `Stream` whose outgoing data can be supplied bit by bit, as it becomes available, and whose
+/// incoming data can be consumed bit by bit, as it arrives.
+///
+/// Unlike the `DefaultStream`, the stream is not closed once its outgoing data is written out;
+/// it stays open until `finish` is called.
+#[derive(Clone, Debug)]
+pub struct DuplexStream {
+    /// The ID of the stream, if already assigned by the connection.
+    pub stream_id: Option<StreamId>,
+    /// The headers received on the stream (i.e. the request or the response headers).
+    pub headers: Option<Vec<Header<'static, 'static>>>,
+    /// The error code with which the peer reset the stream, if it did so.
+    pub error_code: Option<ErrorCode>,
+    /// The current stream state.
+    state: StreamState,
+    /// The received data that has not been consumed yet.
+    incoming: Vec<u8>,
+    /// The data that is yet to be written onto the stream.
+    outgoing: VecDeque<u8>,
+    /// Whether the stream should be closed once the outgoing data is written out.
+    finishing: bool,
+}
+
+impl DuplexStream {
+    /// Creates a new `DuplexStream`, where the ID is not yet assigned.
+    pub fn new() -> DuplexStream {
+        DuplexStream {
+            stream_id: None,
+            headers: None,
+            error_code: None,
+            state: StreamState::Open,
+            incoming: Vec::new(),
+            outgoing: VecDeque::new(),
+            finishing: false,
+        }
+    }
+
+    /// Creates a new `DuplexStream` with the given ID.
+    pub fn with_id(stream_id: StreamId) -> DuplexStream {
+        let mut stream = DuplexStream::new();
+        stream.stream_id = Some(stream_id);
+        stream
+    }
+
+    /// Queues the given data to be written onto the stream.
+    ///
+    /// # Returns
+    ///
+    /// `StreamDataError::Closed` if the stream is already closed locally or `finish` has been
+    /// called, in which case the data is discarded.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), StreamDataError> {
+        if self.finishing || self.is_closed_local() {
+            return Err(StreamDataError::Closed);
+        }
+        self.outgoing.extend(data);
+        Ok(())
+    }
+
+    /// Closes the stream locally once all the data queued so far has been written out.
+    pub fn finish(&mut self) {
+        self.finishing = true;
+    }
+
+    /// Returns the number of bytes of the data queued by `send` that are yet to be written.
+    pub fn pending(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Takes all the data received on the stream since the last call.
+    pub fn recv(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.incoming, Vec::new())
+    }
+
+    /// Returns the value of the `:protocol` pseudo-header that the stream was opened with, if it
+    /// was opened by an extended CONNECT request.
+    pub fn protocol(&self) -> Option<&[u8]> {
+        self.headers
+            .as_ref()
+            .and_then(|headers| headers.iter().find(|h| h.name() == b":protocol"))
+            .map(|header| header.value())
+    }
+}
+
+impl Stream for DuplexStream {
+    fn new_data_chunk(&mut self, data: &[u8]) {
+        self.incoming.extend(data);
+    }
+
+    fn set_headers<'n, 'v>(&mut self, headers: Vec<Header<'n, 'v>>) {
+        let new_headers = headers.into_iter().map(|h| {
+            let owned: OwnedHeader = h.into();
+            owned.into()
+        });
+        match self.headers {
+            Some(ref mut headers) => headers.extend(new_headers),
+            None => self.headers = Some(new_headers.collect()),
+        }
+    }
+
+    fn set_state(&mut self, state: StreamState) {
+        self.state = state;
+    }
+
+    fn state(&self) -> StreamState {
+        self.state
+    }
+
+    fn on_rst_stream(&mut self, error_code: ErrorCode) {
+        self.error_code = Some(error_code);
+        self.close();
+    }
+
+    fn get_data_chunk(&mut self, buf: &mut [u8]) -> Result<StreamDataChunk, StreamDataError> {
+        if self.is_closed_local() {
+            return Err(StreamDataError::Closed);
+        }
+        let len = buf.len().min(self.outgoing.len());
+        for (dst, src) in buf.iter_mut().zip(self.outgoing.drain(..len)) {
+            *dst = src;
+        }
+
+        if self.outgoing.is_empty() && self.finishing {
+            self.close_local();
+            Ok(StreamDataChunk::Last(len))
+        } else if len == 0 {
+            Ok(StreamDataChunk::Unavailable)
+        } else {
+            Ok(StreamDataChunk::Chunk(len))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DuplexStream;
+
+    use http::{Header, ErrorCode};
+    use http::session::{Stream, StreamDataChunk, StreamDataError};
+
+    #[test]
+    fn test_outgoing_data() {
+        let mut stream = DuplexStream::new();
+        let mut buf = [0; 4];
+        // Nothing to write yet, but the stream stays open.
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Unavailable);
+
+        assert!(stream.send(b"hello").is_ok());
+        assert_eq!(stream.pending(), 5);
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Chunk(4));
+        assert_eq!(&buf, b"hell");
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Chunk(1));
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Unavailable);
+        assert!(!stream.is_closed_local());
+
+        assert!(stream.send(b"!").is_ok());
+        stream.finish();
+        assert!(stream.send(b"more").is_err());
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Last(1));
+        assert!(stream.is_closed_local());
+        match stream.get_data_chunk(&mut buf) {
+            Err(StreamDataError::Closed) => {}
+            _ => panic!("Expected the stream to be closed"),
+        }
+    }
+
+    /// Tests that a stream without pending data is closed by an empty final chunk.
+    #[test]
+    fn test_finish_without_data() {
+        let mut stream = DuplexStream::new();
+        stream.finish();
+        assert_eq!(stream.get_data_chunk(&mut [0; 4]).ok().unwrap(), StreamDataChunk::Last(0));
+        assert!(stream.is_closed_local());
+    }
+
+    #[test]
+    fn test_incoming_data() {
+        let mut stream = DuplexStream::new();
+        stream.set_headers(vec![Header::new(b":method", b"CONNECT"),
+                                Header::new(b":protocol", b"websocket")]);
+        assert_eq!(stream.protocol(), Some(&b"websocket"[..]));
+
+        stream.new_data_chunk(b"ab");
+        stream.new_data_chunk(b"c");
+        assert_eq!(stream.recv(), b"abc".to_vec());
+        assert!(stream.recv().is_empty());
+
+        stream.on_rst_stream(ErrorCode::Cancel);
+        assert_eq!(stream.error_code, Some(ErrorCode::Cancel));
+        assert!(stream.is_closed());
+    }
+}
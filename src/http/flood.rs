@@ -0,0 +1,227 @@
+//! The module contains the `FloodGuard`, which limits the rate at which the peer of a connection
+//! may send control frames that cost us work, but cost the peer next to nothing.
+//!
+//! A peer that goes over any of the limits is considered abusive: the connection is terminated
+//! with a GOAWAY frame carrying the `ENHANCE_YOUR_CALM` error code.
+
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use http::{HttpError, HttpResult};
+
+/// An enum representing the kinds of frames (or of frame patterns) that the `FloodGuard` limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flood {
+    /// RST_STREAM frames.
+    RstStream,
+    /// PING frames, other than acknowledgements.
+    Ping,
+    /// SETTINGS frames, other than acknowledgements.
+    Settings,
+    /// DATA frames that carry no data and do not end their stream.
+    EmptyData,
+    /// Streams that the peer opened and then reset before they were complete (i.e. the "rapid
+    /// reset" attack).
+    RapidReset,
+}
+
+impl Flood {
+    /// Returns the index of the counter that tracks the kind of frames within the `FloodGuard`.
+    fn index(&self) -> usize {
+        match *self {
+            Flood::RstStream => 0,
+            Flood::Ping => 1,
+            Flood::Settings => 2,
+            Flood::EmptyData => 3,
+            Flood::RapidReset => 4,
+        }
+    }
+}
+
+impl fmt::Display for Flood {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+
+impl Error for Flood {
+    fn description(&self) -> &str {
+        match *self {
+            Flood::RstStream => "The peer sent too many RST_STREAM frames",
+            Flood::Ping => "The peer sent too many PING frames",
+            Flood::Settings => "The peer sent too many SETTINGS frames",
+            Flood::EmptyData => "The peer sent too many empty DATA frames",
+            Flood::RapidReset => "The peer reset too many of the streams that it opened",
+        }
+    }
+}
+
+/// The limits on how many frames of each kind the peer may send within a single interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloodLimits {
+    /// The length of the interval over which the frames are counted.
+    pub interval: Duration,
+    /// The maximum number of RST_STREAM frames per interval.
+    pub max_rst_streams: u32,
+    /// The maximum number of PING frames (other than acknowledgements) per interval.
+    pub max_pings: u32,
+    /// The maximum number of SETTINGS frames (other than acknowledgements) per interval.
+    pub max_settings: u32,
+    /// The maximum number of DATA frames that carry no data and do not end their stream, per
+    /// interval.
+    pub max_empty_data: u32,
+    /// The maximum number of streams that the peer may open and then reset before they are
+    /// complete, per interval.
+    pub max_rapid_resets: u32,
+}
+
+impl FloodLimits {
+    /// Returns the limit on the given kind of frames.
+    pub fn limit(&self, flood: Flood) -> u32 {
+        match flood {
+            Flood::RstStream => self.max_rst_streams,
+            Flood::Ping => self.max_pings,
+            Flood::Settings => self.max_settings,
+            Flood::EmptyData => self.max_empty_data,
+            Flood::RapidReset => self.max_rapid_resets,
+        }
+    }
+}
+
+impl Default for FloodLimits {
+    fn default() -> FloodLimits {
+        FloodLimits {
+            interval: Duration::from_secs(10),
+            max_rst_streams: 500,
+            max_pings: 50,
+            max_settings: 50,
+            max_empty_data: 500,
+            max_rapid_resets: 200,
+        }
+    }
+}
+
+/// Counts the frames of a single kind received within the current interval.
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    start: Instant,
+    count: u32,
+}
+
+/// Tracks the rate at which the peer sends each kind of the limited frames.
+///
+/// The frames are counted over fixed intervals; the counter of each kind starts over once an
+/// interval has passed since it last did.
+#[derive(Debug, Clone)]
+pub struct FloodGuard {
+    limits: FloodLimits,
+    counters: [Option<Counter>; 5],
+}
+
+impl FloodGuard {
+    /// Creates a new `FloodGuard` that enforces the given limits.
+    pub fn new(limits: FloodLimits) -> FloodGuard {
+        FloodGuard {
+            limits: limits,
+            counters: [None; 5],
+        }
+    }
+
+    /// Returns the limits that the guard enforces.
+    pub fn limits(&self) -> &FloodLimits {
+        &self.limits
+    }
+
+    /// Replaces the limits that the guard enforces. The frames counted so far are kept.
+    pub fn set_limits(&mut self, limits: FloodLimits) {
+        self.limits = limits;
+    }
+
+    /// Records that a frame of the given kind was received now.
+    ///
+    /// # Returns
+    ///
+    /// An `HttpError::Flood` error if the frame puts the peer over the limit for the current
+    /// interval.
+    pub fn check(&mut self, flood: Flood) -> HttpResult<()> {
+        self.check_at(flood, Instant::now())
+    }
+
+    /// Records that a frame of the given kind was received at the given instant.
+    pub fn check_at(&mut self, flood: Flood, now: Instant) -> HttpResult<()> {
+        let interval = self.limits.interval;
+        let counter = &mut self.counters[flood.index()];
+        let count = match *counter {
+            Some(ref mut counter) if now.duration_since(counter.start) < interval => {
+                counter.count += 1;
+                counter.count
+            }
+            _ => {
+                *counter = Some(Counter {
+                    start: now,
+                    count: 1,
+                });
+                1
+            }
+        };
+        if count > self.limits.limit(flood) {
+            debug!("Flood detected: {}", flood);
+            return Err(HttpError::Flood(flood));
+        }
+        Ok(())
+    }
+}
+
+impl Default for FloodGuard {
+    fn default() -> FloodGuard {
+        FloodGuard::new(FloodLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Flood, FloodGuard, FloodLimits};
+    use http::HttpError;
+
+    fn limits() -> FloodLimits {
+        FloodLimits {
+            interval: Duration::from_secs(1),
+            max_rst_streams: 1,
+            max_pings: 2,
+            max_settings: 1,
+            max_empty_data: 1,
+            max_rapid_resets: 1,
+        }
+    }
+
+    /// Tests that the guard only lets through the given number of frames within an interval.
+    #[test]
+    fn test_flood_guard_limit() {
+        let mut guard = FloodGuard::new(limits());
+        let now = Instant::now();
+
+        assert!(guard.check_at(Flood::Ping, now).is_ok());
+        assert!(guard.check_at(Flood::Ping, now).is_ok());
+        assert_eq!(guard.check_at(Flood::Ping, now).err().unwrap(),
+                   HttpError::Flood(Flood::Ping));
+        // The other kinds of frames are counted separately.
+        assert!(guard.check_at(Flood::Settings, now).is_ok());
+        assert_eq!(guard.check_at(Flood::Settings, now).err().unwrap(),
+                   HttpError::Flood(Flood::Settings));
+    }
+
+    /// Tests that the counters start over once the interval has passed.
+    #[test]
+    fn test_flood_guard_interval() {
+        let mut guard = FloodGuard::new(limits());
+        let now = Instant::now();
+
+        assert!(guard.check_at(Flood::RstStream, now).is_ok());
+        assert!(guard.check_at(Flood::RstStream, now + Duration::from_millis(999)).is_err());
+        assert!(guard.check_at(Flood::RstStream, now + Duration::from_secs(1)).is_ok());
+        assert!(guard.check_at(Flood::RstStream, now + Duration::from_millis(1500)).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! The module contains the implementation of the `ALTSVC` frame (RFC 7838, Section 4), which
+//! advertises alternative services for an origin.
+use std::fmt;
+use std::io;
+
+use http::StreamId;
+use http::frame::{Frame, FrameIR, FrameBuilder, FrameHeader, RawFrame, NoFlag};
+use http::frame::bs_debug::BsDebug;
+
+/// The frame type of the `ALTSVC` frame.
+pub const ALTSVC_FRAME_TYPE: u8 = 0xa;
+
+/// The struct represents the `ALTSVC` frame.
+///
+/// When sent on stream `0`, the frame carries the origin to which the alternative services apply.
+/// When sent on any other stream, the origin is empty and the services apply to the origin of
+/// that stream's request.
+#[derive(Clone, PartialEq)]
+pub struct AltSvcFrame {
+    stream_id: StreamId,
+    origin: Vec<u8>,
+    field_value: Vec<u8>,
+    flags: u8,
+}
+
+impl fmt::Debug for AltSvcFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("AltSvcFrame")
+            .field("stream_id", &self.stream_id)
+            .field("origin", &BsDebug(&self.origin))
+            .field("field_value", &BsDebug(&self.field_value))
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+impl AltSvcFrame {
+    /// Constructs a new `AltSvcFrame` that advertises the given alternative services (the value
+    /// of an `Alt-Svc` header field, e.g. `h2="alt.example.com:443"`) for the given origin.
+    ///
+    /// Frames on stream `0` need a non-empty origin, while the frames on other streams need an
+    /// empty one; otherwise, the peer ignores the frame.
+    pub fn new<O, V>(stream_id: StreamId, origin: O, field_value: V) -> AltSvcFrame
+        where O: Into<Vec<u8>>,
+              V: Into<Vec<u8>>
+    {
+        AltSvcFrame {
+            stream_id: stream_id,
+            origin: origin.into(),
+            field_value: field_value.into(),
+            flags: 0,
+        }
+    }
+
+    /// Returns the origin to which the alternative services apply. It is empty for frames that
+    /// are not sent on stream `0`.
+    pub fn origin(&self) -> &[u8] {
+        &self.origin
+    }
+
+    /// Returns the advertised alternative services, in the format of the `Alt-Svc` header field.
+    pub fn field_value(&self) -> &[u8] {
+        &self.field_value
+    }
+
+    /// Returns the length of the payload of the frame.
+    fn payload_len(&self) -> u32 {
+        (2 + self.origin.len() + self.field_value.len()) as u32
+    }
+}
+
+impl<'a> Frame<'a> for AltSvcFrame {
+    type FlagType = NoFlag;
+
+    /// Creates a new `AltSvcFrame` from the given `RawFrame`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the payload cannot be parsed or if the frame is one that the spec requires to be
+    /// ignored: a frame on stream `0` without an origin, or a frame on any other stream with one.
+    fn from_raw(raw_frame: &'a RawFrame<'a>) -> Option<AltSvcFrame> {
+        let (len, frame_type, flags, stream_id) = raw_frame.header();
+        if frame_type != ALTSVC_FRAME_TYPE {
+            return None;
+        }
+        let payload = raw_frame.payload();
+        if (len as usize) != payload.len() || payload.len() < 2 {
+            return None;
+        }
+        let origin_len = ((payload[0] as usize) << 8) | payload[1] as usize;
+        if payload.len() < 2 + origin_len {
+            return None;
+        }
+        if (stream_id == 0) == (origin_len == 0) {
+            return None;
+        }
+
+        Some(AltSvcFrame {
+            stream_id: stream_id,
+            origin: payload[2..2 + origin_len].to_vec(),
+            field_value: payload[2 + origin_len..].to_vec(),
+            flags: flags,
+        })
+    }
+
+    fn is_set(&self, _: NoFlag) -> bool {
+        false
+    }
+    fn get_stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+    fn get_header(&self) -> FrameHeader {
+        (self.payload_len(), ALTSVC_FRAME_TYPE, self.flags, self.stream_id)
+    }
+}
+
+impl FrameIR for AltSvcFrame {
+    fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()> {
+        try!(builder.write_header(self.get_header()));
+        let origin_len = self.origin.len();
+        try!(builder.write_all(&[(origin_len >> 8) as u8, origin_len as u8]));
+        try!(builder.write_all(&self.origin));
+        try!(builder.write_all(&self.field_value));
+        Ok(())
+    }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AltSvcFrame;
+
+    use http::tests::common::serialize_frame;
+    use http::frame::{Frame, RawFrame};
+
+    #[test]
+    fn test_parse_connection_level() {
+        let mut payload = vec![0, 11];
+        payload.extend(b"example.com");
+        payload.extend(b"h2=\":8443\"");
+        let raw = RawFrame::from_parts(0xa, 0, 0, &payload);
+
+        let frame = AltSvcFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.get_stream_id(), 0);
+        assert_eq!(frame.origin(), b"example.com");
+        assert_eq!(frame.field_value(), b"h2=\":8443\"");
+    }
+
+    #[test]
+    fn test_parse_stream_level() {
+        let raw = RawFrame::from_parts(0xa, 0, 3, b"\x00\x00clear");
+
+        let frame = AltSvcFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.get_stream_id(), 3);
+        assert_eq!(frame.origin(), b"");
+        assert_eq!(frame.field_value(), b"clear");
+    }
+
+    /// Tests that the frames that must be ignored are not parsed.
+    #[test]
+    fn test_parse_invalid() {
+        let is_ignored = |frame_type, stream, payload: &[u8]| {
+            AltSvcFrame::from_raw(&RawFrame::from_parts(frame_type, 0, stream, payload)).is_none()
+        };
+        // No origin on stream 0
+        assert!(is_ignored(0xa, 0, b"\x00\x00clear"));
+        // An origin on a non-zero stream
+        assert!(is_ignored(0xa, 1, b"\x00\x01aclear"));
+        // The origin length exceeds the payload
+        assert!(is_ignored(0xa, 0, b"\x00\x09a"));
+        // Too short for the origin length
+        assert!(is_ignored(0xa, 0, b"\x00"));
+        // Wrong frame type
+        assert!(is_ignored(0xb, 0, b"\x00\x01a"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let frame = AltSvcFrame::new(0, "a.com", "clear");
+        let expected = RawFrame::from_parts(0xa, 0, 0, b"\x00\x05a.comclear");
+        assert_eq!(serialize_frame(&frame), expected.serialize());
+
+        let raw = RawFrame::from(expected.serialize());
+        assert_eq!(AltSvcFrame::from_raw(&raw), Some(frame));
+    }
+}
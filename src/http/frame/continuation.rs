@@ -0,0 +1,153 @@
+//! The module contains the implementation of the `CONTINUATION` frame (HTTP/2 spec, section 6.10),
+//! which carries the rest of a header block that did not fit into its HEADERS or PUSH_PROMISE
+//! frame.
+use std::fmt;
+use std::io;
+
+use http::StreamId;
+use http::frame::{Flag, Frame, FrameIR, FrameBuilder, FrameHeader, RawFrame};
+use http::frame::bs_debug::BsDebug;
+
+/// The frame type of the `CONTINUATION` frame.
+pub const CONTINUATION_FRAME_TYPE: u8 = 0x9;
+
+/// An enum representing the flags that a `ContinuationFrame` can have.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ContinuationFlag {
+    EndHeaders = 0x4,
+}
+
+impl Flag for ContinuationFlag {
+    #[inline]
+    fn bitmask(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// The struct represents the `CONTINUATION` frame.
+#[derive(Clone, PartialEq)]
+pub struct ContinuationFrame {
+    stream_id: StreamId,
+    header_fragment: Vec<u8>,
+    flags: u8,
+}
+
+impl fmt::Debug for ContinuationFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ContinuationFrame")
+            .field("stream_id", &self.stream_id)
+            .field("header_fragment", &BsDebug(&self.header_fragment))
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+impl ContinuationFrame {
+    /// Constructs a new `ContinuationFrame` with the given header block fragment. No flags are
+    /// set.
+    pub fn new(stream_id: StreamId, fragment: Vec<u8>) -> ContinuationFrame {
+        ContinuationFrame {
+            stream_id: stream_id,
+            header_fragment: fragment,
+            flags: 0,
+        }
+    }
+
+    /// Returns the header block fragment carried by the frame.
+    pub fn header_fragment(&self) -> &[u8] {
+        &self.header_fragment
+    }
+
+    /// Returns whether this frame ends the header block.
+    pub fn is_headers_end(&self) -> bool {
+        self.is_set(ContinuationFlag::EndHeaders)
+    }
+
+    /// Sets the given flag for the frame.
+    pub fn set_flag(&mut self, flag: ContinuationFlag) {
+        self.flags |= flag.bitmask();
+    }
+}
+
+impl<'a> Frame<'a> for ContinuationFrame {
+    type FlagType = ContinuationFlag;
+
+    /// Creates a new `ContinuationFrame` from the given `RawFrame`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the frame is sent on stream `0`.
+    fn from_raw(raw_frame: &'a RawFrame<'a>) -> Option<ContinuationFrame> {
+        let (len, frame_type, flags, stream_id) = raw_frame.header();
+        if frame_type != CONTINUATION_FRAME_TYPE {
+            return None;
+        }
+        if (len as usize) != raw_frame.payload().len() || stream_id == 0 {
+            return None;
+        }
+
+        Some(ContinuationFrame {
+            stream_id: stream_id,
+            header_fragment: raw_frame.payload().to_vec(),
+            flags: flags,
+        })
+    }
+
+    fn is_set(&self, flag: ContinuationFlag) -> bool {
+        self.flags & flag.bitmask() != 0
+    }
+    fn get_stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+    fn get_header(&self) -> FrameHeader {
+        (self.header_fragment.len() as u32, CONTINUATION_FRAME_TYPE, self.flags, self.stream_id)
+    }
+}
+
+impl FrameIR for ContinuationFrame {
+    fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()> {
+        try!(builder.write_header(self.get_header()));
+        builder.write_all(&self.header_fragment)
+    }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContinuationFlag, ContinuationFrame};
+
+    use http::tests::common::serialize_frame;
+    use http::frame::{Frame, RawFrame};
+
+    #[test]
+    fn test_parse() {
+        let raw = RawFrame::from_parts(0x9, 0x4, 1, b"abc");
+
+        let frame = ContinuationFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.get_stream_id(), 1);
+        assert_eq!(frame.header_fragment(), b"abc");
+        assert!(frame.is_headers_end());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        // Stream 0
+        assert!(ContinuationFrame::from_raw(&RawFrame::from_parts(0x9, 0, 0, b"abc")).is_none());
+        // Wrong frame type
+        assert!(ContinuationFrame::from_raw(&RawFrame::from_parts(0x1, 0, 1, b"abc")).is_none());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut frame = ContinuationFrame::new(3, b"abc".to_vec());
+        frame.set_flag(ContinuationFlag::EndHeaders);
+        let expected = RawFrame::from_parts(0x9, 0x4, 3, b"abc");
+        assert_eq!(serialize_frame(&frame), expected.serialize());
+
+        let raw = RawFrame::from(expected.serialize());
+        assert_eq!(ContinuationFrame::from_raw(&raw), Some(frame));
+    }
+}
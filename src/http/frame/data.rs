@@ -241,6 +241,10 @@ impl<'a> FrameIR for DataFrame<'a> {
         }
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
@@ -248,7 +252,7 @@ mod tests {
     use super::{DataFlag, DataFrame};
     use http::frame::tests::build_padded_frame_payload;
     use http::tests::common::{raw_frame_from_parts, serialize_frame};
-    use http::frame::{pack_header, Frame};
+    use http::frame::{pack_header, Frame, FrameIR};
 
     /// Tests that the `DataFrame` struct correctly interprets a DATA frame
     /// with no padding set.
@@ -482,6 +486,8 @@ mod tests {
         let serialized = serialize_frame(&frame);
 
         assert_eq!(serialized, expected);
+        // The header is known up front, padding included.
+        assert_eq!(frame.frame_header(), Some((6 + 1 + 5, 0, 8, 1)));
     }
 
     /// Tests that `DataFrame`s get correctly serialized when created with
@@ -0,0 +1,138 @@
+//! The module contains the support for extension frames: frames of types that are not defined by
+//! the HTTP/2 spec itself, but by protocol extensions (Section 5.5).
+//!
+//! Parsers for the frame types of an extension can be registered with an `ExtensionRegistry`.
+//! The frames of any other unknown type are passed on as `RawFrame`s.
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+
+use http::StreamId;
+use http::frame::RawFrame;
+
+/// A trait that the frames of extension frame types implement, once parsed.
+///
+/// Since the concrete type of an extension frame is only known to the extension itself, the
+/// `as_any` method allows it to recover the frame's type by downcasting.
+pub trait ExtensionFrame: fmt::Debug {
+    /// Returns the type of the frame, as found in the frame header.
+    fn frame_type(&self) -> u8;
+    /// Returns the `StreamId` of the stream to which the frame is associated.
+    fn get_stream_id(&self) -> StreamId;
+    /// Returns the frame as an `Any`, so that it can be downcast to its concrete type.
+    fn as_any(&self) -> &Any;
+}
+
+/// A parser of the frames of an extension frame type.
+///
+/// It returns `None` for frames that it cannot parse, which are then handled like the frames of
+/// any other unknown type.
+pub type ExtensionParser = Box<Fn(&RawFrame) -> Option<Box<ExtensionFrame>> + Send>;
+
+/// A collection of the `ExtensionParser`s that should be used to parse the frames of extension
+/// frame types, keyed by the frame type.
+#[derive(Default)]
+pub struct ExtensionRegistry {
+    parsers: HashMap<u8, ExtensionParser>,
+}
+
+impl ExtensionRegistry {
+    /// Creates a new `ExtensionRegistry`, without any registered parsers.
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry::default()
+    }
+
+    /// Registers the given parser for the frames of the given type, replacing the parser that
+    /// was previously registered for it, if any.
+    pub fn register<F>(&mut self, frame_type: u8, parser: F)
+        where F: Fn(&RawFrame) -> Option<Box<ExtensionFrame>> + Send + 'static
+    {
+        self.parsers.insert(frame_type, Box::new(parser));
+    }
+
+    /// Returns whether a parser is registered for the given frame type.
+    pub fn is_registered(&self, frame_type: u8) -> bool {
+        self.parsers.contains_key(&frame_type)
+    }
+
+    /// Parses the given raw frame using the parser registered for its type.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no parser is registered for the frame's type or the parser rejects the frame.
+    pub fn parse(&self, raw_frame: &RawFrame) -> Option<Box<ExtensionFrame>> {
+        self.parsers.get(&raw_frame.header().1).and_then(|parser| parser(raw_frame))
+    }
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut types: Vec<&u8> = self.parsers.keys().collect();
+        types.sort();
+        fmt.debug_struct("ExtensionRegistry").field("frame_types", &types).finish()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::any::Any;
+
+    use super::{ExtensionFrame, ExtensionRegistry};
+    use http::StreamId;
+    use http::frame::RawFrame;
+
+    /// An extension frame that simply keeps the payload of the frame.
+    #[derive(Debug, PartialEq)]
+    pub struct TestExtensionFrame {
+        pub stream_id: StreamId,
+        pub payload: Vec<u8>,
+    }
+
+    impl ExtensionFrame for TestExtensionFrame {
+        fn frame_type(&self) -> u8 {
+            0xf0
+        }
+        fn get_stream_id(&self) -> StreamId {
+            self.stream_id
+        }
+        fn as_any(&self) -> &Any {
+            self
+        }
+    }
+
+    /// Parses a `TestExtensionFrame`, rejecting frames with an empty payload.
+    pub fn parse_test_frame(raw: &RawFrame) -> Option<Box<ExtensionFrame>> {
+        if raw.payload().is_empty() {
+            return None;
+        }
+        Some(Box::new(TestExtensionFrame {
+            stream_id: raw.get_stream_id(),
+            payload: raw.payload().to_vec(),
+        }))
+    }
+
+    #[test]
+    fn test_parse_registered() {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(0xf0, parse_test_frame);
+        assert!(registry.is_registered(0xf0));
+
+        let raw = RawFrame::from_parts(0xf0, 0, 3, &[1, 2]);
+        let frame = registry.parse(&raw).unwrap();
+        assert_eq!(frame.frame_type(), 0xf0);
+        assert_eq!(frame.as_any().downcast_ref::<TestExtensionFrame>(),
+                   Some(&TestExtensionFrame {
+                       stream_id: 3,
+                       payload: vec![1, 2],
+                   }));
+    }
+
+    #[test]
+    fn test_parse_unregistered_or_rejected() {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(0xf0, parse_test_frame);
+
+        assert!(registry.parse(&RawFrame::from_parts(0xf1, 0, 3, &[1])).is_none());
+        assert!(registry.parse(&RawFrame::from_parts(0xf0, 0, 3, &[])).is_none());
+    }
+}
@@ -121,6 +121,10 @@ impl<'a> FrameIR for GoawayFrame<'a> {
         }
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
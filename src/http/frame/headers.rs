@@ -321,6 +321,10 @@ impl<'a> FrameIR for HeadersFrame<'a> {
 
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
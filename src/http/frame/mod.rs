@@ -41,9 +41,15 @@ pub const FRAME_HEADER_LEN: usize = 9;
 
 mod bs_debug;
 
+pub mod altsvc;
 pub mod builder;
+pub mod continuation;
 pub mod data;
+pub mod extension;
 pub mod headers;
+pub mod origin;
+pub mod priority;
+pub mod push_promise;
 pub mod rst_stream;
 pub mod settings;
 pub mod goaway;
@@ -51,17 +57,24 @@ pub mod ping;
 pub mod window_update;
 
 pub use self::builder::FrameBuilder;
+pub use self::extension::{ExtensionFrame, ExtensionParser, ExtensionRegistry};
 
 /// Rexports related to the `DATA` frame.
 pub use self::data::{DataFlag, DataFrame};
 /// Rexports related to the `HEADERS` frame.
-pub use self::headers::{HeadersFlag, HeadersFrame};
+pub use self::headers::{HeadersFlag, HeadersFrame, StreamDependency};
+pub use self::priority::PriorityFrame;
 pub use self::rst_stream::RstStreamFrame;
 /// Rexports related to the `SETTINGS` frame.
 pub use self::settings::{SettingsFlag, SettingsFrame, HttpSetting};
 pub use self::goaway::GoawayFrame;
 pub use self::ping::PingFrame;
 pub use self::window_update::WindowUpdateFrame;
+pub use self::altsvc::AltSvcFrame;
+pub use self::origin::OriginFrame;
+/// Rexports related to the `PUSH_PROMISE` and `CONTINUATION` frames.
+pub use self::push_promise::{PushPromiseFlag, PushPromiseFrame};
+pub use self::continuation::{ContinuationFlag, ContinuationFrame};
 
 /// An alias for the 9-byte buffer that each HTTP/2 frame header must be stored
 /// in.
@@ -137,6 +150,14 @@ fn parse_padded_payload(payload: &[u8]) -> Option<(&[u8], u8)> {
 pub trait FrameIR {
     /// Write out the on-the-wire representation of the frame into the given `FrameBuilder`.
     fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()>;
+
+    /// Returns the header that the frame is serialized with, if it is known without serializing
+    /// the frame.
+    ///
+    /// The default implementation returns `None`.
+    fn frame_header(&self) -> Option<FrameHeader> {
+        None
+    }
 }
 
 /// A trait that all HTTP/2 frame header flags need to implement.
@@ -257,6 +278,28 @@ impl<'a> RawFrame<'a> {
         Some(raw.into())
     }
 
+    /// Creates a new `RawFrame` of the given type, with the given flags, on the given stream,
+    /// carrying the given payload.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use solicit_fork::http::frame::RawFrame;
+    ///
+    /// let frame = RawFrame::from_parts(0xf0, 0x1, 3, &[1, 2]);
+    /// assert_eq!(frame.header(), (2, 0xf0, 0x1, 3));
+    /// assert_eq!(frame.payload(), &[1, 2]);
+    /// ```
+    pub fn from_parts(frame_type: u8,
+                      flags: u8,
+                      stream_id: StreamId,
+                      payload: &[u8])
+                      -> RawFrame<'static> {
+        let mut raw = pack_header(&(payload.len() as u32, frame_type, flags, stream_id)).to_vec();
+        raw.extend_from_slice(payload);
+        raw.into()
+    }
+
     /// Returns the total length of the `RawFrame`, including both headers, as well as the entire
     /// payload.
     #[inline]
@@ -321,6 +364,10 @@ impl<'a> FrameIR for RawFrame<'a> {
         try!(b.write_header(self.header()));
         b.write_all(self.payload())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.header())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,167 @@
+//! The module contains the implementation of the `ORIGIN` frame (RFC 8336, Section 2), which
+//! advertises the origins for which the server is authoritative.
+use std::fmt;
+use std::io;
+
+use http::StreamId;
+use http::frame::{Frame, FrameIR, FrameBuilder, FrameHeader, RawFrame, NoFlag};
+use http::frame::bs_debug::BsDebug;
+
+/// The frame type of the `ORIGIN` frame.
+pub const ORIGIN_FRAME_TYPE: u8 = 0xc;
+
+/// The struct represents the `ORIGIN` frame. It is always sent on stream `0`.
+///
+/// The origins found in the frames that a client receives on a connection make up the connection's
+/// origin set.
+#[derive(Clone, PartialEq)]
+pub struct OriginFrame {
+    origins: Vec<Vec<u8>>,
+    flags: u8,
+}
+
+impl fmt::Debug for OriginFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let origins: Vec<BsDebug> = self.origins.iter().map(|origin| BsDebug(origin)).collect();
+        fmt.debug_struct("OriginFrame")
+            .field("origins", &origins)
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+impl OriginFrame {
+    /// Constructs a new `OriginFrame` that advertises the given origins (ASCII serializations,
+    /// such as `https://example.com`).
+    pub fn new<O: Into<Vec<u8>>>(origins: Vec<O>) -> OriginFrame {
+        OriginFrame {
+            origins: origins.into_iter().map(Into::into).collect(),
+            flags: 0,
+        }
+    }
+
+    /// Returns the origins that the frame advertises.
+    pub fn origins(&self) -> &[Vec<u8>] {
+        &self.origins
+    }
+
+    /// Returns the length of the payload of the frame.
+    fn payload_len(&self) -> u32 {
+        self.origins.iter().map(|origin| 2 + origin.len() as u32).sum()
+    }
+}
+
+impl<'a> Frame<'a> for OriginFrame {
+    type FlagType = NoFlag;
+
+    /// Creates a new `OriginFrame` from the given `RawFrame`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the payload cannot be parsed into a list of origins or if the frame is not on
+    /// stream `0`; the spec requires such frames to be ignored.
+    fn from_raw(raw_frame: &'a RawFrame<'a>) -> Option<OriginFrame> {
+        let (len, frame_type, flags, stream_id) = raw_frame.header();
+        if frame_type != ORIGIN_FRAME_TYPE || stream_id != 0 {
+            return None;
+        }
+        let mut payload = raw_frame.payload();
+        if (len as usize) != payload.len() {
+            return None;
+        }
+
+        let mut origins = Vec::new();
+        while !payload.is_empty() {
+            if payload.len() < 2 {
+                return None;
+            }
+            let origin_len = ((payload[0] as usize) << 8) | payload[1] as usize;
+            if payload.len() < 2 + origin_len {
+                return None;
+            }
+            origins.push(payload[2..2 + origin_len].to_vec());
+            payload = &payload[2 + origin_len..];
+        }
+
+        Some(OriginFrame {
+            origins: origins,
+            flags: flags,
+        })
+    }
+
+    fn is_set(&self, _: NoFlag) -> bool {
+        false
+    }
+    fn get_stream_id(&self) -> StreamId {
+        0
+    }
+    fn get_header(&self) -> FrameHeader {
+        (self.payload_len(), ORIGIN_FRAME_TYPE, self.flags, 0)
+    }
+}
+
+impl FrameIR for OriginFrame {
+    fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()> {
+        try!(builder.write_header(self.get_header()));
+        for origin in &self.origins {
+            let origin_len = origin.len();
+            try!(builder.write_all(&[(origin_len >> 8) as u8, origin_len as u8]));
+            try!(builder.write_all(origin));
+        }
+        Ok(())
+    }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OriginFrame;
+
+    use http::tests::common::serialize_frame;
+    use http::frame::{Frame, RawFrame};
+
+    #[test]
+    fn test_parse_valid() {
+        let raw = RawFrame::from_parts(0xc, 0, 0, b"\x00\x05a.com\x00\x07b.a.com");
+
+        let frame = OriginFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.origins(), &[b"a.com".to_vec(), b"b.a.com".to_vec()][..]);
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let raw = RawFrame::from_parts(0xc, 0, 0, b"");
+
+        let frame = OriginFrame::from_raw(&raw).expect("Valid frame expected");
+        assert!(frame.origins().is_empty());
+    }
+
+    /// Tests that the frames that must be ignored are not parsed.
+    #[test]
+    fn test_parse_invalid() {
+        let is_ignored = |frame_type, stream, payload: &[u8]| {
+            OriginFrame::from_raw(&RawFrame::from_parts(frame_type, 0, stream, payload)).is_none()
+        };
+        // A non-zero stream
+        assert!(is_ignored(0xc, 1, b"\x00\x01a"));
+        // A truncated origin
+        assert!(is_ignored(0xc, 0, b"\x00\x01a\x00\x05a"));
+        // A truncated length
+        assert!(is_ignored(0xc, 0, b"\x00\x01a\x00"));
+        // Wrong frame type
+        assert!(is_ignored(0xa, 0, b"\x00\x01a"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let frame = OriginFrame::new(vec!["a.com", "b.a.com"]);
+        let expected = RawFrame::from_parts(0xc, 0, 0, b"\x00\x05a.com\x00\x07b.a.com");
+        assert_eq!(serialize_frame(&frame), expected.serialize());
+
+        let raw = RawFrame::from(expected.serialize());
+        assert_eq!(OriginFrame::from_raw(&raw), Some(frame));
+    }
+}
@@ -109,6 +109,10 @@ impl<'a> FrameIR for PingFrame {
         try!(builder.write_u32(self.opaque_data as u32));
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,142 @@
+//! The module contains the implementation of the `PRIORITY` frame.
+use std::io;
+
+use http::StreamId;
+use http::frame::{Frame, FrameIR, FrameBuilder, FrameHeader, RawFrame, NoFlag};
+use http::frame::headers::StreamDependency;
+
+/// The total allowed size for the `PRIORITY` frame payload.
+pub const PRIORITY_FRAME_LEN: u32 = 5;
+/// The frame type of the `PRIORITY` frame.
+pub const PRIORITY_FRAME_TYPE: u8 = 0x2;
+
+/// The struct represents the `PRIORITY` HTTP/2 frame, which changes the dependency and weight of
+/// a stream (Section 6.3).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriorityFrame {
+    stream_id: StreamId,
+    stream_dep: StreamDependency,
+    flags: u8,
+}
+
+impl PriorityFrame {
+    /// Constructs a new `PriorityFrame` that assigns the given dependency to the given stream.
+    pub fn new(stream_id: StreamId, stream_dep: StreamDependency) -> PriorityFrame {
+        PriorityFrame {
+            stream_id: stream_id,
+            stream_dep: stream_dep,
+            flags: 0,
+        }
+    }
+
+    /// Returns the dependency that the frame assigns to its stream.
+    pub fn stream_dependency(&self) -> &StreamDependency {
+        &self.stream_dep
+    }
+}
+
+impl<'a> Frame<'a> for PriorityFrame {
+    type FlagType = NoFlag;
+
+    fn from_raw(raw_frame: &'a RawFrame<'a>) -> Option<Self> {
+        let (payload_len, frame_type, flags, stream_id) = raw_frame.header();
+        if payload_len != PRIORITY_FRAME_LEN {
+            return None;
+        }
+        if frame_type != PRIORITY_FRAME_TYPE {
+            return None;
+        }
+        if stream_id == 0x0 {
+            return None;
+        }
+
+        Some(PriorityFrame {
+            stream_id: stream_id,
+            stream_dep: StreamDependency::parse(raw_frame.payload()),
+            flags: flags,
+        })
+    }
+
+    fn is_set(&self, _: NoFlag) -> bool {
+        false
+    }
+    fn get_stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+    fn get_header(&self) -> FrameHeader {
+        (PRIORITY_FRAME_LEN,
+         PRIORITY_FRAME_TYPE,
+         self.flags,
+         self.stream_id)
+    }
+}
+
+impl FrameIR for PriorityFrame {
+    fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()> {
+        try!(builder.write_header(self.get_header()));
+        try!(builder.write_all(&self.stream_dep.serialize()));
+        Ok(())
+    }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PriorityFrame;
+
+    use http::tests::common::serialize_frame;
+    use http::frame::{pack_header, FrameHeader, Frame};
+    use http::frame::headers::StreamDependency;
+
+    /// A helper function that creates a new Vec containing the serialized representation of the
+    /// given `FrameHeader` followed by the raw provided payload.
+    fn prepare_frame_bytes(header: FrameHeader, payload: Vec<u8>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(pack_header(&header).to_vec());
+        buf.extend(payload);
+        buf
+    }
+
+    #[test]
+    fn test_parse_valid() {
+        let raw = prepare_frame_bytes((5, 0x2, 0, 3), vec![0x80, 0, 0, 1, 15]);
+        let frame = PriorityFrame::from_raw(&raw.into()).expect("Valid frame expected");
+        assert_eq!(frame.get_stream_id(), 3);
+        assert_eq!(frame.stream_dependency(), &StreamDependency::new(1, 15, true));
+    }
+
+    #[test]
+    fn test_parse_invalid_stream_id() {
+        let raw = prepare_frame_bytes((5, 0x2, 0, 0), vec![0, 0, 0, 1, 15]);
+        assert!(PriorityFrame::from_raw(&raw.into()).is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_payload_size() {
+        let raw = prepare_frame_bytes((4, 0x2, 0, 3), vec![0, 0, 0, 1]);
+        assert!(PriorityFrame::from_raw(&raw.into()).is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_type() {
+        let raw = prepare_frame_bytes((5, 0x3, 0, 3), vec![0, 0, 0, 1, 15]);
+        assert!(PriorityFrame::from_raw(&raw.into()).is_none());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let frame = PriorityFrame::new(5, StreamDependency::new(3, 255, false));
+        let raw = serialize_frame(&frame);
+        assert_eq!(raw, prepare_frame_bytes((5, 0x2, 0, 5), vec![0, 0, 0, 3, 255]));
+    }
+
+    #[test]
+    fn test_serialize_exclusive() {
+        let frame = PriorityFrame::new(1, StreamDependency::new(0, 0, true));
+        let raw = serialize_frame(&frame);
+        assert_eq!(raw, prepare_frame_bytes((5, 0x2, 0, 1), vec![0x80, 0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,239 @@
+//! The module contains the implementation of the `PUSH_PROMISE` frame (HTTP/2 spec, section 6.6),
+//! which notifies the peer in advance of the streams that the sender intends to initiate.
+use std::fmt;
+use std::io;
+
+use http::StreamId;
+use http::frame::{Flag, Frame, FrameIR, FrameBuilder, FrameHeader, RawFrame, parse_padded_payload};
+use http::frame::bs_debug::BsDebug;
+
+/// The frame type of the `PUSH_PROMISE` frame.
+pub const PUSH_PROMISE_FRAME_TYPE: u8 = 0x5;
+
+/// An enum representing the flags that a `PushPromiseFrame` can have.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PushPromiseFlag {
+    EndHeaders = 0x4,
+    Padded = 0x8,
+}
+
+impl Flag for PushPromiseFlag {
+    #[inline]
+    fn bitmask(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// The struct represents the `PUSH_PROMISE` frame.
+///
+/// It carries the ID of the promised stream along with the (first fragment of the) header block
+/// of the request that the pushed response answers. The rest of the header block, if any, follows
+/// in CONTINUATION frames.
+#[derive(Clone, PartialEq)]
+pub struct PushPromiseFrame {
+    stream_id: StreamId,
+    promised_stream_id: StreamId,
+    header_fragment: Vec<u8>,
+    padding_len: Option<u8>,
+    flags: u8,
+}
+
+impl fmt::Debug for PushPromiseFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("PushPromiseFrame")
+            .field("stream_id", &self.stream_id)
+            .field("promised_stream_id", &self.promised_stream_id)
+            .field("header_fragment", &BsDebug(&self.header_fragment))
+            .field("padding_len", &self.padding_len)
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+impl PushPromiseFrame {
+    /// Constructs a new `PushPromiseFrame` that promises the given stream on the given stream,
+    /// carrying the given header block fragment. No padding and no flags are set.
+    pub fn new(stream_id: StreamId,
+               promised_stream_id: StreamId,
+               fragment: Vec<u8>)
+               -> PushPromiseFrame {
+        PushPromiseFrame {
+            stream_id: stream_id,
+            promised_stream_id: promised_stream_id,
+            header_fragment: fragment,
+            padding_len: None,
+            flags: 0,
+        }
+    }
+
+    /// Returns the ID of the stream that the frame reserves.
+    pub fn promised_stream_id(&self) -> StreamId {
+        self.promised_stream_id
+    }
+
+    /// Returns the header block fragment carried by the frame.
+    pub fn header_fragment(&self) -> &[u8] {
+        &self.header_fragment
+    }
+
+    /// Returns the length of the padding, if the frame is padded.
+    pub fn padding_len(&self) -> Option<u8> {
+        self.padding_len
+    }
+
+    /// Returns whether this frame ends the header block. If not, CONTINUATION frames with the
+    /// rest of the header block follow.
+    pub fn is_headers_end(&self) -> bool {
+        self.is_set(PushPromiseFlag::EndHeaders)
+    }
+
+    /// Sets the padding length for the frame, as well as the corresponding Padded flag.
+    pub fn set_padding(&mut self, padding_len: u8) {
+        self.padding_len = Some(padding_len);
+        self.set_flag(PushPromiseFlag::Padded);
+    }
+
+    /// Sets the given flag for the frame.
+    pub fn set_flag(&mut self, flag: PushPromiseFlag) {
+        self.flags |= flag.bitmask();
+    }
+
+    /// Returns the length of the payload of the frame, including any padding.
+    fn payload_len(&self) -> u32 {
+        let padding = if self.is_set(PushPromiseFlag::Padded) {
+            1 + self.padding_len.unwrap_or(0) as u32
+        } else {
+            0
+        };
+        4 + self.header_fragment.len() as u32 + padding
+    }
+}
+
+impl<'a> Frame<'a> for PushPromiseFrame {
+    type FlagType = PushPromiseFlag;
+
+    /// Creates a new `PushPromiseFrame` from the given `RawFrame`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the frame is sent on stream `0` or its (unpadded) payload is too short to hold
+    /// the promised stream ID.
+    fn from_raw(raw_frame: &'a RawFrame<'a>) -> Option<PushPromiseFrame> {
+        let (len, frame_type, flags, stream_id) = raw_frame.header();
+        if frame_type != PUSH_PROMISE_FRAME_TYPE {
+            return None;
+        }
+        if (len as usize) != raw_frame.payload().len() || stream_id == 0 {
+            return None;
+        }
+        let (actual, padding_len) = if flags & PushPromiseFlag::Padded.bitmask() != 0 {
+            match parse_padded_payload(raw_frame.payload()) {
+                Some((data, pad_len)) => (data, Some(pad_len)),
+                None => return None,
+            }
+        } else {
+            (raw_frame.payload(), None)
+        };
+        if actual.len() < 4 {
+            return None;
+        }
+
+        Some(PushPromiseFrame {
+            stream_id: stream_id,
+            promised_stream_id: unpack_octets_4!(actual, 0, u32) & !(1 << 31),
+            header_fragment: actual[4..].to_vec(),
+            padding_len: padding_len,
+            flags: flags,
+        })
+    }
+
+    fn is_set(&self, flag: PushPromiseFlag) -> bool {
+        self.flags & flag.bitmask() != 0
+    }
+    fn get_stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+    fn get_header(&self) -> FrameHeader {
+        (self.payload_len(), PUSH_PROMISE_FRAME_TYPE, self.flags, self.stream_id)
+    }
+}
+
+impl FrameIR for PushPromiseFrame {
+    fn serialize_into<B: FrameBuilder>(self, builder: &mut B) -> io::Result<()> {
+        try!(builder.write_header(self.get_header()));
+        let padded = self.is_set(PushPromiseFlag::Padded);
+        if padded {
+            try!(builder.write_all(&[self.padding_len.unwrap_or(0)]));
+        }
+        try!(builder.write_u32(self.promised_stream_id));
+        try!(builder.write_all(&self.header_fragment));
+        if padded {
+            try!(builder.write_padding(self.padding_len.unwrap_or(0)));
+        }
+        Ok(())
+    }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PushPromiseFlag, PushPromiseFrame};
+
+    use http::tests::common::serialize_frame;
+    use http::frame::{Frame, RawFrame};
+
+    #[test]
+    fn test_parse_simple() {
+        let raw = RawFrame::from_parts(0x5, 0x4, 1, b"\x80\x00\x00\x02abc");
+
+        let frame = PushPromiseFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.get_stream_id(), 1);
+        // The reserved bit is ignored
+        assert_eq!(frame.promised_stream_id(), 2);
+        assert_eq!(frame.header_fragment(), b"abc");
+        assert_eq!(frame.padding_len(), None);
+        assert!(frame.is_headers_end());
+    }
+
+    #[test]
+    fn test_parse_padded() {
+        let raw = RawFrame::from_parts(0x5, 0x8, 3, b"\x02\x00\x00\x00\x04ab\x00\x00");
+
+        let frame = PushPromiseFrame::from_raw(&raw).expect("Valid frame expected");
+        assert_eq!(frame.promised_stream_id(), 4);
+        assert_eq!(frame.header_fragment(), b"ab");
+        assert_eq!(frame.padding_len(), Some(2));
+        assert!(!frame.is_headers_end());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        // Stream 0
+        assert!(PushPromiseFrame::from_raw(&RawFrame::from_parts(0x5, 0, 0, b"\x00\x00\x00\x02"))
+                    .is_none());
+        // No room for the promised stream ID
+        assert!(PushPromiseFrame::from_raw(&RawFrame::from_parts(0x5, 0, 1, b"\x00\x00\x02"))
+                    .is_none());
+        // ...once the padding is taken off
+        let raw = RawFrame::from_parts(0x5, 0x8, 1, b"\x01\x00\x00\x02\x00");
+        assert!(PushPromiseFrame::from_raw(&raw).is_none());
+        // Wrong frame type
+        assert!(PushPromiseFrame::from_raw(&RawFrame::from_parts(0x1, 0, 1, b"\x00\x00\x00\x02"))
+                    .is_none());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut frame = PushPromiseFrame::new(1, 2, b"abc".to_vec());
+        frame.set_flag(PushPromiseFlag::EndHeaders);
+        frame.set_padding(1);
+        let expected = RawFrame::from_parts(0x5, 0xc, 1, b"\x01\x00\x00\x00\x02abc\x00");
+        assert_eq!(serialize_frame(&frame), expected.serialize());
+
+        let raw = RawFrame::from(expected.serialize());
+        assert_eq!(PushPromiseFrame::from_raw(&raw), Some(frame));
+    }
+}
@@ -93,6 +93,10 @@ impl FrameIR for RstStreamFrame {
         try!(builder.write_u32(self.raw_error_code));
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
@@ -19,12 +19,15 @@ pub enum HttpSetting {
     InitialWindowSize(u32),
     MaxFrameSize(u32),
     MaxHeaderListSize(u32),
+    /// Enables the extended CONNECT method, which bootstraps other protocols (such as WebSockets)
+    /// over a stream (RFC 8441, Section 3).
+    EnableConnectProtocol(u32),
 }
 
 impl HttpSetting {
     /// Creates a new `HttpSetting` with the correct variant corresponding to
     /// the given setting id, based on the settings IDs defined in section
-    /// 6.5.2.
+    /// 6.5.2, as well as those defined by extensions.
     pub fn from_id(id: u16, val: u32) -> Option<HttpSetting> {
         match id {
             1 => Some(HttpSetting::HeaderTableSize(val)),
@@ -33,6 +36,7 @@ impl HttpSetting {
             4 => Some(HttpSetting::InitialWindowSize(val)),
             5 => Some(HttpSetting::MaxFrameSize(val)),
             6 => Some(HttpSetting::MaxHeaderListSize(val)),
+            8 => Some(HttpSetting::EnableConnectProtocol(val)),
             _ => None,
         }
     }
@@ -64,6 +68,7 @@ impl HttpSetting {
             HttpSetting::InitialWindowSize(_) => 4,
             HttpSetting::MaxFrameSize(_) => 5,
             HttpSetting::MaxHeaderListSize(_) => 6,
+            HttpSetting::EnableConnectProtocol(_) => 8,
         }
     }
 
@@ -75,7 +80,8 @@ impl HttpSetting {
             HttpSetting::MaxConcurrentStreams(ref val) |
             HttpSetting::InitialWindowSize(ref val) |
             HttpSetting::MaxFrameSize(ref val) |
-            HttpSetting::MaxHeaderListSize(ref val) => *val,
+            HttpSetting::MaxHeaderListSize(ref val) |
+            HttpSetting::EnableConnectProtocol(ref val) => *val,
         }
     }
 
@@ -296,6 +302,10 @@ impl FrameIR for SettingsFrame {
 
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
@@ -588,6 +598,13 @@ mod tests {
 
             assert!(setting.is_none());
         }
+        {
+            let buf = [0, 8, 0, 0, 0, 1];
+
+            let setting = HttpSetting::parse_setting(&buf).unwrap();
+
+            assert_eq!(setting, HttpSetting::EnableConnectProtocol(1));
+        }
         {
             let buf = [0, 0, 0, 0, 0, 255];
 
@@ -641,6 +658,13 @@ mod tests {
 
             let setting = HttpSetting::MaxHeaderListSize((1 << 8) - 1);
 
+            assert_eq!(buf, setting.serialize());
+        }
+        {
+            let buf = [0, 8, 0, 0, 0, 1];
+
+            let setting = HttpSetting::EnableConnectProtocol(1);
+
             assert_eq!(buf, setting.serialize());
         }
     }
@@ -88,6 +88,10 @@ impl FrameIR for WindowUpdateFrame {
         try!(builder.write_u32(self.increment));
         Ok(())
     }
+
+    fn frame_header(&self) -> Option<FrameHeader> {
+        Some(self.get_header())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,663 @@
+//! The module contains the `StreamHandle`, which lets application threads read from and write to
+//! a stream as it progresses, while the connection that the stream belongs to is driven on
+//! another thread.
+//!
+//! The handle is created along with a `SharedStream`, the `Stream` implementation that the
+//! connection driver tracks in its session state. The two share the buffers of the stream's
+//! incoming and outgoing data: the driver fills the former with the received DATA frames and
+//! drains the latter as it sends DATA frames (by calling `send_next_data`), while the application
+//! does the opposite through the handle's `Read` and `Write` implementations.
+//!
+//! The data written to the handle is only sent, and the flow control window of the data read from
+//! it only given back to the peer, once the driver calls `send_next_data`. A driver that blocks
+//! on reading from the socket should therefore set a waker on the `SharedStream`, which the handle
+//! invokes whenever it reads or writes, so that the driver calls `send_next_data` (e.g. by queuing
+//! a work item for itself, in the same way that the frames read from the socket are queued).
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use http::{Header, OwnedHeader, StaticHeader, ErrorCode, HttpError, INITIAL_CONNECTION_WINDOW_SIZE};
+use http::session::{Stream, StreamState, StreamDataChunk, StreamDataError};
+
+/// The state of a stream that is shared between its `StreamHandle` and `SharedStream`.
+struct Buffers {
+    /// The headers received on the stream.
+    headers: Option<Vec<StaticHeader>>,
+    /// The current stream state.
+    state: StreamState,
+    /// The error code with which the peer reset the stream, if it did so.
+    error_code: Option<ErrorCode>,
+    /// The received data that the application has not read yet.
+    incoming: VecDeque<u8>,
+    /// The number of received bytes that the application has read, but whose flow control window
+    /// has not been given back to the peer yet.
+    consumed: u32,
+    /// The data that the application has written, but which has not been sent yet.
+    outgoing: VecDeque<u8>,
+    /// The number of bytes that the stream may send, as last reported by the connection and less
+    /// the data sent since.
+    out_window: i32,
+    /// The maximum number of bytes that the outgoing buffer holds, regardless of the window.
+    capacity: usize,
+    /// Whether the stream should be closed once the outgoing data is sent.
+    finishing: bool,
+    /// Whether the application has dropped its handle.
+    handle_dropped: bool,
+    /// Whether the connection has dropped the stream.
+    stream_dropped: bool,
+}
+
+/// The buffers, along with the condition variable that signals any change to them.
+struct Shared {
+    buffers: Mutex<Buffers>,
+    changed: Condvar,
+    /// Wakes up the connection driver, so that it sends the written data and gives back the
+    /// window of the read data.
+    waker: Mutex<Option<Box<Fn() + Send>>>,
+}
+
+impl Shared {
+    /// Locks the buffers.
+    fn lock<'a>(&'a self) -> MutexGuard<'a, Buffers> {
+        self.buffers.lock().unwrap()
+    }
+
+    /// Blocks until the buffers change.
+    fn wait<'a>(&self, guard: MutexGuard<'a, Buffers>) -> MutexGuard<'a, Buffers> {
+        self.changed.wait(guard).unwrap()
+    }
+
+    /// Invokes the waker of the connection driver, if one is set. Must not be called while the
+    /// buffers are locked.
+    fn wake(&self) {
+        if let Some(ref waker) = *self.waker.lock().unwrap() {
+            waker();
+        }
+    }
+}
+
+/// Returns the error that the handle reports for the given buffers, if the stream can no longer
+/// make progress: either the peer reset it or the connection dropped it while it was open.
+fn stream_error(buffers: &Buffers) -> Option<io::Error> {
+    if let Some(error_code) = buffers.error_code {
+        Some(io::Error::new(io::ErrorKind::ConnectionReset, HttpError::StreamReset(error_code)))
+    } else if buffers.stream_dropped && !buffers.state.is_closed() {
+        Some(io::Error::new(io::ErrorKind::ConnectionAborted,
+                            "The connection no longer tracks the stream"))
+    } else {
+        None
+    }
+}
+
+/// The application's end of a stream: a blocking `Read` and `Write` handle that can be moved to
+/// any thread.
+///
+/// Reads block until the connection receives more data on the stream, and return `0` once the
+/// peer closes its end of the stream. The flow control window taken up by the received data is
+/// only given back to the peer once the data is read, so a peer whose data is not being read
+/// stops sending once it has used up the stream's window.
+///
+/// Writes block while the stream's outgoing buffer holds as much data as the stream's outbound
+/// flow control window allows to be sent, that is, until the connection sends some of the data
+/// written before and the peer updates the window. The `close` method ends the stream once all the
+/// written data is sent; dropping the handle does the same.
+pub struct StreamHandle {
+    shared: Arc<Shared>,
+}
+
+impl StreamHandle {
+    /// Creates a new `StreamHandle`, along with the `SharedStream` that the connection should
+    /// track (e.g. by passing it to `start_request` or returning it from a `StreamFactory`).
+    pub fn new() -> (StreamHandle, SharedStream) {
+        StreamHandle::with_capacity(usize::MAX)
+    }
+
+    /// Creates a new `StreamHandle`, whose outgoing buffer holds at most the given number of
+    /// bytes, even if the stream's window allows more, along with its `SharedStream`.
+    pub fn with_capacity(capacity: usize) -> (StreamHandle, SharedStream) {
+        let shared = Arc::new(Shared {
+            buffers: Mutex::new(Buffers {
+                headers: None,
+                state: StreamState::Open,
+                error_code: None,
+                incoming: VecDeque::new(),
+                consumed: 0,
+                outgoing: VecDeque::new(),
+                out_window: INITIAL_CONNECTION_WINDOW_SIZE,
+                capacity: capacity,
+                finishing: false,
+                handle_dropped: false,
+                stream_dropped: false,
+            }),
+            changed: Condvar::new(),
+            waker: Mutex::new(None),
+        });
+        (StreamHandle { shared: shared.clone() }, SharedStream { shared: shared })
+    }
+
+    /// Blocks until headers are received on the stream and returns them.
+    ///
+    /// # Returns
+    ///
+    /// An error if the stream is reset or closed by the peer before it sends any headers.
+    pub fn headers(&self) -> io::Result<Vec<StaticHeader>> {
+        let mut buffers = self.shared.lock();
+        loop {
+            if let Some(ref headers) = buffers.headers {
+                return Ok(headers.clone());
+            }
+            if let Some(err) = stream_error(&buffers) {
+                return Err(err);
+            }
+            if buffers.state.is_closed_remote() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "The stream was closed without any headers"));
+            }
+            buffers = self.shared.wait(buffers);
+        }
+    }
+
+    /// Returns the error code with which the peer reset the stream, if it did so.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.shared.lock().error_code
+    }
+
+    /// Closes the local end of the stream, once all the data written so far is sent. Any further
+    /// writes fail.
+    pub fn close(&mut self) {
+        self.shared.lock().finishing = true;
+        self.shared.changed.notify_all();
+        self.shared.wake();
+    }
+}
+
+impl io::Read for StreamHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffers = self.shared.lock();
+        loop {
+            if !buffers.incoming.is_empty() {
+                let len = buf.len().min(buffers.incoming.len());
+                for (dst, src) in buf.iter_mut().zip(buffers.incoming.drain(..len)) {
+                    *dst = src;
+                }
+                // Once some data is consumed, the driver has already been woken up to give back
+                // its window.
+                let wake = buffers.consumed == 0;
+                buffers.consumed += len as u32;
+                drop(buffers);
+                if wake {
+                    self.shared.wake();
+                }
+                return Ok(len);
+            }
+            if let Some(err) = stream_error(&buffers) {
+                return Err(err);
+            }
+            if buffers.state.is_closed_remote() || buffers.stream_dropped {
+                return Ok(0);
+            }
+            buffers = self.shared.wait(buffers);
+        }
+    }
+}
+
+impl io::Write for StreamHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut buffers = self.shared.lock();
+        loop {
+            if let Some(err) = stream_error(&buffers) {
+                return Err(err);
+            }
+            if buffers.finishing || buffers.state.is_closed_local() || buffers.stream_dropped {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The stream is closed"));
+            }
+            let window = cmp::max(buffers.out_window, 0) as usize;
+            let free = buffers.capacity.min(window).saturating_sub(buffers.outgoing.len());
+            if free > 0 || buf.is_empty() {
+                let len = buf.len().min(free);
+                buffers.outgoing.extend(&buf[..len]);
+                self.shared.changed.notify_all();
+                drop(buffers);
+                self.shared.wake();
+                return Ok(len);
+            }
+            buffers = self.shared.wait(buffers);
+        }
+    }
+
+    /// Blocks until all the data written so far is sent.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut buffers = self.shared.lock();
+        loop {
+            if let Some(err) = stream_error(&buffers) {
+                return Err(err);
+            }
+            if buffers.outgoing.is_empty() {
+                return Ok(());
+            }
+            if buffers.state.is_closed_local() || buffers.stream_dropped {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "The stream is closed"));
+            }
+            buffers = self.shared.wait(buffers);
+        }
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        let mut buffers = self.shared.lock();
+        buffers.finishing = true;
+        buffers.handle_dropped = true;
+        // Nothing is going to read the remaining data, so its window can be given back.
+        let unread = buffers.incoming.len() as u32;
+        buffers.incoming.clear();
+        buffers.consumed += unread;
+        drop(buffers);
+        self.shared.wake();
+    }
+}
+
+/// The connection's end of a stream created along with a `StreamHandle`. It is a `Stream` that
+/// passes the received headers and data on to the handle and sends the data written to it.
+pub struct SharedStream {
+    shared: Arc<Shared>,
+}
+
+impl SharedStream {
+    /// Sets the waker that the handle invokes whenever the connection driver has to call
+    /// `send_next_data`: once data is written to the handle, some received data is read from it,
+    /// or it is closed.
+    ///
+    /// The waker is invoked on the thread that uses the handle, so it should only signal the
+    /// driver, e.g. by sending a message on a channel that the driver waits on.
+    pub fn set_waker<F: Fn() + Send + 'static>(&mut self, waker: F) {
+        *self.shared.waker.lock().unwrap() = Some(Box::new(waker));
+    }
+}
+
+impl Stream for SharedStream {
+    fn new_data_chunk(&mut self, data: &[u8]) {
+        let mut buffers = self.shared.lock();
+        if buffers.handle_dropped {
+            buffers.consumed += data.len() as u32;
+        } else {
+            buffers.incoming.extend(data);
+            self.shared.changed.notify_all();
+        }
+    }
+
+    fn set_headers<'n, 'v>(&mut self, headers: Vec<Header<'n, 'v>>) {
+        let new_headers = headers.into_iter().map(|h| {
+            let owned: OwnedHeader = h.into();
+            owned.into()
+        });
+        let mut buffers = self.shared.lock();
+        match buffers.headers {
+            Some(ref mut headers) => headers.extend(new_headers),
+            None => buffers.headers = Some(new_headers.collect()),
+        }
+        self.shared.changed.notify_all();
+    }
+
+    fn set_state(&mut self, state: StreamState) {
+        self.shared.lock().state = state;
+        self.shared.changed.notify_all();
+    }
+
+    fn state(&self) -> StreamState {
+        self.shared.lock().state
+    }
+
+    fn on_rst_stream(&mut self, error_code: ErrorCode) {
+        let mut buffers = self.shared.lock();
+        buffers.error_code = Some(error_code);
+        buffers.state = StreamState::Closed;
+        self.shared.changed.notify_all();
+    }
+
+    fn defers_window_updates(&self) -> bool {
+        true
+    }
+
+    fn take_consumed(&mut self) -> u32 {
+        mem::replace(&mut self.shared.lock().consumed, 0)
+    }
+
+    fn set_out_window(&mut self, window: i32) {
+        let mut buffers = self.shared.lock();
+        if buffers.out_window != window {
+            buffers.out_window = window;
+            // Wake up the writers that are waiting for the window to grow.
+            self.shared.changed.notify_all();
+        }
+    }
+
+    fn get_data_chunk(&mut self, buf: &mut [u8]) -> Result<StreamDataChunk, StreamDataError> {
+        let mut buffers = self.shared.lock();
+        if buffers.state.is_closed_local() {
+            return Err(StreamDataError::Closed);
+        }
+        let window = cmp::max(buffers.out_window, 0) as usize;
+        let len = buf.len().min(buffers.outgoing.len()).min(window);
+        for (dst, src) in buf.iter_mut().zip(buffers.outgoing.drain(..len)) {
+            *dst = src;
+        }
+        buffers.out_window -= len as i32;
+        if len > 0 {
+            // Wake up the writers that are waiting for room in the buffer.
+            self.shared.changed.notify_all();
+        }
+
+        if buffers.outgoing.is_empty() && buffers.finishing {
+            buffers.state = match buffers.state {
+                StreamState::HalfClosedRemote => StreamState::Closed,
+                _ => StreamState::HalfClosedLocal,
+            };
+            self.shared.changed.notify_all();
+            Ok(StreamDataChunk::Last(len))
+        } else if len == 0 {
+            Ok(StreamDataChunk::Unavailable)
+        } else {
+            Ok(StreamDataChunk::Chunk(len))
+        }
+    }
+}
+
+impl Drop for SharedStream {
+    fn drop(&mut self) {
+        self.shared.lock().stream_dropped = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::sync::mpsc::{self, Sender};
+    use std::thread;
+
+    use super::{StreamHandle, SharedStream};
+
+    use http::{Header, ErrorCode, HttpResult, HttpScheme, StreamId};
+    use http::connection::{HttpConnection, HttpFrame, SendStatus, EndStream};
+    use http::client::{ClientConnection, RequestStream};
+    use http::server::{ServerConnection, StreamFactory};
+    use http::session::{Stream, StreamDataChunk, DefaultSessionState, SessionState};
+    use http::session::Client as ClientMarker;
+    use http::session::Server as ServerMarker;
+    use http::tests::common::{MockSendFrame, MockReceiveFrame};
+
+    #[test]
+    fn test_write_and_close() {
+        let (mut handle, mut stream) = StreamHandle::new();
+        let mut buf = [0; 4];
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Unavailable);
+
+        handle.write_all(b"hello").unwrap();
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Chunk(4));
+        assert_eq!(&buf, b"hell");
+        handle.close();
+        assert_eq!(handle.write(b"more").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Last(1));
+        assert!(stream.is_closed_local());
+        handle.flush().unwrap();
+    }
+
+    /// Tests that writes block while the outgoing buffer is full, until the connection sends some
+    /// of the buffered data.
+    #[test]
+    fn test_write_backpressure() {
+        let (mut handle, mut stream) = StreamHandle::with_capacity(4);
+        assert_eq!(handle.write(b"0123456789").unwrap(), 4);
+
+        let writer = thread::spawn(move || {
+            handle.write_all(b"456789").unwrap();
+            handle.close();
+        });
+        let mut sent: Vec<u8> = Vec::new();
+        loop {
+            let mut buf = [0; 3];
+            match stream.get_data_chunk(&mut buf).ok().unwrap() {
+                StreamDataChunk::Chunk(len) => sent.extend(&buf[..len]),
+                StreamDataChunk::Last(len) => {
+                    sent.extend(&buf[..len]);
+                    break;
+                }
+                StreamDataChunk::Unavailable => thread::yield_now(),
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(sent, b"0123456789".to_vec());
+    }
+
+    /// Tests that writes block once the data written fills the stream's outbound window and that
+    /// the chunks taken from the stream do not go over it.
+    #[test]
+    fn test_write_window() {
+        let (mut handle, mut stream) = StreamHandle::new();
+        let (wake_tx, wake_rx) = mpsc::channel();
+        stream.set_waker(move || wake_tx.send(()).unwrap());
+        stream.set_out_window(4);
+        assert_eq!(handle.write(b"0123456789").unwrap(), 4);
+        assert!(wake_rx.try_recv().is_ok());
+
+        let writer = thread::spawn(move || {
+            handle.write_all(b"456789").unwrap();
+            handle.close();
+        });
+        let mut buf = [0; 8];
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Chunk(4));
+        // The window is used up, even though the buffer may have room for more.
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Unavailable);
+        stream.set_out_window(8);
+        writer.join().unwrap();
+        assert_eq!(stream.get_data_chunk(&mut buf).ok().unwrap(), StreamDataChunk::Last(6));
+        assert_eq!(&buf[..6], b"456789");
+    }
+
+    #[test]
+    fn test_read() {
+        let (mut handle, mut stream) = StreamHandle::new();
+        stream.set_headers(vec![Header::new(b":status", b"200")]);
+        assert_eq!(handle.headers().unwrap()[0].value(), b"200");
+
+        let reader = thread::spawn(move || {
+            let mut body = Vec::new();
+            handle.read_to_end(&mut body).unwrap();
+            body
+        });
+        stream.new_data_chunk(b"abc");
+        stream.new_data_chunk(b"de");
+        stream.close_remote();
+        assert_eq!(reader.join().unwrap(), b"abcde".to_vec());
+        // The read data can now be given back to the peer.
+        assert_eq!(stream.take_consumed(), 5);
+        assert_eq!(stream.take_consumed(), 0);
+    }
+
+    /// Tests that data that is never read is considered consumed once the handle is dropped.
+    #[test]
+    fn test_drop_handle() {
+        let (handle, mut stream) = StreamHandle::new();
+        stream.new_data_chunk(b"abc");
+        drop(handle);
+        stream.new_data_chunk(b"de");
+        assert_eq!(stream.take_consumed(), 5);
+        // Dropping the handle ends the stream.
+        assert_eq!(stream.get_data_chunk(&mut [0; 4]).ok().unwrap(), StreamDataChunk::Last(0));
+    }
+
+    #[test]
+    fn test_reset() {
+        let (mut handle, mut stream) = StreamHandle::new();
+        stream.new_data_chunk(b"ab");
+        stream.on_rst_stream(ErrorCode::Cancel);
+        assert_eq!(handle.error_code(), Some(ErrorCode::Cancel));
+        // The data received before the reset can still be read.
+        let mut buf = [0; 4];
+        assert_eq!(handle.read(&mut buf).unwrap(), 2);
+        assert_eq!(handle.read(&mut buf).unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(handle.write(b"x").unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(handle.headers().unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    /// Tests that the handle reports an error once the connection drops a stream that was open.
+    #[test]
+    fn test_drop_stream() {
+        let (mut handle, stream) = StreamHandle::new();
+        drop(stream);
+        assert_eq!(handle.read(&mut [0; 4]).unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+    }
+
+    /// The events on which the test drives the connections.
+    enum Event {
+        /// A handle needs the driver to send data or window updates.
+        Wake,
+        /// The client is done with its request.
+        Done,
+    }
+
+    /// Returns a new `StreamHandle`, whose `SharedStream` wakes the driver by queuing an event.
+    fn waking_handle(events: &Sender<Event>) -> (StreamHandle, SharedStream) {
+        let (handle, mut stream) = StreamHandle::new();
+        let events = events.clone();
+        stream.set_waker(move || {
+            let _ = events.send(Event::Wake);
+        });
+        (handle, stream)
+    }
+
+    /// Creates the server's streams, passing their handles on to the application.
+    struct HandleFactory {
+        events: Sender<Event>,
+        handles: Sender<StreamHandle>,
+    }
+
+    impl StreamFactory for HandleFactory {
+        type Stream = SharedStream;
+
+        fn create(&mut self, _: StreamId) -> SharedStream {
+            let (handle, stream) = waking_handle(&self.events);
+            self.handles.send(handle).unwrap();
+            stream
+        }
+    }
+
+    /// Hands the frames sent by one end of a connection to the other one. Returns whether there
+    /// were any.
+    fn deliver<F>(sent: &mut MockSendFrame, mut handle: F) -> bool
+        where F: FnMut(&mut MockReceiveFrame) -> HttpResult<()>
+    {
+        let frames = mem::replace(&mut sent.sent, Vec::new());
+        for raw in frames.iter() {
+            let frame = HttpFrame::from_raw(raw).unwrap();
+            handle(&mut MockReceiveFrame::new(vec![frame])).unwrap();
+        }
+        !frames.is_empty()
+    }
+
+    /// Tests that handles on both ends of a connection can exchange more data than fits into the
+    /// initial flow control windows, with the connections only driven when a frame is sent or a
+    /// handle wakes them up.
+    #[test]
+    fn test_handles_over_connection() {
+        let (events_tx, events_rx) = mpsc::channel();
+        let (handles_tx, handles_rx) = mpsc::channel::<StreamHandle>();
+        // The server echoes the body of each request back in its response.
+        thread::spawn(move || {
+            for mut handle in handles_rx {
+                let mut body = Vec::new();
+                handle.read_to_end(&mut body).unwrap();
+                handle.write_all(&body).unwrap();
+                handle.close();
+            }
+        });
+        let factory = HandleFactory {
+            events: events_tx.clone(),
+            handles: handles_tx,
+        };
+        let mut server =
+            ServerConnection::with_connection(HttpConnection::new(HttpScheme::Http),
+                                              DefaultSessionState::<ServerMarker, _>::new(),
+                                              factory);
+        let mut client = ClientConnection::with_connection(HttpConnection::new(HttpScheme::Http),
+                                                           DefaultSessionState::<ClientMarker,
+                                                                                 _>::new());
+        let mut to_server = MockSendFrame::new();
+        let mut to_client = MockSendFrame::new();
+        let (mut handle, stream) = waking_handle(&events_tx);
+        let headers = vec![
+            Header::new(b":method", b"POST"),
+            Header::new(b":scheme", b"http"),
+            Header::new(b":path", b"/"),
+            Header::new(b":authority", b"localhost"),
+        ];
+        client.start_request(RequestStream {
+                                 headers: headers,
+                                 stream: stream,
+                                 priority: None,
+                             },
+                             &mut to_server)
+              .unwrap();
+
+        let body: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        let app = {
+            let body = body.clone();
+            thread::spawn(move || {
+                let res = (|| -> io::Result<(Vec<u8>, Vec<u8>)> {
+                    try!(handle.write_all(&body));
+                    handle.close();
+                    let status = try!(handle.headers())[0].value().to_vec();
+                    let mut echoed = Vec::new();
+                    try!(handle.read_to_end(&mut echoed));
+                    Ok((status, echoed))
+                })();
+                let _ = events_tx.send(Event::Done);
+                res
+            })
+        };
+        let mut responded = HashSet::new();
+        loop {
+            let mut progress = deliver(&mut to_server,
+                                       |rx| server.handle_next_frame(rx, &mut to_client));
+            let new: Vec<StreamId> = server.state
+                                           .iter()
+                                           .map(|(&id, _)| id)
+                                           .filter(|id| !responded.contains(id))
+                                           .collect();
+            for id in new {
+                server.start_response(vec![Header::new(b":status", b"200")],
+                                      id,
+                                      EndStream::No,
+                                      &mut to_client)
+                      .unwrap();
+                responded.insert(id);
+            }
+            progress |= deliver(&mut to_client,
+                                |rx| client.handle_next_frame(rx, &mut to_server));
+            while let SendStatus::Sent = client.send_next_data(&mut to_server).unwrap() {
+                progress = true;
+            }
+            while let SendStatus::Sent = server.send_next_data(&mut to_client).unwrap() {
+                progress = true;
+            }
+            // Once there is nothing to do, wait for one of the handles to ask for more.
+            let idle = !progress && to_server.sent.is_empty() && to_client.sent.is_empty();
+            if idle {
+                if let Event::Done = events_rx.recv().unwrap() {
+                    break;
+                }
+            }
+        }
+
+        let (status, echoed) = app.join().unwrap().unwrap();
+        assert_eq!(status, b"200".to_vec());
+        assert!(echoed == body);
+    }
+}
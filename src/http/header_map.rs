@@ -0,0 +1,383 @@
+//! The module contains the `HeaderMap`, a structured representation of a header list that
+//! provides efficient (case-insensitive) lookups by header name.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::{Chain, FromIterator};
+use std::slice;
+use std::str;
+
+use http::{Header, HeaderPart, OwnedHeader, StaticHeader};
+
+/// An iterator over all the headers of a `HeaderMap`, yielding the pseudo-headers first.
+pub type Iter<'a> = Chain<slice::Iter<'a, StaticHeader>, slice::Iter<'a, StaticHeader>>;
+
+/// A collection of headers that supports multiple values for the same header name.
+///
+/// Pseudo-headers (whose names start with a colon) are kept separately from the regular headers,
+/// so that they always precede the regular headers once the map is converted back to a header
+/// list, as HTTP/2 requires. Within each of the two groups, the insertion order is preserved.
+///
+/// Header names are compared case-insensitively. The values of each header are indexed by the
+/// lowercased name, so looking them up does not scan the whole list; removing or replacing a
+/// header re-indexes the headers of its group, though.
+///
+/// # Example
+///
+/// ```rust
+/// use solicit_fork::http::{Header, HeaderMap};
+///
+/// let mut map = HeaderMap::new();
+/// map.append(b"set-cookie", b"a=1");
+/// map.append(b":status", b"200");
+/// map.append(b"Set-Cookie", b"b=2");
+///
+/// assert_eq!(map.get(b"SET-COOKIE"), Some(&b"a=1"[..]));
+/// assert_eq!(map.get_all(b"set-cookie"), vec![&b"a=1"[..], &b"b=2"[..]]);
+/// // The pseudo-header is moved to the front.
+/// let headers: Vec<_> = map.into();
+/// assert_eq!(headers[0], Header::new(b":status", b"200"));
+/// ```
+#[derive(Clone, Default)]
+pub struct HeaderMap {
+    /// The pseudo-headers, in insertion order.
+    pseudo: Vec<StaticHeader>,
+    /// The regular headers, in insertion order.
+    regular: Vec<StaticHeader>,
+    /// The positions of the values of each header, keyed by its lowercased name, within the list
+    /// that the header belongs to.
+    index: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl HeaderMap {
+    /// Creates a new, empty, `HeaderMap`.
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    /// Returns the number of headers in the map, counting each value separately.
+    pub fn len(&self) -> usize {
+        self.pseudo.len() + self.regular.len()
+    }
+
+    /// Returns whether the map contains no headers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a new value for the header with the given name, keeping any values that the header
+    /// already has.
+    pub fn append<N, V>(&mut self, name: N, value: V)
+        where N: Into<HeaderPart<'static>>,
+              V: Into<HeaderPart<'static>>
+    {
+        let header = Header::new(name, value);
+        let key = header.name().to_ascii_lowercase();
+        let list = if is_pseudo(&key) {
+            &mut self.pseudo
+        } else {
+            &mut self.regular
+        };
+        self.index.entry(key).or_insert_with(Vec::new).push(list.len());
+        list.push(header);
+    }
+
+    /// Sets the single value of the header with the given name, replacing any values that the
+    /// header already has. The header keeps the position of its first value, if it had any.
+    pub fn insert<N, V>(&mut self, name: N, value: V)
+        where N: Into<HeaderPart<'static>>,
+              V: Into<HeaderPart<'static>>
+    {
+        let header = Header::new(name, value);
+        let pos = match self.positions(header.name()).first() {
+            Some(&pos) => pos,
+            None => return self.append(header.name, header.value),
+        };
+        let pseudo = is_pseudo(header.name());
+        {
+            let list = if pseudo {
+                &mut self.pseudo
+            } else {
+                &mut self.regular
+            };
+            list.retain(|h| !eq_name(h.name(), header.name()));
+            list.insert(pos, header);
+        }
+        self.reindex(pseudo);
+    }
+
+    /// Returns the first value of the header with the given name, if any.
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.positions(name).first().map(|&pos| self.list(name)[pos].value())
+    }
+
+    /// Returns all values of the header with the given name, in insertion order.
+    pub fn get_all(&self, name: &[u8]) -> Vec<&[u8]> {
+        let list = self.list(name);
+        self.positions(name).iter().map(|&pos| list[pos].value()).collect()
+    }
+
+    /// Returns whether the map contains a header with the given name.
+    pub fn contains(&self, name: &[u8]) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Removes all values of the header with the given name, returning the number of removed
+    /// values.
+    pub fn remove(&mut self, name: &[u8]) -> usize {
+        let removed = self.positions(name).len();
+        if removed > 0 {
+            let pseudo = is_pseudo(name);
+            if pseudo {
+                self.pseudo.retain(|h| !eq_name(h.name(), name));
+            } else {
+                self.regular.retain(|h| !eq_name(h.name(), name));
+            }
+            self.reindex(pseudo);
+        }
+        removed
+    }
+
+    /// Returns the pseudo-headers in the map.
+    pub fn pseudo_headers(&self) -> &[StaticHeader] {
+        &self.pseudo
+    }
+
+    /// Returns the regular headers in the map.
+    pub fn regular_headers(&self) -> &[StaticHeader] {
+        &self.regular
+    }
+
+    /// Returns an iterator over all the headers in the map, starting with the pseudo-headers.
+    pub fn iter(&self) -> Iter {
+        self.pseudo.iter().chain(self.regular.iter())
+    }
+
+    /// Returns the value of the `content-length` header as a number.
+    ///
+    /// Returns `None` if the header is missing or its value is not a valid length. Multiple values
+    /// are only considered valid if they are all the same.
+    pub fn content_length(&self) -> Option<u64> {
+        let values = self.get_all(b"content-length");
+        let first = match values.first() {
+            Some(first) => *first,
+            None => return None,
+        };
+        if values.iter().any(|value| *value != first) || first.is_empty() ||
+           !first.iter().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        str::from_utf8(first).ok().and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the value of the `content-type` header, if it is present and valid UTF-8.
+    pub fn content_type(&self) -> Option<&str> {
+        self.get(b"content-type").and_then(|value| str::from_utf8(value).ok())
+    }
+
+    /// Internal helper method. Returns the list in which a header with the given name is found.
+    fn list(&self, name: &[u8]) -> &[StaticHeader] {
+        if is_pseudo(name) {
+            &self.pseudo
+        } else {
+            &self.regular
+        }
+    }
+
+    /// Internal helper method. Returns the positions of the values of the header with the given
+    /// name within its list.
+    fn positions(&self, name: &[u8]) -> &[usize] {
+        let key = if name.iter().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(name.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        };
+        self.index.get(&key[..]).map_or(&[], |positions| &positions[..])
+    }
+
+    /// Internal helper method. Rebuilds the index entries of either the pseudo-headers or the
+    /// regular headers, once their positions have changed.
+    fn reindex(&mut self, pseudo: bool) {
+        self.index.retain(|name, _| is_pseudo(name) != pseudo);
+        let list = if pseudo {
+            &self.pseudo
+        } else {
+            &self.regular
+        };
+        for (pos, header) in list.iter().enumerate() {
+            self.index
+                .entry(header.name().to_ascii_lowercase())
+                .or_insert_with(Vec::new)
+                .push(pos);
+        }
+    }
+}
+
+impl fmt::Debug for HeaderMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HeaderMap")
+         .field("pseudo", &self.pseudo)
+         .field("regular", &self.regular)
+         .finish()
+    }
+}
+
+/// Two maps are equal if they contain the same headers, in the same order; the index follows
+/// from the headers.
+impl PartialEq for HeaderMap {
+    fn eq(&self, other: &HeaderMap) -> bool {
+        self.pseudo == other.pseudo && self.regular == other.regular
+    }
+}
+
+/// Returns whether the given header name is the name of a pseudo-header.
+fn is_pseudo(name: &[u8]) -> bool {
+    name.first() == Some(&b':')
+}
+
+/// Returns whether the given header names are the same, ignoring the case.
+fn eq_name(a: &[u8], b: &[u8]) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+impl<'n, 'v> FromIterator<Header<'n, 'v>> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = Header<'n, 'v>>>(iter: I) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for header in iter {
+            let (name, value): OwnedHeader = header.into();
+            map.append(name, value);
+        }
+        map
+    }
+}
+
+impl<'n, 'v> From<Vec<Header<'n, 'v>>> for HeaderMap {
+    fn from(headers: Vec<Header<'n, 'v>>) -> HeaderMap {
+        headers.into_iter().collect()
+    }
+}
+
+impl<'a, 'n, 'v> From<&'a [Header<'n, 'v>]> for HeaderMap {
+    fn from(headers: &'a [Header<'n, 'v>]) -> HeaderMap {
+        headers.iter().cloned().collect()
+    }
+}
+
+impl From<HeaderMap> for Vec<StaticHeader> {
+    fn from(map: HeaderMap) -> Vec<StaticHeader> {
+        let HeaderMap { pseudo: mut headers, regular, .. } = map;
+        headers.extend(regular);
+        headers
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = &'a StaticHeader;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderMap;
+    use http::{Header, StaticHeader};
+
+    /// Tests that lookups ignore the case of the header name.
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let mut map = HeaderMap::new();
+        map.append(b"Content-Type", b"text/plain");
+
+        assert_eq!(map.get(b"content-type"), Some(&b"text/plain"[..]));
+        assert!(map.contains(b"CONTENT-TYPE"));
+        assert_eq!(map.get(b"content-length"), None);
+        assert_eq!(map.content_type(), Some("text/plain"));
+    }
+
+    /// Tests that multiple values are kept in insertion order and can be replaced or removed.
+    #[test]
+    fn test_multiple_values() {
+        let mut map = HeaderMap::new();
+        map.append(b"x-a", b"1");
+        map.append(b"x-b", b"2");
+        map.append(b"x-a", b"3");
+        assert_eq!(map.get_all(b"x-a"), vec![&b"1"[..], &b"3"[..]]);
+        assert_eq!(map.len(), 3);
+
+        map.insert(b"x-a", b"4");
+        assert_eq!(map.get_all(b"x-a"), vec![&b"4"[..]]);
+        let headers: Vec<StaticHeader> = map.clone().into();
+        assert_eq!(headers, vec![Header::new(b"x-a", b"4"), Header::new(b"x-b", b"2")]);
+
+        assert_eq!(map.remove(b"X-B"), 1);
+        assert_eq!(map.remove(b"x-b"), 0);
+        assert_eq!(map.len(), 1);
+    }
+
+    /// Tests that the lookups keep finding the right values once removing or replacing a header
+    /// shifts the ones that follow it.
+    #[test]
+    fn test_lookup_after_shift() {
+        let mut map = HeaderMap::new();
+        map.append(b"x-a", b"1");
+        map.append(b"X-B", b"2");
+        map.append(b"x-c", b"3");
+        map.append(b"x-b", b"4");
+        map.append(b":path", b"/");
+
+        assert_eq!(map.remove(b"x-a"), 1);
+        assert_eq!(map.get_all(b"x-b"), vec![&b"2"[..], &b"4"[..]]);
+        assert_eq!(map.get(b"x-c"), Some(&b"3"[..]));
+
+        map.insert(b"x-b", b"5");
+        assert_eq!(map.get_all(b"x-b"), vec![&b"5"[..]]);
+        assert_eq!(map.get(b"X-C"), Some(&b"3"[..]));
+        assert_eq!(map.get(b":path"), Some(&b"/"[..]));
+        let headers: Vec<StaticHeader> = map.into();
+        assert_eq!(headers,
+                   vec![Header::new(b":path", b"/"),
+                        Header::new(b"x-b", b"5"),
+                        Header::new(b"x-c", b"3")]);
+    }
+
+    /// Tests that pseudo-headers are separated from the regular headers and precede them once
+    /// converted back into a header list.
+    #[test]
+    fn test_pseudo_headers() {
+        let map: HeaderMap = vec![Header::new(b"x-a", b"1"),
+                                  Header::new(b":status", b"200"),
+                                  Header::new(b"x-b", b"2")]
+                                 .into();
+
+        assert_eq!(map.pseudo_headers(), &[Header::new(b":status", b"200")][..]);
+        assert_eq!(map.regular_headers().len(), 2);
+        assert_eq!(map.get(b":status"), Some(&b"200"[..]));
+        let names: Vec<&[u8]> = map.iter().map(|h| h.name()).collect();
+        assert_eq!(names, vec![&b":status"[..], &b"x-a"[..], &b"x-b"[..]]);
+    }
+
+    /// Tests the parsing of the `content-length` header.
+    #[test]
+    fn test_content_length() {
+        let mut map = HeaderMap::new();
+        assert_eq!(map.content_length(), None);
+
+        map.append(b"content-length", b"42");
+        assert_eq!(map.content_length(), Some(42));
+        // Repeated identical values are fine...
+        map.append(b"content-length", b"42");
+        assert_eq!(map.content_length(), Some(42));
+        // ...but conflicting ones are not.
+        map.append(b"content-length", b"43");
+        assert_eq!(map.content_length(), None);
+
+        for value in &[&b"-1"[..], b"+1", b"", b"1 ", b"99999999999999999999999"] {
+            map.insert(b"content-length", value.to_vec());
+            assert_eq!(map.content_length(), None);
+        }
+    }
+}
@@ -8,18 +8,44 @@ use std::error::Error;
 
 use hpack::decoder::DecoderError;
 
+use self::validation::ContentLengthError;
+use self::flood::Flood;
+
 pub mod frame;
 pub mod transport;
 pub mod connection;
 pub mod session;
 pub mod priority;
+pub mod padding;
+pub mod duplex;
+pub mod handle;
+pub mod compression;
+pub mod flood;
+pub mod queue;
+pub mod stats;
+pub mod observer;
+pub mod capture;
+pub mod request;
+pub mod header_map;
+pub mod validation;
 
 pub mod client;
 pub mod server;
 
+pub use self::request::{Request, RequestBuilder, Method, Uri};
+pub use self::header_map::HeaderMap;
+
 /// The initial size of the connections' flow control window.
 pub const INITIAL_CONNECTION_WINDOW_SIZE: i32 = 65_535;
 
+/// The default maximum size of a received header list (as defined for
+/// `SETTINGS_MAX_HEADER_LIST_SIZE`) that the connections accept.
+pub const DEFAULT_MAX_HEADER_LIST_SIZE: u32 = 64 * 1024;
+
+/// The default maximum size of a received header block, across the HEADERS frame and any of its
+/// CONTINUATION frames, before it is decoded.
+pub const DEFAULT_MAX_HEADER_BLOCK_SIZE: usize = 128 * 1024;
+
 /// An alias for the type that represents the ID of an HTTP/2 stream
 pub type StreamId = u32;
 /// An alias for the type that represents an HTTP/2 header where both the name and the value is
@@ -242,7 +268,7 @@ pub const ALPN_PROTOCOLS: &'static [&'static [u8]] = &[b"h2", b"h2-16", b"h2-15"
 /// The enum represents an error code that are used in `RST_STREAM` and `GOAWAY` frames.
 /// These are defined in [Section 7](http://http2.github.io/http2-spec/#ErrorCodes) of the HTTP/2
 /// spec.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ErrorCode {
     /// The associated condition is not a result of an error. For example, a GOAWAY might include
     /// this code to indicate graceful shutdown of a connection.
@@ -339,6 +365,7 @@ impl Into<u32> for ErrorCode {
 pub struct ConnectionError {
     error_code: ErrorCode,
     debug_data: Option<Vec<u8>>,
+    last_stream_id: Option<StreamId>,
 }
 
 impl ConnectionError {
@@ -347,6 +374,7 @@ impl ConnectionError {
         ConnectionError {
             error_code: error_code,
             debug_data: None,
+            last_stream_id: None,
         }
     }
     /// Creates a new `ConnectionError` with the given associated debug data.
@@ -354,9 +382,16 @@ impl ConnectionError {
         ConnectionError {
             error_code: error_code,
             debug_data: Some(debug_data),
+            last_stream_id: None,
         }
     }
 
+    /// Sets the ID of the last stream that the peer might have processed (as indicated by a
+    /// GOAWAY frame). Streams with higher IDs were not processed and can safely be retried.
+    pub fn set_last_stream_id(&mut self, last_stream_id: StreamId) {
+        self.last_stream_id = Some(last_stream_id);
+    }
+
     /// The error code of the underlying error.
     pub fn error_code(&self) -> ErrorCode {
         self.error_code
@@ -365,6 +400,10 @@ impl ConnectionError {
     pub fn debug_data(&self) -> Option<&[u8]> {
         self.debug_data.as_ref().map(|d| d.as_ref())
     }
+    /// The ID of the last stream that the peer might have processed, if known.
+    pub fn last_stream_id(&self) -> Option<StreamId> {
+        self.last_stream_id
+    }
     /// The debug data interpreted as a string, if possible.
     pub fn debug_str(&self) -> Option<&str> {
         self.debug_data().and_then(|data| ::std::str::from_utf8(data).ok())
@@ -405,6 +444,27 @@ pub enum HttpError {
     UnknownStreamId,
     UnableToConnect,
     MalformedResponse,
+    /// The URI of a request could not be parsed.
+    InvalidUri,
+    /// The body of the received message does not match its declared `content-length`.
+    ContentLength(ContentLengthError),
+    /// An extended CONNECT request was attempted, but the peer has not enabled extended CONNECT
+    /// (RFC 8441) in its settings.
+    ConnectProtocolDisabled,
+    /// The server refused to open a CONNECT tunnel, responding with the given non-2xx status.
+    TunnelRefused(u16),
+    /// The peer reset the stream with the given error code (e.g. `CONNECT_ERROR` when a tunnel
+    /// could not be established).
+    StreamReset(ErrorCode),
+    /// The peer sent a header block larger than the connection's hard limit on its size, which
+    /// cannot be skipped without losing the HPACK decoder's state.
+    HeaderBlockTooLarge,
+    /// The peer sent frames of the given kind faster than the connection allows, so it is
+    /// considered abusive.
+    Flood(Flood),
+    /// The peer stopped reading the frames sent to it, so that they could not be queued without
+    /// going over the limits of the queue.
+    PeerStalled,
     Other(Box<Error + Send + Sync>),
 }
 
@@ -433,6 +493,14 @@ impl Error for HttpError {
             HttpError::UnknownStreamId => "Attempted an operation with an unknown HTTP/2 stream ID",
             HttpError::UnableToConnect => "An error attempting to establish an HTTP/2 connection",
             HttpError::MalformedResponse => "The received response was malformed",
+            HttpError::InvalidUri => "The request URI is invalid",
+            HttpError::ContentLength(ref err) => err.description(),
+            HttpError::ConnectProtocolDisabled => "The peer has not enabled extended CONNECT",
+            HttpError::TunnelRefused(_) => "The server refused to open the tunnel",
+            HttpError::StreamReset(_) => "The peer reset the stream",
+            HttpError::HeaderBlockTooLarge => "The peer sent a header block that is too large",
+            HttpError::Flood(ref flood) => flood.description(),
+            HttpError::PeerStalled => "The peer stopped reading the frames sent to it",
             HttpError::Other(_) => "An unknown error",
         }
     }
@@ -442,6 +510,8 @@ impl Error for HttpError {
             HttpError::Other(ref e) => Some(&**e),
             HttpError::IoError(ref e) => Some(e),
             HttpError::PeerConnectionError(ref e) => Some(e),
+            HttpError::ContentLength(ref e) => Some(e),
+            HttpError::Flood(ref e) => Some(e),
             _ => None,
         }
     }
@@ -461,6 +531,14 @@ impl PartialEq for HttpError {
             (&HttpError::UnknownStreamId, &HttpError::UnknownStreamId) => true,
             (&HttpError::UnableToConnect, &HttpError::UnableToConnect) => true,
             (&HttpError::MalformedResponse, &HttpError::MalformedResponse) => true,
+            (&HttpError::InvalidUri, &HttpError::InvalidUri) => true,
+            (&HttpError::ContentLength(e1), &HttpError::ContentLength(e2)) => e1 == e2,
+            (&HttpError::ConnectProtocolDisabled, &HttpError::ConnectProtocolDisabled) => true,
+            (&HttpError::TunnelRefused(s1), &HttpError::TunnelRefused(s2)) => s1 == s2,
+            (&HttpError::StreamReset(e1), &HttpError::StreamReset(e2)) => e1 == e2,
+            (&HttpError::HeaderBlockTooLarge, &HttpError::HeaderBlockTooLarge) => true,
+            (&HttpError::Flood(f1), &HttpError::Flood(f2)) => f1 == f2,
+            (&HttpError::PeerStalled, &HttpError::PeerStalled) => true,
             (&HttpError::Other(ref e1), &HttpError::Other(ref e2)) => {
                 e1.description() == e2.description()
             }
@@ -566,7 +644,7 @@ impl WindowSize {
 }
 
 /// An enum representing the two possible HTTP schemes.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum HttpScheme {
     /// The variant corresponding to `http://`
     Http,
@@ -634,6 +712,15 @@ impl<'n, 'v> Response<'n, 'v> {
         }
     }
 
+    /// Returns the headers of the response (including the `:status` pseudo-header) as a
+    /// `HeaderMap`, which allows them to be looked up by name.
+    ///
+    /// The map is built anew on each call, so it should be kept around when looking up more than
+    /// a single header.
+    pub fn header_map(&self) -> HeaderMap {
+        HeaderMap::from(&self.headers[..])
+    }
+
     /// A helper function that parses a given buffer as a status code and
     /// returns it as a `u16`, if it is valid.
     fn parse_status_code(buf: &[u8]) -> HttpResult<u16> {
@@ -658,15 +745,6 @@ impl<'n, 'v> Response<'n, 'v> {
     }
 }
 
-/// A struct representing a full HTTP/2 request, along with the full body, as a
-/// sequence of bytes.
-#[derive(Clone)]
-pub struct Request<'n, 'v> {
-    pub stream_id: u32,
-    pub headers: Vec<Header<'n, 'v>>,
-    pub body: Vec<u8>,
-}
-
 
 #[cfg(test)]
 pub mod tests;
@@ -0,0 +1,32 @@
+//! The module contains the `FrameObserver` trait, which allows the frames that an
+//! `HttpConnection` sends and receives to be inspected (e.g. in order to trace them or to capture
+//! a connection's traffic), without any change in how the connection handles them.
+
+use http::{StreamId, Header};
+use http::connection::HttpFrame;
+
+/// A trait that should be implemented by types that want to observe every frame that passes
+/// through an `HttpConnection`, as well as the header lists carried by the header blocks.
+///
+/// All the methods have default implementations that do nothing, so that an implementation only
+/// needs to provide the ones that it is interested in.
+///
+/// The observer is called synchronously, as the connection handles the frames; it should not
+/// block for long.
+pub trait FrameObserver: Send {
+    /// Called for each frame received from the peer, before the connection handles it.
+    fn on_frame_received(&mut self, _frame: &HttpFrame) {}
+
+    /// Called for each frame sent to the peer, just before it is handed to the `SendFrame`. The
+    /// frame is exactly the one that is written on the wire (e.g. including its padding).
+    fn on_frame_sent(&mut self, _frame: &HttpFrame) {}
+
+    /// Called with the decoded headers of each header block received on the given stream, before
+    /// they are passed on to the session. Header lists over the connection's
+    /// `max_header_list_size` are not decoded in full and so are not passed on.
+    fn on_headers_received(&mut self, _stream_id: StreamId, _headers: &[Header]) {}
+
+    /// Called with the headers of each header block sent on the given stream, before they are
+    /// encoded.
+    fn on_headers_sent(&mut self, _stream_id: StreamId, _headers: &[Header]) {}
+}
@@ -0,0 +1,108 @@
+//! The module contains the `PaddingPolicy`, which decides how much padding an `HttpConnection`
+//! adds to the DATA and HEADERS frames that it sends.
+//!
+//! Padding obscures the true size of the frames' contents, making traffic analysis harder, at the
+//! cost of bandwidth. The padding of DATA frames is subject to flow control, just like their data.
+
+use std::cmp;
+
+/// The largest frame payload that padding may grow a frame to. This is the default (and minimal)
+/// value of `SETTINGS_MAX_FRAME_SIZE`, which the connection never raises.
+const MAX_PADDED_PAYLOAD_LEN: usize = 16_384;
+
+/// An enum representing the policies for padding the frames that a connection sends.
+///
+/// Whenever a frame is padded, its payload additionally carries a one-byte padding length field,
+/// so even a padding of length `0` grows the frame by one byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingPolicy {
+    /// Frames are not padded.
+    None,
+    /// Each frame is padded with the given number of bytes.
+    Fixed(u8),
+    /// Each frame is padded with a random number of bytes, up to (and including) the given one.
+    Random(u8),
+    /// Each frame is padded so that the length of its payload becomes a multiple of the given
+    /// bucket size. Since a frame can be padded by at most 255 bytes, buckets larger than 256
+    /// bytes cannot always be filled, in which case the maximum padding is used.
+    Bucket(u16),
+}
+
+impl Default for PaddingPolicy {
+    fn default() -> PaddingPolicy {
+        PaddingPolicy::None
+    }
+}
+
+impl PaddingPolicy {
+    /// Returns the length of the padding that should be added to a frame whose payload, without
+    /// any padding, is `payload_len` bytes long. The `random` value is the source of randomness
+    /// for the `Random` policy; other policies ignore it.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the frame should not be padded. The padding never grows the payload of a frame
+    /// beyond the maximum frame size; if the frame cannot be padded at all without doing so,
+    /// `None` is returned.
+    pub fn padding_len(&self, payload_len: usize, random: u64) -> Option<u8> {
+        let padding = match *self {
+            PaddingPolicy::None => return None,
+            PaddingPolicy::Fixed(len) => len as usize,
+            PaddingPolicy::Random(max) => (random % (max as u64 + 1)) as usize,
+            PaddingPolicy::Bucket(0) => return None,
+            PaddingPolicy::Bucket(size) => {
+                let size = size as usize;
+                let padded_len = payload_len + 1;
+                cmp::min((size - padded_len % size) % size, 255)
+            }
+        };
+        // Account for the padding length field.
+        let room = match MAX_PADDED_PAYLOAD_LEN.checked_sub(payload_len + 1) {
+            Some(room) => room,
+            None => return None,
+        };
+        Some(cmp::min(padding, room) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PaddingPolicy;
+
+    #[test]
+    fn test_fixed_and_none() {
+        assert_eq!(PaddingPolicy::None.padding_len(10, 0), None);
+        assert_eq!(PaddingPolicy::Fixed(0).padding_len(10, 0), Some(0));
+        assert_eq!(PaddingPolicy::Fixed(20).padding_len(10, 7), Some(20));
+    }
+
+    #[test]
+    fn test_random() {
+        let policy = PaddingPolicy::Random(9);
+        assert_eq!(policy.padding_len(10, 0), Some(0));
+        assert_eq!(policy.padding_len(10, 9), Some(9));
+        assert_eq!(policy.padding_len(10, 23), Some(3));
+        assert_eq!(PaddingPolicy::Random(255).padding_len(10, 255), Some(255));
+    }
+
+    #[test]
+    fn test_bucket() {
+        let policy = PaddingPolicy::Bucket(64);
+        // The padding length field and the padding fill the payload up to the bucket size.
+        assert_eq!(policy.padding_len(10, 0), Some(53));
+        assert_eq!(policy.padding_len(63, 0), Some(0));
+        assert_eq!(policy.padding_len(64, 0), Some(63));
+        // Large buckets are filled as much as possible.
+        assert_eq!(PaddingPolicy::Bucket(1024).padding_len(10, 0), Some(255));
+        assert_eq!(PaddingPolicy::Bucket(0).padding_len(10, 0), None);
+    }
+
+    /// Tests that padding never grows a frame beyond the maximum frame size.
+    #[test]
+    fn test_max_frame_size() {
+        let policy = PaddingPolicy::Fixed(100);
+        assert_eq!(policy.padding_len(16_300, 0), Some(83));
+        assert_eq!(policy.padding_len(16_383, 0), Some(0));
+        assert_eq!(policy.padding_len(16_384, 0), None);
+    }
+}
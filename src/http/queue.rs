@@ -0,0 +1,381 @@
+//! The module contains the `FrameQueue`, a bounded queue of the frames that a connection has
+//! sent, but which have not been written to the socket yet.
+//!
+//! The connection queues its frames through the `FrameQueueHandle`, which never blocks, while a
+//! dedicated thread pops them off the `FrameQueue` and performs the (blocking) writes. Control
+//! frames are written ahead of the frames that belong to streams, so that acknowledgements and
+//! window updates are not held up by request headers.
+//!
+//! A peer that stops reading makes the queue fill up; once it goes over its limits, or its writes
+//! stall for too long, queueing fails with `HttpError::PeerStalled`, so that the connection can be
+//! closed instead of buffering frames without bound.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use http::{HttpError, HttpResult};
+use http::connection::SendFrame;
+use http::frame::FrameIR;
+
+/// The limits on the frames that a `FrameQueue` holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimits {
+    /// The maximum number of queued control frames (SETTINGS, PING, GOAWAY, WINDOW_UPDATE and
+    /// PRIORITY frames).
+    pub max_control_frames: usize,
+    /// The maximum number of queued bytes of all other frames.
+    pub max_buffered: usize,
+    /// How long the queue may go without any frame being written, while it is not empty, before
+    /// the peer is considered to have stopped reading.
+    pub stall_timeout: Duration,
+}
+
+impl Default for QueueLimits {
+    fn default() -> QueueLimits {
+        QueueLimits {
+            max_control_frames: 512,
+            max_buffered: 1024 * 1024,
+            stall_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The queued frames, shared between the `FrameQueue` and its `FrameQueueHandle`.
+struct Frames {
+    limits: QueueLimits,
+    /// The queued control frames, which are written first.
+    control: VecDeque<Vec<u8>>,
+    /// The queued frames that belong to streams, in the order in which they were sent.
+    stream: VecDeque<Vec<u8>>,
+    /// The total size of the frames in `stream`.
+    buffered: usize,
+    /// The last time at which a frame was written, or at which the queue stopped being empty.
+    progress: Instant,
+    /// Whether the handle has been dropped, meaning that no more frames are coming.
+    handle_dropped: bool,
+    /// Whether the queue has been dropped, meaning that no more frames are written.
+    queue_dropped: bool,
+}
+
+impl Frames {
+    fn is_empty(&self) -> bool {
+        self.control.is_empty() && self.stream.is_empty()
+    }
+}
+
+/// The frames, along with the condition variables that signal that a frame was queued or popped.
+struct Shared {
+    frames: Mutex<Frames>,
+    queued: Condvar,
+    popped: Condvar,
+}
+
+impl Shared {
+    /// Locks the frames.
+    fn lock<'a>(&'a self) -> MutexGuard<'a, Frames> {
+        self.frames.lock().unwrap()
+    }
+}
+
+/// Returns whether the frame of the given type is a control frame, which need not be written in
+/// the order in which it was sent relative to the frames of the streams.
+fn is_control_frame(frame_type: u8) -> bool {
+    match frame_type {
+        // PRIORITY, SETTINGS, PING, GOAWAY and WINDOW_UPDATE
+        0x2 | 0x4 | 0x6 | 0x7 | 0x8 => true,
+        _ => false,
+    }
+}
+
+/// The writing end of the queue, from which a dedicated thread pops the frames to write.
+pub struct FrameQueue {
+    shared: Arc<Shared>,
+}
+
+impl FrameQueue {
+    /// Creates a new empty `FrameQueue` with the given limits, along with the `FrameQueueHandle`
+    /// through which frames are queued.
+    pub fn new(limits: QueueLimits) -> (FrameQueue, FrameQueueHandle) {
+        let shared = Arc::new(Shared {
+            frames: Mutex::new(Frames {
+                limits: limits,
+                control: VecDeque::new(),
+                stream: VecDeque::new(),
+                buffered: 0,
+                progress: Instant::now(),
+                handle_dropped: false,
+                queue_dropped: false,
+            }),
+            queued: Condvar::new(),
+            popped: Condvar::new(),
+        });
+        (FrameQueue { shared: shared.clone() }, FrameQueueHandle { shared: shared })
+    }
+
+    /// Pops the next frame to write, blocking until one is queued.
+    ///
+    /// Returns `None` once the handle is dropped and all the frames that it queued are popped.
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        let mut frames = self.shared.lock();
+        loop {
+            if let Some(frame) = self.pop_locked(&mut frames) {
+                return Some(frame);
+            }
+            if frames.handle_dropped {
+                return None;
+            }
+            frames = self.shared.queued.wait(frames).unwrap();
+        }
+    }
+
+    /// Pops the next frame to write, if there is one, without blocking.
+    pub fn try_pop(&self) -> Option<Vec<u8>> {
+        let mut frames = self.shared.lock();
+        self.pop_locked(&mut frames)
+    }
+
+    /// Returns the number of queued frames.
+    pub fn len(&self) -> usize {
+        let frames = self.shared.lock();
+        frames.control.len() + frames.stream.len()
+    }
+
+    /// Returns whether no frames are queued.
+    pub fn is_empty(&self) -> bool {
+        self.shared.lock().is_empty()
+    }
+
+    fn pop_locked(&self, frames: &mut Frames) -> Option<Vec<u8>> {
+        let frame = match frames.control.pop_front() {
+            Some(frame) => frame,
+            None => {
+                let frame = match frames.stream.pop_front() {
+                    Some(frame) => frame,
+                    None => return None,
+                };
+                frames.buffered -= frame.len();
+                frame
+            }
+        };
+        frames.progress = Instant::now();
+        self.shared.popped.notify_all();
+        Some(frame)
+    }
+}
+
+impl Drop for FrameQueue {
+    fn drop(&mut self) {
+        self.shared.lock().queue_dropped = true;
+        self.shared.popped.notify_all();
+    }
+}
+
+/// The queueing end of the `FrameQueue`. It implements `SendFrame`, so that it can be passed to
+/// the connection in place of the socket.
+pub struct FrameQueueHandle {
+    shared: Arc<Shared>,
+}
+
+impl FrameQueueHandle {
+    /// Replaces the limits of the queue. The frames that are already queued are kept.
+    pub fn set_limits(&mut self, limits: QueueLimits) {
+        self.shared.lock().limits = limits;
+    }
+
+    /// Blocks until a frame of a stream of the given size fits into the queue, so that a sender
+    /// with a lot of data to send can keep pace with the writes.
+    ///
+    /// Fails with `HttpError::PeerStalled` once no frame has been written for the stall timeout.
+    pub fn wait_for_room(&self, len: usize) -> HttpResult<()> {
+        let mut frames = self.shared.lock();
+        loop {
+            if frames.queue_dropped {
+                return Err(HttpError::from(io::Error::new(io::ErrorKind::Other,
+                                                          "Unable to send frame")));
+            }
+            if frames.buffered + len <= frames.limits.max_buffered {
+                return Ok(());
+            }
+            let stalled_for = frames.progress.elapsed();
+            if stalled_for >= frames.limits.stall_timeout {
+                debug!("No frame was written for {:?}", stalled_for);
+                return Err(HttpError::PeerStalled);
+            }
+            let timeout = frames.limits.stall_timeout - stalled_for;
+            frames = self.shared.popped.wait_timeout(frames, timeout).unwrap().0;
+        }
+    }
+
+    /// Queues the given serialized frame.
+    fn push(&mut self, buf: Vec<u8>) -> HttpResult<()> {
+        let mut frames = self.shared.lock();
+        if frames.queue_dropped {
+            return Err(HttpError::from(io::Error::new(io::ErrorKind::Other,
+                                                      "Unable to send frame")));
+        }
+        let now = Instant::now();
+        if frames.is_empty() {
+            frames.progress = now;
+        } else if now.duration_since(frames.progress) >= frames.limits.stall_timeout {
+            debug!("No frame was written for {:?}", now.duration_since(frames.progress));
+            return Err(HttpError::PeerStalled);
+        }
+        if is_control_frame(buf[3]) {
+            if frames.control.len() >= frames.limits.max_control_frames {
+                debug!("Too many control frames are queued");
+                return Err(HttpError::PeerStalled);
+            }
+            frames.control.push_back(buf);
+        } else {
+            if frames.buffered + buf.len() > frames.limits.max_buffered {
+                debug!("Too many bytes are queued");
+                return Err(HttpError::PeerStalled);
+            }
+            frames.buffered += buf.len();
+            frames.stream.push_back(buf);
+        }
+        self.shared.queued.notify_one();
+        Ok(())
+    }
+}
+
+impl SendFrame for FrameQueueHandle {
+    fn send_frame<F: FrameIR>(&mut self, frame: F) -> HttpResult<()> {
+        let mut buf = io::Cursor::new(Vec::with_capacity(1024));
+        try!(frame.serialize_into(&mut buf));
+        try!(self.push(buf.into_inner()));
+        debug!("Queued the frame for sending...");
+        Ok(())
+    }
+}
+
+impl Drop for FrameQueueHandle {
+    fn drop(&mut self) {
+        self.shared.lock().handle_dropped = true;
+        self.shared.queued.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{FrameQueue, QueueLimits};
+    use http::{HttpError, ErrorCode};
+    use http::connection::SendFrame;
+    use http::frame::{DataFrame, PingFrame, RstStreamFrame};
+    use http::tests::common::serialize_frame;
+
+    fn limits() -> QueueLimits {
+        QueueLimits {
+            max_control_frames: 2,
+            max_buffered: 30,
+            stall_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Tests that control frames are popped ahead of the frames of streams, which otherwise keep
+    /// their order.
+    #[test]
+    fn test_frame_queue_priority() {
+        let (queue, mut handle) = FrameQueue::new(limits());
+        let data = DataFrame::with_data(1, &b"abc"[..]);
+        let rst = RstStreamFrame::new(1, ErrorCode::Cancel);
+        handle.send_frame(data.clone()).unwrap();
+        handle.send_frame(rst.clone()).unwrap();
+        handle.send_frame(PingFrame::new_ack(1)).unwrap();
+
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop().unwrap(), serialize_frame(&PingFrame::new_ack(1)));
+        assert_eq!(queue.pop().unwrap(), serialize_frame(&data));
+        assert_eq!(queue.pop().unwrap(), serialize_frame(&rst));
+        assert!(queue.try_pop().is_none());
+        drop(handle);
+        assert!(queue.pop().is_none());
+    }
+
+    /// Tests that queueing fails once the queue is over its limits, until frames are popped.
+    #[test]
+    fn test_frame_queue_limits() {
+        let (queue, mut handle) = FrameQueue::new(limits());
+        handle.send_frame(PingFrame::new_ack(1)).unwrap();
+        handle.send_frame(PingFrame::new_ack(2)).unwrap();
+        assert_eq!(handle.send_frame(PingFrame::new_ack(3)).err().unwrap(),
+                   HttpError::PeerStalled);
+        // Two frames of 9 + 3 bytes fit, but a third one does not.
+        handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).unwrap();
+        handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).unwrap();
+        assert_eq!(handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).err().unwrap(),
+                   HttpError::PeerStalled);
+
+        queue.pop().unwrap();
+        handle.send_frame(PingFrame::new_ack(3)).unwrap();
+        queue.pop().unwrap();
+        queue.pop().unwrap();
+        queue.pop().unwrap();
+        assert_eq!(queue.len(), 1);
+        handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).unwrap();
+    }
+
+    /// Tests that queueing fails once no frame has been written for longer than the stall
+    /// timeout, while the queue is not empty.
+    #[test]
+    fn test_frame_queue_stall() {
+        // Without any stall timeout, the queue stalls as soon as it is not empty...
+        let (queue, mut handle) = FrameQueue::new(QueueLimits {
+            stall_timeout: Duration::from_millis(0),
+            ..limits()
+        });
+        handle.send_frame(PingFrame::new_ack(1)).unwrap();
+        assert_eq!(handle.send_frame(PingFrame::new_ack(2)).err().unwrap(),
+                   HttpError::PeerStalled);
+
+        // ...while an empty queue never stalls.
+        queue.pop().unwrap();
+        assert!(queue.is_empty());
+        handle.send_frame(PingFrame::new_ack(2)).unwrap();
+    }
+
+    /// Tests that `wait_for_room` blocks until enough frames are popped, failing once none is
+    /// popped for the stall timeout.
+    #[test]
+    fn test_frame_queue_wait_for_room() {
+        let (queue, mut handle) = FrameQueue::new(limits());
+        handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).unwrap();
+        handle.send_frame(DataFrame::with_data(1, &b"abc"[..])).unwrap();
+        handle.wait_for_room(6).unwrap();
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            queue.pop().unwrap();
+            queue
+        });
+        handle.wait_for_room(12).unwrap();
+        let _queue = writer.join().unwrap();
+
+        handle.set_limits(QueueLimits {
+            stall_timeout: Duration::from_millis(0),
+            ..limits()
+        });
+        assert_eq!(handle.wait_for_room(20).err().unwrap(), HttpError::PeerStalled);
+    }
+
+    /// Tests that a frame queued from another thread wakes up a blocked `pop`.
+    #[test]
+    fn test_frame_queue_pop_blocks() {
+        let (queue, mut handle) = FrameQueue::new(limits());
+        let writer = thread::spawn(move || {
+            let frame = queue.pop();
+            (frame, queue.pop())
+        });
+        handle.send_frame(PingFrame::new_ack(1)).unwrap();
+        drop(handle);
+
+        let (frame, end) = writer.join().unwrap();
+        assert_eq!(frame.unwrap(), serialize_frame(&PingFrame::new_ack(1)));
+        assert!(end.is_none());
+    }
+}
@@ -0,0 +1,562 @@
+//! The module contains the high-level representation of HTTP/2 requests, along with a builder
+//! that assembles them from a method and a URI.
+//!
+//! # Example
+//!
+//! ```rust
+//! use solicit_fork::http::{Request, HttpScheme};
+//!
+//! let request = Request::get("http://example.com/search")
+//!                   .query("q", "http/2")
+//!                   .header("accept", "text/html")
+//!                   .build()
+//!                   .unwrap();
+//!
+//! assert_eq!(request.scheme, Some(HttpScheme::Http));
+//! assert_eq!(request.authority, Some(b"example.com".to_vec()));
+//! assert_eq!(request.path, b"/search?q=http%2F2".to_vec());
+//! ```
+
+use std::str;
+
+use http::{Header, HeaderPart, StaticHeader, HttpScheme, HttpError, HttpResult};
+use http::frame::StreamDependency;
+
+/// An enum representing the methods of HTTP requests.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any method not known to the library, given by its (case-sensitive) token.
+    Extension(Vec<u8>),
+}
+
+impl Method {
+    /// Returns the token that represents the method on the wire.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            Method::Get => b"GET",
+            Method::Head => b"HEAD",
+            Method::Post => b"POST",
+            Method::Put => b"PUT",
+            Method::Delete => b"DELETE",
+            Method::Connect => b"CONNECT",
+            Method::Options => b"OPTIONS",
+            Method::Trace => b"TRACE",
+            Method::Patch => b"PATCH",
+            Method::Extension(ref token) => token,
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for Method {
+    fn from(token: &'a [u8]) -> Method {
+        // Method tokens are case-sensitive, so only the exact upper-case tokens are recognized.
+        match token {
+            b"GET" => Method::Get,
+            b"HEAD" => Method::Head,
+            b"POST" => Method::Post,
+            b"PUT" => Method::Put,
+            b"DELETE" => Method::Delete,
+            b"CONNECT" => Method::Connect,
+            b"OPTIONS" => Method::Options,
+            b"TRACE" => Method::Trace,
+            b"PATCH" => Method::Patch,
+            _ => Method::Extension(token.to_vec()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Method {
+    fn from(token: &'a str) -> Method {
+        token.as_bytes().into()
+    }
+}
+
+/// A struct representing the components of a URI that are relevant to an HTTP/2 request, i.e.
+/// the ones that are carried by the `:scheme`, `:authority` and `:path` pseudo-headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uri {
+    /// The scheme, if the URI is absolute.
+    pub scheme: Option<HttpScheme>,
+    /// The authority (host and optional port), if the URI is absolute.
+    pub authority: Option<Vec<u8>>,
+    /// The path, along with the query string, if any.
+    pub path: Vec<u8>,
+}
+
+impl Uri {
+    /// Parses the given URI, which can either be absolute (e.g. `https://example.com/a?b=c`), in
+    /// origin form (e.g. `/a?b=c`), or the asterisk form (`*`) used for server-wide `OPTIONS`
+    /// requests.
+    ///
+    /// The fragment, if any, is dropped, as it is never sent to the server. An absolute URI with
+    /// an empty path gets the path `/`.
+    ///
+    /// # Returns
+    ///
+    /// The parsed `Uri` or `HttpError::InvalidUri` if the URI is not in one of the supported
+    /// forms, has a scheme other than `http` or `https`, an authority without a host or with an
+    /// invalid port, or contains whitespace or control characters.
+    pub fn parse(uri: &str) -> HttpResult<Uri> {
+        if uri.is_empty() || uri.bytes().any(|b| b <= b' ' || b == 0x7f) {
+            return Err(HttpError::InvalidUri);
+        }
+        let uri = match uri.find('#') {
+            Some(pos) => &uri[..pos],
+            None => uri,
+        };
+
+        if uri == "*" || uri.starts_with('/') {
+            return Ok(Uri {
+                scheme: None,
+                authority: None,
+                path: uri.as_bytes().to_vec(),
+            });
+        }
+
+        let pos = try!(uri.find("://").ok_or(HttpError::InvalidUri));
+        let scheme = match &uri[..pos] {
+            s if s.eq_ignore_ascii_case("http") => HttpScheme::Http,
+            s if s.eq_ignore_ascii_case("https") => HttpScheme::Https,
+            _ => return Err(HttpError::InvalidUri),
+        };
+        let rest = &uri[pos + 3..];
+        let end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(end);
+        match split_authority(authority) {
+            Some((host, _)) if !host.is_empty() => {}
+            _ => return Err(HttpError::InvalidUri),
+        }
+        let path = if path.starts_with('/') {
+            path.as_bytes().to_vec()
+        } else {
+            // Either empty or just the query string.
+            let mut full = b"/".to_vec();
+            full.extend(path.as_bytes());
+            full
+        };
+
+        Ok(Uri {
+            scheme: Some(scheme),
+            authority: Some(authority.as_bytes().to_vec()),
+            path: path,
+        })
+    }
+
+    /// Returns the host of the URI's authority, without the brackets of an IPv6 address.
+    ///
+    /// Returns `None` if the URI has no authority.
+    pub fn host(&self) -> Option<&str> {
+        self.split_authority().map(|(host, _)| host)
+    }
+
+    /// Returns the port of the URI's authority or, if it has none, the default port of the URI's
+    /// scheme (`80` for `http` and `443` for `https`).
+    ///
+    /// Returns `None` if the URI has no authority, or neither a port nor a scheme.
+    pub fn port(&self) -> Option<u16> {
+        let port = match self.split_authority() {
+            Some((_, port)) => port,
+            None => return None,
+        };
+        port.or_else(|| {
+            self.scheme.map(|scheme| {
+                match scheme {
+                    HttpScheme::Http => 80,
+                    HttpScheme::Https => 443,
+                }
+            })
+        })
+    }
+
+    /// Internal helper method. Splits the authority into the host and the port, if there is one.
+    fn split_authority(&self) -> Option<(&str, Option<u16>)> {
+        match self.authority {
+            Some(ref authority) => str::from_utf8(authority).ok().and_then(split_authority),
+            None => None,
+        }
+    }
+}
+
+/// Splits the given authority into the host, without the brackets of an IPv6 address, and the
+/// port, if there is one. Returns `None` if the port is not a number.
+fn split_authority(authority: &str) -> Option<(&str, Option<u16>)> {
+    fn trim(host: &str) -> &str {
+        host.trim_matches(|c| c == '[' || c == ']')
+    }
+    // The port follows the last colon, unless that colon is within an IPv6 address.
+    match authority.rfind(':') {
+        Some(pos) if !authority[pos..].contains(']') => {
+            let host = trim(&authority[..pos]);
+            match &authority[pos + 1..] {
+                "" => Some((host, None)),
+                port => port.parse().ok().map(|port| (host, Some(port))),
+            }
+        }
+        _ => Some((trim(authority), None)),
+    }
+}
+
+/// A struct representing a full HTTP/2 request, along with the full body, as a
+/// sequence of bytes.
+///
+/// The `scheme` and `authority` are optional, since a client can fill them in from the
+/// connection that the request is sent on.
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// The method of the request
+    pub method: Method,
+    /// The scheme of the request, if it should differ from the connection's
+    pub scheme: Option<HttpScheme>,
+    /// The authority of the request, if it should differ from the connection's
+    pub authority: Option<Vec<u8>>,
+    /// The path being requested, along with the query string. Empty for `CONNECT` requests.
+    pub path: Vec<u8>,
+    /// The regular headers of the request. Does *not* include the pseudo-headers.
+    pub headers: Vec<StaticHeader>,
+    /// The body of the request, if any.
+    pub body: Option<Vec<u8>>,
+    /// The priority of the request's stream, if it should differ from the default one.
+    pub priority: Option<StreamDependency>,
+}
+
+impl Request {
+    /// Creates a new `Request` with the given method and path, without any headers or body.
+    pub fn new<P: Into<Vec<u8>>>(method: Method, path: P) -> Request {
+        Request {
+            method: method,
+            scheme: None,
+            authority: None,
+            path: path.into(),
+            headers: Vec::new(),
+            body: None,
+            priority: None,
+        }
+    }
+
+    /// Starts building a request with the given method to the given URI.
+    ///
+    /// For `CONNECT` requests, the URI is the authority (`host:port`) of the tunnel's target.
+    pub fn builder(method: Method, uri: &str) -> RequestBuilder {
+        RequestBuilder {
+            method: method,
+            uri: uri.into(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+            priority: None,
+        }
+    }
+
+    /// Starts building a GET request to the given URI.
+    pub fn get(uri: &str) -> RequestBuilder {
+        Request::builder(Method::Get, uri)
+    }
+
+    /// Starts building a POST request to the given URI.
+    pub fn post(uri: &str) -> RequestBuilder {
+        Request::builder(Method::Post, uri)
+    }
+
+    /// Returns the full header list of the request: the pseudo-headers, followed by the regular
+    /// headers. The given scheme and authority are used unless the request specifies its own.
+    ///
+    /// As required for `CONNECT` requests, those only include the `:method` and `:authority`
+    /// pseudo-headers.
+    pub fn header_list(&self, scheme: HttpScheme, authority: &[u8]) -> Vec<StaticHeader> {
+        let authority = self.authority.as_ref().map_or(authority, |authority| authority);
+        let mut headers = vec![Header::new(b":method", self.method.as_bytes().to_vec())];
+        if self.method != Method::Connect {
+            let scheme = self.scheme.unwrap_or(scheme);
+            headers.push(Header::new(b":path", self.path.clone()));
+            headers.push(Header::new(b":authority", authority.to_vec()));
+            headers.push(Header::new(b":scheme", scheme.as_bytes()));
+        } else {
+            headers.push(Header::new(b":authority", authority.to_vec()));
+        }
+        headers.extend(self.headers.iter().cloned());
+        headers
+    }
+}
+
+/// A builder of `Request`s.
+///
+/// It is obtained from `Request::builder` (or one of the per-method shortcuts, such as
+/// `Request::get`) and collects the query parameters, headers and body, until the `build` method
+/// parses the URI and assembles the final `Request`.
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    method: Method,
+    uri: String,
+    query: Vec<(String, String)>,
+    headers: Vec<StaticHeader>,
+    body: Option<Vec<u8>>,
+    priority: Option<StreamDependency>,
+}
+
+impl RequestBuilder {
+    /// Appends the given parameter to the query string of the URI. Both the name and the value are
+    /// percent-encoded.
+    pub fn query(mut self, name: &str, value: &str) -> RequestBuilder {
+        self.query.push((name.into(), value.into()));
+        self
+    }
+
+    /// Adds a header to the request. Since HTTP/2 requires header names to be lower-case, the name
+    /// is converted to lower-case.
+    pub fn header<N, V>(mut self, name: N, value: V) -> RequestBuilder
+        where N: Into<HeaderPart<'static>>,
+              V: Into<HeaderPart<'static>>
+    {
+        let name = name.into().0.to_ascii_lowercase();
+        self.headers.push(Header::new(name, value));
+        self
+    }
+
+    /// Sets the `content-type` header of the request.
+    pub fn content_type<V: Into<HeaderPart<'static>>>(self, value: V) -> RequestBuilder {
+        self.header(b"content-type", value)
+    }
+
+    /// Sets the `accept` header of the request.
+    pub fn accept<V: Into<HeaderPart<'static>>>(self, value: V) -> RequestBuilder {
+        self.header(b"accept", value)
+    }
+
+    /// Sets the `user-agent` header of the request.
+    pub fn user_agent<V: Into<HeaderPart<'static>>>(self, value: V) -> RequestBuilder {
+        self.header(b"user-agent", value)
+    }
+
+    /// Sets the body of the request.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> RequestBuilder {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sets the priority of the request's stream: the stream that it depends on, its weight and
+    /// whether the dependency is exclusive.
+    pub fn priority(mut self, priority: StreamDependency) -> RequestBuilder {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Parses the URI and assembles the `Request`.
+    ///
+    /// # Returns
+    ///
+    /// The `Request` or `HttpError::InvalidUri` if the URI cannot be parsed (see `Uri::parse`).
+    pub fn build(self) -> HttpResult<Request> {
+        let uri = if self.method == Method::Connect {
+            try!(RequestBuilder::parse_authority(&self.uri))
+        } else {
+            try!(Uri::parse(&self.uri))
+        };
+
+        let mut path = uri.path;
+        for (name, value) in self.query {
+            path.push(if path.contains(&b'?') { b'&' } else { b'?' });
+            percent_encode(name.as_bytes(), &mut path);
+            path.push(b'=');
+            percent_encode(value.as_bytes(), &mut path);
+        }
+
+        Ok(Request {
+            method: self.method,
+            scheme: uri.scheme,
+            authority: uri.authority,
+            path: path,
+            headers: self.headers,
+            body: self.body,
+            priority: self.priority,
+        })
+    }
+
+    /// Internal helper method. Parses the target of a `CONNECT` request, which needs to be a bare
+    /// authority, including the port.
+    fn parse_authority(uri: &str) -> HttpResult<Uri> {
+        let valid = match uri.rfind(':') {
+            Some(pos) => pos > 0 && pos + 1 < uri.len() && uri[pos + 1..].parse::<u16>().is_ok(),
+            None => false,
+        };
+        if !valid || uri.bytes().any(|b| b <= b' ' || b == 0x7f || b == b'/' || b == b'?') {
+            return Err(HttpError::InvalidUri);
+        }
+        Ok(Uri {
+            scheme: None,
+            authority: Some(uri.as_bytes().to_vec()),
+            path: Vec::new(),
+        })
+    }
+}
+
+/// Appends the percent-encoding of the given bytes to the buffer. Only the unreserved characters
+/// (RFC 3986, Section 2.3) are left as-is.
+fn percent_encode(bytes: &[u8], buf: &mut Vec<u8>) {
+    const HEX: &'static [u8] = b"0123456789ABCDEF";
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_' || b == b'~' {
+            buf.push(b);
+        } else {
+            buf.push(b'%');
+            buf.push(HEX[(b >> 4) as usize]);
+            buf.push(HEX[(b & 0xf) as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Method, Uri, Request};
+    use http::{Header, HttpScheme, HttpError};
+    use http::frame::StreamDependency;
+
+    /// Tests that URIs in all supported forms are split into their components.
+    #[test]
+    fn test_parse_uri() {
+        let uri = Uri::parse("https://example.com:8443/a/b?c=d#frag").unwrap();
+        assert_eq!(uri.scheme, Some(HttpScheme::Https));
+        assert_eq!(uri.authority, Some(b"example.com:8443".to_vec()));
+        assert_eq!(uri.path, b"/a/b?c=d".to_vec());
+
+        let uri = Uri::parse("HTTP://example.com").unwrap();
+        assert_eq!(uri.scheme, Some(HttpScheme::Http));
+        assert_eq!(uri.path, b"/".to_vec());
+
+        let uri = Uri::parse("http://example.com?x=y").unwrap();
+        assert_eq!(uri.path, b"/?x=y".to_vec());
+
+        let uri = Uri::parse("/index.html").unwrap();
+        assert_eq!(uri.scheme, None);
+        assert_eq!(uri.authority, None);
+        assert_eq!(uri.path, b"/index.html".to_vec());
+
+        assert_eq!(Uri::parse("*").unwrap().path, b"*".to_vec());
+    }
+
+    /// Tests that the host and the port are taken from the authority, with the port defaulting
+    /// to the one of the scheme.
+    #[test]
+    fn test_uri_host_and_port() {
+        let uri = Uri::parse("https://example.com:8443/a").unwrap();
+        assert_eq!(uri.host(), Some("example.com"));
+        assert_eq!(uri.port(), Some(8443));
+
+        let uri = Uri::parse("https://example.com/a").unwrap();
+        assert_eq!(uri.port(), Some(443));
+        let uri = Uri::parse("http://example.com:/a").unwrap();
+        assert_eq!(uri.port(), Some(80));
+
+        let uri = Uri::parse("http://[::1]:8080/").unwrap();
+        assert_eq!(uri.host(), Some("::1"));
+        assert_eq!(uri.port(), Some(8080));
+        let uri = Uri::parse("http://[::1]/").unwrap();
+        assert_eq!(uri.host(), Some("::1"));
+        assert_eq!(uri.port(), Some(80));
+
+        let uri = Uri::parse("/index.html").unwrap();
+        assert_eq!(uri.host(), None);
+        assert_eq!(uri.port(), None);
+
+        let request = Request::builder(Method::Connect, "example.com:443").build().unwrap();
+        let uri = Uri {
+            scheme: None,
+            authority: request.authority,
+            path: Vec::new(),
+        };
+        assert_eq!(uri.host(), Some("example.com"));
+        assert_eq!(uri.port(), Some(443));
+    }
+
+    /// Tests that malformed URIs are rejected.
+    #[test]
+    fn test_parse_invalid_uri() {
+        for uri in &["",
+                     "example.com/a",
+                     "ftp://example.com/",
+                     "http:///a",
+                     "http://:80/a",
+                     "http://example.com:x/",
+                     "http://example.com:65536/",
+                     "/a b",
+                     "/a\r\n"] {
+            assert_eq!(Uri::parse(uri).err().unwrap(), HttpError::InvalidUri);
+        }
+    }
+
+    /// Tests that methods are converted from and into their tokens.
+    #[test]
+    fn test_method_tokens() {
+        assert_eq!(Method::from("GET"), Method::Get);
+        assert_eq!(Method::from(&b"PATCH"[..]), Method::Patch);
+        assert_eq!(Method::from("get"), Method::Extension(b"get".to_vec()));
+        assert_eq!(Method::Extension(b"PURGE".to_vec()).as_bytes(), b"PURGE");
+        assert_eq!(Method::Delete.as_bytes(), b"DELETE");
+    }
+
+    /// Tests that the builder assembles the full request.
+    #[test]
+    fn test_build_request() {
+        let request = Request::post("/upload?a=1")
+                          .query("name", "a b&c")
+                          .header("X-Custom", "yes")
+                          .content_type("text/plain")
+                          .body("hello")
+                          .priority(StreamDependency::new(0, 63, true))
+                          .build()
+                          .unwrap();
+
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, b"/upload?a=1&name=a%20b%26c".to_vec());
+        assert_eq!(request.headers,
+                   vec![Header::new(b"x-custom", b"yes"),
+                        Header::new(b"content-type", b"text/plain")]);
+        assert_eq!(request.body, Some(b"hello".to_vec()));
+        assert_eq!(request.priority, Some(StreamDependency::new(0, 63, true)));
+
+        let request = Request::get("/").query("q", "~ok").build().unwrap();
+        assert_eq!(request.path, b"/?q=~ok".to_vec());
+        assert_eq!(request.priority, None);
+    }
+
+    /// Tests that the header list starts with the pseudo-headers, filled in from the defaults
+    /// unless the request specifies its own.
+    #[test]
+    fn test_header_list() {
+        let request = Request::get("/").header("accept", "*/*").build().unwrap();
+        assert_eq!(request.header_list(HttpScheme::Http, b"localhost"),
+                   vec![Header::new(b":method", b"GET"),
+                        Header::new(b":path", b"/"),
+                        Header::new(b":authority", b"localhost"),
+                        Header::new(b":scheme", b"http"),
+                        Header::new(b"accept", b"*/*")]);
+
+        let request = Request::get("https://example.com/").build().unwrap();
+        assert_eq!(request.header_list(HttpScheme::Http, b"localhost"),
+                   vec![Header::new(b":method", b"GET"),
+                        Header::new(b":path", b"/"),
+                        Header::new(b":authority", b"example.com"),
+                        Header::new(b":scheme", b"https")]);
+    }
+
+    /// Tests that CONNECT requests target an authority and omit the `:scheme` and `:path`.
+    #[test]
+    fn test_connect_request() {
+        let request = Request::builder(Method::Connect, "example.com:443").build().unwrap();
+        assert_eq!(request.header_list(HttpScheme::Https, b"proxy"),
+                   vec![Header::new(b":method", b"CONNECT"),
+                        Header::new(b":authority", b"example.com:443")]);
+
+        for uri in &["example.com", "http://example.com:443/", ":443", "example.com:"] {
+            assert!(Request::builder(Method::Connect, uri).build().is_err());
+        }
+    }
+}
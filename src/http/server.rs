@@ -1,12 +1,19 @@
 //! The module contains a number of reusable components for implementing the server side of an
 //! HTTP/2 connection.
 
-use http::{StreamId, Header, HttpResult, HttpScheme, ErrorCode};
-use http::frame::{HttpSetting, PingFrame};
+use http::{StreamId, Header, HttpError, HttpResult, HttpScheme, ErrorCode};
+use http::frame::{HttpSetting, PingFrame, FrameIR, RawFrame, ExtensionFrame, AltSvcFrame,
+                  OriginFrame};
 use http::connection::{SendFrame, ReceiveFrame, HttpConnection, EndStream, SendStatus};
 use http::session::{Session, SessionState, Stream, DefaultStream, DefaultSessionState};
 use http::session::Server as ServerMarker;
 use http::priority::SimplePrioritizer;
+use http::padding::PaddingPolicy;
+use http::compression::IndexingStrategy;
+use http::flood::{Flood, FloodLimits};
+use http::stats::ConnectionStats;
+use http::observer::FrameObserver;
+use http::validation::{validate_headers, HeaderBlockKind, MalformedHeaders, ContentLengthError};
 
 /// The `ServerSession` requires an instance of a type that implements this trait in order to
 /// create a new `Stream` instance once it detects that a client has initiated a new stream. The
@@ -17,6 +24,45 @@ pub trait StreamFactory {
     type Stream: Stream;
     /// Create a new `Stream` with the given ID.
     fn create(&mut self, id: StreamId) -> Self::Stream;
+
+    /// Invoked when the client opens a stream with a CONNECT request, asking for a tunnel to the
+    /// given authority (`host:port`) to be established over it (Section 8.3). The `stream` is the
+    /// one that was just created for the request.
+    ///
+    /// If the method returns `true`, the server accepts the tunnel with a `200` response, after
+    /// which any data on the stream is the tunnel's; hence, the stream is usually a
+    /// `DuplexStream`. Otherwise, the tunnel could not be established and the stream is reset
+    /// with `CONNECT_ERROR`.
+    ///
+    /// The default implementation refuses all tunnels.
+    fn accept_connect(&mut self,
+                      _id: StreamId,
+                      _authority: &[u8],
+                      _stream: &mut Self::Stream)
+                      -> bool {
+        false
+    }
+}
+
+/// Returns the authority that a CONNECT request asks for a tunnel to, or `None` if the given
+/// request headers are not those of a CONNECT request. Extended CONNECT requests do not ask for a
+/// tunnel, so `None` is returned for them as well.
+fn connect_authority<'a>(headers: &'a [Header]) -> Option<&'a [u8]> {
+    let mut method = None;
+    let mut authority = None;
+    for header in headers {
+        match header.name() {
+            b":method" => method = Some(header.value()),
+            b":authority" => authority = Some(header.value()),
+            b":protocol" => return None,
+            _ => {}
+        }
+    }
+    if method == Some(b"CONNECT") {
+        authority
+    } else {
+        None
+    }
 }
 
 /// An implementation of the `Session` trait for a server-side HTTP/2 connection.
@@ -56,39 +102,73 @@ impl<'a, State, F, S> Session for ServerSession<'a, State, F, S>
     fn new_data_chunk(&mut self,
                       stream_id: StreamId,
                       data: &[u8],
-                      _: &mut HttpConnection)
+                      conn: &mut HttpConnection)
                       -> HttpResult<()> {
         debug!("Data chunk for stream {}", stream_id);
-        let mut stream = match self.state.get_stream_mut(stream_id) {
-            None => {
-                debug!("Received a frame for an unknown stream!");
-                return Ok(());
+        match self.state.get_stream_mut(stream_id) {
+            None => debug!("Received a frame for an unknown stream!"),
+            // Now let the stream handle the data chunk
+            Some(stream) => {
+                stream.new_data_chunk(data);
+                if stream.defers_window_updates() {
+                    conn.hold_window(stream_id, data.len() as u32);
+                }
             }
-            Some(stream) => stream,
         };
-        // Now let the stream handle the data chunk
-        stream.new_data_chunk(data);
-        Ok(())
+        conn.sender(self.sender).send_window_updates()
     }
 
     fn new_headers<'n, 'v>(&mut self,
                            stream_id: StreamId,
                            headers: Vec<Header<'n, 'v>>,
-                           _conn: &mut HttpConnection)
+                           conn: &mut HttpConnection)
                            -> HttpResult<()> {
         debug!("Headers for stream {}", stream_id);
+        // Headers on a known stream correspond to having received trailers...
+        let kind = match self.state.get_stream_ref(stream_id) {
+            Some(_) => HeaderBlockKind::Trailers,
+            None => HeaderBlockKind::Request,
+        };
+        let mut valid = validate_headers(&headers, kind);
+        if valid.is_ok() && !conn.connect_protocol_enabled &&
+           headers.iter().any(|header| header.name() == b":protocol") {
+            // The client may only use extended CONNECT once the server has enabled it.
+            valid = Err(MalformedHeaders::UnexpectedProtocol);
+        }
+        if let Err(err) = valid {
+            // A malformed request is a stream error of type PROTOCOL_ERROR, so the stream is
+            // reset and dropped without the request ever being handled.
+            debug!("Stream {}: {}", stream_id, err);
+            try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::ProtocolError));
+            self.state.remove_stream(stream_id);
+            return Ok(());
+        }
         if let Some(stream) = self.state.get_stream_mut(stream_id) {
-            // This'd correspond to having received trailers...
             stream.set_headers(headers);
             return Ok(());
         };
         // New stream initiated by the client
         let mut stream = self.factory.create(stream_id);
+        let tunnel = connect_authority(&headers)
+            .map(|authority| self.factory.accept_connect(stream_id, authority, &mut stream));
         stream.set_headers(headers);
         // TODO(mlalic): Once the `Session` trait is able to signal connection failure, handle
         //               the error case here and return the corresponding protocol error.
         let _ = self.state.insert_incoming(stream_id, stream);
-        Ok(())
+        match tunnel {
+            Some(true) => {
+                debug!("Accepted a tunnel on stream {}", stream_id);
+                let headers = vec![Header::new(b":status", b"200")];
+                conn.sender(self.sender).send_headers(headers, stream_id, EndStream::No)
+            }
+            Some(false) => {
+                debug!("Refused a tunnel on stream {}", stream_id);
+                try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::ConnectError));
+                self.state.remove_stream(stream_id);
+                Ok(())
+            }
+            None => Ok(()),
+        }
     }
 
     fn end_of_stream(&mut self, stream_id: StreamId, _: &mut HttpConnection) -> HttpResult<()> {
@@ -104,13 +184,53 @@ impl<'a, State, F, S> Session for ServerSession<'a, State, F, S>
         Ok(())
     }
 
+    fn content_length_mismatch(&mut self,
+                               stream_id: StreamId,
+                               err: ContentLengthError,
+                               conn: &mut HttpConnection)
+                               -> HttpResult<()> {
+        // A request with a malformed body is reset and dropped before it is ever handled.
+        debug!("Stream {}: {}", stream_id, err);
+        try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::ProtocolError));
+        self.state.remove_stream(stream_id);
+        Ok(())
+    }
+
+    fn header_list_too_large(&mut self,
+                             stream_id: StreamId,
+                             conn: &mut HttpConnection)
+                             -> HttpResult<()> {
+        if self.state.get_stream_ref(stream_id).is_some() {
+            // Oversized trailers can no longer be answered with a response of their own.
+            try!(conn.sender(self.sender).rst_stream(stream_id, ErrorCode::Cancel));
+            self.state.remove_stream(stream_id);
+            return Ok(());
+        }
+        // A request whose headers are too large is refused with a 431 response, without the
+        // stream ever being created.
+        let headers = vec![Header::new(b":status", b"431")];
+        conn.sender(self.sender).send_headers(headers, stream_id, EndStream::Yes)
+    }
+
     fn rst_stream(&mut self,
                   stream_id: StreamId,
                   error_code: ErrorCode,
-                  _: &mut HttpConnection)
+                  conn: &mut HttpConnection)
                   -> HttpResult<()> {
         debug!("RST_STREAM id={:?}, error={:?}", stream_id, error_code);
-        self.state.get_stream_mut(stream_id).map(|stream| stream.on_rst_stream(error_code));
+        let in_progress = match self.state.get_stream_mut(stream_id) {
+            Some(stream) => {
+                let in_progress = !stream.is_closed_local();
+                stream.on_rst_stream(error_code);
+                in_progress
+            }
+            None => false,
+        };
+        if in_progress {
+            // The client opened the stream and reset it before it got the whole response, which
+            // costs the server far more than it costs the client.
+            try!(conn.flood_guard.check(Flood::RapidReset));
+        }
         Ok(())
     }
 
@@ -175,6 +295,131 @@ impl<F, State> ServerConnection<F, State>
         self.conn.scheme
     }
 
+    /// Sets the policy that decides how the frames sent on the connection are padded.
+    #[inline]
+    pub fn set_padding_policy(&mut self, policy: PaddingPolicy) {
+        self.conn.padding_policy = policy;
+    }
+
+    /// Sets the strategy with which the HPACK encoder indexes the headers with the given name
+    /// (e.g. `IndexingStrategy::NeverIndexed` for headers that carry secrets).
+    #[inline]
+    pub fn set_indexing_strategy<N: Into<Vec<u8>>>(&mut self, name: N, strategy: IndexingStrategy) {
+        self.conn.encoder.set_strategy(name, strategy);
+    }
+
+    /// Advertises the given maximum size of the HPACK dynamic table that the connection's decoder
+    /// uses, by sending a SETTINGS frame. The decoder adopts the new size once the client
+    /// acknowledges the settings.
+    pub fn set_header_table_size<S: SendFrame>(&mut self,
+                                               size: u32,
+                                               sender: &mut S)
+                                               -> HttpResult<()> {
+        self.conn.sender(sender).send_settings(vec![HttpSetting::HeaderTableSize(size)])
+    }
+
+    /// Advertises the given maximum size of the header lists that the connection accepts, by
+    /// sending a SETTINGS frame. Larger header lists that the client sends are discarded from then
+    /// on.
+    pub fn set_max_header_list_size<S: SendFrame>(&mut self,
+                                                  size: u32,
+                                                  sender: &mut S)
+                                                  -> HttpResult<()> {
+        self.conn.sender(sender).send_settings(vec![HttpSetting::MaxHeaderListSize(size)])
+    }
+
+    /// Sets the limits on the rate at which the client may send RST_STREAM, PING, SETTINGS and
+    /// empty DATA frames, as well as reset the streams that it opens. A client that goes over any
+    /// of them is sent a GOAWAY frame with the `ENHANCE_YOUR_CALM` error code.
+    #[inline]
+    pub fn set_flood_limits(&mut self, limits: FloodLimits) {
+        self.conn.flood_guard.set_limits(limits);
+    }
+
+    /// Attaches the given observer to the connection, replacing the previous one, if any. It is
+    /// shown every frame that is sent to and received from the client, along with the headers.
+    pub fn set_frame_observer<O: FrameObserver + 'static>(&mut self, observer: O) {
+        self.conn.observer = Some(Box::new(observer));
+    }
+
+    /// Returns a snapshot of the connection's statistics, including the flow control windows of
+    /// all the streams that the session state tracks.
+    pub fn stats(&mut self) -> ConnectionStats {
+        let mut stats = self.conn.stats();
+        let mut stream_ids: Vec<StreamId> = self.state.iter().map(|(&id, _)| id).collect();
+        stream_ids.sort();
+        stats.streams = stream_ids.iter().map(|&id| self.conn.stream_stats(id)).collect();
+        stats.active_streams = stream_ids.len();
+        stats
+    }
+
+    /// Registers the given parser for the frames of the given extension frame type. Parsed frames
+    /// are passed to the session's `on_extension_frame` callback.
+    pub fn register_extension<P>(&mut self, frame_type: u8, parser: P)
+        where P: Fn(&RawFrame) -> Option<Box<ExtensionFrame>> + Send + 'static
+    {
+        self.conn.extensions.register(frame_type, parser);
+    }
+
+    /// Sends the given frame of an extension frame type.
+    pub fn send_extension_frame<Ext: FrameIR, S: SendFrame>(&mut self,
+                                                             frame: Ext,
+                                                             sender: &mut S)
+                                                             -> HttpResult<()> {
+        self.conn.sender(sender).send_extension_frame(frame)
+    }
+
+    /// Advertises the given alternative services (in the format of the `Alt-Svc` header field)
+    /// by sending an `ALTSVC` frame (RFC 7838).
+    ///
+    /// On stream `0`, the services apply to the given origin. On any other stream, they apply to
+    /// the origin of the stream's request and the `origin` must be empty.
+    pub fn send_altsvc<S: SendFrame>(&mut self,
+                                     stream_id: StreamId,
+                                     origin: &[u8],
+                                     field_value: &[u8],
+                                     sender: &mut S)
+                                     -> HttpResult<()> {
+        let frame = AltSvcFrame::new(stream_id, origin, field_value);
+        self.conn.sender(sender).send_extension_frame(frame)
+    }
+
+    /// Advertises the origins for which the server is authoritative by sending an `ORIGIN` frame
+    /// (RFC 8336). Each origin is the ASCII serialization of an origin, such as
+    /// `https://example.com`.
+    pub fn send_origin<S: SendFrame>(&mut self,
+                                     origins: &[&[u8]],
+                                     sender: &mut S)
+                                     -> HttpResult<()> {
+        let frame = OriginFrame::new(origins.to_vec());
+        self.conn.sender(sender).send_extension_frame(frame)
+    }
+
+    /// Enables extended CONNECT (RFC 8441) by sending the `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    /// setting, after which the client may open streams with extended CONNECT requests. Before
+    /// that, requests carrying the `:protocol` pseudo-header are rejected as malformed.
+    ///
+    /// Such streams carry data in both directions until either side closes them, so the streams
+    /// created by the `StreamFactory` would usually be `DuplexStream`s.
+    pub fn enable_connect_protocol<S: SendFrame>(&mut self, sender: &mut S) -> HttpResult<()> {
+        self.conn.sender(sender).send_settings(vec![HttpSetting::EnableConnectProtocol(1)])
+    }
+
+    /// Signals that the tunnel established over the stream with the given ID has failed (e.g.
+    /// the connection to its target was reset or closed with an error), by resetting the stream
+    /// with `CONNECT_ERROR` (Section 8.3) and removing it from the session state.
+    ///
+    /// # Returns
+    ///
+    /// The `Stream` instance that was removed from the session state, if the stream was known.
+    pub fn fail_tunnel<S: SendFrame>(&mut self,
+                                     stream_id: StreamId,
+                                     sender: &mut S)
+                                     -> HttpResult<Option<State::Stream>> {
+        try!(self.conn.sender(sender).rst_stream(stream_id, ErrorCode::ConnectError));
+        Ok(self.state.remove_stream(stream_id))
+    }
+
     /// Send the current settings associated to the `ServerConnection` to the client.
     pub fn send_settings<S: SendFrame>(&mut self, sender: &mut S) -> HttpResult<()> {
         // TODO: `HttpConnection` should provide a better API for sending settings.
@@ -198,8 +443,21 @@ impl<F, State> ServerConnection<F, State>
                                                                     rx: &mut Recv,
                                                                     tx: &mut Sender)
                                                                     -> HttpResult<()> {
-        let mut session = ServerSession::new(&mut self.state, &mut self.factory, tx);
-        self.conn.handle_next_frame(rx, &mut session)
+        let res = {
+            let mut session = ServerSession::new(&mut self.state, &mut self.factory, tx);
+            self.conn.handle_next_frame(rx, &mut session)
+        };
+        try!(self.conn.sender(tx).send_pending_resets());
+        let error_code = match res {
+            Err(HttpError::Flood(_)) => Some(ErrorCode::EnhanceYourCalm),
+            Err(HttpError::WindowSizeOverflow) => Some(ErrorCode::FlowControlError),
+            _ => None,
+        };
+        if let Some(error_code) = error_code {
+            let last_stream_id = self.conn.last_received_stream_id();
+            try!(self.conn.sender(tx).send_goaway(last_stream_id, error_code));
+        }
+        res
     }
 
     /// Starts a response on the stream with the given ID by sending the given headers.
@@ -227,7 +485,16 @@ impl<F, State> ServerConnection<F, State>
         const MAX_CHUNK_SIZE: usize = 8 * 1024;
         let mut buf = [0; MAX_CHUNK_SIZE];
 
-        // TODO: Additionally account for the flow control windows.
+        // Give back the window of the data that the streams have consumed in the meantime, and
+        // let the streams know how much data they may send.
+        for (&stream_id, stream) in self.state.iter() {
+            stream.set_out_window(self.conn.stream_out_window(stream_id));
+            if stream.defers_window_updates() {
+                self.conn.release_window(stream_id, stream.take_consumed());
+            }
+        }
+        try!(self.conn.sender(sender).send_window_updates());
+
         let mut prioritizer = SimplePrioritizer::new(&mut self.state, &mut buf);
 
         self.conn.sender(sender).send_next_data(&mut prioritizer)
@@ -236,13 +503,20 @@ impl<F, State> ServerConnection<F, State>
 
 #[cfg(test)]
 mod tests {
-    use super::ServerSession;
+    use super::{ServerSession, ServerConnection, StreamFactory};
 
-    use http::tests::common::{TestStream, TestStreamFactory, build_mock_http_conn, MockSendFrame};
+    use http::tests::common::{TestStream, TestStreamFactory, build_mock_http_conn, MockSendFrame,
+                              MockReceiveFrame, build_headers_frame};
 
-    use http::{Header, ErrorCode, HttpError};
+    use http::{Header, ErrorCode, HttpError, StreamId};
     use http::session::{DefaultSessionState, SessionState, Stream, Session};
     use http::session::Server as ServerMarker;
+    use http::connection::HttpFrame;
+    use http::frame::{Frame, AltSvcFrame, OriginFrame, HttpSetting, RstStreamFrame};
+    use hpack;
+    use http::validation::ContentLengthError;
+    use http::flood::{Flood, FloodLimits};
+    use std::time::Duration;
 
     /// Tests that the `ServerSession` correctly manages the stream state.
     #[test]
@@ -252,7 +526,9 @@ mod tests {
         let mut sender = MockSendFrame::new();
 
         // Receiving new headers results in a new stream being created
-        let headers = vec![Header::new(b":method".to_vec(), b"GET".to_vec())];
+        let headers = vec![Header::new(b":method".to_vec(), b"GET".to_vec()),
+                           Header::new(b":scheme".to_vec(), b"http".to_vec()),
+                           Header::new(b":path".to_vec(), b"/".to_vec())];
         {
             let mut factory = TestStreamFactory;
             let mut session = ServerSession::new(&mut state, &mut factory, &mut sender);
@@ -300,6 +576,97 @@ mod tests {
         assert!(!state.get_stream_ref(3).unwrap().is_closed_remote());
     }
 
+    /// Tests that the `ServerSession` resets a stream on which a malformed request is received,
+    /// without ever starting to track it.
+    #[test]
+    fn test_server_session_malformed_request() {
+        let mut state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        {
+            let mut factory = TestStreamFactory;
+            let mut session = ServerSession::new(&mut state, &mut factory, &mut sender);
+            // The :scheme and :path pseudo-headers are missing.
+            let headers = vec![Header::new(b":method", b"GET")];
+            session.new_headers(1, headers, &mut conn).unwrap();
+        }
+
+        assert!(state.get_stream_ref(1).is_none());
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that the `ServerSession` resets and drops a request whose body does not match its
+    /// declared `content-length`, so that it is never handled.
+    #[test]
+    fn test_server_session_content_length_mismatch() {
+        let mut state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        state.insert_incoming(1, TestStream::new()).unwrap();
+        {
+            let mut factory = TestStreamFactory;
+            let mut session = ServerSession::new(&mut state, &mut factory, &mut sender);
+            let err = ContentLengthError {
+                declared: 5,
+                received: 6,
+            };
+            session.content_length_mismatch(1, err, &mut conn).unwrap();
+        }
+
+        assert!(state.get_stream_ref(1).is_none());
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
+    /// Tests that the `ServerSession` answers a request whose header list is too large with a 431
+    /// response, without creating its stream, and resets a stream whose trailers are too large.
+    #[test]
+    fn test_server_session_header_list_too_large() {
+        let mut state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = build_mock_http_conn();
+        let mut sender = MockSendFrame::new();
+        state.insert_incoming(3, TestStream::new()).unwrap();
+        {
+            let mut factory = TestStreamFactory;
+            let mut session = ServerSession::new(&mut state, &mut factory, &mut sender);
+            session.header_list_too_large(1, &mut conn).unwrap();
+            session.header_list_too_large(3, &mut conn).unwrap();
+        }
+
+        assert!(state.get_stream_ref(1).is_none());
+        assert!(state.get_stream_ref(3).is_none());
+        assert_eq!(sender.sent.len(), 2);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert!(frame.is_end_of_stream());
+                let headers = hpack::Decoder::new().decode(frame.header_fragment()).unwrap();
+                assert_eq!(headers, vec![(b":status".to_vec(), b"431".to_vec())]);
+            }
+            _ => panic!("Expected a HEADERS frame"),
+        };
+        match HttpFrame::from_raw(&sender.sent[1]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 3);
+                assert_eq!(frame.error_code(), ErrorCode::Cancel);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
+
     #[test]
     fn test_server_session_rst_stream() {
         let mut state = DefaultSessionState::<ServerMarker, TestStream>::new();
@@ -341,4 +708,178 @@ mod tests {
             panic!("Expected a PeerConnectionError");
         }
     }
+
+    /// Tests that the `ServerConnection` sends the ALTSVC and ORIGIN frames it is asked to.
+    #[test]
+    fn test_server_conn_send_altsvc_and_origin() {
+        let state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = ServerConnection::with_connection(build_mock_http_conn(),
+                                                         state,
+                                                         TestStreamFactory);
+        let mut sender = MockSendFrame::new();
+
+        conn.send_altsvc(1, b"", b"clear", &mut sender).unwrap();
+        conn.send_origin(&[b"https://a.com", b"https://b.com"], &mut sender).unwrap();
+
+        assert_eq!(sender.sent.len(), 2);
+        assert_eq!(HttpFrame::from_raw(&sender.sent[0]).unwrap(),
+                   HttpFrame::AltSvcFrame(AltSvcFrame::new(1, "", "clear")));
+        assert_eq!(HttpFrame::from_raw(&sender.sent[1]).unwrap(),
+                   HttpFrame::OriginFrame(OriginFrame::new(vec!["https://a.com",
+                                                                "https://b.com"])));
+    }
+
+    /// Tests that the `ServerConnection` only accepts extended CONNECT requests once it has
+    /// enabled them.
+    #[test]
+    fn test_server_conn_extended_connect() {
+        let request: Vec<(&[u8], &[u8])> = vec![(b":method", b"CONNECT"),
+                                                (b":protocol", b"websocket"),
+                                                (b":scheme", b"https"),
+                                                (b":path", b"/chat"),
+                                                (b":authority", b"example.com")];
+        let fragment = hpack::Encoder::new().encode(request);
+        let frames = vec![
+            HttpFrame::HeadersFrame(build_headers_frame(fragment.clone(), 1)),
+            HttpFrame::HeadersFrame(build_headers_frame(fragment, 3)),
+        ];
+        let state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = ServerConnection::with_connection(build_mock_http_conn(),
+                                                         state,
+                                                         TestStreamFactory);
+        let mut receiver = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        assert!(conn.state.get_stream_ref(1).is_none());
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ProtocolError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+
+        conn.enable_connect_protocol(&mut sender).unwrap();
+        match HttpFrame::from_raw(&sender.sent[1]).unwrap() {
+            HttpFrame::SettingsFrame(frame) => {
+                assert_eq!(frame.settings, vec![HttpSetting::EnableConnectProtocol(1)]);
+            }
+            _ => panic!("Expected a SETTINGS frame"),
+        };
+
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        assert!(conn.state.get_stream_ref(3).is_some());
+        assert_eq!(sender.sent.len(), 2);
+    }
+
+    /// Tests that the `ServerConnection` sends a GOAWAY frame with the `ENHANCE_YOUR_CALM` error
+    /// code to a client that keeps opening streams and resetting them.
+    #[test]
+    fn test_server_conn_rapid_reset() {
+        let request: Vec<(&[u8], &[u8])> = vec![(b":method", b"GET"),
+                                                (b":scheme", b"http"),
+                                                (b":path", b"/")];
+        let mut encoder = hpack::Encoder::new();
+        let frames = vec![
+            HttpFrame::HeadersFrame(build_headers_frame(encoder.encode(request.clone()), 1)),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(1, ErrorCode::Cancel)),
+            HttpFrame::HeadersFrame(build_headers_frame(encoder.encode(request), 3)),
+            HttpFrame::RstStreamFrame(RstStreamFrame::new(3, ErrorCode::Cancel)),
+        ];
+        let state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = ServerConnection::with_connection(build_mock_http_conn(),
+                                                         state,
+                                                         TestStreamFactory);
+        conn.set_flood_limits(FloodLimits {
+            interval: Duration::from_secs(60),
+            max_rapid_resets: 1,
+            ..FloodLimits::default()
+        });
+        let mut receiver = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+
+        for _ in 0..3 {
+            conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        }
+        assert!(sender.sent.is_empty());
+        assert_eq!(conn.handle_next_frame(&mut receiver, &mut sender).err().unwrap(),
+                   HttpError::Flood(Flood::RapidReset));
+
+        assert_eq!(sender.sent.len(), 1);
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::GoawayFrame(frame) => {
+                assert_eq!(frame.last_stream_id(), 3);
+                assert_eq!(frame.error_code(), ErrorCode::EnhanceYourCalm);
+            }
+            _ => panic!("Expected a GOAWAY frame"),
+        };
+    }
+
+    /// A `StreamFactory` that accepts the tunnels to `example.com:443` only.
+    struct TunnelFactory;
+    impl StreamFactory for TunnelFactory {
+        type Stream = TestStream;
+        fn create(&mut self, _id: StreamId) -> TestStream {
+            TestStream::new()
+        }
+        fn accept_connect(&mut self, _id: StreamId, authority: &[u8], _: &mut TestStream) -> bool {
+            authority == b"example.com:443"
+        }
+    }
+
+    /// Tests that CONNECT requests are accepted or refused as decided by the `StreamFactory`.
+    #[test]
+    fn test_server_conn_connect() {
+        let accepted = vec![(&b":method"[..], &b"CONNECT"[..]),
+                            (&b":authority"[..], &b"example.com:443"[..])];
+        let refused = vec![(&b":method"[..], &b"CONNECT"[..]),
+                           (&b":authority"[..], &b"example.com:80"[..])];
+        let mut encoder = hpack::Encoder::new();
+        let frames = vec![
+            HttpFrame::HeadersFrame(build_headers_frame(encoder.encode(accepted), 1)),
+            HttpFrame::HeadersFrame(build_headers_frame(encoder.encode(refused), 3)),
+        ];
+        let state = DefaultSessionState::<ServerMarker, TestStream>::new();
+        let mut conn = ServerConnection::with_connection(build_mock_http_conn(),
+                                                         state,
+                                                         TunnelFactory);
+        let mut receiver = MockReceiveFrame::new(frames);
+        let mut sender = MockSendFrame::new();
+
+        // The accepted tunnel gets a `200` response that keeps the stream open...
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        assert!(conn.state.get_stream_ref(1).is_some());
+        match HttpFrame::from_raw(&sender.sent[0]).unwrap() {
+            HttpFrame::HeadersFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert!(!frame.is_end_of_stream());
+                let headers = hpack::Decoder::new().decode(frame.header_fragment()).unwrap();
+                assert_eq!(headers, vec![(b":status".to_vec(), b"200".to_vec())]);
+            }
+            _ => panic!("Expected a HEADERS frame"),
+        };
+
+        // ...while the refused one is reset with `CONNECT_ERROR`.
+        conn.handle_next_frame(&mut receiver, &mut sender).unwrap();
+        assert!(conn.state.get_stream_ref(3).is_none());
+        match HttpFrame::from_raw(&sender.sent[1]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 3);
+                assert_eq!(frame.error_code(), ErrorCode::ConnectError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+
+        // A tunnel that fails later on is reset with `CONNECT_ERROR` as well.
+        assert!(conn.fail_tunnel(1, &mut sender).unwrap().is_some());
+        assert!(conn.state.get_stream_ref(1).is_none());
+        match HttpFrame::from_raw(&sender.sent[2]).unwrap() {
+            HttpFrame::RstStreamFrame(frame) => {
+                assert_eq!(frame.get_stream_id(), 1);
+                assert_eq!(frame.error_code(), ErrorCode::ConnectError);
+            }
+            _ => panic!("Expected a RST_STREAM frame"),
+        };
+    }
 }
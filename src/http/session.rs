@@ -10,8 +10,9 @@ use std::io::Read;
 use std::io::Cursor;
 use std::iter::FromIterator;
 use http::{StreamId, OwnedHeader, Header, HttpResult, ErrorCode, HttpError, ConnectionError};
-use http::frame::{HttpSetting, PingFrame};
+use http::frame::{HttpSetting, PingFrame, RawFrame, ExtensionFrame, AltSvcFrame, OriginFrame};
 use http::connection::HttpConnection;
+use http::validation::{ContentLengthError, MalformedHeaders};
 
 /// A trait that defines the interface between an `HttpConnection` and the higher-levels that use
 /// it. Essentially, it allows the `HttpConnection` to pass information onto those higher levels
@@ -38,6 +39,35 @@ pub trait Session {
                            -> HttpResult<()>;
     /// Notifies the `Session` that a particular stream got closed by the peer.
     fn end_of_stream(&mut self, stream_id: StreamId, conn: &mut HttpConnection) -> HttpResult<()>;
+    /// Notifies the `Session` that the body received on a particular stream does not match the
+    /// length declared by the `content-length` header of its message. The message is malformed,
+    /// which the session should treat as a stream error of type `PROTOCOL_ERROR`.
+    ///
+    /// Neither the offending data chunk nor the end of the stream is passed on to the session.
+    ///
+    /// The default implementation resets the stream with `PROTOCOL_ERROR` (see
+    /// `HttpConnection::reset_stream`).
+    fn content_length_mismatch(&mut self,
+                               stream_id: StreamId,
+                               _err: ContentLengthError,
+                               conn: &mut HttpConnection)
+                               -> HttpResult<()> {
+        conn.reset_stream(stream_id, ErrorCode::ProtocolError);
+        Ok(())
+    }
+    /// Notifies the `Session` that the header list received on a particular stream is over the
+    /// connection's `max_header_list_size`. The headers are discarded, as is the end of the
+    /// stream, if the header block carried it.
+    ///
+    /// The default implementation resets the stream with `CANCEL` (see
+    /// `HttpConnection::reset_stream`).
+    fn header_list_too_large(&mut self,
+                             stream_id: StreamId,
+                             conn: &mut HttpConnection)
+                             -> HttpResult<()> {
+        conn.reset_stream(stream_id, ErrorCode::Cancel);
+        Ok(())
+    }
     /// Notifies the `Session` that a particular stream was reset by the peer and provides the
     /// reason behind it.
     fn rst_stream(&mut self,
@@ -68,15 +98,57 @@ pub trait Session {
     /// Concrete `Session` implementations can override this in order to, for example, figure out
     /// which streams can be safely retried (based on the last processed stream id).
     fn on_goaway(&mut self,
-                 _last_stream_id: StreamId,
+                 last_stream_id: StreamId,
                  error_code: ErrorCode,
                  debug_data: Option<&[u8]>,
                  _conn: &mut HttpConnection)
                  -> HttpResult<()> {
-        Err(HttpError::PeerConnectionError(ConnectionError {
-            error_code: error_code,
-            debug_data: debug_data.map(|data| data.to_vec()),
-        }))
+        let mut err = match debug_data {
+            Some(data) => ConnectionError::with_debug_data(error_code, data.to_vec()),
+            None => ConnectionError::new(error_code),
+        };
+        err.set_last_stream_id(last_stream_id);
+        Err(HttpError::PeerConnectionError(err))
+    }
+
+    /// Notifies the `Session` that an `ALTSVC` frame, advertising alternative services for an
+    /// origin (RFC 7838), has been received.
+    ///
+    /// The default implementation ignores the frame.
+    fn on_altsvc(&mut self, _frame: AltSvcFrame, _conn: &mut HttpConnection) -> HttpResult<()> {
+        Ok(())
+    }
+
+    /// Notifies the `Session` that an `ORIGIN` frame, advertising origins for which the peer is
+    /// authoritative (RFC 8336), has been received.
+    ///
+    /// The default implementation ignores the frame.
+    fn on_origin(&mut self, _frame: OriginFrame, _conn: &mut HttpConnection) -> HttpResult<()> {
+        Ok(())
+    }
+
+    /// Notifies the `Session` that a frame of an extension type has been received and parsed by
+    /// the parser registered for its type with the connection's `ExtensionRegistry`.
+    ///
+    /// The default implementation ignores the frame.
+    fn on_extension_frame(&mut self,
+                          _frame: Box<ExtensionFrame>,
+                          _conn: &mut HttpConnection)
+                          -> HttpResult<()> {
+        Ok(())
+    }
+
+    /// Notifies the `Session` that a frame of an unknown type has been received, for which no
+    /// extension parser is registered (or whose parser rejected it).
+    ///
+    /// The default implementation ignores the frame, as the spec requires (Section 4.1).
+    /// Concrete `Session` implementations can override this in order to implement frame-level
+    /// protocol extensions.
+    fn on_unknown_frame(&mut self,
+                        _frame: &RawFrame,
+                        _conn: &mut HttpConnection)
+                        -> HttpResult<()> {
+        Ok(())
     }
 }
 
@@ -395,6 +467,47 @@ pub trait Stream {
         self.close();
     }
 
+    /// Invoked when the session resets the stream because the length of the received body does
+    /// not match the `content-length` that the peer declared for it.
+    ///
+    /// The default implementation handles it like a reset with the `PROTOCOL_ERROR` code.
+    fn on_content_length_mismatch(&mut self, _err: ContentLengthError) {
+        self.on_rst_stream(ErrorCode::ProtocolError);
+    }
+
+    /// Invoked when the session resets the stream because the header block that the peer sent on
+    /// it is malformed (e.g. a response without a `:status`).
+    ///
+    /// The default implementation handles it like a reset with the `PROTOCOL_ERROR` code.
+    fn on_malformed_headers(&mut self, _err: MalformedHeaders) {
+        self.on_rst_stream(ErrorCode::ProtocolError);
+    }
+
+    /// Returns whether the flow control window taken up by the data received on the stream should
+    /// only be given back to the peer as the data is consumed (as reported by `take_consumed`),
+    /// rather than as soon as it is received. This makes the peer stop sending while the data is
+    /// not being consumed.
+    ///
+    /// The default implementation returns `false`.
+    fn defers_window_updates(&self) -> bool {
+        false
+    }
+
+    /// Returns the number of received bytes that were consumed since the last call. It is only
+    /// called for streams that defer window updates.
+    ///
+    /// The default implementation returns `0`.
+    fn take_consumed(&mut self) -> u32 {
+        0
+    }
+
+    /// Tells the stream how many octets of data it may currently send without going over its own
+    /// or the connection's outbound flow control window. It is called by the connection's
+    /// `send_next_data` before the next chunk of data is taken from the streams.
+    ///
+    /// The default implementation ignores the window.
+    fn set_out_window(&mut self, _window: i32) {}
+
     /// Places the next data chunk that should be written onto the stream into the given buffer.
     ///
     /// # Returns
@@ -468,6 +581,14 @@ pub struct DefaultStream {
     pub body: Vec<u8>,
     /// The current stream state.
     pub state: StreamState,
+    /// The error code with which the peer reset the stream, if it did so.
+    pub error_code: Option<ErrorCode>,
+    /// The mismatch between the received body and its declared `content-length`, if the stream was
+    /// reset because of one.
+    pub content_length_error: Option<ContentLengthError>,
+    /// The reason for which the headers received on the stream are malformed, if the stream was
+    /// reset because of them.
+    pub malformed_headers: Option<MalformedHeaders>,
     /// The outgoing data associated to the stream. The `Cursor` points into the `Vec` at the
     /// position where the data has been sent out.
     data: Option<Cursor<Vec<u8>>>,
@@ -481,6 +602,9 @@ impl DefaultStream {
             headers: None,
             body: Vec::new(),
             state: StreamState::Open,
+            error_code: None,
+            content_length_error: None,
+            malformed_headers: None,
             data: None,
         }
     }
@@ -492,6 +616,9 @@ impl DefaultStream {
             headers: None,
             body: Vec::new(),
             state: StreamState::Open,
+            error_code: None,
+            content_length_error: None,
+            malformed_headers: None,
             data: None,
         }
     }
@@ -533,6 +660,21 @@ impl Stream for DefaultStream {
         self.state
     }
 
+    fn on_rst_stream(&mut self, error_code: ErrorCode) {
+        self.error_code = Some(error_code);
+        self.close();
+    }
+
+    fn on_content_length_mismatch(&mut self, err: ContentLengthError) {
+        self.content_length_error = Some(err);
+        self.on_rst_stream(ErrorCode::ProtocolError);
+    }
+
+    fn on_malformed_headers(&mut self, err: MalformedHeaders) {
+        self.malformed_headers = Some(err);
+        self.on_rst_stream(ErrorCode::ProtocolError);
+    }
+
     fn get_data_chunk(&mut self, buf: &mut [u8]) -> Result<StreamDataChunk, StreamDataError> {
         if self.is_closed_local() {
             return Err(StreamDataError::Closed);
@@ -767,6 +909,8 @@ mod tests {
 
         // Now signal the stream that it's been reset.
         stream.on_rst_stream(ErrorCode::Cancel);
+        assert!(stream.is_closed());
+        assert_eq!(stream.error_code, Some(ErrorCode::Cancel));
         // The stream no longer provides data, as there's no point in sending any once it is fully
         // closed on both ends for whatever reason.
         assert!(match stream.get_data_chunk(&mut buf) {
@@ -0,0 +1,161 @@
+//! The module contains the statistics that an `HttpConnection` keeps about its traffic, which are
+//! retrieved as a `ConnectionStats` snapshot.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use http::{StreamId, ErrorCode};
+
+/// The number of frames of each type that were sent or received on a connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameCounts {
+    pub data: u64,
+    pub headers: u64,
+    pub priority: u64,
+    pub rst_stream: u64,
+    pub settings: u64,
+    pub push_promise: u64,
+    pub ping: u64,
+    pub goaway: u64,
+    pub window_update: u64,
+    pub continuation: u64,
+    /// Frames of extension (or unknown) types.
+    pub other: u64,
+}
+
+impl FrameCounts {
+    /// Counts a frame of the given type.
+    pub fn record(&mut self, frame_type: u8) {
+        let count = match frame_type {
+            0x0 => &mut self.data,
+            0x1 => &mut self.headers,
+            0x2 => &mut self.priority,
+            0x3 => &mut self.rst_stream,
+            0x4 => &mut self.settings,
+            0x5 => &mut self.push_promise,
+            0x6 => &mut self.ping,
+            0x7 => &mut self.goaway,
+            0x8 => &mut self.window_update,
+            0x9 => &mut self.continuation,
+            _ => &mut self.other,
+        };
+        *count += 1;
+    }
+
+    /// Returns the number of frames of all types.
+    pub fn total(&self) -> u64 {
+        self.data + self.headers + self.priority + self.rst_stream + self.settings +
+        self.push_promise + self.ping + self.goaway + self.window_update +
+        self.continuation + self.other
+    }
+}
+
+/// The flow control windows of a single stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub stream_id: StreamId,
+    /// The number of octets that the peer may still send on the stream.
+    pub in_window: i32,
+    /// The number of octets that the peer allows us to send on the stream, as granted by its
+    /// settings and WINDOW_UPDATE frames.
+    pub out_window: i32,
+}
+
+/// The statistics of the HPACK compression of the header blocks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HpackStats {
+    /// The current size of the encoder's dynamic table.
+    pub table_size: usize,
+    /// The maximum size of the encoder's dynamic table.
+    pub max_table_size: usize,
+    /// The total size of the names and values of the headers that were sent.
+    pub header_bytes_sent: u64,
+    /// The total size of the header blocks that the sent headers were encoded into.
+    pub block_bytes_sent: u64,
+    /// The total size of the names and values of the headers that were received.
+    pub header_bytes_received: u64,
+    /// The total size of the received header blocks.
+    pub block_bytes_received: u64,
+}
+
+impl HpackStats {
+    /// Returns the size of the sent header blocks relative to the size of the headers that they
+    /// encode, if any headers were sent. The lower, the better the compression.
+    pub fn sent_compression_ratio(&self) -> Option<f64> {
+        ratio(self.block_bytes_sent, self.header_bytes_sent)
+    }
+
+    /// Returns the size of the received header blocks relative to the size of the headers that
+    /// they encode, if any headers were received.
+    pub fn received_compression_ratio(&self) -> Option<f64> {
+        ratio(self.block_bytes_received, self.header_bytes_received)
+    }
+}
+
+fn ratio(compressed: u64, raw: u64) -> Option<f64> {
+    if raw == 0 {
+        None
+    } else {
+        Some(compressed as f64 / raw as f64)
+    }
+}
+
+/// A snapshot of the statistics of a connection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionStats {
+    pub frames_sent: FrameCounts,
+    pub frames_received: FrameCounts,
+    /// The total size of the sent frames, including their headers.
+    pub bytes_sent: u64,
+    /// The total size of the received frames, including their headers.
+    pub bytes_received: u64,
+    /// The size of the connection's inbound flow control window.
+    pub in_window: i32,
+    /// The size of the connection's outbound flow control window.
+    pub out_window: i32,
+    /// The flow control windows of the active streams. Only the client and server connections,
+    /// which track the streams, fill these in.
+    pub streams: Vec<StreamStats>,
+    /// The number of active streams.
+    pub active_streams: usize,
+    /// The number of streams that we reset, per error code.
+    pub resets_sent: HashMap<ErrorCode, u64>,
+    /// The number of streams that the peer reset, per error code.
+    pub resets_received: HashMap<ErrorCode, u64>,
+    pub hpack: HpackStats,
+    /// The round-trip time of the last PING that the peer acknowledged.
+    pub ping_rtt: Option<Duration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameCounts, HpackStats};
+
+    /// Tests that the frames are counted by their type.
+    #[test]
+    fn test_frame_counts() {
+        let mut counts = FrameCounts::default();
+        for &frame_type in &[0x0, 0x0, 0x1, 0x6, 0x9, 0xa, 0xff] {
+            counts.record(frame_type);
+        }
+
+        assert_eq!(counts.data, 2);
+        assert_eq!(counts.headers, 1);
+        assert_eq!(counts.ping, 1);
+        assert_eq!(counts.continuation, 1);
+        assert_eq!(counts.other, 2);
+        assert_eq!(counts.total(), 7);
+    }
+
+    /// Tests that the compression ratios are only available once headers were sent or received.
+    #[test]
+    fn test_compression_ratio() {
+        let mut stats = HpackStats::default();
+        assert!(stats.sent_compression_ratio().is_none());
+
+        stats.header_bytes_sent = 100;
+        stats.block_bytes_sent = 25;
+        assert_eq!(stats.sent_compression_ratio(), Some(0.25));
+        assert!(stats.received_compression_ratio().is_none());
+    }
+}
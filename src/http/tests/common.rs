@@ -4,16 +4,20 @@ use std::io;
 use std::rc::Rc;
 use std::cell::{RefCell, Cell};
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, Mutex, Condvar};
 
 use http::{HttpResult, HttpScheme, StreamId, Header, OwnedHeader, ErrorCode};
-use http::frame::{RawFrame, FrameIR, FrameHeader, pack_header, HttpSetting, PingFrame};
+use http::frame::{RawFrame, FrameIR, FrameHeader, pack_header, HttpSetting, PingFrame,
+                  ExtensionFrame, AltSvcFrame, OriginFrame, HeadersFrame, HeadersFlag};
 use http::session::{Session, DefaultSessionState, SessionState, Stream, StreamState,
                     StreamDataChunk, StreamDataError};
 use http::session::Client as ClientMarker;
 use http::priority::DataPrioritizer;
 use http::transport::TransportStream;
 use http::connection::{SendFrame, ReceiveFrame, HttpFrame, HttpConnection, EndStream, DataChunk};
+use http::validation::ContentLengthError;
 use http::client::ClientConnection;
 use http::server::StreamFactory;
 
@@ -27,6 +31,14 @@ pub fn raw_frame_from_parts<'a>(header: FrameHeader, payload: Vec<u8>) -> RawFra
     buf.into()
 }
 
+/// Creates a new HEADERS frame that carries the given complete header block (i.e. one that has the
+/// `END_HEADERS` flag set) on the given stream.
+pub fn build_headers_frame<'a>(fragment: Vec<u8>, stream_id: StreamId) -> HeadersFrame<'a> {
+    let mut frame = HeadersFrame::new(fragment, stream_id);
+    frame.set_flag(HeadersFlag::EndHeaders);
+    frame
+}
+
 /// Serializes the given frame into a newly allocated vector (without consuming the frame).
 pub fn serialize_frame<F: FrameIR + Clone>(frame: &F) -> Vec<u8> {
     let mut buf = io::Cursor::new(Vec::new());
@@ -165,11 +177,14 @@ pub fn build_stub_from_frames(frames: &Vec<HttpFrame>) -> Vec<u8> {
         let serialized = match *frame {
             HttpFrame::DataFrame(ref frame) => serialize_frame(frame),
             HttpFrame::HeadersFrame(ref frame) => serialize_frame(frame),
+            HttpFrame::PriorityFrame(ref frame) => serialize_frame(frame),
             HttpFrame::RstStreamFrame(ref frame) => serialize_frame(frame),
             HttpFrame::SettingsFrame(ref frame) => serialize_frame(frame),
             HttpFrame::PingFrame(ref frame) => serialize_frame(frame),
             HttpFrame::GoawayFrame(ref frame) => serialize_frame(frame),
             HttpFrame::WindowUpdateFrame(ref frame) => serialize_frame(frame),
+            HttpFrame::AltSvcFrame(ref frame) => serialize_frame(frame),
+            HttpFrame::OriginFrame(ref frame) => serialize_frame(frame),
             HttpFrame::UnknownFrame(ref frame) => serialize_frame(frame),
         };
         buf.extend(serialized.into_iter());
@@ -222,6 +237,114 @@ fn sanity_check_stub_stream() {
     }
 }
 
+/// The bytes that are in flight in one direction of a `PipeTransportStream`.
+struct Pipe {
+    /// The bytes written, but not read yet, and whether the pipe is closed.
+    buf: Mutex<(VecDeque<u8>, bool)>,
+    /// Signals that bytes were written or that the pipe was closed.
+    changed: Condvar,
+}
+
+impl Pipe {
+    fn new() -> Arc<Pipe> {
+        Arc::new(Pipe {
+            buf: Mutex::new((VecDeque::new(), false)),
+            changed: Condvar::new(),
+        })
+    }
+
+    fn close(&self) {
+        self.buf.lock().unwrap().1 = true;
+        self.changed.notify_all();
+    }
+}
+
+/// One end of an in-memory connection, created by `pipe`. Unlike the `StubTransportStream`, it can
+/// be moved to other threads, so it can back the connections of the `Client`.
+///
+/// Whatever is written to one end can be read from the other. Reads block until there is
+/// something to read. Once either end is closed, reads return an EOF (after the bytes that are
+/// still in flight) and writes fail.
+#[derive(Clone)]
+pub struct PipeTransportStream {
+    reader: Arc<Pipe>,
+    writer: Arc<Pipe>,
+}
+
+/// Returns the two ends of a new in-memory connection.
+pub fn pipe() -> (PipeTransportStream, PipeTransportStream) {
+    let (first, second) = (Pipe::new(), Pipe::new());
+    (PipeTransportStream {
+        reader: first.clone(),
+        writer: second.clone(),
+    },
+     PipeTransportStream {
+        reader: second,
+        writer: first,
+    })
+}
+
+impl PipeTransportStream {
+    /// Reads the next frame written to the other end, skipping over the client preface, if that
+    /// is what is read.
+    pub fn read_frame(&mut self) -> io::Result<RawFrame<'static>> {
+        let mut header = [0; 9];
+        try!(TransportStream::read_exact(self, &mut header));
+        if &header == b"PRI * HTT" {
+            try!(TransportStream::read_exact(self, &mut [0; 15]));
+            try!(TransportStream::read_exact(self, &mut header));
+        }
+        let len = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+        let mut frame = header.to_vec();
+        frame.resize(9 + len, 0);
+        try!(TransportStream::read_exact(self, &mut frame[9..]));
+        Ok(RawFrame::from(frame))
+    }
+}
+
+impl io::Read for PipeTransportStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.reader.buf.lock().unwrap();
+        while pipe.0.is_empty() && !pipe.1 {
+            pipe = self.reader.changed.wait(pipe).unwrap();
+        }
+        let len = buf.len().min(pipe.0.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.0.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for PipeTransportStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.writer.buf.lock().unwrap();
+        if pipe.1 {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Closed"));
+        }
+        pipe.0.extend(buf);
+        self.writer.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TransportStream for PipeTransportStream {
+    fn try_split(&self) -> Result<PipeTransportStream, io::Error> {
+        Ok(self.clone())
+    }
+
+    /// Closes both directions of the connection, for both ends.
+    fn close(&mut self) -> io::Result<()> {
+        self.reader.close();
+        self.writer.close();
+        Ok(())
+    }
+}
+
 /// A helper struct implementing the `Session` trait, intended for testing
 /// purposes.
 ///
@@ -248,6 +371,20 @@ pub struct TestSession {
     pub rst_streams: Vec<StreamId>,
     /// All the goaway error codes received.
     pub goaways: Vec<ErrorCode>,
+    /// The streams that were closed by the peer
+    pub ends: Vec<StreamId>,
+    /// The content-length mismatches detected on streams
+    pub content_length_errors: Vec<(StreamId, ContentLengthError)>,
+    /// The streams whose header lists were over the size limit
+    pub oversized_headers: Vec<StreamId>,
+    /// The ALTSVC frames received
+    pub altsvcs: Vec<AltSvcFrame>,
+    /// The ORIGIN frames received
+    pub origins: Vec<OriginFrame>,
+    /// The extension frames received
+    pub extension_frames: Vec<Box<ExtensionFrame>>,
+    /// The unknown frames received
+    pub unknown_frames: Vec<RawFrame<'static>>,
     /// All the ping data received
     pub pings: Vec<u64>,
     /// All the ping ack data received
@@ -266,6 +403,13 @@ impl TestSession {
             curr_chunk: 0,
             rst_streams: Vec::new(),
             goaways: Vec::new(),
+            ends: Vec::new(),
+            content_length_errors: Vec::new(),
+            oversized_headers: Vec::new(),
+            altsvcs: Vec::new(),
+            origins: Vec::new(),
+            extension_frames: Vec::new(),
+            unknown_frames: Vec::new(),
             pings: Vec::new(),
             pongs: Vec::new(),
         }
@@ -283,6 +427,13 @@ impl TestSession {
             curr_chunk: 0,
             rst_streams: Vec::new(),
             goaways: Vec::new(),
+            ends: Vec::new(),
+            content_length_errors: Vec::new(),
+            oversized_headers: Vec::new(),
+            altsvcs: Vec::new(),
+            origins: Vec::new(),
+            extension_frames: Vec::new(),
+            unknown_frames: Vec::new(),
             pings: Vec::new(),
             pongs: Vec::new(),
         }
@@ -314,7 +465,25 @@ impl Session for TestSession {
         Ok(())
     }
 
-    fn end_of_stream(&mut self, _: StreamId, _: &mut HttpConnection) -> HttpResult<()> {
+    fn end_of_stream(&mut self, stream_id: StreamId, _: &mut HttpConnection) -> HttpResult<()> {
+        self.ends.push(stream_id);
+        Ok(())
+    }
+
+    fn content_length_mismatch(&mut self,
+                               stream_id: StreamId,
+                               err: ContentLengthError,
+                               _: &mut HttpConnection)
+                               -> HttpResult<()> {
+        self.content_length_errors.push((stream_id, err));
+        Ok(())
+    }
+
+    fn header_list_too_large(&mut self,
+                             stream_id: StreamId,
+                             _: &mut HttpConnection)
+                             -> HttpResult<()> {
+        self.oversized_headers.push(stream_id);
         Ok(())
     }
 
@@ -344,6 +513,29 @@ impl Session for TestSession {
         Ok(())
     }
 
+    fn on_altsvc(&mut self, frame: AltSvcFrame, _: &mut HttpConnection) -> HttpResult<()> {
+        self.altsvcs.push(frame);
+        Ok(())
+    }
+
+    fn on_origin(&mut self, frame: OriginFrame, _: &mut HttpConnection) -> HttpResult<()> {
+        self.origins.push(frame);
+        Ok(())
+    }
+
+    fn on_extension_frame(&mut self,
+                          frame: Box<ExtensionFrame>,
+                          _: &mut HttpConnection)
+                          -> HttpResult<()> {
+        self.extension_frames.push(frame);
+        Ok(())
+    }
+
+    fn on_unknown_frame(&mut self, frame: &RawFrame, _: &mut HttpConnection) -> HttpResult<()> {
+        self.unknown_frames.push(frame.as_ref().to_vec().into());
+        Ok(())
+    }
+
     fn on_ping(&mut self, ping: &PingFrame, _conn: &mut HttpConnection) -> HttpResult<()> {
         self.pings.push(ping.opaque_data());
         Ok(())
@@ -41,11 +41,25 @@ mod root_tests {
         }
     }
 
+    /// Tests that the headers of a `Response` can be looked up by name.
+    #[test]
+    fn test_response_header_map() {
+        let resp = Response::new(1,
+                                 vec![(b":status".to_vec(), b"200".to_vec()),
+                                      (b"Content-Length".to_vec(), b"3".to_vec())],
+                                 b"abc".to_vec());
+        let map = resp.header_map();
+
+        assert_eq!(map.get(b":status"), Some(&b"200"[..]));
+        assert_eq!(map.content_length(), Some(3));
+    }
+
     #[test]
     fn test_connection_error_no_debug_data() {
         let err = ConnectionError::new(ErrorCode::ProtocolError);
         assert_eq!(err.error_code(), ErrorCode::ProtocolError);
         assert!(err.debug_data().is_none());
+        assert!(err.last_stream_id().is_none());
         assert!(err.debug_str().is_none());
         assert_eq!(err.description(), "ProtocolError");
     }
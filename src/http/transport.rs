@@ -183,6 +183,11 @@ mod tests {
                 sender.send_frame(frame).unwrap();
                 ret
             }
+            HttpFrame::PriorityFrame(frame) => {
+                let ret = serialize_frame(&frame);
+                sender.send_frame(frame).unwrap();
+                ret
+            }
             HttpFrame::RstStreamFrame(frame) => {
                 let ret = serialize_frame(&frame);
                 sender.send_frame(frame).unwrap();
@@ -208,6 +213,16 @@ mod tests {
                 sender.send_frame(frame).unwrap();
                 ret
             }
+            HttpFrame::AltSvcFrame(frame) => {
+                let ret = serialize_frame(&frame);
+                sender.send_frame(frame).unwrap();
+                ret
+            }
+            HttpFrame::OriginFrame(frame) => {
+                let ret = serialize_frame(&frame);
+                sender.send_frame(frame).unwrap();
+                ret
+            }
             HttpFrame::UnknownFrame(frame) => {
                 let ret = serialize_frame(&frame);
                 let raw: RawFrame = frame.into();
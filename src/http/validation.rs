@@ -0,0 +1,395 @@
+//! The module implements the validation of received header blocks against the rules of HTTP/2
+//! message semantics (RFC 7540, Section 8.1.2).
+//!
+//! A header block that violates any of the rules makes the message malformed, which the
+//! receiver is required to treat as a stream error of type `PROTOCOL_ERROR`. The same holds for a
+//! message whose body does not match the length declared by its `content-length` header.
+
+use std::error::Error;
+use std::fmt;
+
+use http::{Header, HeaderMap};
+
+/// An enum representing the kinds of header blocks, which differ in the pseudo-headers that they
+/// are required (or allowed) to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderBlockKind {
+    /// The header block that starts a request.
+    Request,
+    /// The header block that starts a (possibly informational) response.
+    Response,
+    /// A header block that follows the message's body. It must not contain any pseudo-headers.
+    Trailers,
+}
+
+/// An enum representing the reasons for which a header block can be malformed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedHeaders {
+    /// A header name contains upper-case characters.
+    UppercaseName,
+    /// A connection-specific header field (e.g. `connection` or `transfer-encoding`) is present.
+    ConnectionSpecific,
+    /// The `te` header has a value other than `trailers`.
+    InvalidTe,
+    /// A pseudo-header follows a regular header.
+    PseudoAfterRegular,
+    /// A pseudo-header that is not defined for the kind of the header block is present.
+    UnknownPseudo,
+    /// A pseudo-header is present more than once.
+    DuplicatePseudo,
+    /// A pseudo-header that is required for the kind of the header block is missing or empty.
+    MissingPseudo,
+    /// The `:protocol` pseudo-header is present on a request that is not an extended CONNECT
+    /// request, or that the receiver has not allowed (RFC 8441, Section 4).
+    UnexpectedProtocol,
+}
+
+impl fmt::Display for MalformedHeaders {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Malformed header block: {}", self.description())
+    }
+}
+
+impl Error for MalformedHeaders {
+    fn description(&self) -> &str {
+        match *self {
+            MalformedHeaders::UppercaseName => "A header name contains upper-case characters",
+            MalformedHeaders::ConnectionSpecific => "A connection-specific header is present",
+            MalformedHeaders::InvalidTe => "The te header has a value other than trailers",
+            MalformedHeaders::PseudoAfterRegular => "A pseudo-header follows a regular header",
+            MalformedHeaders::UnknownPseudo => "An unknown pseudo-header is present",
+            MalformedHeaders::DuplicatePseudo => "A pseudo-header is duplicated",
+            MalformedHeaders::MissingPseudo => "A required pseudo-header is missing",
+            MalformedHeaders::UnexpectedProtocol => "The :protocol pseudo-header is not allowed",
+        }
+    }
+}
+
+/// The header fields that are specific to HTTP/1.x connections and must not be used in HTTP/2.
+const CONNECTION_SPECIFIC: &'static [&'static [u8]] = &[b"connection",
+                                                         b"keep-alive",
+                                                         b"proxy-connection",
+                                                         b"transfer-encoding",
+                                                         b"upgrade"];
+
+/// The pseudo-headers defined for requests.
+const REQUEST_PSEUDO: &'static [&'static [u8]] = &[b":method",
+                                                     b":scheme",
+                                                     b":authority",
+                                                     b":path",
+                                                     b":protocol"];
+
+/// The pseudo-headers defined for responses.
+const RESPONSE_PSEUDO: &'static [&'static [u8]] = &[b":status"];
+
+/// Validates the given header block of the given kind.
+///
+/// # Returns
+///
+/// `Ok(())` if the header block is well-formed; otherwise, the first rule that it violates.
+pub fn validate_headers(headers: &[Header], kind: HeaderBlockKind) -> Result<(), MalformedHeaders> {
+    let allowed = match kind {
+        HeaderBlockKind::Request => REQUEST_PSEUDO,
+        HeaderBlockKind::Response => RESPONSE_PSEUDO,
+        HeaderBlockKind::Trailers => &[],
+    };
+
+    let mut pseudo: Vec<&[u8]> = Vec::new();
+    let mut seen_regular = false;
+    for header in headers {
+        let name = header.name();
+        if name.iter().any(|b| b.is_ascii_uppercase()) {
+            return Err(MalformedHeaders::UppercaseName);
+        }
+        if name.first() == Some(&b':') {
+            if seen_regular {
+                return Err(MalformedHeaders::PseudoAfterRegular);
+            }
+            if !allowed.contains(&name) {
+                return Err(MalformedHeaders::UnknownPseudo);
+            }
+            if pseudo.contains(&name) {
+                return Err(MalformedHeaders::DuplicatePseudo);
+            }
+            pseudo.push(name);
+            continue;
+        }
+
+        seen_regular = true;
+        if CONNECTION_SPECIFIC.contains(&name) {
+            return Err(MalformedHeaders::ConnectionSpecific);
+        }
+        if name == b"te" && header.value() != b"trailers" {
+            return Err(MalformedHeaders::InvalidTe);
+        }
+    }
+
+    let value = |name: &[u8]| headers.iter().find(|h| h.name() == name).map(|h| h.value());
+    let has = |name: &[u8]| value(name).map_or(false, |value| !value.is_empty());
+    let complete = match kind {
+        // Extended CONNECT requests carry all the pseudo-headers of a regular request, along with
+        // the protocol that the stream is to be used for (RFC 8441, Section 4).
+        HeaderBlockKind::Request if pseudo.contains(&&b":protocol"[..]) => {
+            if value(b":method") != Some(b"CONNECT") {
+                return Err(MalformedHeaders::UnexpectedProtocol);
+            }
+            has(b":protocol") && has(b":scheme") && has(b":path") && has(b":authority")
+        }
+        // CONNECT requests only carry the authority of the tunnel's target (Section 8.3).
+        HeaderBlockKind::Request if value(b":method") == Some(b"CONNECT") => {
+            has(b":authority") && !pseudo.contains(&&b":scheme"[..]) &&
+            !pseudo.contains(&&b":path"[..])
+        }
+        HeaderBlockKind::Request => has(b":method") && has(b":scheme") && has(b":path"),
+        HeaderBlockKind::Response => has(b":status"),
+        HeaderBlockKind::Trailers => true,
+    };
+    if !complete {
+        return Err(MalformedHeaders::MissingPseudo);
+    }
+
+    Ok(())
+}
+
+/// An error indicating that the length of a received message body does not match the length
+/// declared by the message's `content-length` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLengthError {
+    /// The length declared by the `content-length` header.
+    pub declared: u64,
+    /// The number of bytes received by the time the mismatch was detected.
+    pub received: u64,
+}
+
+impl fmt::Display for ContentLengthError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt,
+               "Received {} bytes of a body with a content-length of {}",
+               self.received,
+               self.declared)
+    }
+}
+
+impl Error for ContentLengthError {
+    fn description(&self) -> &str {
+        "The length of the body does not match its content-length"
+    }
+}
+
+/// Tracks the length of a message body that is being received against the length that the
+/// message declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyLength {
+    declared: u64,
+    received: u64,
+}
+
+impl BodyLength {
+    /// Starts tracking a body of the given declared length.
+    pub fn new(declared: u64) -> BodyLength {
+        BodyLength {
+            declared: declared,
+            received: 0,
+        }
+    }
+
+    /// Accounts for a received chunk of the body of the given length.
+    ///
+    /// Returns an error if the body has become longer than declared.
+    pub fn add(&mut self, len: usize) -> Result<(), ContentLengthError> {
+        self.received += len as u64;
+        if self.received > self.declared {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Checks that the full body has been received, once the message has ended.
+    pub fn finish(&self) -> Result<(), ContentLengthError> {
+        if self.received != self.declared {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    /// Internal helper method. Returns the error that describes the current mismatch.
+    fn error(&self) -> ContentLengthError {
+        ContentLengthError {
+            declared: self.declared,
+            received: self.received,
+        }
+    }
+}
+
+/// Returns the length that the given header block declares for the body of its message, if any.
+///
+/// Responses that never carry a body (informational, `204` and `304` responses) declare none,
+/// regardless of their headers.
+pub fn declared_body_length(headers: &[Header]) -> Option<u64> {
+    let map = HeaderMap::from(headers);
+    match map.get(b":status") {
+        Some(status) if status.first() == Some(&b'1') || status == b"204" || status == b"304" => {
+            None
+        }
+        _ => map.content_length(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_headers, declared_body_length, HeaderBlockKind, MalformedHeaders,
+                BodyLength, ContentLengthError};
+    use http::Header;
+
+    /// A helper function that builds a header list from the given name/value pairs.
+    fn headers<'a>(pairs: &[(&'a [u8], &'a [u8])]) -> Vec<Header<'a, 'a>> {
+        pairs.iter().map(|&(name, value)| Header::new(name, value)).collect()
+    }
+
+    /// The pseudo-headers of a well-formed GET request.
+    fn request<'a>() -> Vec<(&'a [u8], &'a [u8])> {
+        vec![(b":method", b"GET"), (b":scheme", b"https"), (b":path", b"/")]
+    }
+
+    #[test]
+    fn test_valid_header_blocks() {
+        let mut req = request();
+        req.push((b":authority", b"example.com"));
+        req.push((b"te", b"trailers"));
+        req.push((b"accept", b"*/*"));
+        assert_eq!(validate_headers(&headers(&req), HeaderBlockKind::Request), Ok(()));
+
+        let connect = headers(&[(b":method", b"CONNECT"), (b":authority", b"example.com:443")]);
+        assert_eq!(validate_headers(&connect, HeaderBlockKind::Request), Ok(()));
+
+        let extended_connect = headers(&[(b":method", b"CONNECT"),
+                                         (b":protocol", b"websocket"),
+                                         (b":scheme", b"https"),
+                                         (b":path", b"/chat"),
+                                         (b":authority", b"example.com")]);
+        assert_eq!(validate_headers(&extended_connect, HeaderBlockKind::Request), Ok(()));
+
+        let resp = headers(&[(b":status", b"200"), (b"content-length", b"0")]);
+        assert_eq!(validate_headers(&resp, HeaderBlockKind::Response), Ok(()));
+
+        let trailers = headers(&[(b"grpc-status", b"0")]);
+        assert_eq!(validate_headers(&trailers, HeaderBlockKind::Trailers), Ok(()));
+    }
+
+    #[test]
+    fn test_uppercase_name() {
+        let resp = headers(&[(b":status", b"200"), (b"Content-Length", b"0")]);
+        assert_eq!(validate_headers(&resp, HeaderBlockKind::Response),
+                   Err(MalformedHeaders::UppercaseName));
+    }
+
+    #[test]
+    fn test_connection_specific_headers() {
+        for name in &[&b"connection"[..], b"keep-alive", b"transfer-encoding"] {
+            let resp = headers(&[(b":status", b"200"), (name, b"x")]);
+            assert_eq!(validate_headers(&resp, HeaderBlockKind::Response),
+                       Err(MalformedHeaders::ConnectionSpecific));
+        }
+    }
+
+    #[test]
+    fn test_te_header() {
+        let mut req = request();
+        req.push((b"te", b"gzip"));
+        assert_eq!(validate_headers(&headers(&req), HeaderBlockKind::Request),
+                   Err(MalformedHeaders::InvalidTe));
+    }
+
+    #[test]
+    fn test_pseudo_after_regular() {
+        let resp = headers(&[(b"x-a", b"b"), (b":status", b"200")]);
+        assert_eq!(validate_headers(&resp, HeaderBlockKind::Response),
+                   Err(MalformedHeaders::PseudoAfterRegular));
+    }
+
+    #[test]
+    fn test_unknown_and_duplicate_pseudo() {
+        let resp = headers(&[(b":status", b"200"), (b":path", b"/")]);
+        assert_eq!(validate_headers(&resp, HeaderBlockKind::Response),
+                   Err(MalformedHeaders::UnknownPseudo));
+
+        let trailers = headers(&[(b":status", b"200")]);
+        assert_eq!(validate_headers(&trailers, HeaderBlockKind::Trailers),
+                   Err(MalformedHeaders::UnknownPseudo));
+
+        let mut req = request();
+        req.push((b":path", b"/other"));
+        assert_eq!(validate_headers(&headers(&req), HeaderBlockKind::Request),
+                   Err(MalformedHeaders::DuplicatePseudo));
+    }
+
+    #[test]
+    fn test_missing_pseudo() {
+        let req = headers(&[(b":method", b"GET"), (b":path", b"/")]);
+        assert_eq!(validate_headers(&req, HeaderBlockKind::Request),
+                   Err(MalformedHeaders::MissingPseudo));
+
+        let req = headers(&[(b":method", b"GET"), (b":scheme", b"http"), (b":path", b"")]);
+        assert_eq!(validate_headers(&req, HeaderBlockKind::Request),
+                   Err(MalformedHeaders::MissingPseudo));
+
+        let connect = headers(&[(b":method", b"CONNECT"),
+                                (b":authority", b"example.com:443"),
+                                (b":path", b"/")]);
+        assert_eq!(validate_headers(&connect, HeaderBlockKind::Request),
+                   Err(MalformedHeaders::MissingPseudo));
+
+        assert_eq!(validate_headers(&[], HeaderBlockKind::Response),
+                   Err(MalformedHeaders::MissingPseudo));
+    }
+
+    #[test]
+    fn test_protocol_pseudo() {
+        let extended_connect = headers(&[(b":method", b"CONNECT"),
+                                         (b":protocol", b"websocket"),
+                                         (b":authority", b"example.com")]);
+        assert_eq!(validate_headers(&extended_connect, HeaderBlockKind::Request),
+                   Err(MalformedHeaders::MissingPseudo));
+
+        let mut req = request();
+        req.push((b":protocol", b"websocket"));
+        assert_eq!(validate_headers(&headers(&req), HeaderBlockKind::Request),
+                   Err(MalformedHeaders::UnexpectedProtocol));
+
+        let resp = headers(&[(b":status", b"200"), (b":protocol", b"websocket")]);
+        assert_eq!(validate_headers(&resp, HeaderBlockKind::Response),
+                   Err(MalformedHeaders::UnknownPseudo));
+    }
+
+    #[test]
+    fn test_declared_body_length() {
+        let resp = headers(&[(b":status", b"200"), (b"content-length", b"10")]);
+        assert_eq!(declared_body_length(&resp), Some(10));
+        let resp = headers(&[(b":status", b"304"), (b"content-length", b"10")]);
+        assert_eq!(declared_body_length(&resp), None);
+        let resp = headers(&[(b":status", b"100"), (b"content-length", b"10")]);
+        assert_eq!(declared_body_length(&resp), None);
+        let mut req = request();
+        req.push((b"content-length", b"3"));
+        assert_eq!(declared_body_length(&headers(&req)), Some(3));
+        assert_eq!(declared_body_length(&headers(&request())), None);
+    }
+
+    #[test]
+    fn test_body_length() {
+        let mut length = BodyLength::new(5);
+        assert_eq!(length.add(3), Ok(()));
+        assert_eq!(length.finish(),
+                   Err(ContentLengthError {
+                       declared: 5,
+                       received: 3,
+                   }));
+        assert_eq!(length.add(2), Ok(()));
+        assert_eq!(length.finish(), Ok(()));
+        assert_eq!(length.add(1),
+                   Err(ContentLengthError {
+                       declared: 5,
+                       received: 6,
+                   }));
+    }
+}
@@ -1,8 +1,13 @@
 //! The module contains a simple HTTP/2 server implementation.
 
-use http::{Response, StaticResponse, HttpResult, HttpError, HttpScheme, StreamId, Header};
+use std::thread;
+
+use http::{Response, StaticResponse, HttpResult, HttpError, HttpScheme, StreamId, Header,
+           HeaderMap};
+use http::frame::FrameIR;
 use http::transport::{TransportStream, TransportReceiveFrame};
-use http::connection::{HttpConnection, EndStream, SendStatus};
+use http::connection::{HttpConnection, EndStream, SendStatus, SendFrame};
+use http::queue::{FrameQueue, FrameQueueHandle, QueueLimits};
 use http::session::{DefaultSessionState, SessionState, Stream, DefaultStream};
 use http::session::Server as ServerMarker;
 use http::server::{ServerConnection, StreamFactory};
@@ -17,6 +22,17 @@ pub struct ServerRequest<'a, 'n, 'v>
     pub body: &'a [u8],
 }
 
+impl<'a, 'n, 'v> ServerRequest<'a, 'n, 'v> {
+    /// Returns the headers of the request (including the pseudo-headers) as a `HeaderMap`, which
+    /// allows them to be looked up by name.
+    ///
+    /// The map is built anew on each call, so it should be kept around when looking up more than
+    /// a single header.
+    pub fn header_map(&self) -> HeaderMap {
+        HeaderMap::from(self.headers)
+    }
+}
+
 /// A simple implementation of the `http::server::StreamFactory` trait that creates new
 /// `DefaultStream` instances.
 struct SimpleFactory;
@@ -34,6 +50,12 @@ impl StreamFactory for SimpleFactory {
 /// The `handle_next` method needs to be called regularly in order to have the server process
 /// received frames, as well as send out the responses.
 ///
+/// By default, the frames are written to the client directly, blocking `handle_next` for as long
+/// as the client does not read them. A server created by `with_queue_limits` instead has the
+/// frames written by a dedicated thread, from a bounded `FrameQueue`. Once the client stops
+/// reading for longer than the queue's stall timeout, `handle_next` fails with
+/// `HttpError::PeerStalled`.
+///
 /// This is an exceedingly simple implementation of an HTTP/2 server and is mostly an example of
 /// how the `solicit::http` API can be used to make one.
 ///
@@ -88,10 +110,31 @@ pub struct SimpleServer<TS, H>
 {
     conn: ServerConnection<SimpleFactory>,
     receiver: TS,
-    sender: TS,
+    sender: ServerSender<TS>,
     handler: H,
 }
 
+/// The means by which a `SimpleServer` sends its frames to the client.
+enum ServerSender<TS: TransportStream> {
+    /// The frames are written to the stream directly.
+    Direct(TS),
+    /// The frames are queued for a writer thread.
+    Queued(FrameQueueHandle),
+}
+
+impl<TS: TransportStream> SendFrame for ServerSender<TS> {
+    fn send_frame<F: FrameIR>(&mut self, frame: F) -> HttpResult<()> {
+        match *self {
+            ServerSender::Direct(ref mut stream) => stream.send_frame(frame),
+            ServerSender::Queued(ref mut queue) => queue.send_frame(frame),
+        }
+    }
+}
+
+/// The largest DATA frame that the server sends: a chunk of 8 KiB, along with the frame header
+/// and the most padding that a frame can have.
+const MAX_DATA_FRAME_SIZE: usize = 9 + 256 + 8 * 1024;
+
 impl<TS, H> SimpleServer<TS, H>
     where TS: TransportStream,
           H: FnMut(ServerRequest) -> Response<'static, 'static>
@@ -99,20 +142,31 @@ impl<TS, H> SimpleServer<TS, H>
     /// Creates a new `SimpleServer` that will use the given `TransportStream` to communicate to
     /// the client. Assumes that the stream is fully uninitialized -- no preface sent or read yet.
     pub fn new(mut stream: TS, handler: H) -> HttpResult<SimpleServer<TS, H>> {
-        // First assert that the preface is received
+        try!(SimpleServer::<TS, H>::read_preface(&mut stream));
+        let receiver = try!(stream.try_split());
+        SimpleServer::init(receiver, ServerSender::Direct(stream), handler)
+    }
+
+    /// Internal helper method. Asserts that the client preface is received on the given stream.
+    fn read_preface(stream: &mut TS) -> HttpResult<()> {
         let mut preface = [0; 24];
-        TransportStream::read_exact(&mut stream, &mut preface).unwrap();
+        TransportStream::read_exact(stream, &mut preface).unwrap();
         if &preface != b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n" {
             return Err(HttpError::UnableToConnect);
         }
+        Ok(())
+    }
 
+    /// Internal helper method. Creates the server on top of the given halves of the stream and
+    /// initializes its connection.
+    fn init(receiver: TS, sender: ServerSender<TS>, handler: H) -> HttpResult<SimpleServer<TS, H>> {
         let conn = HttpConnection::new(HttpScheme::Http);
         let state = DefaultSessionState::<ServerMarker, _>::new();
         let conn = ServerConnection::with_connection(conn, state, SimpleFactory);
         let mut server = SimpleServer {
             conn: conn,
-            receiver: try!(stream.try_split()),
-            sender: stream,
+            receiver: receiver,
+            sender: sender,
             handler: handler,
         };
 
@@ -129,8 +183,21 @@ impl<TS, H> SimpleServer<TS, H>
     /// underlying stream.
     ///
     /// Handling the frame can trigger the handler callback. Any responses returned by the handler
-    /// are immediately flushed out to the client (blocking the call until it's done).
+    /// are immediately flushed out to the client (or, if the frames are queued, to the queue),
+    /// blocking the call until it's done.
+    ///
+    /// Once the client is found to have stalled, the underlying stream is shut down.
     pub fn handle_next(&mut self) -> HttpResult<()> {
+        let result = self.handle_next_frame();
+        if let Err(HttpError::PeerStalled) = result {
+            // Unblocks the writer thread, which is stuck writing to the client.
+            let _ = self.receiver.close();
+        }
+        result
+    }
+
+    /// Handles the next incoming frame and flushes out the responses that it completes.
+    fn handle_next_frame(&mut self) -> HttpResult<()> {
         try!(self.conn.handle_next_frame(&mut TransportReceiveFrame::new(&mut self.receiver),
                                          &mut self.sender));
         let responses = try!(self.handle_requests());
@@ -179,9 +246,15 @@ impl<TS, H> SimpleServer<TS, H>
     /// Flushes the outgoing buffers of all streams.
     #[inline]
     fn flush_streams(&mut self) -> HttpResult<()> {
-        while let SendStatus::Sent = try!(self.conn.send_next_data(&mut self.sender)) {}
-
-        Ok(())
+        loop {
+            // Keeps pace with the writer thread, so that a large body does not overflow the queue.
+            if let ServerSender::Queued(ref queue) = self.sender {
+                try!(queue.wait_for_room(MAX_DATA_FRAME_SIZE));
+            }
+            if let SendStatus::Nothing = try!(self.conn.send_next_data(&mut self.sender)) {
+                return Ok(());
+            }
+        }
     }
 
     /// Removes closed streams from the connection state.
@@ -192,3 +265,32 @@ impl<TS, H> SimpleServer<TS, H>
         Ok(())
     }
 }
+
+impl<TS, H> SimpleServer<TS, H>
+    where TS: TransportStream + Send + 'static,
+          H: FnMut(ServerRequest) -> Response<'static, 'static>
+{
+    /// Creates a new `SimpleServer` in the same way as `new`, but whose frames are written to the
+    /// client by a dedicated thread, from a queue with the given limits.
+    pub fn with_queue_limits(mut stream: TS,
+                             handler: H,
+                             limits: QueueLimits)
+                             -> HttpResult<SimpleServer<TS, H>> {
+        try!(SimpleServer::<TS, H>::read_preface(&mut stream));
+        let receiver = try!(stream.try_split());
+        let (queue, sender) = FrameQueue::new(limits);
+        try!(thread::Builder::new()
+                 .name("Solicit Server Writer".into())
+                 .spawn(move || {
+                     while let Some(frame) = queue.pop() {
+                         if let Err(err) = stream.write_all(&frame) {
+                             // Dropping the queue fails any frames queued from here on out.
+                             debug!("Failed to write a frame: {:?}", err);
+                             break;
+                         }
+                     }
+                     debug!("Writer thread halting");
+                 }));
+        SimpleServer::init(receiver, ServerSender::Queued(sender), handler)
+    }
+}